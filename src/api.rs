@@ -0,0 +1,419 @@
+//! Машиночитаемое представление LAS файлов: `/api/las` и `/api/data`.
+//!
+//! Используются те же правила разбора, что и в просмотрщике
+//! (`LasFile::parse`, `get_curve_data`, `get_curve_stats`).
+
+use crate::config::Config;
+use crate::derived::DerivedCurve;
+use crate::families::AliasDictionary;
+use crate::las::{HeaderItem, LasFile};
+use crate::petrophysics::{Computation, PetroParams};
+use crate::resample::ResampleMethod;
+use crate::units::UnitSystem;
+use anyhow::{anyhow, bail, Result};
+use serde_json::{json, Value};
+use std::collections::HashMap;
+
+/// Числовой параметр запроса: пустой или отсутствующий - `None`, не число (или не конечное) - ошибка
+pub fn number_param(params: &HashMap<String, String>, name: &str) -> Result<Option<f64>> {
+    match params.get(name).map(|s| s.trim()).filter(|s| !s.is_empty()) {
+        Some(s) => s
+            .parse::<f64>()
+            .ok()
+            .filter(|v| v.is_finite())
+            .map(Some)
+            .ok_or_else(|| anyhow!("Parameter '{}' must be a number, got '{}'", name, s)),
+        None => Ok(None),
+    }
+}
+
+/// Флаг в параметре запроса (`1/true/yes/on`, `0/false/no/off`): пустой или отсутствующий - `None`
+pub fn flag_param(params: &HashMap<String, String>, name: &str) -> Result<Option<bool>> {
+    match params.get(name).map(|s| s.trim()) {
+        None | Some("") => Ok(None),
+        Some("1" | "true" | "yes" | "on") => Ok(Some(true)),
+        Some("0" | "false" | "no" | "off") => Ok(Some(false)),
+        Some(other) => bail!("{} must be true or false, got '{}'", name, other),
+    }
+}
+
+/// Система единиц `units=metric|imperial`: пустой или отсутствующий - `None` (единицы файла)
+pub fn units_param(params: &HashMap<String, String>) -> Result<Option<UnitSystem>> {
+    params.get("units").map(|s| s.trim()).filter(|s| !s.is_empty()).map(UnitSystem::parse).transpose()
+}
+
+/// Формат ответа `/api/data`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DataFormat {
+    Json,
+    Csv,
+    Ndjson,
+}
+
+impl DataFormat {
+    pub fn parse(s: &str) -> Result<Self> {
+        match s.to_lowercase().as_str() {
+            "json" => Ok(DataFormat::Json),
+            "csv" => Ok(DataFormat::Csv),
+            "ndjson" | "jsonl" => Ok(DataFormat::Ndjson),
+            other => bail!("Unknown format '{}' (expected json, csv or ndjson)", other),
+        }
+    }
+
+    pub fn content_type(&self) -> &'static str {
+        match self {
+            DataFormat::Json => "application/json",
+            DataFormat::Csv => "text/csv; charset=utf-8",
+            DataFormat::Ndjson => "application/x-ndjson",
+        }
+    }
+}
+
+/// Параметры выборки данных: `curves=`, `from=`, `to=`, `step=`, `main_param=`;
+/// при `step=` также `resample=nearest|linear|average|median` и `max_gap=`; `units=metric|imperial`;
+/// `compute=vsh,phid,phind,sw` с параметрами расчёта (см. [`PetroParams`]);
+/// `derive=ИМЯ = выражение;...` (см. [`DerivedCurve`]); `kb=` - альтитуда для TVDSS, если в файле есть инклинометрия
+#[derive(Debug, Clone, Default)]
+pub struct DataQuery {
+    pub main_param: Option<String>,
+    pub curves: Option<Vec<String>>,
+    pub from: Option<f64>,
+    pub to: Option<f64>,
+    pub step: Option<f64>,
+    pub resample: ResampleMethod,
+    /// Допуск на пропуск при ресэмплинге (по умолчанию - см. [`LasFile::resample`])
+    pub max_gap: Option<f64>,
+    /// Перевод единиц до выборки: `from`, `to` и `step` задаются уже в новых единицах
+    pub units: Option<UnitSystem>,
+    /// Расчётные кривые, добавляемые до выборки (после перевода единиц, до ресэмплинга)
+    pub compute: Vec<Computation>,
+    pub petrophysics: PetroParams,
+    /// Словарь для поиска исходных кривых расчётов
+    pub aliases: AliasDictionary,
+    /// Производные кривые, добавляемые после расчётных
+    pub derive: Vec<DerivedCurve>,
+    /// Альтитуда точки отсчёта глубин для TVDSS (по умолчанию из заголовка, см. [`LasFile::elevation`])
+    pub kb: Option<f64>,
+}
+
+impl DataQuery {
+    /// Параметры запроса поверх `[petrophysics]`, `[curve_aliases]` и `derived_curves` из конфигурации
+    pub fn with_config(config: &Config, params: &HashMap<String, String>) -> Result<Self> {
+        let mut query = Self::from_params(params)?;
+        query.petrophysics = config.petrophysics.with_params(params)?;
+        query.aliases = config.alias_dictionary()?;
+        query.derive.splice(0..0, config.derived_curves()?);
+        Ok(query)
+    }
+
+    pub fn from_params(params: &HashMap<String, String>) -> Result<Self> {
+        let parse_number = |name: &str| number_param(params, name);
+
+        let step = parse_number("step")?;
+        if step.is_some_and(|s| s <= 0.0) {
+            bail!("Parameter 'step' must be positive");
+        }
+        let (from, to) = (parse_number("from")?, parse_number("to")?);
+        let max_gap = parse_number("max_gap")?;
+        if max_gap.is_some_and(|g| g < 0.0) {
+            bail!("Parameter 'max_gap' must not be negative");
+        }
+        let resample = match params.get("resample").map(|s| s.trim()).filter(|s| !s.is_empty()) {
+            Some(method) => ResampleMethod::parse(method)?,
+            None => ResampleMethod::default(),
+        };
+
+        let curves = params.get("curves").map(|s| {
+            s.split(',')
+                .map(|c| c.trim().to_string())
+                .filter(|c| !c.is_empty())
+                .collect::<Vec<_>>()
+        });
+
+        let units = units_param(params)?;
+
+        Ok(DataQuery {
+            main_param: params.get("main_param").cloned(),
+            curves: curves.filter(|c| !c.is_empty()),
+            from,
+            to,
+            step,
+            resample,
+            max_gap,
+            units,
+            compute: Computation::parse_list(params.get("compute").map_or("", |s| s.as_str()))?,
+            petrophysics: PetroParams::default().with_params(params)?,
+            aliases: AliasDictionary::builtin(),
+            derive: DerivedCurve::parse_list(params.get("derive").map_or("", |s| s.as_str()))?,
+            kb: parse_number("kb")?,
+        })
+    }
+}
+
+/// Колонка выборки (первая колонка - индекс)
+#[derive(Debug, Clone)]
+pub struct DataColumn {
+    pub mnemonic: String,
+    pub unit: String,
+    pub description: String,
+    pub values: Vec<Option<f64>>,
+}
+
+/// Результат выборки данных в колоночном виде
+#[derive(Debug, Clone)]
+pub struct DataSelection {
+    pub columns: Vec<DataColumn>,
+}
+
+impl DataSelection {
+    pub fn row_count(&self) -> usize {
+        self.columns.first().map_or(0, |c| c.values.len())
+    }
+
+    /// Колоночный JSON: `{"index": ..., "curves": [...], "data": {"MNEM": [...]}}`
+    pub fn to_json(&self) -> Value {
+        let curves: Vec<Value> = self
+            .columns
+            .iter()
+            .map(|c| json!({ "mnemonic": c.mnemonic, "unit": c.unit, "description": c.description }))
+            .collect();
+        let data: serde_json::Map<String, Value> = self
+            .columns
+            .iter()
+            .map(|c| (c.mnemonic.clone(), json!(c.values)))
+            .collect();
+
+        json!({
+            "index": self.columns.first().map(|c| c.mnemonic.clone()),
+            "rows": self.row_count(),
+            "curves": curves,
+            "data": data,
+        })
+    }
+
+    /// CSV с заголовком из мнемоник; пропуски - пустые ячейки
+    pub fn to_csv(&self) -> String {
+        let mut out = String::new();
+        let header: Vec<String> = self.columns.iter().map(|c| csv_field(&c.mnemonic)).collect();
+        out.push_str(&header.join(","));
+        out.push('\n');
+
+        for row in 0..self.row_count() {
+            let fields: Vec<String> = self
+                .columns
+                .iter()
+                .map(|c| c.values[row].map(|v| v.to_string()).unwrap_or_default())
+                .collect();
+            out.push_str(&fields.join(","));
+            out.push('\n');
+        }
+        out
+    }
+
+    /// По одному JSON объекту на строку данных
+    pub fn to_ndjson(&self) -> String {
+        let mut out = String::new();
+        for row in 0..self.row_count() {
+            let object: serde_json::Map<String, Value> = self
+                .columns
+                .iter()
+                .map(|c| (c.mnemonic.clone(), json!(c.values[row])))
+                .collect();
+            out.push_str(&Value::Object(object).to_string());
+            out.push('\n');
+        }
+        out
+    }
+}
+
+pub(crate) fn csv_field(s: &str) -> String {
+    if s.contains(',') || s.contains('"') || s.contains('\n') {
+        format!("\"{}\"", s.replace('"', "\"\""))
+    } else {
+        s.to_string()
+    }
+}
+
+/// Находит индекс основного параметра (по умолчанию - первая кривая)
+pub fn resolve_main_param(las_file: &LasFile, main_param: Option<&str>) -> Result<usize> {
+    match main_param {
+        Some(name) => las_file
+            .get_main_parameter_index(name)
+            .ok_or_else(|| anyhow!("Main parameter '{}' not found", name)),
+        None if !las_file.curves.is_empty() => Ok(0),
+        None => bail!("LAS file has no curves"),
+    }
+}
+
+/// Выбирает кривые и интервал индекса согласно запросу.
+/// Результат - новый LAS файл, в котором индекс всегда идёт первой кривой.
+pub fn select_las(las_file: &LasFile, query: &DataQuery) -> Result<LasFile> {
+    let prepared;
+    let prepare = query.units.is_some() || !query.compute.is_empty() || !query.derive.is_empty() || las_file.survey.is_some();
+    let las_file = if prepare {
+        let mut copy = las_file.clone();
+        // TVD, TVDSS, NORTH, EAST по инклинометрии - в исходных единицах индекса
+        copy.add_trajectory(query.kb)?;
+        if let Some(system) = query.units {
+            copy.convert_units(system, None);
+        }
+        copy.add_computed(&query.compute, &query.petrophysics, &query.aliases)?;
+        copy.add_derived(&query.derive)?;
+        prepared = copy;
+        &prepared
+    } else {
+        las_file
+    };
+
+    // Основной параметр может быть и добавленной кривой (main_param=TVD)
+    let main_param_idx = resolve_main_param(las_file, query.main_param.as_deref())?;
+
+    // Строки, попадающие в интервал [from, to] (в любом порядке границ)
+    let (low, high) = match (query.from, query.to) {
+        (Some(a), Some(b)) => (a.min(b), a.max(b)),
+        (a, b) => (a.unwrap_or(f64::NEG_INFINITY), b.unwrap_or(f64::INFINITY)),
+    };
+
+    // Сетка строится только на пересечении интервала с данными: предел узлов не зависит от from/to за их пределами
+    let resampled;
+    let las_file = match query.step {
+        Some(step) => {
+            resampled = las_file.resample_step_within(main_param_idx, step, low, high, query.resample, query.max_gap)?;
+            &resampled
+        }
+        None => las_file,
+    };
+
+    let mut curve_indices = vec![main_param_idx];
+    match &query.curves {
+        Some(names) => {
+            for name in names {
+                let idx = las_file
+                    .get_curve_index(name)
+                    .ok_or_else(|| anyhow!("Curve '{}' not found", name))?;
+                if !curve_indices.contains(&idx) {
+                    curve_indices.push(idx);
+                }
+            }
+        }
+        None => curve_indices.extend((0..las_file.curves.len()).filter(|&i| i != main_param_idx)),
+    }
+
+    let index_data = las_file.get_curve_data(main_param_idx);
+    let rows: Vec<usize> = index_data
+        .iter()
+        .enumerate()
+        .filter(|(_, d)| d.is_some_and(|d| d >= low && d <= high))
+        .map(|(row, _)| row)
+        .collect();
+
+    Ok(las_file.subset(&curve_indices, &rows))
+}
+
+/// Выборка в колоночном виде (см. [`select_las`])
+pub fn select_data(las_file: &LasFile, query: &DataQuery) -> Result<DataSelection> {
+    let selected = select_las(las_file, query)?;
+    let columns = selected
+        .curves
+        .iter()
+        .enumerate()
+        .map(|(idx, curve)| DataColumn {
+            mnemonic: curve.mnemonic.clone(),
+            unit: curve.unit.clone(),
+            description: curve.description.clone(),
+            values: selected.get_curve_data(idx),
+        })
+        .collect();
+
+    Ok(DataSelection { columns })
+}
+
+/// Значение заголовка: число, если разбирается как число, иначе строка (пустое - null)
+fn typed_value(value: &str) -> Value {
+    let value = value.trim();
+    if value.is_empty() {
+        Value::Null
+    } else if let Some(number) = value.parse::<f64>().ok().and_then(serde_json::Number::from_f64) {
+        Value::Number(number)
+    } else {
+        Value::String(value.to_string())
+    }
+}
+
+fn header_items_json(items: &[HeaderItem]) -> Vec<Value> {
+    items
+        .iter()
+        .map(|item| {
+            json!({
+                "mnemonic": item.mnemonic,
+                "unit": item.unit,
+                "value": typed_value(&item.value),
+                "description": item.description,
+            })
+        })
+        .collect()
+}
+
+/// Метаданные файла для `/api/las`
+pub fn las_metadata(las_file: &LasFile) -> Value {
+    let curves: Vec<Value> = las_file
+        .curves
+        .iter()
+        .enumerate()
+        .map(|(idx, curve)| {
+            let data = las_file.get_curve_data(idx);
+            let valid: Vec<f64> = data.iter().filter_map(|&v| v).collect();
+            let mean = if valid.is_empty() {
+                None
+            } else {
+                Some(valid.iter().sum::<f64>() / valid.len() as f64)
+            };
+            let (min, max) = match las_file.get_curve_stats(idx) {
+                Some((min, max)) => (Some(min), Some(max)),
+                None => (None, None),
+            };
+
+            json!({
+                "mnemonic": curve.mnemonic,
+                "unit": curve.unit,
+                "description": curve.description,
+                "api_codes": curve.api_codes,
+                "family": curve.family().map(|family| family.name()),
+                "stats": {
+                    "min": min,
+                    "max": max,
+                    "mean": mean,
+                    "count": valid.len(),
+                    "nulls": data.len() - valid.len(),
+                },
+            })
+        })
+        .collect();
+
+    let tops: Vec<Value> = las_file
+        .tops
+        .iter()
+        .map(|top| json!({ "name": top.name, "depth": top.depth, "unit": top.unit }))
+        .collect();
+
+    let survey = las_file.survey.as_ref().map(|survey| {
+        let stations: Vec<Value> = survey
+            .stations
+            .iter()
+            .map(|s| json!({ "md": s.md, "inc": s.inc, "azi": s.azi }))
+            .collect();
+        json!({ "unit": survey.unit, "stations": stations })
+    });
+
+    json!({
+        "version": las_file.version,
+        "null_value": las_file.null_value,
+        "rows": las_file.data.len(),
+        "well": header_items_json(&las_file.well_items),
+        "parameters": header_items_json(&las_file.parameters),
+        "curves": curves,
+        "tops": tops,
+        "survey": survey,
+        "warnings": las_file.warnings(),
+    })
+}
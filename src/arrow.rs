@@ -0,0 +1,170 @@
+//! Представление LAS файла в виде Apache Arrow `RecordBatch`
+//! и запись в Arrow IPC / Parquet.
+//!
+//! Каждая кривая - колонка Float64 (NULL LAS файла становится null),
+//! единицы и описания - метаданные полей, заголовок ~Well/~Parameter -
+//! метаданные схемы с префиксами `las.well.` и `las.param.`.
+
+use crate::las::LasFile;
+use anyhow::Result;
+use arrow_array::{ArrayRef, Float64Array, RecordBatch};
+use arrow_ipc::writer::{FileWriter, StreamWriter};
+use arrow_schema::{DataType, Field, Schema, SchemaRef};
+use parquet::arrow::ArrowWriter;
+use parquet::basic::Compression;
+use parquet::file::properties::WriterProperties;
+use std::collections::{HashMap, HashSet};
+use std::io::Write;
+use std::sync::Arc;
+
+/// Количество строк в одном RecordBatch / группе строк Parquet при записи
+pub const ROWS_PER_BATCH: usize = 64 * 1024;
+
+impl LasFile {
+    /// Схема Arrow: по колонке Float64 на кривую, заголовок - в метаданных
+    pub fn arrow_schema(&self) -> SchemaRef {
+        // Повторяющиеся мнемоники (бывают в реальных файлах) получают суффикс,
+        // иначе DuckDB/Polars не смогут обратиться к колонке по имени
+        let mut used_names = HashSet::new();
+        let fields: Vec<Field> = self
+            .curves
+            .iter()
+            .map(|curve| {
+                let mut name = curve.mnemonic.clone();
+                let mut suffix = 1;
+                while !used_names.insert(name.to_uppercase()) {
+                    suffix += 1;
+                    name = format!("{}_{}", curve.mnemonic, suffix);
+                }
+
+                let mut metadata = HashMap::from([
+                    ("las.mnemonic".to_string(), curve.mnemonic.clone()),
+                    ("las.unit".to_string(), curve.unit.clone()),
+                    ("las.description".to_string(), curve.description.clone()),
+                ]);
+                if let Some(api_codes) = &curve.api_codes {
+                    metadata.insert("las.api_codes".to_string(), api_codes.clone());
+                }
+                Field::new(name, DataType::Float64, true).with_metadata(metadata)
+            })
+            .collect();
+
+        let mut metadata = HashMap::from([
+            ("las.version".to_string(), self.version.clone()),
+            ("las.null_value".to_string(), self.null_value.to_string()),
+        ]);
+        for item in &self.well_items {
+            metadata.insert(format!("las.well.{}", item.mnemonic), item.value.clone());
+        }
+        for item in &self.parameters {
+            metadata.insert(format!("las.param.{}", item.mnemonic), item.value.clone());
+        }
+
+        Arc::new(Schema::new_with_metadata(fields, metadata))
+    }
+
+    /// RecordBatch для строк данных `rows` со схемой из [`LasFile::arrow_schema`]
+    pub fn to_record_batch(&self, schema: SchemaRef, rows: std::ops::Range<usize>) -> Result<RecordBatch> {
+        let columns: Vec<ArrayRef> = (0..self.curves.len())
+            .map(|idx| {
+                let values = self.data[rows.clone()].iter().map(|row| {
+                    row.values
+                        .get(idx)
+                        .copied()
+                        .filter(|&v| v != self.null_value && !v.is_nan())
+                });
+                Arc::new(values.collect::<Float64Array>()) as ArrayRef
+            })
+            .collect();
+
+        Ok(RecordBatch::try_new(schema, columns)?)
+    }
+}
+
+/// Инкрементальная запись Arrow IPC stream / Parquet в память.
+/// После каждой порции записанные байты забираются через [`ColumnarWriter::take_output`],
+/// что позволяет отдавать результат потоком.
+pub enum ColumnarWriter {
+    Ipc(StreamWriter<Vec<u8>>),
+    Parquet(ArrowWriter<Vec<u8>>),
+}
+
+impl ColumnarWriter {
+    pub fn ipc_stream(schema: &SchemaRef) -> Result<Self> {
+        Ok(ColumnarWriter::Ipc(StreamWriter::try_new(Vec::new(), schema)?))
+    }
+
+    pub fn parquet(schema: &SchemaRef) -> Result<Self> {
+        Ok(ColumnarWriter::Parquet(ArrowWriter::try_new(
+            Vec::new(),
+            schema.clone(),
+            Some(parquet_properties()),
+        )?))
+    }
+
+    /// Записывает батч (для Parquet - отдельной группой строк)
+    pub fn write(&mut self, batch: &RecordBatch) -> Result<()> {
+        match self {
+            ColumnarWriter::Ipc(writer) => {
+                writer.write(batch)?;
+                writer.flush()?;
+            }
+            ColumnarWriter::Parquet(writer) => {
+                writer.write(batch)?;
+                writer.flush()?;
+                writer.sync()?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Завершает запись (конец потока IPC / футер Parquet)
+    pub fn finish(&mut self) -> Result<()> {
+        match self {
+            ColumnarWriter::Ipc(writer) => writer.finish()?,
+            ColumnarWriter::Parquet(writer) => {
+                writer.finish()?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Забирает накопленные байты. Parquet сам считает смещения,
+    /// поэтому очистка буфера не портит футер.
+    pub fn take_output(&mut self) -> Vec<u8> {
+        match self {
+            ColumnarWriter::Ipc(writer) => std::mem::take(writer.get_mut()),
+            ColumnarWriter::Parquet(writer) => std::mem::take(writer.inner_mut()),
+        }
+    }
+}
+
+fn parquet_properties() -> WriterProperties {
+    WriterProperties::builder()
+        .set_compression(Compression::SNAPPY)
+        .build()
+}
+
+/// Записывает файл в формате Arrow IPC file (`.arrow`)
+pub fn write_arrow_file<W: Write>(las_file: &LasFile, writer: W) -> Result<()> {
+    let schema = las_file.arrow_schema();
+    let mut writer = FileWriter::try_new(writer, &schema)?;
+    for start in (0..las_file.data.len()).step_by(ROWS_PER_BATCH) {
+        let rows = start..(start + ROWS_PER_BATCH).min(las_file.data.len());
+        writer.write(&las_file.to_record_batch(schema.clone(), rows)?)?;
+    }
+    writer.finish()?;
+    Ok(())
+}
+
+/// Записывает файл в формате Parquet
+pub fn write_parquet_file<W: Write + Send>(las_file: &LasFile, writer: W) -> Result<()> {
+    let schema = las_file.arrow_schema();
+    let mut writer = ArrowWriter::try_new(writer, schema.clone(), Some(parquet_properties()))?;
+    for start in (0..las_file.data.len()).step_by(ROWS_PER_BATCH) {
+        let rows = start..(start + ROWS_PER_BATCH).min(las_file.data.len());
+        writer.write(&las_file.to_record_batch(schema.clone(), rows)?)?;
+    }
+    writer.close()?;
+    Ok(())
+}
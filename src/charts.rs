@@ -0,0 +1,706 @@
+//! Кросс-плоты (`/crossplot`) и гистограммы (`/histogram`) кривых в PNG или SVG (plotters).
+//!
+//! Точки кросс-плота можно раскрасить по третьей кривой (`color=`), справа тогда рисуется шкала цвета.
+//! Поверх точек - палетки: линии литологии нейтрон-плотностного кросс-плота (`overlay=nd`)
+//! и линии равной водонасыщенности графика Пикетта (`overlay=pickett`).
+//! Интервал глубин, расчётные и производные кривые задаются как в `/api/data` (см. [`crate::api::DataQuery`]).
+
+use crate::api::{flag_param, number_param};
+use crate::las::LasFile;
+use crate::petrophysics::{porosity_scale, PetroParams};
+use crate::plot::encode_png;
+use crate::stats::percentile;
+use crate::units::{find_unit, Quantity};
+use anyhow::{anyhow, bail, Result};
+use image::{DynamicImage, RgbImage};
+use plotters::coord::ranged1d::{AsRangedCoord, ValueFormatter};
+use plotters::coord::Shift;
+use plotters::prelude::*;
+use std::collections::HashMap;
+
+/// Размер картинки по умолчанию и наибольший допустимый
+const DEFAULT_WIDTH: u32 = 800;
+const DEFAULT_HEIGHT: u32 = 600;
+const MAX_CHART_SIZE: u32 = 4000;
+
+const DEFAULT_BINS: usize = 50;
+const MAX_BINS: usize = 1000;
+
+/// Ширина шкалы цвета справа от кросс-плота
+const COLOR_BAR_WIDTH: u32 = 110;
+
+/// Точки без значения кривой цвета
+const NO_COLOR: RGBColor = RGBColor(0x80, 0x80, 0x80);
+const POINT_COLOR: RGBColor = RGBColor(0x1F, 0x4F, 0xB4);
+
+/// Литология для `overlay=nd`: название, плотность матрицы (г/см3), нейтронная пористость матрицы
+/// в единицах известняка (приближение палетки CP-1 прямыми линиями до точки флюида), цвет
+const LITHOLOGIES: &[(&str, f64, f64, RGBColor)] = &[
+    ("Sandstone", 2.65, -0.035, RGBColor(0xB8, 0x86, 0x0B)),
+    ("Limestone", 2.71, 0.0, RGBColor(0x00, 0x64, 0x00)),
+    ("Dolomite", 2.87, 0.02, RGBColor(0x8B, 0x00, 0x8B)),
+];
+
+/// Водонасыщенность линий `overlay=pickett`
+const PICKETT_SW: &[f64] = &[1.0, 0.75, 0.5, 0.25];
+
+/// Формат картинки (`format=`)
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ChartFormat {
+    #[default]
+    Png,
+    Svg,
+}
+
+impl ChartFormat {
+    pub fn parse(s: &str) -> Result<Self> {
+        match s.to_lowercase().as_str() {
+            "" | "png" => Ok(ChartFormat::Png),
+            "svg" => Ok(ChartFormat::Svg),
+            other => bail!("Unknown format '{}' (expected png or svg)", other),
+        }
+    }
+
+    pub fn content_type(&self) -> &'static str {
+        match self {
+            ChartFormat::Png => "image/png",
+            ChartFormat::Svg => "image/svg+xml",
+        }
+    }
+}
+
+/// Палетка поверх кросс-плота (`overlay=`)
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Overlay {
+    /// Нейтрон (x) - плотность (y): линии песчаника, известняка и доломита
+    NeutronDensity,
+    /// Пикетт: сопротивление (x) - пористость (y) в логарифмическом масштабе, линии равной Sw по Арчи
+    Pickett,
+}
+
+impl Overlay {
+    pub fn parse(s: &str) -> Result<Self> {
+        match s.to_lowercase().as_str() {
+            "nd" | "neutron_density" | "neutron-density" => Ok(Overlay::NeutronDensity),
+            "pickett" => Ok(Overlay::Pickett),
+            other => bail!("Unknown overlay '{}' (expected nd or pickett)", other),
+        }
+    }
+}
+
+/// Ось: кривая, логарифмическая шкала и границы (`min > max` - ось в обратную сторону)
+#[derive(Debug, Clone, PartialEq)]
+pub struct Axis {
+    pub curve: String,
+    pub log: bool,
+    pub min: Option<f64>,
+    pub max: Option<f64>,
+}
+
+fn curve_param(params: &HashMap<String, String>, name: &str) -> Option<String> {
+    params.get(name).map(|s| s.trim().to_string()).filter(|s| !s.is_empty())
+}
+
+/// Размер картинки: `width=`, `height=`
+fn size_params(params: &HashMap<String, String>) -> Result<(u32, u32)> {
+    let size = |name: &str, default: u32| -> Result<u32> {
+        let Some(raw) = params.get(name).map(|s| s.trim()).filter(|s| !s.is_empty()) else {
+            return Ok(default);
+        };
+        let value: u32 = raw.parse().map_err(|_| anyhow!("{} must be a positive integer, got '{}'", name, raw))?;
+        if !(100..=MAX_CHART_SIZE).contains(&value) {
+            bail!("{} = {} is out of range 100..={}", name, value, MAX_CHART_SIZE);
+        }
+        Ok(value)
+    };
+    Ok((size("width", DEFAULT_WIDTH)?, size("height", DEFAULT_HEIGHT)?))
+}
+
+impl Axis {
+    /// `<prefix>_log`, `<prefix>_min`, `<prefix>_max` для кривой `curve`
+    fn from_params(params: &HashMap<String, String>, curve: String, prefix: &str) -> Result<Self> {
+        let name = |suffix: &str| if prefix.is_empty() { suffix.to_string() } else { format!("{}_{}", prefix, suffix) };
+        Ok(Axis {
+            curve,
+            log: flag_param(params, &name("log"))?.unwrap_or(false),
+            min: number_param(params, &name("min"))?,
+            max: number_param(params, &name("max"))?,
+        })
+    }
+
+    /// Границы оси по данным `values` с полями 5% (логарифмической - до целых декад), заданные границы важнее
+    fn range(&self, values: impl Iterator<Item = f64>) -> Result<(f64, f64)> {
+        let (lo, hi) = values
+            .filter(|v| !self.log || *v > 0.0)
+            .fold((f64::INFINITY, f64::NEG_INFINITY), |(lo, hi), v| (lo.min(v), hi.max(v)));
+        let (lo, hi) = if lo > hi {
+            (1.0, 10.0)
+        } else if self.log {
+            // Целые декады - иначе на логарифмической оси может не оказаться ни одной подписи
+            (10f64.powf(lo.log10().floor()), 10f64.powf((hi.log10() + 1e-9).ceil()))
+        } else {
+            let pad = if hi > lo { (hi - lo) * 0.05 } else { lo.abs().max(1.0) * 0.1 };
+            (lo - pad, hi + pad)
+        };
+        let (min, max) = (self.min.unwrap_or(lo), self.max.unwrap_or(hi));
+        if min == max {
+            bail!("{}: axis range is empty ({}..{})", self.curve, min, max);
+        }
+        if self.log && (min <= 0.0 || max <= 0.0) {
+            bail!("{}: log axis needs positive bounds ({}..{})", self.curve, min, max);
+        }
+        Ok((min, max))
+    }
+
+    fn contains(&self, (min, max): (f64, f64), value: f64) -> bool {
+        value >= min.min(max) && value <= min.max(max) && (!self.log || value > 0.0)
+    }
+}
+
+/// Параметры кросс-плота: `x=`, `y=`, `color=`, `x_log=`, `x_min=`, `x_max=` (и для `y`, `color`),
+/// `overlay=nd|pickett`, `width=`, `height=`
+#[derive(Debug, Clone, PartialEq)]
+pub struct CrossplotSpec {
+    pub x: Axis,
+    pub y: Axis,
+    pub color: Option<Axis>,
+    pub overlay: Option<Overlay>,
+    pub width: u32,
+    pub height: u32,
+}
+
+impl CrossplotSpec {
+    pub fn from_params(params: &HashMap<String, String>) -> Result<Self> {
+        let x = curve_param(params, "x").ok_or_else(|| anyhow!("Missing 'x' parameter"))?;
+        let y = curve_param(params, "y").ok_or_else(|| anyhow!("Missing 'y' parameter"))?;
+        let overlay = params
+            .get("overlay")
+            .map(|s| s.trim())
+            .filter(|s| !s.is_empty())
+            .map(Overlay::parse)
+            .transpose()?;
+        let mut spec = CrossplotSpec {
+            x: Axis::from_params(params, x, "x")?,
+            y: Axis::from_params(params, y, "y")?,
+            color: curve_param(params, "color").map(|c| Axis::from_params(params, c, "color")).transpose()?,
+            overlay,
+            width: 0,
+            height: 0,
+        };
+        (spec.width, spec.height) = size_params(params)?;
+
+        // График Пикетта строится в логарифмических осях, если не задано иное
+        if overlay == Some(Overlay::Pickett) {
+            spec.x.log = flag_param(params, "x_log")?.unwrap_or(true);
+            spec.y.log = flag_param(params, "y_log")?.unwrap_or(true);
+        }
+        Ok(spec)
+    }
+
+    /// Кривые, которые нужны кросс-плоту (для выборки данных)
+    pub fn curves(&self) -> Vec<String> {
+        let mut curves = vec![self.x.curve.clone(), self.y.curve.clone()];
+        curves.extend(self.color.iter().map(|c| c.curve.clone()));
+        curves
+    }
+}
+
+/// Параметры гистограммы: `curve=`, `bins=`, `log=`, `min=`, `max=`, `width=`, `height=`
+#[derive(Debug, Clone, PartialEq)]
+pub struct HistogramSpec {
+    pub axis: Axis,
+    pub bins: usize,
+    pub width: u32,
+    pub height: u32,
+}
+
+impl HistogramSpec {
+    pub fn from_params(params: &HashMap<String, String>) -> Result<Self> {
+        let curve = curve_param(params, "curve").ok_or_else(|| anyhow!("Missing 'curve' parameter"))?;
+        let bins = match params.get("bins").map(|s| s.trim()).filter(|s| !s.is_empty()) {
+            Some(raw) => raw.parse::<usize>().map_err(|_| anyhow!("bins must be a positive integer, got '{}'", raw))?,
+            None => DEFAULT_BINS,
+        };
+        if !(1..=MAX_BINS).contains(&bins) {
+            bail!("bins = {} is out of range 1..={}", bins, MAX_BINS);
+        }
+        let (width, height) = size_params(params)?;
+        Ok(HistogramSpec { axis: Axis::from_params(params, curve, "")?, bins, width, height })
+    }
+}
+
+/// Подпись оси: `MNEM, UNIT`
+fn axis_label(las_file: &LasFile, idx: usize) -> String {
+    let curve = &las_file.curves[idx];
+    if curve.unit.is_empty() {
+        curve.mnemonic.clone()
+    } else {
+        format!("{}, {}", curve.mnemonic, curve.unit)
+    }
+}
+
+fn curve_index(las_file: &LasFile, name: &str) -> Result<usize> {
+    las_file.get_curve_index(name).ok_or_else(|| anyhow!("Curve '{}' not found", name))
+}
+
+/// Название скважины для заголовка
+fn well_name(las_file: &LasFile) -> Option<String> {
+    las_file
+        .get_well_item("WELL")
+        .map(|item| item.value.trim().to_string())
+        .filter(|name| !name.is_empty())
+}
+
+/// Число для подписей: до 4 значащих цифр
+fn format_value(value: f64) -> String {
+    if value != 0.0 && (value.abs() >= 1e5 || value.abs() < 1e-3) {
+        format!("{:.2e}", value)
+    } else {
+        let digits = (3 - value.abs().log10().floor() as i32).clamp(0, 6) as usize;
+        let text = format!("{:.*}", digits, value);
+        if text.contains('.') { text.trim_end_matches('0').trim_end_matches('.').to_string() } else { text }
+    }
+}
+
+/// Линия палетки в единицах осей; разрывы - отдельные отрезки
+struct OverlayLine {
+    label: String,
+    color: RGBColor,
+    segments: Vec<Vec<(f64, f64)>>,
+    /// Подписанные точки на линии (например, пористость 10, 20, 30%)
+    marks: Vec<((f64, f64), String)>,
+}
+
+impl OverlayLine {
+    /// Точки `(x, y)` в порядке линии; точки вне осей разрывают линию
+    fn new(label: String, color: RGBColor, points: impl Iterator<Item = (f64, f64)>, inside: impl Fn(f64, f64) -> bool) -> Self {
+        let mut segments: Vec<Vec<(f64, f64)>> = vec![Vec::new()];
+        for (x, y) in points {
+            if inside(x, y) {
+                segments.last_mut().expect("segments is never empty").push((x, y));
+            } else if segments.last().is_some_and(|s| !s.is_empty()) {
+                segments.push(Vec::new());
+            }
+        }
+        segments.retain(|s| s.len() > 1);
+        OverlayLine { label, color, segments, marks: Vec::new() }
+    }
+}
+
+/// Подготовленный кросс-плот: точки, диапазоны и палетки
+struct Crossplot {
+    title: String,
+    x_label: String,
+    y_label: String,
+    x_range: (f64, f64),
+    y_range: (f64, f64),
+    x_log: bool,
+    y_log: bool,
+    points: Vec<(f64, f64, RGBColor)>,
+    /// Подпись и диапазон шкалы цвета
+    color_bar: Option<(String, f64, f64)>,
+    overlay: Vec<OverlayLine>,
+}
+
+impl Crossplot {
+    fn prepare(las_file: &LasFile, spec: &CrossplotSpec, params: &PetroParams) -> Result<Self> {
+        let x_idx = curve_index(las_file, &spec.x.curve)?;
+        let y_idx = curve_index(las_file, &spec.y.curve)?;
+        let color_idx = spec.color.as_ref().map(|c| curve_index(las_file, &c.curve)).transpose()?;
+        let x_data = las_file.get_curve_data(x_idx);
+        let y_data = las_file.get_curve_data(y_idx);
+        let z_data = color_idx.map(|idx| las_file.get_curve_data(idx));
+
+        let pairs: Vec<(usize, f64, f64)> = x_data
+            .iter()
+            .zip(&y_data)
+            .enumerate()
+            .filter_map(|(row, (x, y))| Some((row, (*x)?, (*y)?)))
+            .collect();
+        let x_range = spec.x.range(pairs.iter().map(|p| p.1))?;
+        let mut y_range = spec.y.range(pairs.iter().map(|p| p.2))?;
+        // Плотность на нейтрон-плотностном кросс-плоте растёт вниз
+        if spec.overlay == Some(Overlay::NeutronDensity) && spec.y.min.is_none() && spec.y.max.is_none() {
+            y_range = (y_range.1, y_range.0);
+        }
+        let pairs: Vec<(usize, f64, f64)> = pairs
+            .into_iter()
+            .filter(|&(_, x, y)| spec.x.contains(x_range, x) && spec.y.contains(y_range, y))
+            .collect();
+        if pairs.is_empty() {
+            bail!("No points with both {} and {} in range", spec.x.curve, spec.y.curve);
+        }
+
+        // Цвет точки - по значению третьей кривой (Viridis), без значения - серый
+        let color_bar = match (&spec.color, &z_data, color_idx) {
+            (Some(axis), Some(z_data), Some(idx)) => {
+                let range = axis.range(pairs.iter().filter_map(|&(row, _, _)| z_data[row]))?;
+                Some((axis_label(las_file, idx), range.0, range.1))
+            }
+            _ => None,
+        };
+        let points = pairs
+            .iter()
+            .map(|&(row, x, y)| {
+                let color = match (&color_bar, &z_data) {
+                    (Some((_, lo, hi)), Some(z_data)) => z_data[row]
+                        .map_or(NO_COLOR, |z| ViridisRGB::get_color_normalized(z.clamp(lo.min(*hi), lo.max(*hi)), *lo, *hi)),
+                    _ => POINT_COLOR,
+                };
+                (x, y, color)
+            })
+            .collect();
+
+        let mut plot = Crossplot {
+            title: format!("{} vs {}", las_file.curves[y_idx].mnemonic, las_file.curves[x_idx].mnemonic),
+            x_label: axis_label(las_file, x_idx),
+            y_label: axis_label(las_file, y_idx),
+            x_range,
+            y_range,
+            x_log: spec.x.log,
+            y_log: spec.y.log,
+            points,
+            color_bar,
+            overlay: Vec::new(),
+        };
+        if let Some(well) = well_name(las_file) {
+            plot.title = format!("{}: {}", well, plot.title);
+        }
+        plot.overlay = match spec.overlay {
+            Some(Overlay::NeutronDensity) => neutron_density_lines(las_file, x_idx, y_idx, spec, x_range, y_range, params),
+            Some(Overlay::Pickett) => pickett_lines(las_file, y_idx, spec, x_range, y_range, params)?,
+            None => Vec::new(),
+        };
+        Ok(plot)
+    }
+}
+
+/// Линии литологии: смесь матрицы и флюида (нейтрон 1.0, плотность `rho_fluid`),
+/// отметки через 10% пористости; значения переводятся в единицы кривых осей
+fn neutron_density_lines(
+    las_file: &LasFile,
+    x_idx: usize,
+    y_idx: usize,
+    spec: &CrossplotSpec,
+    x_range: (f64, f64),
+    y_range: (f64, f64),
+    params: &PetroParams,
+) -> Vec<OverlayLine> {
+    let neutron_scale = porosity_scale(&las_file.curves[x_idx].unit);
+    let density_unit = find_unit(&las_file.curves[y_idx].unit).filter(|unit| unit.quantity == Quantity::Density);
+    let density = |gcc: f64| match (find_unit("G/C3"), density_unit) {
+        (Some(from), Some(to)) => from.convert(gcc, to),
+        _ => gcc,
+    };
+    let point = |phi: f64, rho_matrix: f64, neutron_matrix: f64| {
+        let neutron = neutron_matrix + phi * (1.0 - neutron_matrix);
+        (neutron / neutron_scale, density(rho_matrix + phi * (params.rho_fluid - rho_matrix)))
+    };
+    let inside = |x: f64, y: f64| spec.x.contains(x_range, x) && spec.y.contains(y_range, y);
+
+    LITHOLOGIES
+        .iter()
+        .map(|&(name, rho_matrix, neutron_matrix, color)| {
+            let points = (0..=90).map(|i| point(i as f64 * 0.005, rho_matrix, neutron_matrix));
+            let mut line = OverlayLine::new(name.to_string(), color, points, inside);
+            line.marks = (0..=4)
+                .map(|i| (point(i as f64 * 0.1, rho_matrix, neutron_matrix), format!("{}", i * 10)))
+                .filter(|((x, y), _)| inside(*x, *y))
+                .collect();
+            line
+        })
+        .collect()
+}
+
+/// Линии равной водонасыщенности по Арчи: `Rt = a * Rw / (phi^m * Sw^n)` по пористости оси y
+fn pickett_lines(
+    las_file: &LasFile,
+    y_idx: usize,
+    spec: &CrossplotSpec,
+    x_range: (f64, f64),
+    y_range: (f64, f64),
+    params: &PetroParams,
+) -> Result<Vec<OverlayLine>> {
+    let rw = las_file.formation_water_resistivity(params, "Pickett overlay")?;
+    let scale = porosity_scale(&las_file.curves[y_idx].unit);
+    let (lo, hi) = (y_range.0.min(y_range.1).max(1e-4 / scale), y_range.0.max(y_range.1));
+    if hi <= lo {
+        return Ok(Vec::new());
+    }
+    let inside = |x: f64, y: f64| spec.x.contains(x_range, x) && spec.y.contains(y_range, y);
+
+    Ok(PICKETT_SW
+        .iter()
+        .enumerate()
+        .map(|(i, &sw)| {
+            let points = (0..=100).map(|step| {
+                let y = lo * (hi / lo).powf(step as f64 / 100.0);
+                let rt = params.archie_a * rw / ((y * scale).powf(params.archie_m) * sw.powf(params.archie_n));
+                (rt, y)
+            });
+            let shade = (i * 0x30) as u8;
+            OverlayLine::new(format!("Sw {}%", (sw * 100.0).round()), RGBColor(shade, shade, shade), points, inside)
+        })
+        .collect())
+}
+
+/// Рисует `draw` на PNG или SVG размером `width` x `height`
+macro_rules! render_chart {
+    ($format:expr, $width:expr, $height:expr, $draw:ident, $($arg:expr),*) => {{
+        let (width, height) = ($width, $height);
+        match $format {
+            ChartFormat::Png => {
+                let mut buffer = vec![0u8; width as usize * height as usize * 3];
+                {
+                    let root = BitMapBackend::with_buffer(&mut buffer, (width, height)).into_drawing_area();
+                    $draw(&root, $($arg),*)?;
+                }
+                let image = RgbImage::from_raw(width, height, buffer).ok_or_else(|| anyhow!("Invalid chart buffer"))?;
+                encode_png(&DynamicImage::ImageRgb8(image).to_rgba8())
+            }
+            ChartFormat::Svg => {
+                let mut svg = String::new();
+                {
+                    let root = SVGBackend::with_string(&mut svg, (width, height)).into_drawing_area();
+                    $draw(&root, $($arg),*)?;
+                }
+                Ok(svg.into_bytes())
+            }
+        }
+    }};
+}
+
+/// Кросс-плот кривых `las_file` (выборка уже сделана); `params` - плотность флюида, коэффициенты Арчи и Rw
+pub fn crossplot(las_file: &LasFile, spec: &CrossplotSpec, params: &PetroParams, format: ChartFormat) -> Result<Vec<u8>> {
+    let plot = Crossplot::prepare(las_file, spec, params)?;
+    render_chart!(format, spec.width, spec.height, draw_crossplot, &plot)
+}
+
+fn draw_crossplot<DB>(root: &DrawingArea<DB, Shift>, plot: &Crossplot) -> Result<()>
+where
+    DB: DrawingBackend,
+    DB::ErrorType: 'static,
+{
+    root.fill(&WHITE)?;
+    let (width, _) = root.dim_in_pixel();
+    let main = match &plot.color_bar {
+        Some((label, lo, hi)) if width > COLOR_BAR_WIDTH * 2 => {
+            let (main, bar) = root.split_horizontally(width - COLOR_BAR_WIDTH);
+            draw_color_bar(&bar, label, *lo, *hi)?;
+            main
+        }
+        _ => root.clone(),
+    };
+
+    let ((x0, x1), (y0, y1)) = (plot.x_range, plot.y_range);
+    match (plot.x_log, plot.y_log) {
+        (false, false) => draw_scatter(&main, plot, x0..x1, y0..y1)?,
+        (true, false) => draw_scatter(&main, plot, log_range(plot.x_range).log_scale(), y0..y1)?,
+        (false, true) => draw_scatter(&main, plot, x0..x1, log_range(plot.y_range).log_scale())?,
+        (true, true) => draw_scatter(&main, plot, log_range(plot.x_range).log_scale(), log_range(plot.y_range).log_scale())?,
+    }
+    root.present()?;
+    Ok(())
+}
+
+/// Логарифмическая ось чуть шире границ: иначе подпись на границе-декаде (0.01) теряется из-за округления
+fn log_range((from, to): (f64, f64)) -> std::ops::Range<f64> {
+    let widen = if from < to { 1e-9 } else { -1e-9 };
+    from * (1.0 - widen)..to * (1.0 + widen)
+}
+
+fn draw_scatter<DB, X, Y>(area: &DrawingArea<DB, Shift>, plot: &Crossplot, x_spec: X, y_spec: Y) -> Result<()>
+where
+    DB: DrawingBackend,
+    DB::ErrorType: 'static,
+    X: AsRangedCoord<Value = f64>,
+    Y: AsRangedCoord<Value = f64>,
+    X::CoordDescType: ValueFormatter<f64>,
+    Y::CoordDescType: ValueFormatter<f64>,
+{
+    let mut chart = ChartBuilder::on(area)
+        .caption(&plot.title, ("sans-serif", 18))
+        .margin(10)
+        .x_label_area_size(45)
+        .y_label_area_size(65)
+        .build_cartesian_2d(x_spec, y_spec)?;
+    chart
+        .configure_mesh()
+        .x_desc(plot.x_label.as_str())
+        .y_desc(plot.y_label.as_str())
+        .x_label_formatter(&|v| format_value(*v))
+        .y_label_formatter(&|v| format_value(*v))
+        .light_line_style(WHITE.mix(0.0))
+        .draw()?;
+
+    chart.draw_series(plot.points.iter().map(|&(x, y, color)| Circle::new((x, y), 2, color.mix(0.7).filled())))?;
+
+    for line in &plot.overlay {
+        for segment in &line.segments {
+            chart.draw_series(LineSeries::new(segment.iter().copied(), line.color.stroke_width(2)))?;
+        }
+        let label_style = ("sans-serif", 13).into_font().color(&line.color);
+        chart.draw_series(line.marks.iter().map(|&(point, ref text)| {
+            EmptyElement::at(point) + Circle::new((0, 0), 3, line.color.filled()) + Text::new(text.clone(), (4, -14), label_style.clone())
+        }))?;
+        // Название линии - у её последней точки
+        if let Some(&end) = line.segments.last().and_then(|segment| segment.last()) {
+            chart.draw_series(std::iter::once(EmptyElement::at(end) + Text::new(line.label.clone(), (6, -6), label_style.clone())))?;
+        }
+    }
+    Ok(())
+}
+
+/// Шкала цвета: градиент Viridis от `lo` (внизу) до `hi`
+fn draw_color_bar<DB>(area: &DrawingArea<DB, Shift>, label: &str, lo: f64, hi: f64) -> Result<()>
+where
+    DB: DrawingBackend,
+    DB::ErrorType: 'static,
+{
+    let mut chart = ChartBuilder::on(area)
+        .margin_top(38)
+        .margin_bottom(55)
+        .margin_right(10)
+        .y_label_area_size(60)
+        .build_cartesian_2d(0.0..1.0, lo..hi)?;
+    chart
+        .configure_mesh()
+        .disable_x_mesh()
+        .disable_y_mesh()
+        .disable_x_axis()
+        .y_desc(label)
+        .y_label_formatter(&|v| format_value(*v))
+        .label_style(("sans-serif", 12))
+        .axis_desc_style(("sans-serif", 12))
+        .draw()?;
+    const STEPS: usize = 100;
+    chart.draw_series((0..STEPS).map(|i| {
+        let (a, b) = (i as f64 / STEPS as f64, (i + 1) as f64 / STEPS as f64);
+        let color = ViridisRGB::get_color_normalized((a + b) / 2.0, 0.0, 1.0);
+        Rectangle::new([(0.0, lo + (hi - lo) * a), (1.0, lo + (hi - lo) * b)], color.filled())
+    }))?;
+    Ok(())
+}
+
+/// Подготовленная гистограмма
+struct Histogram {
+    title: String,
+    label: String,
+    range: (f64, f64),
+    log: bool,
+    /// (левая граница, правая граница, число значений)
+    bins: Vec<(f64, f64, usize)>,
+    /// P10, P50, P90
+    percentiles: Vec<(String, f64)>,
+}
+
+impl Histogram {
+    fn prepare(las_file: &LasFile, spec: &HistogramSpec) -> Result<Self> {
+        let idx = curve_index(las_file, &spec.axis.curve)?;
+        let data = las_file.get_curve_data(idx);
+        let nulls = data.iter().filter(|v| v.is_none()).count();
+        let range = spec.axis.range(data.iter().flatten().copied())?;
+        let (lo, hi) = (range.0.min(range.1), range.0.max(range.1));
+        let mut values: Vec<f64> = data.iter().flatten().copied().filter(|v| spec.axis.contains(range, *v)).collect();
+        if values.is_empty() {
+            bail!("No values of {} in range", spec.axis.curve);
+        }
+        values.sort_by(f64::total_cmp);
+
+        // Границы корзин равномерны по оси: в логарифмическом масштабе - по логарифму
+        let to_axis = |v: f64| if spec.axis.log { v.log10() } else { v };
+        let from_axis = |v: f64| if spec.axis.log { 10f64.powf(v) } else { v };
+        let (a, b) = (to_axis(lo), to_axis(hi));
+        let width = (b - a) / spec.bins as f64;
+        let mut counts = vec![0usize; spec.bins];
+        for &v in &values {
+            let bin = (((to_axis(v) - a) / width) as usize).min(spec.bins - 1);
+            counts[bin] += 1;
+        }
+        let bins = counts
+            .into_iter()
+            .enumerate()
+            .map(|(i, count)| (from_axis(a + width * i as f64), from_axis(a + width * (i + 1) as f64), count))
+            .collect();
+
+        let percentiles = [("P10", 0.1), ("P50", 0.5), ("P90", 0.9)]
+            .into_iter()
+            .filter_map(|(name, p)| Some((name.to_string(), percentile(&values, p)?)))
+            .collect();
+        let mean = values.iter().sum::<f64>() / values.len() as f64;
+        let mut title = format!(
+            "{}: n = {}, nulls = {}, mean = {}",
+            las_file.curves[idx].mnemonic,
+            values.len(),
+            nulls,
+            format_value(mean)
+        );
+        if let Some(well) = well_name(las_file) {
+            title = format!("{} {}", well, title);
+        }
+        Ok(Histogram { title, label: axis_label(las_file, idx), range, log: spec.axis.log, bins, percentiles })
+    }
+}
+
+/// Гистограмма кривой `las_file` (выборка уже сделана) с отметками P10/P50/P90
+pub fn histogram(las_file: &LasFile, spec: &HistogramSpec, format: ChartFormat) -> Result<Vec<u8>> {
+    let histogram = Histogram::prepare(las_file, spec)?;
+    render_chart!(format, spec.width, spec.height, draw_histogram, &histogram)
+}
+
+fn draw_histogram<DB>(root: &DrawingArea<DB, Shift>, histogram: &Histogram) -> Result<()>
+where
+    DB: DrawingBackend,
+    DB::ErrorType: 'static,
+{
+    root.fill(&WHITE)?;
+    let (x0, x1) = histogram.range;
+    if histogram.log {
+        draw_bins(root, histogram, log_range(histogram.range).log_scale())?;
+    } else {
+        draw_bins(root, histogram, x0..x1)?;
+    }
+    root.present()?;
+    Ok(())
+}
+
+fn draw_bins<DB, X>(area: &DrawingArea<DB, Shift>, histogram: &Histogram, x_spec: X) -> Result<()>
+where
+    DB: DrawingBackend,
+    DB::ErrorType: 'static,
+    X: AsRangedCoord<Value = f64>,
+    X::CoordDescType: ValueFormatter<f64>,
+{
+    let max_count = histogram.bins.iter().map(|b| b.2).max().unwrap_or(0).max(1) as f64;
+    let mut chart = ChartBuilder::on(area)
+        .caption(&histogram.title, ("sans-serif", 18))
+        .margin(10)
+        .x_label_area_size(45)
+        .y_label_area_size(65)
+        .build_cartesian_2d(x_spec, 0.0..max_count * 1.1)?;
+    chart
+        .configure_mesh()
+        .x_desc(histogram.label.as_str())
+        .y_desc("Count")
+        .x_label_formatter(&|v| format_value(*v))
+        .y_label_formatter(&|v| format!("{:.0}", v))
+        .light_line_style(WHITE.mix(0.0))
+        .draw()?;
+
+    chart.draw_series(
+        histogram
+            .bins
+            .iter()
+            .filter(|bin| bin.2 > 0)
+            .map(|&(left, right, count)| Rectangle::new([(left, 0.0), (right, count as f64)], POINT_COLOR.mix(0.6).filled())),
+    )?;
+    let marker = RGBColor(0xC0, 0x00, 0x00);
+    for (name, value) in &histogram.percentiles {
+        chart.draw_series(LineSeries::new([(*value, 0.0), (*value, max_count * 1.05)], marker.stroke_width(2)))?;
+        chart.draw_series(std::iter::once(
+            EmptyElement::at((*value, max_count * 1.05))
+                + Text::new(format!("{} {}", name, format_value(*value)), (3, 0), ("sans-serif", 13).into_font().color(&marker)),
+        ))?;
+    }
+    Ok(())
+}
@@ -0,0 +1,387 @@
+//! Командная строка: `lasplot [serve|render|info|convert|check-config] [--config <файл>]`.
+//!
+//! Без подкоманды запускается сервер.
+
+use anyhow::{bail, Context, Result};
+use clap::{Args, Parser, Subcommand};
+use lasplot::api::las_metadata;
+use lasplot::config::Config;
+use lasplot::convert::{collect_las_files, convert_file, is_las_file, ColumnarFormat};
+use lasplot::derived::DerivedCurve;
+use lasplot::edits::Edit;
+use lasplot::las::LasFile;
+use lasplot::petrophysics::Computation;
+use lasplot::render::{render, RenderFormat};
+use lasplot::stats::{Zone, ZoneReport};
+use lasplot::survey::Survey;
+use lasplot::tops::TopsFile;
+use lasplot::units::UnitSystem;
+use lasplot::view::{layout_for_request, LogView, PageLayout};
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+#[derive(Parser)]
+#[command(name = "lasplot", version, about = "LAS well log viewer and converter")]
+pub struct Cli {
+    /// Файл конфигурации (по умолчанию LASPLOT_CONFIG или ./lasplot.toml)
+    #[arg(long, global = true)]
+    pub config: Option<PathBuf>,
+    #[command(subcommand)]
+    pub command: Option<Command>,
+}
+
+#[derive(Subcommand)]
+pub enum Command {
+    /// Запуск HTTP сервера (по умолчанию)
+    Serve,
+    /// Отрисовка LAS файлов в PNG/SVG/PDF/HTML без сервера
+    Render(Box<RenderArgs>),
+    /// Сводка по LAS файлу: заголовок, кривые, предупреждения
+    Info(InfoArgs),
+    /// Конвертация LAS файлов в Arrow IPC / Parquet
+    Convert(ConvertArgs),
+    /// Проверка конфигурации и вывод итоговых значений
+    CheckConfig,
+}
+
+#[derive(Args)]
+pub struct RenderArgs {
+    /// LAS файлы, папки (обходятся рекурсивно) или glob-шаблоны
+    #[arg(required = true)]
+    pub inputs: Vec<String>,
+    /// Папка для результатов
+    #[arg(short, long, default_value = ".")]
+    pub out: PathBuf,
+    /// png, svg, pdf или html
+    #[arg(short, long, default_value = "png", value_parser = RenderFormat::parse)]
+    pub format: RenderFormat,
+    /// Основной параметр (по умолчанию первая кривая)
+    #[arg(long)]
+    pub main_param: Option<String>,
+    /// Цвета кривых через запятую (по умолчанию default_colors из конфигурации)
+    #[arg(long, value_delimiter = ',')]
+    pub colors: Option<Vec<String>>,
+    /// Пресет отображения из [presets.<имя>] в lasplot.toml
+    #[arg(long)]
+    pub preset: Option<String>,
+    /// Перевести единицы: metric или imperial
+    #[arg(long, value_parser = UnitSystem::parse)]
+    pub units: Option<UnitSystem>,
+    /// Расчётные кривые через запятую: vsh, phid, phind, sw (параметры - [petrophysics] в lasplot.toml)
+    #[arg(long, value_delimiter = ',', value_parser = Computation::parse)]
+    pub compute: Vec<Computation>,
+    /// Производная кривая "ИМЯ = выражение" (можно повторять), после derived_curves из lasplot.toml
+    #[arg(long, value_parser = DerivedCurve::parse)]
+    pub derive: Vec<DerivedCurve>,
+    /// Файл кровель: CSV "well, top_name, depth" или LAS с секцией ~Tops (вместо ~Tops самих файлов)
+    #[arg(long)]
+    pub tops: Option<PathBuf>,
+    /// Инклинометрия: CSV "md, inc, azi" или LAS с секцией ~Survey (вместо ~Survey самих файлов)
+    #[arg(long)]
+    pub survey: Option<PathBuf>,
+    /// Альтитуда стола ротора для TVDSS (по умолчанию EKB/EDF/EGL из ~Well)
+    #[arg(long)]
+    pub kb: Option<f64>,
+    /// Список правок кривых (JSON), применяется к каждому файлу до остальной обработки
+    #[arg(long)]
+    pub edits: Option<PathBuf>,
+    /// Количество параллельно обрабатываемых файлов (по умолчанию - число ядер)
+    #[arg(short, long)]
+    pub jobs: Option<usize>,
+}
+
+#[derive(Args)]
+pub struct InfoArgs {
+    /// LAS файл
+    pub file: PathBuf,
+    /// Вывести в JSON (как /api/las)
+    #[arg(long)]
+    pub json: bool,
+}
+
+#[derive(Args)]
+pub struct ConvertArgs {
+    /// Файл или папка с LAS файлами
+    pub input: PathBuf,
+    /// Папка для результатов
+    #[arg(short, long, default_value = ".")]
+    pub out: PathBuf,
+    /// arrow или parquet
+    #[arg(short, long, default_value = "parquet", value_parser = ColumnarFormat::parse)]
+    pub format: ColumnarFormat,
+}
+
+/// Входной файл и путь результата относительно `--out`
+struct Job {
+    path: PathBuf,
+    relative: PathBuf,
+}
+
+fn file_name(path: &Path) -> PathBuf {
+    path.file_name().map(PathBuf::from).unwrap_or_else(|| path.to_path_buf())
+}
+
+/// Раскрывает файлы, папки и glob-шаблоны в список заданий
+fn expand_inputs(inputs: &[String]) -> Result<Vec<Job>> {
+    let mut jobs = Vec::new();
+    for input in inputs {
+        let path = Path::new(input);
+        if path.is_dir() {
+            let mut files = Vec::new();
+            collect_las_files(path, &mut files)?;
+            files.sort();
+            for file in files {
+                let relative = file.strip_prefix(path).unwrap_or(&file).to_path_buf();
+                jobs.push(Job { path: file, relative });
+            }
+        } else if path.is_file() {
+            jobs.push(Job { path: path.to_path_buf(), relative: file_name(path) });
+        } else if input.contains(['*', '?', '[']) {
+            let mut matched = false;
+            for entry in glob::glob(input).with_context(|| format!("Invalid pattern: {}", input))? {
+                let file = entry?;
+                if file.is_file() && is_las_file(&file) {
+                    matched = true;
+                    jobs.push(Job { relative: file_name(&file), path: file });
+                }
+            }
+            if !matched {
+                bail!("No LAS files match '{}'", input);
+            }
+        } else {
+            bail!("File not found: {}", input);
+        }
+    }
+    Ok(jobs)
+}
+
+/// `lasplot render`: файлы обрабатываются параллельно, ошибки печатаются по каждому файлу
+pub fn run_render(args: &RenderArgs, config_path: Option<&Path>) -> Result<()> {
+    let config = Config::load(config_path)?;
+    let params: HashMap<String, String> = args
+        .preset
+        .iter()
+        .map(|preset| ("preset".to_string(), preset.clone()))
+        .collect();
+    let (layout, preset_colors) = layout_for_request(&config, &params)?;
+    let colors = args.colors.clone().unwrap_or(preset_colors);
+    let tops_file = match &args.tops {
+        Some(path) => {
+            let content = std::fs::read(path).with_context(|| format!("Failed to read tops file {:?}", path))?;
+            Some(TopsFile::parse(&String::from_utf8_lossy(&content))?)
+        }
+        None => None,
+    };
+    let survey = match &args.survey {
+        Some(path) => {
+            let content = std::fs::read(path).with_context(|| format!("Failed to read survey file {:?}", path))?;
+            Some(Survey::parse(&String::from_utf8_lossy(&content))?)
+        }
+        None => None,
+    };
+    let edits = match &args.edits {
+        Some(path) => {
+            let content = std::fs::read(path).with_context(|| format!("Failed to read edits file {:?}", path))?;
+            Edit::parse_list(&String::from_utf8_lossy(&content))?
+        }
+        None => Vec::new(),
+    };
+    let sidecars = Sidecars { tops: tops_file, survey, edits };
+
+    let jobs = expand_inputs(&args.inputs)?;
+    let workers = args
+        .jobs
+        .unwrap_or_else(|| std::thread::available_parallelism().map_or(1, |n| n.get()))
+        .clamp(1, jobs.len().max(1));
+
+    // Каждый поток берёт следующий файл из общего счётчика
+    let next = AtomicUsize::new(0);
+    let failed = AtomicUsize::new(0);
+    std::thread::scope(|scope| {
+        for _ in 0..workers {
+            scope.spawn(|| loop {
+                let idx = next.fetch_add(1, Ordering::Relaxed);
+                let Some(job) = jobs.get(idx) else {
+                    break;
+                };
+                let out_path = args.out.join(&job.relative).with_extension(args.format.extension());
+                match render_file(&job.path, &out_path, args, &layout, &colors, &config, &sidecars) {
+                    Ok(()) => println!("{} -> {}", job.path.display(), out_path.display()),
+                    Err(e) => {
+                        failed.fetch_add(1, Ordering::Relaxed);
+                        eprintln!("{}: {:#}", job.path.display(), e);
+                    }
+                }
+            });
+        }
+    });
+
+    let failed = failed.into_inner();
+    println!("Rendered {} of {} files", jobs.len() - failed, jobs.len());
+    if failed > 0 {
+        bail!("{} files failed to render", failed);
+    }
+    Ok(())
+}
+
+/// Данные из отдельных файлов, общие для всех отрисовываемых LAS файлов
+struct Sidecars {
+    tops: Option<TopsFile>,
+    survey: Option<Survey>,
+    edits: Vec<Edit>,
+}
+
+fn render_file(
+    path: &Path,
+    out_path: &Path,
+    args: &RenderArgs,
+    layout: &PageLayout,
+    colors: &[String],
+    config: &Config,
+    sidecars: &Sidecars,
+) -> Result<()> {
+    let mut las_file = LasFile::read(path)?;
+    las_file.apply_edits(&sidecars.edits)?;
+    if let Some(tops_file) = &sidecars.tops {
+        las_file.tops = tops_file.for_well(&las_file)?;
+    }
+    if let Some(survey) = &sidecars.survey {
+        las_file.survey = Some(survey.clone());
+    }
+    las_file.add_trajectory(args.kb)?;
+    if let Some(system) = args.units {
+        las_file.convert_units(system, None);
+    }
+    let aliases = config.alias_dictionary()?;
+    las_file.add_computed(&args.compute, &config.petrophysics, &aliases)?;
+    las_file.add_derived(&config.derived_curves()?)?;
+    las_file.add_derived(&args.derive)?;
+    let layout_aliases = config.curve_families.then_some(&aliases);
+    let mut view = LogView::with_aliases(&las_file, args.main_param.as_deref(), colors, layout_aliases)?
+        .with_top_colors(&config.tops_colors);
+    // Отчёт по зонам между кровлями (в HTML) с граничными значениями из [cutoffs]
+    let zones = if view.main_param_idx == 0 { Zone::from_tops(&las_file, 0) } else { Vec::new() };
+    if args.format == RenderFormat::Html && !zones.is_empty() {
+        view = view.with_zone_report(ZoneReport::compute(&las_file, 0, &zones, &config.cutoffs)?);
+    }
+    let title = path.file_name().map_or_else(|| path.display().to_string(), |n| n.to_string_lossy().into_owned());
+    let output = render(view, layout, args.format, &title)?;
+
+    if let Some(parent) = out_path.parent() {
+        std::fs::create_dir_all(parent)?;
+    }
+    std::fs::write(out_path, output).with_context(|| format!("Failed to write {:?}", out_path))?;
+    Ok(())
+}
+
+/// `lasplot info`: сводка по файлу в читаемом виде или в JSON
+pub fn run_info(args: &InfoArgs) -> Result<()> {
+    let las_file = LasFile::read(&args.file)?;
+
+    if args.json {
+        println!("{}", serde_json::to_string_pretty(&las_metadata(&las_file))?);
+        return Ok(());
+    }
+
+    println!("File:    {}", args.file.display());
+    println!("Version: {}", las_file.version);
+    println!("NULL:    {}", las_file.null_value);
+    println!("Rows:    {}", las_file.data.len());
+
+    if !las_file.well_items.is_empty() {
+        println!("\nWell:");
+        for item in &las_file.well_items {
+            println!("  {:<8} {:<10} {:<24} {}", item.mnemonic, item.unit, item.value, item.description);
+        }
+    }
+
+    if !las_file.parameters.is_empty() {
+        println!("\nParameters:");
+        for item in &las_file.parameters {
+            println!("  {:<8} {:<10} {:<24} {}", item.mnemonic, item.unit, item.value, item.description);
+        }
+    }
+
+    println!("\nCurves:");
+    println!("  {:<10} {:<10} {:>14} {:>14} {:>8}  Description", "Mnemonic", "Unit", "Min", "Max", "Nulls");
+    for (idx, curve) in las_file.curves.iter().enumerate() {
+        let nulls = las_file.get_curve_data(idx).iter().filter(|v| v.is_none()).count();
+        let (min, max) = match las_file.get_curve_stats(idx) {
+            Some((min, max)) => (format!("{:.4}", min), format!("{:.4}", max)),
+            None => ("-".to_string(), "-".to_string()),
+        };
+        println!(
+            "  {:<10} {:<10} {:>14} {:>14} {:>8}  {}",
+            curve.mnemonic, curve.unit, min, max, nulls, curve.description
+        );
+    }
+
+    let warnings = las_file.warnings();
+    if !warnings.is_empty() {
+        println!("\nWarnings:");
+        for warning in warnings {
+            println!("  - {}", warning);
+        }
+    }
+
+    Ok(())
+}
+
+/// `lasplot check-config`: итоговая конфигурация (файл + переменные окружения) в TOML.
+/// Ошибки в значениях или отсутствующие папки дают ненулевой код выхода.
+pub fn run_check_config(config_path: Option<&Path>) -> Result<()> {
+    let source = Config::resolve_path(config_path);
+    let config = Config::load(config_path)?;
+
+    match &source {
+        Some(path) => println!("# Config: {}", path.display()),
+        None => println!("# Config: built-in defaults ({} not found)", lasplot::config::DEFAULT_CONFIG_FILE),
+    }
+    let overrides: Vec<String> = std::env::vars()
+        .map(|(name, _)| name)
+        .filter(|name| name.starts_with(lasplot::config::ENV_PREFIX) && name != lasplot::config::ENV_CONFIG_PATH)
+        .collect();
+    if !overrides.is_empty() {
+        println!("# Environment overrides: {}", overrides.join(", "));
+    }
+    println!("{}", config.to_toml()?);
+
+    config.check_directories()?;
+    println!("# OK");
+    Ok(())
+}
+
+/// `lasplot convert`: папки обходятся рекурсивно, структура подпапок сохраняется в `--out`.
+/// Ошибки отдельных файлов печатаются, но не прерывают обработку остальных.
+pub fn run_convert(args: &ConvertArgs) -> Result<()> {
+    let input = args.input.as_path();
+    let (root, files) = if input.is_dir() {
+        let mut files = Vec::new();
+        collect_las_files(input, &mut files)?;
+        files.sort();
+        (input.to_path_buf(), files)
+    } else {
+        let root = input.parent().map(Path::to_path_buf).unwrap_or_default();
+        (root, vec![input.to_path_buf()])
+    };
+
+    let mut failed = 0;
+    for path in &files {
+        let relative = path.strip_prefix(&root).unwrap_or(path);
+        let out_path = args.out.join(relative).with_extension(args.format.extension());
+        match convert_file(path, &out_path, args.format) {
+            Ok(rows) => println!("{} -> {} ({} rows)", path.display(), out_path.display(), rows),
+            Err(e) => {
+                failed += 1;
+                eprintln!("{}: {:#}", path.display(), e);
+            }
+        }
+    }
+
+    println!("Converted {} of {} files", files.len() - failed, files.len());
+    if failed > 0 {
+        bail!("{} files failed to convert", failed);
+    }
+    Ok(())
+}
@@ -0,0 +1,309 @@
+//! Сравнение двух LAS файлов (`/compare?a=&b=`): повторная запись или переобработка.
+//!
+//! Файл B (со сдвигом по глубине `shift=`) пересчитывается на сетку индекса файла A.
+//! Одноимённые кривые рисуются в одной колонке (B - штрихами), их разности - в отдельной колонке;
+//! статистика расхождений и различия заголовков показываются таблицами над графиками.
+
+use crate::api::number_param;
+use crate::families::AliasDictionary;
+use crate::las::{CurveInfo, HeaderItem, LasFile};
+use crate::plot::{hex_to_rgb, LineStyle, ScaleType};
+use crate::resample::ResampleMethod;
+use crate::units::find_unit;
+use crate::view::LogView;
+use anyhow::{anyhow, bail, Result};
+use std::collections::HashMap;
+
+/// Суффиксы мнемоник кривых файла B и разностей B - A
+pub const B_SUFFIX: &str = "_B";
+pub const DIFF_SUFFIX: &str = "_DIFF";
+
+/// Цвет кривых файла B - контрастный к цветам семейств и палитры
+const B_COLOR: &str = "000000";
+
+/// Параметры сравнения: `a=`, `b=`, `shift=` (прибавляется к глубинам B), `curves=`,
+/// `resample=` - способ пересчёта B на сетку A (по умолчанию linear)
+#[derive(Debug, Clone)]
+pub struct CompareSpec {
+    pub a: String,
+    pub b: String,
+    pub shift: f64,
+    /// Сравниваемые кривые; None - все общие по мнемонике
+    pub curves: Option<Vec<String>>,
+    pub resample: ResampleMethod,
+}
+
+impl CompareSpec {
+    pub fn from_params(params: &HashMap<String, String>) -> Result<Self> {
+        let file = |name: &str| {
+            params
+                .get(name)
+                .map(|s| s.trim().to_string())
+                .filter(|s| !s.is_empty())
+                .ok_or_else(|| anyhow!("Missing '{}' parameter", name))
+        };
+        let shift = number_param(params, "shift")?.unwrap_or(0.0);
+        let curves: Vec<String> = params
+            .get("curves")
+            .map_or("", |s| s.as_str())
+            .split(',')
+            .map(|c| c.trim().to_string())
+            .filter(|c| !c.is_empty())
+            .collect();
+        let resample = match params.get("resample").map(|s| s.trim()).filter(|s| !s.is_empty()) {
+            Some(method) => ResampleMethod::parse(method)?,
+            None => ResampleMethod::Linear,
+        };
+        Ok(CompareSpec {
+            a: file("a")?,
+            b: file("b")?,
+            shift,
+            curves: Some(curves).filter(|c| !c.is_empty()),
+            resample,
+        })
+    }
+}
+
+/// Расхождение кривой B с кривой A по отсчётам, где есть обе
+#[derive(Debug, Clone)]
+pub struct CurveComparison {
+    pub mnemonic: String,
+    pub unit: String,
+    pub count: usize,
+    /// Среднее B - A
+    pub bias: Option<f64>,
+    /// Среднеквадратичная разность
+    pub rms: Option<f64>,
+    pub max_abs: Option<f64>,
+    /// Коэффициент корреляции Пирсона
+    pub correlation: Option<f64>,
+}
+
+impl CurveComparison {
+    fn compute(mnemonic: &str, unit: &str, a: &[Option<f64>], b: &[Option<f64>]) -> Self {
+        let pairs: Vec<(f64, f64)> = a.iter().zip(b).filter_map(|(a, b)| Some(((*a)?, (*b)?))).collect();
+        let mean = |values: Vec<f64>| (!values.is_empty()).then(|| values.iter().sum::<f64>() / values.len() as f64);
+        let bias = mean(pairs.iter().map(|(a, b)| b - a).collect());
+        let rms = mean(pairs.iter().map(|(a, b)| (b - a).powi(2)).collect()).map(f64::sqrt);
+        let max_abs = pairs.iter().map(|(a, b)| (b - a).abs()).reduce(f64::max);
+        let correlation = match (mean(pairs.iter().map(|p| p.0).collect()), mean(pairs.iter().map(|p| p.1).collect())) {
+            (Some(mean_a), Some(mean_b)) => {
+                let cov: f64 = pairs.iter().map(|(a, b)| (a - mean_a) * (b - mean_b)).sum();
+                let var_a: f64 = pairs.iter().map(|(a, _)| (a - mean_a).powi(2)).sum();
+                let var_b: f64 = pairs.iter().map(|(_, b)| (b - mean_b).powi(2)).sum();
+                // У постоянной кривой корреляция не определена
+                (var_a > 0.0 && var_b > 0.0).then(|| cov / (var_a * var_b).sqrt())
+            }
+            _ => None,
+        };
+        CurveComparison {
+            mnemonic: mnemonic.to_string(),
+            unit: unit.to_string(),
+            count: pairs.len(),
+            bias,
+            rms,
+            max_abs,
+            correlation,
+        }
+    }
+}
+
+/// Различие заголовков: значение в A и в B (None - строки или кривой в файле нет)
+#[derive(Debug, Clone)]
+pub struct HeaderDifference {
+    pub section: &'static str,
+    pub mnemonic: String,
+    pub a: Option<String>,
+    pub b: Option<String>,
+}
+
+/// Итоги сравнения для раздела над графиками
+#[derive(Debug, Clone)]
+pub struct ComparisonReport {
+    pub a_name: String,
+    pub b_name: String,
+    pub shift: f64,
+    pub index_unit: String,
+    pub curves: Vec<CurveComparison>,
+    pub header: Vec<HeaderDifference>,
+}
+
+/// Различия строк заголовочной секции: значение вместе с единицей
+fn header_items_difference(section: &'static str, a: &[HeaderItem], b: &[HeaderItem], out: &mut Vec<HeaderDifference>) {
+    let text = |item: &HeaderItem| format!("{} {}", item.value.trim(), item.unit.trim()).trim().to_string();
+    let find = |items: &[HeaderItem], mnemonic: &str| items.iter().find(|i| i.mnemonic.eq_ignore_ascii_case(mnemonic)).map(text);
+    let mut seen: Vec<String> = Vec::new();
+    for item in a.iter().chain(b) {
+        let mnemonic = item.mnemonic.trim().to_uppercase();
+        if seen.contains(&mnemonic) {
+            continue;
+        }
+        let (value_a, value_b) = (find(a, &mnemonic), find(b, &mnemonic));
+        if value_a != value_b {
+            out.push(HeaderDifference { section, mnemonic: item.mnemonic.clone(), a: value_a, b: value_b });
+        }
+        seen.push(mnemonic);
+    }
+}
+
+/// Различия списков кривых: кривая есть только в одном файле или единицы разные
+fn curves_difference(a: &[CurveInfo], b: &[CurveInfo], out: &mut Vec<HeaderDifference>) {
+    let find = |curves: &[CurveInfo], mnemonic: &str| {
+        curves.iter().find(|c| c.mnemonic.eq_ignore_ascii_case(mnemonic)).map(|c| c.unit.trim().to_string())
+    };
+    let mut seen: Vec<String> = Vec::new();
+    for curve in a.iter().chain(b) {
+        let mnemonic = curve.mnemonic.trim().to_uppercase();
+        if seen.contains(&mnemonic) {
+            continue;
+        }
+        let (unit_a, unit_b) = (find(a, &mnemonic), find(b, &mnemonic));
+        if unit_a != unit_b {
+            out.push(HeaderDifference { section: "Curve", mnemonic: curve.mnemonic.clone(), a: unit_a, b: unit_b });
+        }
+        seen.push(mnemonic);
+    }
+}
+
+/// Общий файл сравнения на сетке индекса A: для каждой общей кривой M - M (из A), M_B и M_DIFF (B - A).
+/// Глубины B переводятся в единицу индекса A и сдвигаются на `spec.shift`.
+pub fn compare(a: &LasFile, b: &LasFile, spec: &CompareSpec) -> Result<(LasFile, ComparisonReport)> {
+    if a.curves.is_empty() || b.curves.is_empty() {
+        bail!("LAS file has no curves");
+    }
+    let index_unit = a.curves[0].unit.clone();
+    let factor = match (find_unit(&b.curves[0].unit), find_unit(&index_unit)) {
+        (Some(from), Some(to)) if from.quantity == to.quantity => from.convert(1.0, to),
+        _ => 1.0,
+    };
+    let mut shifted = b.clone();
+    for row in &mut shifted.data {
+        if let Some(depth) = row.values.first_mut().filter(|v| **v != b.null_value) {
+            *depth = *depth * factor + spec.shift;
+        }
+    }
+    let b_on_a = shifted.resample_to(0, a, 0, spec.resample, None);
+
+    // Пары (индекс в A, индекс в B) по мнемонике
+    let pairs: Vec<(usize, usize)> = match &spec.curves {
+        Some(names) => names
+            .iter()
+            .map(|name| match (a.get_curve_index(name), b_on_a.get_curve_index(name)) {
+                (Some(idx_a), Some(idx_b)) if idx_a != 0 && idx_b != 0 => Ok((idx_a, idx_b)),
+                _ => Err(anyhow!("Curve '{}' is not in both files", name)),
+            })
+            .collect::<Result<_>>()?,
+        None => (1..a.curves.len())
+            .filter_map(|idx_a| Some((idx_a, b_on_a.get_curve_index(&a.curves[idx_a].mnemonic).filter(|&i| i != 0)?)))
+            .collect(),
+    };
+    if pairs.is_empty() {
+        bail!("The files have no curves in common");
+    }
+
+    let indices: Vec<usize> = std::iter::once(0).chain(pairs.iter().map(|p| p.0)).collect();
+    let rows: Vec<usize> = (0..a.data.len()).collect();
+    let mut combined = a.subset(&indices, &rows);
+    let null_value = combined.null_value;
+    let mut curves = Vec::new();
+    for &(idx_a, idx_b) in &pairs {
+        let (curve_a, curve_b) = (&a.curves[idx_a], &b_on_a.curves[idx_b]);
+        let (values_a, values_b) = (a.get_curve_data(idx_a), b_on_a.get_curve_data(idx_b));
+        let diff: Vec<Option<f64>> = values_a.iter().zip(&values_b).map(|(a, b)| Some((*b)? - (*a)?)).collect();
+        curves.push(CurveComparison::compute(&curve_a.mnemonic, &curve_a.unit, &values_a, &values_b));
+
+        for (suffix, unit, description, values) in [
+            (B_SUFFIX, &curve_b.unit, format!("{} ({})", curve_b.description, spec.b), values_b),
+            (DIFF_SUFFIX, &curve_a.unit, format!("{} difference B - A", curve_a.mnemonic), diff),
+        ] {
+            let curve_idx = combined.curves.len();
+            for (row, value) in combined.data.iter_mut().zip(values) {
+                // Короткие строки дополняются пропусками, чтобы значение попало в свою колонку
+                row.values.resize(curve_idx, null_value);
+                row.values.push(value.filter(|v| v.is_finite()).unwrap_or(null_value));
+            }
+            combined.curves.push(CurveInfo {
+                mnemonic: format!("{}{}", curve_a.mnemonic, suffix),
+                unit: unit.clone(),
+                description: description.trim().to_string(),
+                api_codes: None,
+            });
+        }
+    }
+
+    let mut header = Vec::new();
+    header_items_difference("Well", &a.well_items, &b.well_items, &mut header);
+    curves_difference(&a.curves, &b.curves, &mut header);
+    header_items_difference("Parameter", &a.parameters, &b.parameters, &mut header);
+
+    let report = ComparisonReport {
+        a_name: spec.a.clone(),
+        b_name: spec.b.clone(),
+        shift: spec.shift,
+        index_unit,
+        curves,
+        header,
+    };
+    Ok((combined, report))
+}
+
+/// Вид сравнения: колонка на каждую пару кривых (A сплошной линией, B - штрихами, общая шкала)
+/// и последняя колонка с разностями (шкалы симметричны относительно нуля)
+pub fn comparison_view(
+    combined: &LasFile,
+    report: ComparisonReport,
+    colors: &[String],
+    aliases: Option<&AliasDictionary>,
+) -> Result<LogView> {
+    let mut view = LogView::with_aliases(combined, None, colors, aliases)?;
+    let displayed: Vec<String> = view.curves_data.iter().map(|(_, mnemonic)| mnemonic.clone()).collect();
+    let position = |mnemonic: &str| displayed.iter().position(|m| m == mnemonic);
+    let diff_track = report.curves.len();
+
+    for (pair, curve) in report.curves.iter().enumerate() {
+        let name_b = format!("{}{}", curve.mnemonic, B_SUFFIX);
+        let name_diff = format!("{}{}", curve.mnemonic, DIFF_SUFFIX);
+        let (i_a, i_b, i_diff) = (position(&curve.mnemonic), position(&name_b), position(&name_diff));
+
+        if let Some(i) = i_a {
+            view.tracks[i] = pair;
+        }
+        if let Some(i) = i_b {
+            view.tracks[i] = pair;
+            view.line_styles[i] = LineStyle::Dashed;
+            view.colors[i] = hex_to_rgb(B_COLOR);
+            if let Some(idx) = combined.get_curve_index(&name_b) {
+                view.curve_to_color.insert(idx, B_COLOR.to_string());
+            }
+        }
+        // Общая шкала A и B: направление и тип - как у A
+        if let (Some(i_a), Some(i_b)) = (i_a, i_b) {
+            view.scale_types[i_b] = view.scale_types[i_a];
+            let ((a0, a1), (b0, b1)) = (view.x_ranges[i_a], view.x_ranges[i_b]);
+            let (mut lo, hi) = (a0.min(a1).min(b0.min(b1)), a0.max(a1).max(b0.max(b1)));
+            // Логарифмической шкале нужен положительный минимум - берётся минимум A
+            if view.scale_types[i_a] == ScaleType::Logarithmic && lo <= 0.0 {
+                lo = a0.min(a1);
+            }
+            let range = if a0 > a1 { (hi, lo) } else { (lo, hi) };
+            view.x_ranges[i_a] = range;
+            view.x_ranges[i_b] = range;
+        }
+        if let Some(i) = i_diff {
+            view.tracks[i] = diff_track;
+            view.scale_types[i] = ScaleType::Linear;
+            let (d0, d1) = view.x_ranges[i];
+            let max = d0.abs().max(d1.abs());
+            view.x_ranges[i] = if max > 0.0 { (-max, max) } else { (-1.0, 1.0) };
+            if let Some(i_a) = i_a {
+                view.colors[i] = view.colors[i_a];
+                if let Some(idx) = combined.get_curve_index(&name_diff) {
+                    let [r, g, b] = view.colors[i_a];
+                    view.curve_to_color.insert(idx, format!("{:02X}{:02X}{:02X}", r, g, b));
+                }
+            }
+        }
+    }
+    view.track_count = view.tracks.iter().max().map_or(1, |t| t + 1);
+    Ok(view.with_comparison(report))
+}
@@ -1,18 +1,46 @@
-use serde::Deserialize;
-use std::path::PathBuf;
+//! Конфигурация из lasplot.toml.
+//!
+//! Порядок: значения по умолчанию, затем файл (`--config`, `LASPLOT_CONFIG` или `./lasplot.toml`),
+//! затем переменные окружения `LASPLOT_<ПОЛЕ>` (например `LASPLOT_BIND_PORT=9000`).
 
-#[derive(Debug, Clone, Deserialize)]
+use anyhow::{anyhow, bail, Context, Result};
+use crate::derived::DerivedCurve;
+use crate::families::{AliasDictionary, AliasRule};
+use crate::petrophysics::PetroParams;
+use crate::stats::Cutoffs;
+use crate::view::LayoutOverrides;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+
+/// Файл конфигурации по умолчанию (в текущей папке)
+pub const DEFAULT_CONFIG_FILE: &str = "lasplot.toml";
+
+/// Префикс переменных окружения, переопределяющих поля конфигурации
+pub const ENV_PREFIX: &str = "LASPLOT_";
+
+/// Переменная окружения с путём к файлу конфигурации
+pub const ENV_CONFIG_PATH: &str = "LASPLOT_CONFIG";
+
+/// Настройки сервера и отрисовки из lasplot.toml
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(deny_unknown_fields)]
 pub struct Config {
     #[serde(default = "default_bind_address")]
     pub bind_address: String,
     #[serde(default = "default_bind_port")]
     pub bind_port: u16,
+    #[serde(default = "default_samples_dir")]
     pub samples_dir: String,
     #[serde(default = "default_laslist_file")]
     pub laslist_file: String,
+    #[serde(default = "default_html_row_steps")]
     pub html_row_steps: usize,
+    #[serde(default = "default_pixels_per_step")]
     pub pixels_per_step: usize,
+    #[serde(default = "default_image_width")]
     pub image_width: usize,
+    #[serde(default = "default_scale_spacing")]
     pub scale_spacing: usize,
     #[serde(default = "default_max_scales")]
     pub max_scales: usize,
@@ -20,8 +48,42 @@ pub struct Config {
     pub tick_size_major: usize,
     #[serde(default = "default_tick_size_minor")]
     pub tick_size_minor: usize,
+    #[serde(default = "default_colors")]
     pub default_colors: Vec<String>,
+    #[serde(default)]
     pub separate_depth_column: bool,
+    #[serde(default = "default_uploads_dir")]
+    pub uploads_dir: String,
+    #[serde(default = "default_max_upload_size")]
+    pub max_upload_size: usize,
+    #[serde(default = "default_upload_ttl_hours")]
+    pub upload_ttl_hours: u64,
+    /// Потоков отрисовки картинок на весь сервер (0 - по числу ядер)
+    #[serde(default)]
+    pub render_concurrency: usize,
+    /// Именованные наборы параметров отображения (`[presets.compact]`), выбираются через `preset=`
+    #[serde(default)]
+    pub presets: BTreeMap<String, LayoutOverrides>,
+    /// Раскладывать кривые по колонкам семейств (ГК, сопротивление, пористость) с их шкалами и цветами;
+    /// по умолчанию выключено, чтобы не менять вид уже настроенных планшетов
+    #[serde(default)]
+    pub curve_families: bool,
+    /// Дополнительные синонимы мнемоник (`[curve_aliases.gamma_ray]`), проверяются раньше встроенных
+    #[serde(default)]
+    pub curve_aliases: BTreeMap<String, AliasRule>,
+    /// Параметры расчётных кривых по умолчанию (`compute=vsh,phid,phind,sw`)
+    #[serde(default)]
+    pub petrophysics: PetroParams,
+    /// Производные кривые для всех файлов (`"RATIO = ILD / MSFL"`), по порядку; определение
+    /// пропускается, если в файле нет нужных кривых
+    #[serde(default)]
+    pub derived_curves: Vec<String>,
+    /// Цвета линий кровель по названию пласта (`Viking = "8B4513"`), остальные - из встроенной палитры
+    #[serde(default)]
+    pub tops_colors: BTreeMap<String, String>,
+    /// Граничные значения для эффективных толщин в отчёте по зонам
+    #[serde(default)]
+    pub cutoffs: Cutoffs,
 }
 
 fn default_bind_address() -> String {
@@ -32,10 +94,30 @@ fn default_bind_port() -> u16 {
     8080
 }
 
+fn default_samples_dir() -> String {
+    "samples".to_string()
+}
+
 fn default_laslist_file() -> String {
     "lasfiles.txt".to_string()
 }
 
+fn default_html_row_steps() -> usize {
+    25
+}
+
+fn default_pixels_per_step() -> usize {
+    6
+}
+
+fn default_image_width() -> usize {
+    1000
+}
+
+fn default_scale_spacing() -> usize {
+    20
+}
+
 fn default_max_scales() -> usize {
     6
 }
@@ -48,15 +130,256 @@ fn default_tick_size_minor() -> usize {
     4
 }
 
+fn default_colors() -> Vec<String> {
+    ["FF0000", "0000FF", "00FF00", "FF00FF", "00FFFF", "FFA500", "800080"]
+        .iter()
+        .map(|s| s.to_string())
+        .collect()
+}
+
+fn default_uploads_dir() -> String {
+    "uploads".to_string()
+}
+
+fn default_max_upload_size() -> usize {
+    50 * 1024 * 1024
+}
+
+fn default_upload_ttl_hours() -> u64 {
+    24
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        toml::from_str("").expect("all config fields have defaults")
+    }
+}
+
+/// Значение переменной окружения как TOML значение того же типа, что и поле:
+/// строки берутся как есть, списки - через запятую, остальное разбирается как TOML.
+fn parse_env_value(field_default: Option<&toml::Value>, raw: &str) -> toml::Value {
+    match field_default {
+        Some(toml::Value::String(_)) => toml::Value::String(raw.to_string()),
+        Some(toml::Value::Array(_)) => toml::Value::Array(
+            raw.split(',')
+                .map(|s| toml::Value::String(s.trim().to_string()))
+                .collect(),
+        ),
+        _ => match toml::from_str::<toml::Table>(&format!("v = {}", raw)) {
+            Ok(mut table) => table.remove("v").unwrap_or_else(|| toml::Value::String(raw.to_string())),
+            Err(_) => toml::Value::String(raw.to_string()),
+        },
+    }
+}
+
+pub(crate) fn is_hex_color(color: &str) -> bool {
+    let hex = color.trim_start_matches('#');
+    hex.len() == 6 && hex.chars().all(|c| c.is_ascii_hexdigit())
+}
+
 impl Config {
-    pub fn load() -> anyhow::Result<Self> {
-        let config_str = std::fs::read_to_string("lasplot.toml")?;
-        let config: Config = toml::from_str(&config_str)?;
+    /// Файл конфигурации: `path`, затем `LASPLOT_CONFIG`, затем `./lasplot.toml`, если он есть.
+    /// `None` - файла нет, используются значения по умолчанию.
+    pub fn resolve_path(path: Option<&Path>) -> Option<PathBuf> {
+        if let Some(path) = path {
+            return Some(path.to_path_buf());
+        }
+        if let Ok(path) = std::env::var(ENV_CONFIG_PATH) {
+            return Some(PathBuf::from(path));
+        }
+        let default_path = PathBuf::from(DEFAULT_CONFIG_FILE);
+        default_path.exists().then_some(default_path)
+    }
+
+    /// Загружает конфигурацию (см. [`Config::resolve_path`]), применяет
+    /// переменные окружения и проверяет значения
+    pub fn load(path: Option<&Path>) -> Result<Self> {
+        let path = Self::resolve_path(path);
+        let mut table = match &path {
+            Some(path) => {
+                let config_str = std::fs::read_to_string(path)
+                    .with_context(|| format!("Failed to read config {:?}", path))?;
+                toml::from_str::<toml::Table>(&config_str)
+                    .with_context(|| format!("Invalid TOML in {:?}", path))?
+            }
+            None => toml::Table::new(),
+        };
+
+        let defaults = toml::Table::try_from(Config::default())?;
+        for (name, raw) in std::env::vars() {
+            let Some(key) = name.strip_prefix(ENV_PREFIX) else {
+                continue;
+            };
+            if name == ENV_CONFIG_PATH {
+                continue;
+            }
+            let key = key.to_lowercase();
+            let value = parse_env_value(defaults.get(&key), &raw);
+            table.insert(key, value);
+        }
+
+        let source = path.as_ref().map_or_else(|| "defaults".to_string(), |p| format!("{:?}", p));
+        let config = Config::deserialize(toml::Value::Table(table))
+            .with_context(|| format!("Invalid config ({} and {}* environment)", source, ENV_PREFIX))?;
+        config.validate()?;
         Ok(config)
     }
 
+    /// Ошибки параметров отображения; `prefix` - путь к полям (для пресетов)
+    fn presentation_errors(&self, prefix: &str) -> Vec<String> {
+        let mut errors = Vec::new();
+
+        // (поле, значение, минимум, максимум)
+        let ranges = [
+            ("html_row_steps", self.html_row_steps, 1, 10_000),
+            ("pixels_per_step", self.pixels_per_step, 1, 100),
+            // Слева 100 пикселей занимает отступ под подписи
+            ("image_width", self.image_width, 200, 10_000),
+            ("scale_spacing", self.scale_spacing, 1, 200),
+            ("max_scales", self.max_scales, 1, 50),
+            ("tick_size_major", self.tick_size_major, 1, 100),
+            ("tick_size_minor", self.tick_size_minor, 1, 100),
+        ];
+        for (name, value, min, max) in ranges {
+            if !(min..=max).contains(&value) {
+                errors.push(format!("{}{} = {} is out of range {}..={}", prefix, name, value, min, max));
+            }
+        }
+        if self.tick_size_minor > self.tick_size_major {
+            errors.push(format!(
+                "{}tick_size_minor = {} must not exceed tick_size_major = {}",
+                prefix, self.tick_size_minor, self.tick_size_major
+            ));
+        }
+        if self.default_colors.is_empty() {
+            errors.push(format!("{}default_colors must not be empty", prefix));
+        }
+        for color in &self.default_colors {
+            if !is_hex_color(color) {
+                errors.push(format!("{}default_colors: '{}' is not a 6-digit hex color (e.g. FF0000)", prefix, color));
+            }
+        }
+        errors
+    }
+
+    /// Конфигурация с применённым пресетом (для проверки его значений)
+    fn with_preset(&self, preset: &LayoutOverrides) -> Config {
+        let mut config = self.clone();
+        config.html_row_steps = preset.html_row_steps.unwrap_or(config.html_row_steps);
+        config.pixels_per_step = preset.pixels_per_step.unwrap_or(config.pixels_per_step);
+        config.image_width = preset.image_width.unwrap_or(config.image_width);
+        config.separate_depth_column = preset.separate_depth_column.unwrap_or(config.separate_depth_column);
+        config.scale_spacing = preset.scale_spacing.unwrap_or(config.scale_spacing);
+        config.max_scales = preset.max_scales.unwrap_or(config.max_scales);
+        config.tick_size_major = preset.tick_size_major.unwrap_or(config.tick_size_major);
+        config.tick_size_minor = preset.tick_size_minor.unwrap_or(config.tick_size_minor);
+        if let Some(colors) = &preset.colors {
+            config.default_colors = colors.clone();
+        }
+        config
+    }
+
+    /// Проверка диапазонов, формата цветов и пресетов; все ошибки собираются в одно сообщение
+    pub fn validate(&self) -> Result<()> {
+        let mut errors = self.presentation_errors("");
+
+        if !(1..=1024 * 1024 * 1024).contains(&self.max_upload_size) {
+            errors.push(format!("max_upload_size = {} is out of range 1..=1073741824", self.max_upload_size));
+        }
+        if self.upload_ttl_hours == 0 {
+            errors.push("upload_ttl_hours must be at least 1".to_string());
+        }
+        if self.render_concurrency > 256 {
+            errors.push(format!("render_concurrency = {} is out of range 0..=256", self.render_concurrency));
+        }
+        for (name, preset) in &self.presets {
+            errors.extend(self.with_preset(preset).presentation_errors(&format!("presets.{}.", name)));
+        }
+        if let Err(e) = AliasDictionary::with_overrides(&self.curve_aliases) {
+            errors.push(format!("curve_aliases: {}", e));
+        }
+        errors.extend(self.petrophysics.errors("petrophysics."));
+        errors.extend(self.cutoffs.errors("cutoffs."));
+        for (name, color) in &self.tops_colors {
+            if !is_hex_color(color) {
+                errors.push(format!("tops_colors.{}: '{}' is not a 6-digit hex color (e.g. FF0000)", name, color));
+            }
+        }
+        for definition in &self.derived_curves {
+            if let Err(e) = DerivedCurve::parse(definition) {
+                errors.push(format!("derived_curves: {}", e));
+            }
+        }
+
+        if !errors.is_empty() {
+            bail!("Invalid config:\n  - {}", errors.join("\n  - "));
+        }
+        Ok(())
+    }
+
+    /// Проверка папок, нужных серверу: samples_dir должна существовать,
+    /// uploads_dir создаётся при первой загрузке, но не может быть файлом
+    pub fn check_directories(&self) -> Result<()> {
+        let mut errors = Vec::new();
+
+        let samples_path = self.get_samples_path();
+        if !samples_path.is_dir() {
+            errors.push(format!("samples_dir {:?} does not exist or is not a directory", samples_path));
+        }
+        let uploads_path = self.get_uploads_path();
+        if uploads_path.exists() && !uploads_path.is_dir() {
+            errors.push(format!("uploads_dir {:?} is not a directory", uploads_path));
+        }
+
+        if !errors.is_empty() {
+            bail!("Invalid config:\n  - {}", errors.join("\n  - "));
+        }
+        Ok(())
+    }
+
+    /// Словарь синонимов: встроенный и `[curve_aliases]`
+    pub fn alias_dictionary(&self) -> Result<AliasDictionary> {
+        AliasDictionary::with_overrides(&self.curve_aliases)
+    }
+
+    /// Производные кривые из `derived_curves` (необязательные, см. [`DerivedCurve::optional`])
+    pub fn derived_curves(&self) -> Result<Vec<DerivedCurve>> {
+        self.derived_curves
+            .iter()
+            .map(|definition| {
+                let mut curve = DerivedCurve::parse(definition)?;
+                curve.optional = true;
+                Ok(curve)
+            })
+            .collect()
+    }
+
+    /// Итоговая конфигурация в формате TOML
+    pub fn to_toml(&self) -> Result<String> {
+        Ok(toml::to_string_pretty(self)?)
+    }
+
     pub fn get_samples_path(&self) -> PathBuf {
         PathBuf::from(&self.samples_dir)
     }
-}
 
+    pub fn get_uploads_path(&self) -> PathBuf {
+        PathBuf::from(&self.uploads_dir)
+    }
+
+    /// Путь к файлу из запроса относительно samples_dir. Итоговый путь (после `..`
+    /// и символических ссылок) должен остаться внутри samples_dir или uploads_dir;
+    /// в ошибке только имя из запроса, без путей сервера
+    pub fn resolve_sample_path(&self, name: &str) -> Result<PathBuf> {
+        let not_found = || anyhow!("File not found: {}", name);
+        let path = self.get_samples_path().join(name).canonicalize().map_err(|_| not_found())?;
+        let contained = [self.get_samples_path(), self.get_uploads_path()]
+            .iter()
+            .filter_map(|dir| dir.canonicalize().ok())
+            .any(|dir| path.starts_with(dir));
+        if !contained || !path.is_file() {
+            return Err(not_found());
+        }
+        Ok(path)
+    }
+}
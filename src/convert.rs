@@ -0,0 +1,68 @@
+//! Конвертация LAS файлов в Arrow IPC / Parquet (используется командой `lasplot convert`).
+
+use crate::arrow::{write_arrow_file, write_parquet_file};
+use crate::las::LasFile;
+use anyhow::{bail, Context, Result};
+use std::io::BufWriter;
+use std::path::{Path, PathBuf};
+
+/// Колоночный формат для [`convert_file`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ColumnarFormat {
+    Arrow,
+    Parquet,
+}
+
+impl ColumnarFormat {
+    pub fn parse(s: &str) -> Result<Self> {
+        match s.to_lowercase().as_str() {
+            "arrow" | "ipc" => Ok(ColumnarFormat::Arrow),
+            "parquet" => Ok(ColumnarFormat::Parquet),
+            other => bail!("Unknown format '{}' (expected arrow or parquet)", other),
+        }
+    }
+
+    pub fn extension(&self) -> &'static str {
+        match self {
+            ColumnarFormat::Arrow => "arrow",
+            ColumnarFormat::Parquet => "parquet",
+        }
+    }
+}
+
+pub fn is_las_file(path: &Path) -> bool {
+    path.extension()
+        .and_then(|ext| ext.to_str())
+        .is_some_and(|ext| ext.eq_ignore_ascii_case("las"))
+}
+
+pub fn collect_las_files(dir: &Path, files: &mut Vec<PathBuf>) -> Result<()> {
+    let entries = std::fs::read_dir(dir).with_context(|| format!("Failed to read dir: {:?}", dir))?;
+    for entry in entries.flatten() {
+        let path = entry.path();
+        if path.is_dir() {
+            collect_las_files(&path, files)?;
+        } else if is_las_file(&path) {
+            files.push(path);
+        }
+    }
+    Ok(())
+}
+
+/// Конвертирует один файл; возвращает количество строк данных
+pub fn convert_file(path: &Path, out_path: &Path, format: ColumnarFormat) -> Result<usize> {
+    let las_file = LasFile::read(path)?;
+    if las_file.curves.is_empty() {
+        bail!("No curves found");
+    }
+
+    if let Some(parent) = out_path.parent() {
+        std::fs::create_dir_all(parent)?;
+    }
+    let writer = BufWriter::new(std::fs::File::create(out_path)?);
+    match format {
+        ColumnarFormat::Arrow => write_arrow_file(&las_file, writer)?,
+        ColumnarFormat::Parquet => write_parquet_file(&las_file, writer)?,
+    }
+    Ok(las_file.data.len())
+}
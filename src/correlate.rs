@@ -0,0 +1,513 @@
+//! Корреляция скважин (`/correlate`): несколько LAS файлов рядом на общей оси глубины или TVDSS.
+//!
+//! Скважины можно выровнять по кровле (`datum=`): ось тогда отсчитывается от неё.
+//! Одноимённые кривые получают общие шкалы, одноимённые кровли соединяются линиями
+//! между соседними скважинами. Картинки строк - те же, что у [`crate::view`], но ось у всех общая.
+
+use crate::families::AliasDictionary;
+use crate::html::escape_html;
+use crate::las::{CurveInfo, LasFile};
+use crate::plot::{encode_png, generate_plot_png, hex_to_rgb, PlotTop, ScaleType};
+use crate::pool::RenderPool;
+use crate::resample::ResampleMethod;
+use crate::survey::{EAST, NORTH, TVD, TVDSS};
+use crate::tops::top_color;
+use crate::view::{LogView, PageLayout};
+use anyhow::{anyhow, bail, Context, Result};
+use base64::Engine;
+use bytes::Bytes;
+use futures::future::ok;
+use futures::stream::{self, once, Stream, StreamExt};
+use std::collections::{BTreeMap, HashMap};
+use std::sync::Arc;
+
+/// Не больше стольких скважин в одном запросе
+pub const MAX_WELLS: usize = 12;
+
+/// Ширина колонки с линиями кровель между скважинами, пикселей
+const CONNECTOR_WIDTH: usize = 60;
+
+/// Общая ось корреляции
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum CorrelationAxis {
+    /// Индекс файла (глубина по стволу)
+    #[default]
+    Depth,
+    /// Абсолютная отметка: по инклинометрии или, без неё, как у вертикальной скважины
+    Tvdss,
+}
+
+impl CorrelationAxis {
+    pub fn parse(s: &str) -> Result<Self> {
+        match s.trim().to_lowercase().as_str() {
+            "" | "depth" | "md" => Ok(CorrelationAxis::Depth),
+            "tvdss" => Ok(CorrelationAxis::Tvdss),
+            other => bail!("Unknown axis '{}' (expected depth or tvdss)", other),
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            CorrelationAxis::Depth => "Depth",
+            CorrelationAxis::Tvdss => TVDSS,
+        }
+    }
+}
+
+/// Параметры корреляции: `files=a.las,b.las`, `curves=GR,ILD`, `axis=depth|tvdss`, `datum=<кровля>`
+#[derive(Debug, Clone)]
+pub struct CorrelationSpec {
+    pub files: Vec<String>,
+    /// Кривые по мнемонике или синониму семейства; None - все кривые каждой скважины
+    pub curves: Option<Vec<String>>,
+    pub axis: CorrelationAxis,
+    /// Кровля, от которой отсчитывается ось (выравнивание)
+    pub datum: Option<String>,
+}
+
+impl CorrelationSpec {
+    pub fn from_params(params: &HashMap<String, String>) -> Result<Self> {
+        let list = |name: &str| -> Vec<String> {
+            params.get(name).map_or_else(Vec::new, |s| {
+                s.split(',').map(|v| v.trim().to_string()).filter(|v| !v.is_empty()).collect()
+            })
+        };
+        let files = list("files");
+        if files.is_empty() {
+            bail!("Missing 'files' parameter (comma-separated LAS files)");
+        }
+        if files.len() > MAX_WELLS {
+            bail!("Too many files: {} (at most {})", files.len(), MAX_WELLS);
+        }
+        let curves = Some(list("curves")).filter(|c| !c.is_empty());
+        Ok(CorrelationSpec {
+            files,
+            curves,
+            axis: CorrelationAxis::parse(params.get("axis").map_or("", |s| s.as_str()))?,
+            datum: params.get("datum").map(|s| s.trim().to_string()).filter(|s| !s.is_empty()),
+        })
+    }
+}
+
+/// Скважина на корреляции
+pub struct CorrelationWell {
+    /// WELL из ~Well или имя файла
+    pub title: String,
+    pub file_name: String,
+    pub view: LogView,
+}
+
+/// Скважины, подготовленные к отрисовке на общей оси
+pub struct Correlation {
+    pub axis: CorrelationAxis,
+    pub datum: Option<String>,
+    pub wells: Vec<CorrelationWell>,
+}
+
+/// Кривая по мнемонике, иначе первая кривая того же семейства, что и `name` (GR найдёт GRC)
+fn find_curve(las_file: &LasFile, name: &str, aliases: &AliasDictionary) -> Option<usize> {
+    las_file.get_curve_index(name).or_else(|| {
+        let family = aliases.classify(&CurveInfo {
+            mnemonic: name.to_string(),
+            unit: String::new(),
+            description: String::new(),
+            api_codes: None,
+        })?;
+        (1..las_file.curves.len()).find(|&idx| aliases.classify(&las_file.curves[idx]) == Some(family))
+    })
+}
+
+/// Значение `values` при значении индекса `at` (линейно между соседними отсчётами)
+fn interpolate(index: &[Option<f64>], values: &[Option<f64>], at: f64) -> Option<f64> {
+    let samples: Vec<(f64, f64)> = index.iter().zip(values).filter_map(|(x, y)| Some(((*x)?, (*y)?))).collect();
+    samples.windows(2).find_map(|w| {
+        let ((x0, y0), (x1, y1)) = (w[0], w[1]);
+        if !(x0.min(x1)..=x0.max(x1)).contains(&at) {
+            return None;
+        }
+        Some(if x1 == x0 { y0 } else { y0 + (y1 - y0) * (at - x0) / (x1 - x0) })
+    })
+}
+
+/// Файл для отображения на общей оси: первая кривая - ось (со сдвигом на отметку `datum`),
+/// за ней выбранные кривые; кровли - в единицах оси. Вместе с файлом - ключ общей шкалы
+/// каждой кривой (запрошенное имя или мнемоника).
+fn prepare_well(
+    las_file: &LasFile,
+    title: &str,
+    spec: &CorrelationSpec,
+    aliases: &AliasDictionary,
+) -> Result<(LasFile, Vec<PlotTop>, HashMap<String, String>)> {
+    if las_file.curves.is_empty() {
+        bail!("LAS file has no curves");
+    }
+    let index = las_file.get_curve_data(0);
+    let index_unit = las_file.curves[0].unit.clone();
+
+    // Значения оси по строкам; TVDSS без инклинометрии - как у вертикальной скважины
+    let (axis_idx, axis_values) = match spec.axis {
+        CorrelationAxis::Depth => (Some(0), index.clone()),
+        CorrelationAxis::Tvdss => match las_file.get_curve_index(TVDSS) {
+            Some(idx) => (Some(idx), las_file.get_curve_data(idx)),
+            None if las_file.survey.is_none() => {
+                let elevation = las_file
+                    .elevation()
+                    .ok_or_else(|| anyhow!("No TVDSS: set a KB elevation (EKB, EDF or EGL in ~Well)"))?;
+                (None, index.iter().map(|v| v.map(|v| v - elevation)).collect())
+            }
+            None => bail!("No TVDSS: the survey needs a KB elevation (EKB, EDF or EGL in ~Well)"),
+        },
+    };
+    let axis_at = |depth: f64| match spec.axis {
+        CorrelationAxis::Depth => Some(depth),
+        CorrelationAxis::Tvdss => interpolate(&index, &axis_values, depth),
+    };
+
+    let shift = match &spec.datum {
+        Some(datum) => {
+            let top = las_file
+                .tops
+                .iter()
+                .find(|top| top.name.trim().eq_ignore_ascii_case(datum))
+                .ok_or_else(|| anyhow!("Well '{}' has no top '{}'", title, datum))?;
+            axis_at(top.depth_in(&index_unit))
+                .ok_or_else(|| anyhow!("Top '{}' of well '{}' is outside the logged interval", datum, title))?
+        }
+        None => 0.0,
+    };
+
+    // Выбранные кривые без повторов; без curves= - все, кроме индекса и траектории
+    let mut selected: Vec<(usize, String)> = Vec::new();
+    match &spec.curves {
+        Some(names) => {
+            for name in names {
+                if let Some(idx) = find_curve(las_file, name, aliases).filter(|idx| selected.iter().all(|(i, _)| i != idx)) {
+                    selected.push((idx, name.to_uppercase()));
+                }
+            }
+            if selected.is_empty() {
+                bail!("None of the curves {} found", names.join(", "));
+            }
+        }
+        None => {
+            let trajectory = [TVD, TVDSS, NORTH, EAST];
+            for (idx, curve) in las_file.curves.iter().enumerate().skip(1) {
+                if !trajectory.iter().any(|t| curve.mnemonic.eq_ignore_ascii_case(t)) {
+                    selected.push((idx, curve.mnemonic.to_uppercase()));
+                }
+            }
+        }
+    }
+    let keys = selected.iter().map(|(idx, key)| (las_file.curves[*idx].mnemonic.to_uppercase(), key.clone())).collect();
+
+    let curve_indices: Vec<usize> = std::iter::once(0).chain(selected.iter().map(|(idx, _)| *idx)).collect();
+    let rows: Vec<usize> = (0..las_file.data.len()).collect();
+    let mut well = las_file.subset(&curve_indices, &rows);
+    for (row, value) in well.data.iter_mut().zip(&axis_values) {
+        row.values[0] = value.map_or(las_file.null_value, |v| v - shift);
+    }
+    if spec.axis == CorrelationAxis::Tvdss {
+        well.curves[0].mnemonic = TVDSS.to_string();
+        well.curves[0].description = "True vertical depth subsea".to_string();
+    }
+    // TVDSS поднимающегося ствола немонотонна - как и в LogView, усредняется на сетке
+    if let Some(step) = axis_idx.filter(|&idx| idx != 0).and_then(|idx| las_file.non_monotonic_step(idx)) {
+        well = well.resample_step(0, step, ResampleMethod::Average, None)?;
+    }
+
+    let tops = las_file
+        .tops
+        .iter()
+        .filter_map(|top| {
+            Some(PlotTop {
+                depth: axis_at(top.depth_in(&index_unit))? - shift,
+                label: top.name.clone(),
+                color: hex_to_rgb(&top_color(&top.name, &BTreeMap::new())),
+            })
+        })
+        .collect();
+    Ok((well, tops, keys))
+}
+
+impl Correlation {
+    /// `wells` - (имя файла, разобранный файл с кровлями и кривыми траектории);
+    /// `aliases` - для поиска кривых по синонимам, `layout_aliases` - раскладка по колонкам, как в [`LogView`]
+    pub fn build(
+        wells: Vec<(String, LasFile)>,
+        spec: &CorrelationSpec,
+        colors: &[String],
+        aliases: &AliasDictionary,
+        layout_aliases: Option<&AliasDictionary>,
+        top_colors: &BTreeMap<String, String>,
+    ) -> Result<Self> {
+        let mut prepared = Vec::new();
+        for (file_name, las_file) in wells {
+            let title = las_file
+                .get_well_item("WELL")
+                .map(|item| item.value.trim().to_string())
+                .filter(|name| !name.is_empty())
+                .unwrap_or_else(|| file_name.clone());
+            let (well, tops, keys) =
+                prepare_well(&las_file, &title, spec, aliases).with_context(|| file_name.clone())?;
+            let mut view = LogView::with_aliases(&well, None, colors, layout_aliases).with_context(|| file_name.clone())?;
+            view.tops = tops;
+            let view = view.with_top_colors(top_colors);
+            prepared.push((CorrelationWell { title, file_name, view }, keys));
+        }
+
+        // Общие шкалы одноимённых кривых: объединение диапазонов (раздельно для линейных и логарифмических)
+        let curve_key = |keys: &HashMap<String, String>, view: &LogView, i: usize| {
+            let mnemonic = view.curves_data[i].1.to_uppercase();
+            (keys.get(&mnemonic).cloned().unwrap_or(mnemonic), view.scale_types[i] == ScaleType::Logarithmic)
+        };
+        let mut ranges: HashMap<(String, bool), (f64, f64)> = HashMap::new();
+        for (well, keys) in &prepared {
+            for (i, &(a, b)) in well.view.x_ranges.iter().enumerate() {
+                let range = ranges.entry(curve_key(keys, &well.view, i)).or_insert((a.min(b), a.max(b)));
+                *range = (range.0.min(a.min(b)), range.1.max(a.max(b)));
+            }
+        }
+
+        // Общая ось: от самой мелкой до самой глубокой скважины, шаг - самый крупный из шагов,
+        // чтобы линии скважин с редкими отсчётами не разрывались
+        let depth_min = prepared.iter().map(|(w, _)| w.view.depth_min).fold(f64::INFINITY, f64::min);
+        let depth_max = prepared.iter().map(|(w, _)| w.view.depth_max).fold(f64::NEG_INFINITY, f64::max);
+        let depth_step = prepared.iter().map(|(w, _)| w.view.depth_step).fold(0.0, f64::max);
+
+        let mut wells = Vec::new();
+        for (mut well, keys) in prepared {
+            for i in 0..well.view.x_ranges.len() {
+                let (lo, hi) = ranges[&curve_key(&keys, &well.view, i)];
+                let (a, b) = well.view.x_ranges[i];
+                well.view.x_ranges[i] = if a > b { (hi, lo) } else { (lo, hi) };
+            }
+            well.view.depth_min = depth_min;
+            well.view.depth_max = depth_max;
+            well.view.depth_step = depth_step;
+            wells.push(well);
+        }
+        if wells.is_empty() {
+            bail!("No wells to correlate");
+        }
+
+        Ok(Correlation { axis: spec.axis, datum: spec.datum.clone(), wells })
+    }
+
+    /// Подпись оси: "Depth", "TVDSS" или отсчёт от кровли
+    pub fn axis_label(&self) -> String {
+        match &self.datum {
+            Some(datum) => format!("{} relative to {}", self.axis.name(), datum),
+            None => self.axis.name().to_string(),
+        }
+    }
+}
+
+/// Ячейка с линиями одноимённых кровель между соседними скважинами в строке, начинающейся с `row_top`
+fn connector_cell(left: &LogView, right: &LogView, layout: &PageLayout, row_top: f64) -> String {
+    let height = layout.block_height();
+    let y = |depth: f64| (depth - row_top) / left.depth_step * layout.pixels_per_step as f64;
+    let mut lines = String::new();
+    for top in &left.tops {
+        let Some(other) = right.tops.iter().find(|t| t.label.trim().eq_ignore_ascii_case(top.label.trim())) else {
+            continue;
+        };
+        let (y1, y2) = (y(top.depth), y(other.depth));
+        if y1.max(y2) < 0.0 || y1.min(y2) > height as f64 {
+            continue;
+        }
+        let [r, g, b] = top.color;
+        lines.push_str(&format!(
+            "<line x1='0' y1='{:.1}' x2='{}' y2='{:.1}' stroke='#{:02X}{:02X}{:02X}' stroke-width='1.5'/>",
+            y1, CONNECTOR_WIDTH, y2, r, g, b
+        ));
+    }
+    format!(
+        "<td style='padding: 0; margin: 0; border: 1px solid #ccc; vertical-align: top;'><svg width='{}' height='{}' style='display: block;'>{}</svg></td>",
+        CONNECTOR_WIDTH, height, lines
+    )
+}
+
+/// Пустая ячейка колонки кровель (в строках заголовка и шкал)
+fn empty_connector_cell() -> String {
+    format!("<td style='padding: 0; margin: 0; border: 1px solid #ccc; width: {}px;'></td>", CONNECTOR_WIDTH)
+}
+
+/// Строка таблицы: картинки всех скважин на одном интервале оси и линии кровель между ними
+fn correlation_row_html(correlation: &Correlation, layout: &PageLayout, row_idx: usize) -> Result<String> {
+    let (_, _, row_top) = correlation.wells[0].view.row_bounds(layout, row_idx);
+    let row_height = layout.block_height();
+    let mut cells = String::new();
+    if layout.separate_depth_column {
+        cells.push_str(&format!(
+            "<td valign='top' style='padding: 0; margin: 0; border: 1px solid #ccc;'>{:.2}</td>",
+            row_top
+        ));
+    }
+    for (i, well) in correlation.wells.iter().enumerate() {
+        if i > 0 {
+            cells.push_str(&connector_cell(&correlation.wells[i - 1].view, &well.view, layout, row_top));
+        }
+        let (start, end, _) = well.view.row_bounds(layout, row_idx);
+        let png_data = generate_plot_png(
+            &well.view.row_plot_config(layout, row_idx),
+            &well.view.curves_data,
+            &well.view.depth_data,
+            start,
+            end,
+        )?;
+        let img = format!(
+            "<img src='data:image/png;base64,{}' alt='Plot' width='{}' height='{}' style='display: block; margin: 0; padding: 0;'>",
+            base64::engine::general_purpose::STANDARD.encode(&png_data),
+            layout.image_width,
+            row_height
+        );
+        // Без отдельной колонки глубина подписывается на картинке первой скважины
+        let content = if i == 0 && !layout.separate_depth_column {
+            format!(
+                "<div style='position:relative; margin: 0; padding: 0;'><div style='position:absolute;left:5px;top:5px'>{:.2}</div>{}</div>",
+                row_top, img
+            )
+        } else {
+            img
+        };
+        cells.push_str(&format!(
+            "<td style='padding: 0; margin: 0; border: 1px solid #ccc; vertical-align: top;'>{}</td>",
+            content
+        ));
+    }
+    Ok(format!(
+        "<tr id='row-{}' height='{}' style='vertical-align: top; margin: 0; padding: 0;'>{}</tr>\n",
+        row_idx, row_height, cells
+    ))
+}
+
+/// Строки заголовков скважин и шкал
+fn header_rows_html(correlation: &Correlation, layout: &PageLayout) -> Result<String> {
+    let depth_cell = if layout.separate_depth_column {
+        "<td style='padding: 0; margin: 0; border: 1px solid #ccc;'></td>"
+    } else {
+        ""
+    };
+    let mut titles = String::from(depth_cell);
+    let mut scales = String::from(depth_cell);
+    let mut scale_height = 0;
+    for (i, well) in correlation.wells.iter().enumerate() {
+        if i > 0 {
+            titles.push_str(&empty_connector_cell());
+            scales.push_str(&empty_connector_cell());
+        }
+        titles.push_str(&format!(
+            "<th style='padding: 5px; border: 1px solid #ccc;'>{}<br><span style='font-weight: normal; font-size: 0.9em; color: #666;'>{}</span></th>",
+            escape_html(&well.title),
+            escape_html(&well.file_name)
+        ));
+        let scale_png = encode_png(&well.view.render_scale(layout)?)?;
+        let height = well.view.scale_height(layout);
+        scale_height = scale_height.max(height);
+        scales.push_str(&format!(
+            "<td style='padding: 0; margin: 0; border: 1px solid #ccc; vertical-align: top;'><img src='data:image/png;base64,{}' alt='Scales' width='{}' height='{}' style='display: block; margin: 0; padding: 0;'></td>",
+            base64::engine::general_purpose::STANDARD.encode(&scale_png),
+            layout.image_width,
+            height
+        ));
+    }
+    Ok(format!(
+        "<tr>{}</tr>\n<tr height='{}' style='vertical-align: top; margin: 0; padding: 0;'>{}</tr>\n",
+        titles, scale_height, scales
+    ))
+}
+
+/// Таблица кровель: строка на кровлю, колонка на скважину; значение - ссылка на строку с кровлей
+fn tops_table_html(correlation: &Correlation, layout: &PageLayout) -> String {
+    let mut names: Vec<(&str, [u8; 3])> = Vec::new();
+    for well in &correlation.wells {
+        for top in &well.view.tops {
+            if !names.iter().any(|(name, _)| name.trim().eq_ignore_ascii_case(top.label.trim())) {
+                names.push((&top.label, top.color));
+            }
+        }
+    }
+    if names.is_empty() {
+        return String::new();
+    }
+
+    let mut html = String::from("<table border='1' cellpadding='5' style='border-collapse: collapse; border: 1px solid #ccc; font-family: monospace; margin-bottom: 15px;'>\n<tr><th></th><th>Top</th>");
+    for well in &correlation.wells {
+        html.push_str(&format!("<th>{}</th>", escape_html(&well.title)));
+    }
+    html.push_str("</tr>\n");
+    for (name, [r, g, b]) in names {
+        html.push_str(&format!(
+            "<tr><td style='background-color: #{:02X}{:02X}{:02X}; width: 1em;'></td><td>{}</td>",
+            r, g, b, escape_html(name)
+        ));
+        for well in &correlation.wells {
+            let top = well.view.tops.iter().find(|t| t.label.trim().eq_ignore_ascii_case(name.trim()));
+            let cell = match top.map(|t| (t.depth, well.view.row_of(layout, t.depth))) {
+                Some((depth, Some(row_idx))) => format!("<a href='#row-{}'>{:.2}</a>", row_idx, depth),
+                Some((depth, None)) => format!("{:.2}", depth),
+                None => String::new(),
+            };
+            html.push_str(&format!("<td style='text-align: right;'>{}</td>", cell));
+        }
+        html.push_str("</tr>\n");
+    }
+    html.push_str("</table>\n");
+    html
+}
+
+/// HTML страница корреляции, отдаваемая потоком, как и [`crate::view::generate_html`]:
+/// заголовок и таблица кровель сразу, затем шкалы и строки по мере отрисовки в `pool`
+pub fn generate_html(
+    correlation: Correlation,
+    layout: PageLayout,
+    pool: &RenderPool,
+) -> Result<impl Stream<Item = Result<Bytes>> + 'static> {
+    // Строки общие для всех скважин: интервал показывается, если в нём есть отсчёты хоть одной
+    let mut cells: Vec<usize> = correlation.wells.iter().flat_map(|w| w.view.occupied_cells(&layout)).collect();
+    cells.sort_unstable();
+    cells.dedup();
+    let rows = LogView::rows_from_cells(&cells);
+    for well in &correlation.wells {
+        well.view.share_rows(&layout, rows.clone());
+    }
+
+    let correlation = Arc::new(correlation);
+    let layout = Arc::new(layout);
+    let num_rows = correlation.wells[0].view.row_count(&layout);
+    let rows_in_flight = pool.threads();
+
+    let mut html_before = String::new();
+    let titles: Vec<String> = correlation.wells.iter().map(|w| escape_html(&w.title)).collect();
+    html_before.push_str(&format!(
+        "<html><head><meta charset='utf-8'><title>LAS Correlation - {}</title></head><body>\n",
+        titles.join(", ")
+    ));
+    html_before.push_str(&format!("<h2>LAS Correlation - {}</h2>\n", titles.join(", ")));
+    html_before.push_str("<p style='font-size: 0.9em; color: #666; margin-top: 5px; margin-bottom: 15px;'>Free for non-commercial use. License: <a href='https://github.com/shestero/lasplot/blob/main/LICENSE' target='_blank'>https://github.com/shestero/lasplot/blob/main/LICENSE</a></p>\n");
+    html_before.push_str(&format!("<p style='font-family: monospace;'>Axis: {}</p>\n", escape_html(&correlation.axis_label())));
+    html_before.push_str(&tops_table_html(&correlation, &layout));
+    html_before.push_str("<table border='0' cellspacing='0' cellpadding='0' style='border-collapse: collapse; border-spacing: 0; margin: 0; padding: 0; font-family: monospace;'>\n");
+
+    let header = {
+        let correlation = correlation.clone();
+        let layout = layout.clone();
+        pool.run(move || header_rows_html(&correlation, &layout))
+    };
+
+    let pool = pool.clone();
+    let rows = stream::iter(0..num_rows)
+        .map(move |row_idx| {
+            let correlation = correlation.clone();
+            let layout = layout.clone();
+            pool.run(move || correlation_row_html(&correlation, &layout, row_idx))
+        })
+        .buffered(rows_in_flight)
+        .map(|res| res.map(Bytes::from));
+
+    let before = once(ok::<_, anyhow::Error>(Bytes::from(html_before)));
+    let header = once(header).map(|res| res.map(Bytes::from));
+    let after = once(ok::<_, anyhow::Error>(Bytes::from("</table>\n</body></html>\n")));
+
+    Ok(before.chain(header).chain(rows).chain(after))
+}
@@ -0,0 +1,528 @@
+//! Производные кривые по формулам: `RATIO = ILD / MSFL`, `GR_N.V/V = (GR - 20) / (150 - 20)`,
+//! `PAY = PHIND > 0.1 && SW < 0.5`.
+//!
+//! Выражения разбираются в дерево и вычисляются построчно, без доступа к чему-либо кроме
+//! кривых файла. Арифметика (`+ - * / ^`), сравнения (`< <= > >= == !=`), логика (`&& || !`,
+//! истина - 1, ложь - 0) и функции `log10`, `ln`, `exp`, `sqrt`, `abs`, `min`, `max`,
+//! `clamp(x, lo, hi)`, `if(условие, да, нет)`. Пропуск в любом операнде даёт пропуск,
+//! как и деление на ноль или логарифм неположительного числа.
+
+use crate::las::{CurveInfo, LasFile};
+use anyhow::{anyhow, bail, Result};
+
+/// Ограничения на формулу из запроса
+const MAX_DEFINITION_LEN: usize = 2000;
+const MAX_DEPTH: usize = 64;
+const MAX_DEFINITIONS: usize = 32;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Token {
+    Number(f64),
+    Ident(usize, usize),
+    Op(&'static str),
+    LParen,
+    RParen,
+    Comma,
+}
+
+/// Операторы, от длинных к коротким (чтобы `<=` не разобрался как `<`)
+const OPERATORS: &[&str] = &["&&", "||", "<=", ">=", "==", "!=", "+", "-", "*", "/", "^", "<", ">", "!"];
+
+/// Разбивает выражение на лексемы; идентификатор - позиции в исходной строке
+/// (обычная мнемоника или любая строка в обратных кавычках: `` `GR:1` ``)
+fn tokenize(source: &str) -> Result<Vec<Token>> {
+    let bytes = source.as_bytes();
+    let mut tokens = Vec::new();
+    let mut pos = 0;
+    while pos < bytes.len() {
+        let c = bytes[pos];
+        if c.is_ascii_whitespace() {
+            pos += 1;
+        } else if c.is_ascii_digit() || (c == b'.' && bytes.get(pos + 1).is_some_and(u8::is_ascii_digit)) {
+            let start = pos;
+            while pos < bytes.len() && (bytes[pos].is_ascii_digit() || bytes[pos] == b'.') {
+                pos += 1;
+            }
+            if pos < bytes.len() && (bytes[pos] == b'e' || bytes[pos] == b'E') {
+                let mut exp_end = pos + 1;
+                if exp_end < bytes.len() && (bytes[exp_end] == b'+' || bytes[exp_end] == b'-') {
+                    exp_end += 1;
+                }
+                if exp_end < bytes.len() && bytes[exp_end].is_ascii_digit() {
+                    pos = exp_end;
+                    while pos < bytes.len() && bytes[pos].is_ascii_digit() {
+                        pos += 1;
+                    }
+                }
+            }
+            let text = &source[start..pos];
+            let value = text.parse::<f64>().map_err(|_| anyhow!("Invalid number '{}'", text))?;
+            tokens.push(Token::Number(value));
+        } else if c.is_ascii_alphabetic() || c == b'_' {
+            let start = pos;
+            while pos < bytes.len() && (bytes[pos].is_ascii_alphanumeric() || bytes[pos] == b'_') {
+                pos += 1;
+            }
+            tokens.push(Token::Ident(start, pos));
+        } else if c == b'`' {
+            let start = pos + 1;
+            let end = source[start..]
+                .find('`')
+                .map(|len| start + len)
+                .ok_or_else(|| anyhow!("Unterminated `quoted` curve name"))?;
+            tokens.push(Token::Ident(start, end));
+            pos = end + 1;
+        } else if c == b'(' {
+            tokens.push(Token::LParen);
+            pos += 1;
+        } else if c == b')' {
+            tokens.push(Token::RParen);
+            pos += 1;
+        } else if c == b',' {
+            tokens.push(Token::Comma);
+            pos += 1;
+        } else if let Some(op) = OPERATORS.iter().find(|op| source[pos..].starts_with(**op)) {
+            tokens.push(Token::Op(op));
+            pos += op.len();
+        } else {
+            let ch = source[pos..].chars().next().unwrap_or('?');
+            bail!("Unexpected character '{}' at position {}", ch, pos + 1);
+        }
+    }
+    Ok(tokens)
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Function {
+    Log10,
+    Ln,
+    Exp,
+    Sqrt,
+    Abs,
+    Min,
+    Max,
+    Clamp,
+    If,
+}
+
+impl Function {
+    fn parse(name: &str) -> Option<Self> {
+        Some(match name.to_lowercase().as_str() {
+            "log10" => Function::Log10,
+            "ln" | "log" => Function::Ln,
+            "exp" => Function::Exp,
+            "sqrt" => Function::Sqrt,
+            "abs" => Function::Abs,
+            "min" => Function::Min,
+            "max" => Function::Max,
+            "clamp" => Function::Clamp,
+            "if" => Function::If,
+            _ => return None,
+        })
+    }
+
+    /// Допустимое количество аргументов
+    fn arity(&self) -> (usize, usize) {
+        match self {
+            Function::Min | Function::Max => (1, usize::MAX),
+            Function::Clamp | Function::If => (3, 3),
+            _ => (1, 1),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+enum Expr {
+    Number(f64),
+    /// Номер исходной кривой в [`DerivedCurve::sources`]
+    Curve(usize),
+    Neg(Box<Expr>),
+    Not(Box<Expr>),
+    Binary(&'static str, Box<Expr>, Box<Expr>),
+    Call(Function, Vec<Expr>),
+}
+
+/// Приоритет бинарного оператора (больше - связывает сильнее)
+fn precedence(op: &str) -> Option<u8> {
+    Some(match op {
+        "||" => 1,
+        "&&" => 2,
+        "==" | "!=" => 3,
+        "<" | "<=" | ">" | ">=" => 4,
+        "+" | "-" => 5,
+        "*" | "/" => 6,
+        "^" => 7,
+        _ => return None,
+    })
+}
+
+struct Parser<'a> {
+    source: &'a str,
+    tokens: Vec<Token>,
+    pos: usize,
+    sources: Vec<String>,
+}
+
+impl Parser<'_> {
+    fn peek(&self) -> Option<Token> {
+        self.tokens.get(self.pos).copied()
+    }
+
+    fn next(&mut self) -> Option<Token> {
+        let token = self.peek();
+        self.pos += 1;
+        token
+    }
+
+    fn expect(&mut self, expected: Token, what: &str) -> Result<()> {
+        match self.next() {
+            Some(token) if token == expected => Ok(()),
+            _ => bail!("Expected {}", what),
+        }
+    }
+
+    /// Бинарные операторы с приоритетом не ниже `min_precedence`; `^` - правоассоциативный
+    fn binary(&mut self, min_precedence: u8, depth: usize) -> Result<Expr> {
+        let mut left = self.unary(depth)?;
+        while let Some(Token::Op(op)) = self.peek() {
+            let Some(prec) = precedence(op).filter(|p| *p >= min_precedence) else {
+                break;
+            };
+            self.pos += 1;
+            let next_min = if op == "^" { prec } else { prec + 1 };
+            let right = self.binary(next_min, depth + 1)?;
+            left = Expr::Binary(op, Box::new(left), Box::new(right));
+        }
+        Ok(left)
+    }
+
+    fn unary(&mut self, depth: usize) -> Result<Expr> {
+        if depth > MAX_DEPTH {
+            bail!("Expression is nested too deeply");
+        }
+        match self.peek() {
+            Some(Token::Op("-")) => {
+                self.pos += 1;
+                // -x^2 = -(x^2)
+                Ok(Expr::Neg(Box::new(self.binary(7, depth + 1)?)))
+            }
+            Some(Token::Op("+")) => {
+                self.pos += 1;
+                self.binary(7, depth + 1)
+            }
+            Some(Token::Op("!")) => {
+                self.pos += 1;
+                Ok(Expr::Not(Box::new(self.unary(depth + 1)?)))
+            }
+            _ => self.primary(depth),
+        }
+    }
+
+    fn primary(&mut self, depth: usize) -> Result<Expr> {
+        match self.next() {
+            Some(Token::Number(value)) => Ok(Expr::Number(value)),
+            Some(Token::LParen) => {
+                let expr = self.binary(0, depth + 1)?;
+                self.expect(Token::RParen, "')'")?;
+                Ok(expr)
+            }
+            Some(Token::Ident(start, end)) => {
+                let name = &self.source[start..end];
+                if self.peek() == Some(Token::LParen) {
+                    let function = Function::parse(name).ok_or_else(|| anyhow!("Unknown function '{}'", name))?;
+                    self.pos += 1;
+                    let mut args = Vec::new();
+                    if self.peek() != Some(Token::RParen) {
+                        loop {
+                            args.push(self.binary(0, depth + 1)?);
+                            if self.peek() == Some(Token::Comma) {
+                                self.pos += 1;
+                            } else {
+                                break;
+                            }
+                        }
+                    }
+                    self.expect(Token::RParen, "')' after function arguments")?;
+                    let (min, max) = function.arity();
+                    if args.len() < min || args.len() > max {
+                        let expected = if min == max { min.to_string() } else { format!("at least {}", min) };
+                        bail!("Function '{}' takes {} arguments, got {}", name, expected, args.len());
+                    }
+                    return Ok(Expr::Call(function, args));
+                }
+                match name.to_lowercase().as_str() {
+                    "true" => return Ok(Expr::Number(1.0)),
+                    "false" => return Ok(Expr::Number(0.0)),
+                    _ => {}
+                }
+                let slot = match self.sources.iter().position(|s| s.eq_ignore_ascii_case(name)) {
+                    Some(slot) => slot,
+                    None => {
+                        self.sources.push(name.to_string());
+                        self.sources.len() - 1
+                    }
+                };
+                Ok(Expr::Curve(slot))
+            }
+            Some(Token::Op(op)) => bail!("Unexpected operator '{}'", op),
+            Some(Token::RParen) => bail!("Unexpected ')'"),
+            Some(Token::Comma) => bail!("Unexpected ','"),
+            None => bail!("Unexpected end of expression"),
+        }
+    }
+}
+
+fn truth(value: bool) -> f64 {
+    if value {
+        1.0
+    } else {
+        0.0
+    }
+}
+
+impl Expr {
+    /// Значение в строке `row`; None - пропуск
+    fn eval(&self, columns: &[Vec<Option<f64>>], row: usize) -> Option<f64> {
+        let value = match self {
+            Expr::Number(value) => *value,
+            Expr::Curve(slot) => columns[*slot].get(row).copied().flatten()?,
+            Expr::Neg(expr) => -expr.eval(columns, row)?,
+            Expr::Not(expr) => truth(expr.eval(columns, row)? == 0.0),
+            Expr::Binary(op, left, right) => {
+                let (a, b) = (left.eval(columns, row)?, right.eval(columns, row)?);
+                match *op {
+                    "+" => a + b,
+                    "-" => a - b,
+                    "*" => a * b,
+                    "/" if b == 0.0 => return None,
+                    "/" => a / b,
+                    "^" => a.powf(b),
+                    "<" => truth(a < b),
+                    "<=" => truth(a <= b),
+                    ">" => truth(a > b),
+                    ">=" => truth(a >= b),
+                    "==" => truth(a == b),
+                    "!=" => truth(a != b),
+                    "&&" => truth(a != 0.0 && b != 0.0),
+                    "||" => truth(a != 0.0 || b != 0.0),
+                    _ => return None,
+                }
+            }
+            Expr::Call(Function::If, args) => {
+                // Вычисляется только выбранная ветка
+                let branch = if args[0].eval(columns, row)? != 0.0 { &args[1] } else { &args[2] };
+                branch.eval(columns, row)?
+            }
+            Expr::Call(function, args) => {
+                let values = args.iter().map(|arg| arg.eval(columns, row)).collect::<Option<Vec<f64>>>()?;
+                match function {
+                    Function::Log10 if values[0] > 0.0 => values[0].log10(),
+                    Function::Ln if values[0] > 0.0 => values[0].ln(),
+                    Function::Log10 | Function::Ln => return None,
+                    Function::Exp => values[0].exp(),
+                    Function::Sqrt if values[0] >= 0.0 => values[0].sqrt(),
+                    Function::Sqrt => return None,
+                    Function::Abs => values[0].abs(),
+                    Function::Min => values.iter().copied().fold(f64::INFINITY, f64::min),
+                    Function::Max => values.iter().copied().fold(f64::NEG_INFINITY, f64::max),
+                    Function::Clamp => values[0].max(values[1]).min(values[2]),
+                    Function::If => unreachable!("handled above"),
+                }
+            }
+        };
+        value.is_finite().then_some(value)
+    }
+}
+
+/// Определение производной кривой: `ИМЯ[.ЕДИНИЦА] = выражение`
+#[derive(Debug, Clone)]
+pub struct DerivedCurve {
+    pub mnemonic: String,
+    pub unit: String,
+    /// Текст выражения (попадает в описание кривой)
+    pub expression: String,
+    /// Мнемоники кривых, на которые ссылается выражение
+    pub sources: Vec<String>,
+    /// Определение из конфигурации: пропускается, если в файле нет исходных кривых
+    pub optional: bool,
+    expr: Expr,
+}
+
+impl DerivedCurve {
+    pub fn parse(definition: &str) -> Result<Self> {
+        if definition.len() > MAX_DEFINITION_LEN {
+            bail!("Derived curve definition is longer than {} characters", MAX_DEFINITION_LEN);
+        }
+        let (name, expression) = definition
+            .split_once('=')
+            .filter(|(_, rest)| !rest.starts_with('='))
+            .ok_or_else(|| anyhow!("Derived curve must be 'NAME = expression', got '{}'", definition.trim()))?;
+        let (mnemonic, unit) = name.trim().split_once('.').unwrap_or((name.trim(), ""));
+        let (mnemonic, unit, expression) = (mnemonic.trim(), unit.trim(), expression.trim());
+        if mnemonic.is_empty()
+            || !mnemonic.starts_with(|c: char| c.is_ascii_alphabetic() || c == '_')
+            || !mnemonic.chars().all(|c| c.is_ascii_alphanumeric() || c == '_')
+        {
+            bail!("Invalid derived curve name '{}' (letters, digits and '_')", mnemonic);
+        }
+
+        let mut parser = Parser { source: expression, tokens: tokenize(expression).map_err(|e| anyhow!("{}: {}", mnemonic, e))?, pos: 0, sources: Vec::new() };
+        let expr = parser.binary(0, 0).map_err(|e| anyhow!("{}: {}", mnemonic, e))?;
+        if parser.pos < parser.tokens.len() {
+            bail!("{}: unexpected input after the end of expression", mnemonic);
+        }
+
+        Ok(DerivedCurve {
+            mnemonic: mnemonic.to_string(),
+            unit: unit.to_string(),
+            expression: expression.to_string(),
+            sources: parser.sources,
+            optional: false,
+            expr,
+        })
+    }
+
+    /// Несколько определений через `;` (параметр `derive=`)
+    pub fn parse_list(definitions: &str) -> Result<Vec<Self>> {
+        let curves = definitions
+            .split(';')
+            .filter(|d| !d.trim().is_empty())
+            .map(DerivedCurve::parse)
+            .collect::<Result<Vec<_>>>()?;
+        if curves.len() > MAX_DEFINITIONS {
+            bail!("Too many derived curves: {} (at most {})", curves.len(), MAX_DEFINITIONS);
+        }
+        Ok(curves)
+    }
+}
+
+impl LasFile {
+    /// Добавляет производные кривые по порядку (формула может ссылаться на предыдущие);
+    /// возвращает индексы добавленных. Необязательные определения пропускаются,
+    /// если исходных кривых нет или кривая с таким именем уже есть.
+    pub fn add_derived(&mut self, curves: &[DerivedCurve]) -> Result<Vec<usize>> {
+        let mut added = Vec::new();
+        for curve in curves {
+            let exists = self.get_curve_index(&curve.mnemonic).is_some();
+            let missing: Vec<&str> = curve
+                .sources
+                .iter()
+                .filter(|name| self.get_curve_index(name).is_none())
+                .map(|name| name.as_str())
+                .collect();
+            if curve.optional && (exists || !missing.is_empty()) {
+                continue;
+            }
+            if exists {
+                bail!("Curve '{}' already exists in the file", curve.mnemonic);
+            }
+            if !missing.is_empty() {
+                bail!("{}: curve '{}' not found", curve.mnemonic, missing.join("', '"));
+            }
+
+            let source_indices: Vec<usize> = curve.sources.iter().filter_map(|name| self.get_curve_index(name)).collect();
+            let columns: Vec<Vec<Option<f64>>> = source_indices.iter().map(|&idx| self.get_curve_data(idx)).collect();
+            let null_value = self.null_value;
+            let curve_idx = self.curves.len();
+            for (row, data_row) in self.data.iter_mut().enumerate() {
+                // Короткие строки дополняются пропусками, чтобы значение попало в свою колонку
+                data_row.values.resize(curve_idx, null_value);
+                data_row.values.push(curve.expr.eval(&columns, row).unwrap_or(null_value));
+            }
+            self.curves.push(CurveInfo {
+                mnemonic: curve.mnemonic.clone(),
+                unit: curve.unit.clone(),
+                description: format!("= {}", curve.expression),
+                api_codes: None,
+            });
+            added.push(curve_idx);
+        }
+        Ok(added)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Значение выражения при заданных значениях кривых
+    fn eval(expression: &str, curves: &[(&str, Option<f64>)]) -> Option<f64> {
+        let curve = DerivedCurve::parse(&format!("X = {}", expression)).unwrap();
+        let columns: Vec<Vec<Option<f64>>> = curve
+            .sources
+            .iter()
+            .map(|name| vec![curves.iter().find(|(n, _)| n.eq_ignore_ascii_case(name)).unwrap().1])
+            .collect();
+        curve.expr.eval(&columns, 0)
+    }
+
+    #[test]
+    fn operators_follow_precedence() {
+        assert_eq!(eval("1 + 2 * 3", &[]), Some(7.0));
+        assert_eq!(eval("(1 + 2) * 3", &[]), Some(9.0));
+        assert_eq!(eval("10 - 4 - 3", &[]), Some(3.0));
+        assert_eq!(eval("2 ^ 3 ^ 2", &[]), Some(512.0));
+        assert_eq!(eval("1 + 2 > 2 && 0 || 1", &[]), Some(1.0));
+        assert_eq!(eval("1 < 2 == 1", &[]), Some(1.0));
+    }
+
+    #[test]
+    fn unary_minus_binds_looser_than_power() {
+        assert_eq!(eval("-2 ^ 2", &[]), Some(-4.0));
+        assert_eq!(eval("(-2) ^ 2", &[]), Some(4.0));
+        assert_eq!(eval("3 - -2", &[]), Some(5.0));
+        assert_eq!(eval("-X * 2", &[("X", Some(1.5))]), Some(-3.0));
+        assert_eq!(eval("!0 + !5", &[]), Some(1.0));
+    }
+
+    #[test]
+    fn functions_check_domain_and_arity() {
+        assert_eq!(eval("log10(1000)", &[]), Some(3.0));
+        assert_eq!(eval("sqrt(abs(-16))", &[]), Some(4.0));
+        assert_eq!(eval("min(3, 1, 2) + max(3, 1, 2)", &[]), Some(4.0));
+        assert_eq!(eval("clamp(1.5, 0, 1)", &[]), Some(1.0));
+        assert_eq!(eval("if(GR > 75, 1, 0)", &[("GR", Some(80.0))]), Some(1.0));
+        assert_eq!(eval("ln(0)", &[]), None);
+        assert_eq!(eval("sqrt(-1)", &[]), None);
+        assert!(DerivedCurve::parse("X = clamp(1, 2)").is_err());
+        assert!(DerivedCurve::parse("X = nosuch(1)").is_err());
+    }
+
+    #[test]
+    fn missing_values_propagate() {
+        assert_eq!(eval("GR * 2 + 1", &[("GR", None)]), None);
+        assert_eq!(eval("ILD / MSFL", &[("ILD", Some(10.0)), ("MSFL", Some(0.0))]), None);
+        assert_eq!(eval("max(GR, 1)", &[("GR", None)]), None);
+        // Пропуск в невыбранной ветке if не мешает
+        assert_eq!(eval("if(1, 2, GR)", &[("GR", None)]), Some(2.0));
+    }
+
+    #[test]
+    fn sources_are_collected_once() {
+        let curve = DerivedCurve::parse("RATIO.V/V = ILD / msfl + `ild` - `GR:1`").unwrap();
+        assert_eq!((curve.mnemonic.as_str(), curve.unit.as_str()), ("RATIO", "V/V"));
+        assert_eq!(curve.sources, ["ILD", "msfl", "GR:1"]);
+    }
+
+    #[test]
+    fn limits_reject_oversized_input() {
+        let nested = format!("X = {}1{}", "(".repeat(MAX_DEPTH + 1), ")".repeat(MAX_DEPTH + 1));
+        assert!(DerivedCurve::parse(&nested).is_err());
+        let negations = format!("X = {}1", "-".repeat(MAX_DEPTH + 1));
+        assert!(DerivedCurve::parse(&negations).is_err());
+        let shallow = format!("X = {}1{}", "(".repeat(10), ")".repeat(10));
+        assert!(DerivedCurve::parse(&shallow).is_ok());
+
+        let long = format!("X = {}", "1+".repeat(MAX_DEFINITION_LEN / 2) + "1");
+        assert!(DerivedCurve::parse(&long).is_err());
+        let many = vec!["A = 1"; MAX_DEFINITIONS + 1].join("; ");
+        assert!(DerivedCurve::parse_list(&many).is_err());
+    }
+
+    #[test]
+    fn malformed_definitions_are_rejected() {
+        for definition in ["GR", "= 1", "1X = 2", "X = 1 +", "X = (1", "X = 1 2", "X = `GR", "X == 1"] {
+            assert!(DerivedCurve::parse(definition).is_err(), "{}", definition);
+        }
+    }
+}
@@ -0,0 +1,357 @@
+//! Правка кривых: сдвиг по глубине, растяжение между точками привязки, обнуление интервала
+//! и удаление выбросов медианным фильтром.
+//!
+//! Правки задаются упорядоченным списком JSON (параметр `edits=`) и применяются к исходному
+//! файлу при каждой загрузке, сам файл не меняется. Применённые правки дописываются в секцию
+//! ~Other, так что выгруженный LAS несёт историю, по которой её можно повторить:
+//!
+//! ```json
+//! [{"op": "shift", "curves": ["GR"], "shift": 1.5},
+//!  {"op": "stretch", "curves": ["RHOB", "NPHI"], "ties": [[1012.0, 1012.4], [1080.0, 1081.1]]},
+//!  {"op": "null_interval", "top": 1050.0, "bottom": 1054.5},
+//!  {"op": "despike", "curves": ["GR"], "window": 5}]
+//! ```
+//!
+//! Пустой или отсутствующий `curves` означает все кривые, кроме индексной.
+
+use crate::las::LasFile;
+use crate::resample::interpolate_samples;
+use anyhow::{bail, Context, Result};
+use serde::{Deserialize, Serialize};
+
+/// Предел длины списка правок
+const MAX_EDITS: usize = 100;
+/// Предел окна медианного фильтра, отсчётов
+const MAX_WINDOW: usize = 101;
+/// Коэффициент перевода MAD в стандартное отклонение для нормального распределения
+const MAD_TO_SIGMA: f64 = 1.4826;
+/// Порог выброса по умолчанию, в стандартных отклонениях остатков
+const DEFAULT_SPIKE_SIGMAS: f64 = 3.0;
+
+fn default_window() -> usize {
+    5
+}
+
+/// Одна правка
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "op", rename_all = "snake_case", deny_unknown_fields)]
+pub enum Edit {
+    /// Сдвиг кривых вниз на `shift` (вверх при отрицательном), в единицах индекса
+    Shift {
+        #[serde(default, skip_serializing_if = "Vec::is_empty")]
+        curves: Vec<String>,
+        shift: f64,
+    },
+    /// Кусочно-линейное растяжение: глубина `from` каждой пары переходит в `to`;
+    /// выше первой и ниже последней пары - сдвиг, как у крайней пары
+    Stretch {
+        #[serde(default, skip_serializing_if = "Vec::is_empty")]
+        curves: Vec<String>,
+        ties: Vec<(f64, f64)>,
+    },
+    /// Пропуск вместо значений в интервале `top`..`bottom` включительно
+    NullInterval {
+        #[serde(default, skip_serializing_if = "Vec::is_empty")]
+        curves: Vec<String>,
+        top: f64,
+        bottom: f64,
+    },
+    /// Замена выбросов медианой окна `window` отсчётов; выброс - отклонение от медианы больше
+    /// `threshold` (по умолчанию три стандартных отклонения остатков, оценённых по MAD; если
+    /// MAD нулевой, как у почти постоянной кривой, без `threshold` кривая не меняется)
+    Despike {
+        #[serde(default, skip_serializing_if = "Vec::is_empty")]
+        curves: Vec<String>,
+        #[serde(default = "default_window")]
+        window: usize,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        threshold: Option<f64>,
+    },
+}
+
+impl Edit {
+    /// Список правок из JSON-массива
+    pub fn parse_list(json: &str) -> Result<Vec<Edit>> {
+        let edits: Vec<Edit> = serde_json::from_str(json).context("Invalid edit list")?;
+        if edits.len() > MAX_EDITS {
+            bail!("Too many edits: {} (at most {})", edits.len(), MAX_EDITS);
+        }
+        for (n, edit) in edits.iter().enumerate() {
+            edit.validate().with_context(|| format!("edit {}", n + 1))?;
+        }
+        Ok(edits)
+    }
+
+    fn validate(&self) -> Result<()> {
+        match self {
+            Edit::Shift { shift, .. } => {
+                if !shift.is_finite() {
+                    bail!("Shift must be a finite number");
+                }
+            }
+            Edit::Stretch { ties, .. } => {
+                if ties.is_empty() {
+                    bail!("Stretch needs at least one tie point");
+                }
+                if ties.iter().any(|(from, to)| !from.is_finite() || !to.is_finite()) {
+                    bail!("Tie points must be finite numbers");
+                }
+                if ties.windows(2).any(|w| w[1].0 <= w[0].0 || w[1].1 <= w[0].1) {
+                    bail!("Tie points must increase in both 'from' and 'to' depths");
+                }
+            }
+            Edit::NullInterval { top, bottom, .. } => {
+                if !top.is_finite() || !bottom.is_finite() || top > bottom {
+                    bail!("Interval top must not be below its bottom");
+                }
+            }
+            Edit::Despike { window, threshold, .. } => {
+                if *window < 3 || *window > MAX_WINDOW || window % 2 == 0 {
+                    bail!("Despike window must be an odd number from 3 to {}", MAX_WINDOW);
+                }
+                if threshold.is_some_and(|t| !t.is_finite() || t <= 0.0) {
+                    bail!("Despike threshold must be a positive number");
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn curves(&self) -> &[String] {
+        match self {
+            Edit::Shift { curves, .. }
+            | Edit::Stretch { curves, .. }
+            | Edit::NullInterval { curves, .. }
+            | Edit::Despike { curves, .. } => curves,
+        }
+    }
+
+    /// Описание для истории правок, `unit` - единица индекса
+    pub fn describe(&self, unit: &str) -> String {
+        let curves = if self.curves().is_empty() { "all curves".to_string() } else { self.curves().join(", ") };
+        match self {
+            Edit::Shift { shift, .. } => format!("shift {} by {:+} {}", curves, shift, unit),
+            Edit::Stretch { ties, .. } => {
+                let ties: Vec<String> = ties.iter().map(|(from, to)| format!("{} -> {}", from, to)).collect();
+                format!("stretch {} through {} ({})", curves, ties.join(", "), unit)
+            }
+            Edit::NullInterval { top, bottom, .. } => format!("null {} from {} to {} {}", curves, top, bottom, unit),
+            Edit::Despike { window, threshold, .. } => match threshold {
+                Some(threshold) => format!("despike {} (window {}, threshold {})", curves, window, threshold),
+                None => format!("despike {} (window {})", curves, window),
+            },
+        }
+    }
+}
+
+/// Кусочно-линейное отображение глубины через точки привязки
+fn map_depth(ties: &[(f64, f64)], depth: f64) -> f64 {
+    let pos = ties.partition_point(|t| t.0 < depth);
+    if pos == 0 {
+        return depth + (ties[0].1 - ties[0].0);
+    }
+    if pos == ties.len() {
+        let (from, to) = ties[ties.len() - 1];
+        return depth + (to - from);
+    }
+    let (a, b) = (ties[pos - 1], ties[pos]);
+    a.1 + (depth - a.0) * (b.1 - a.1) / (b.0 - a.0)
+}
+
+fn median(values: &mut [f64]) -> f64 {
+    let mid = values.len() / 2;
+    let (_, median, _) = values.select_nth_unstable_by(mid, f64::total_cmp);
+    *median
+}
+
+/// Медианный фильтр по непустым отсчётам: выбросы заменяются медианой окна.
+/// При нулевом MAD порог по умолчанию был бы нулевым и выбросом считался бы любой шум округления,
+/// поэтому такая кривая остаётся как есть
+fn despike(column: &[Option<f64>], window: usize, threshold: Option<f64>) -> Vec<Option<f64>> {
+    let present: Vec<(usize, f64)> = column.iter().enumerate().filter_map(|(row, v)| v.map(|v| (row, v))).collect();
+    let half = window / 2;
+    let medians: Vec<f64> = (0..present.len())
+        .map(|i| {
+            let range = i.saturating_sub(half)..(i + half + 1).min(present.len());
+            let mut values: Vec<f64> = present[range].iter().map(|p| p.1).collect();
+            median(&mut values)
+        })
+        .collect();
+    let residuals: Vec<f64> = present.iter().zip(&medians).map(|(p, m)| (p.1 - m).abs()).collect();
+    let threshold = match threshold {
+        Some(threshold) => threshold,
+        None if residuals.is_empty() => return column.to_vec(),
+        None => match median(&mut residuals.clone()) {
+            0.0 => return column.to_vec(),
+            mad => DEFAULT_SPIKE_SIGMAS * MAD_TO_SIGMA * mad,
+        },
+    };
+
+    let mut result = column.to_vec();
+    for ((&(row, _), &median), &residual) in present.iter().zip(&medians).zip(&residuals) {
+        if residual > threshold {
+            result[row] = Some(median);
+        }
+    }
+    result
+}
+
+impl LasFile {
+    fn edit_targets(&self, curves: &[String]) -> Result<Vec<usize>> {
+        if curves.is_empty() {
+            return Ok((1..self.curves.len()).collect());
+        }
+        curves
+            .iter()
+            .map(|name| match self.get_curve_index(name) {
+                Some(0) => bail!("The index curve '{}' cannot be edited", name),
+                Some(idx) => Ok(idx),
+                None => bail!("Curve '{}' not found", name),
+            })
+            .collect()
+    }
+
+    fn set_curve_data(&mut self, curve_idx: usize, column: &[Option<f64>]) {
+        let null_value = self.null_value;
+        for (row, value) in self.data.iter_mut().zip(column) {
+            if row.values.len() <= curve_idx {
+                row.values.resize(curve_idx + 1, null_value);
+            }
+            row.values[curve_idx] = value.unwrap_or(null_value);
+        }
+    }
+
+    /// Применяет одну правка к данным файла
+    pub fn apply_edit(&mut self, edit: &Edit) -> Result<()> {
+        edit.validate()?;
+        let targets = self.edit_targets(edit.curves())?;
+        let index = self.get_curve_data(0);
+        for curve_idx in targets {
+            let column = self.get_curve_data(curve_idx);
+            let edited = match edit {
+                Edit::Shift { shift, .. } => shift_column(&index, &column, |depth| depth + shift),
+                Edit::Stretch { ties, .. } => shift_column(&index, &column, |depth| map_depth(ties, depth)),
+                Edit::NullInterval { top, bottom, .. } => index
+                    .iter()
+                    .zip(&column)
+                    .map(|(depth, value)| if depth.is_some_and(|d| d >= *top && d <= *bottom) { None } else { *value })
+                    .collect(),
+                Edit::Despike { window, threshold, .. } => despike(&column, *window, *threshold),
+            };
+            self.set_curve_data(curve_idx, &edited);
+        }
+        Ok(())
+    }
+
+    /// Применяет правки по порядку и дописывает их в ~Other как историю, которую можно
+    /// повторить через `edits=`
+    pub fn apply_edits(&mut self, edits: &[Edit]) -> Result<()> {
+        if edits.is_empty() {
+            return Ok(());
+        }
+        for (n, edit) in edits.iter().enumerate() {
+            self.apply_edit(edit).with_context(|| format!("edit {}", n + 1))?;
+        }
+
+        let unit = self.curves.first().map(|c| c.unit.clone()).unwrap_or_default();
+        if !self.other.is_empty() {
+            self.other.push(String::new());
+        }
+        self.other.push("Edit history (replay with edits=):".to_string());
+        for (n, edit) in edits.iter().enumerate() {
+            let json = serde_json::to_string(edit).unwrap_or_default();
+            self.other.push(format!("{}. {}  {}", n + 1, edit.describe(&unit), json));
+        }
+        Ok(())
+    }
+}
+
+/// Переносит отсчёты кривой на глубины `map(индекс)` и снимает значения в узлах исходного индекса
+fn shift_column(index: &[Option<f64>], column: &[Option<f64>], map: impl Fn(f64) -> f64) -> Vec<Option<f64>> {
+    let samples: Vec<(f64, f64)> = index
+        .iter()
+        .zip(column)
+        .filter_map(|(depth, value)| Some((map((*depth)?), (*value)?)))
+        .collect();
+    interpolate_samples(samples, index)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// DEPT 100..110 с шагом 1, GR = 10 * (DEPT - 100)
+    fn sample_file() -> LasFile {
+        let mut content = String::from(
+            "~Version Information\n VERS.  2.0:\n~Well Information\n NULL.  -999.25:\n~Curve Information\n DEPT.M  :  DEPTH\n GR.GAPI  :  GAMMA\n~ASCII\n",
+        );
+        for i in 0..=10 {
+            content.push_str(&format!("{} {}\n", 100 + i, 10 * i));
+        }
+        LasFile::parse(&content).unwrap()
+    }
+
+    fn edited(edit: &str) -> Vec<Option<f64>> {
+        let mut las_file = sample_file();
+        las_file.apply_edits(&Edit::parse_list(edit).unwrap()).unwrap();
+        las_file.get_curve_data(1)
+    }
+
+    #[test]
+    fn shift_moves_samples_down() {
+        let gr = edited(r#"[{"op": "shift", "shift": 1.5}]"#);
+        // Отсчёты переезжают на 101.5, 102.5, ... и снимаются в узлах исходного индекса
+        assert_eq!(gr[..3], [None, None, Some(5.0)]);
+        assert_eq!(gr[10], Some(85.0));
+    }
+
+    #[test]
+    fn stretch_maps_ties_and_shifts_outside() {
+        // 102 -> 102, 108 -> 105: между точками сжатие вдвое, ниже - сдвиг на -3
+        let gr = edited(r#"[{"op": "stretch", "curves": ["GR"], "ties": [[102, 102], [108, 105]]}]"#);
+        assert_eq!(gr[2], Some(20.0));
+        assert_eq!(gr[3], Some(40.0));
+        assert_eq!(gr[5], Some(80.0));
+        assert_eq!(gr[7], Some(100.0));
+        assert_eq!(gr[8], None);
+    }
+
+    #[test]
+    fn null_interval_clears_inclusive_range() {
+        let gr = edited(r#"[{"op": "null_interval", "top": 102, "bottom": 104}]"#);
+        assert_eq!(gr[1..6], [Some(10.0), None, None, None, Some(50.0)]);
+    }
+
+    #[test]
+    fn despike_replaces_outliers_with_window_median() {
+        let column = vec![Some(1.0), Some(2.0), Some(1.5), Some(50.0), Some(2.5), Some(1.0), Some(2.0), None, Some(1.5)];
+        let mut expected = column.clone();
+        expected[3] = Some(2.5);
+        assert_eq!(despike(&column, 3, None), expected);
+        // Явный порог выше выброса - ничего не меняется
+        assert_eq!(despike(&column, 3, Some(100.0)), column);
+    }
+
+    #[test]
+    fn despike_keeps_flat_curve_with_rounding_noise() {
+        let column: Vec<Option<f64>> = (0..20).map(|i| Some(if i == 7 { 50.000_000_1 } else { 50.0 })).collect();
+        assert_eq!(despike(&column, 5, None), column);
+        // С явным порогом шум всё же можно убрать
+        assert_eq!(despike(&column, 5, Some(1e-9))[7], Some(50.0));
+    }
+
+    #[test]
+    fn invalid_edits_are_rejected() {
+        for json in [
+            r#"[{"op": "shift", "shift": 1, "extra": 2}]"#,
+            r#"[{"op": "stretch", "ties": [[100, 100], [99, 101]]}]"#,
+            r#"[{"op": "null_interval", "top": 5, "bottom": 1}]"#,
+            r#"[{"op": "despike", "window": 4}]"#,
+        ] {
+            assert!(Edit::parse_list(json).is_err(), "{}", json);
+        }
+        let mut las_file = sample_file();
+        let edits = Edit::parse_list(r#"[{"op": "shift", "curves": ["DEPT"], "shift": 1}]"#).unwrap();
+        assert!(las_file.apply_edits(&edits).is_err());
+    }
+}
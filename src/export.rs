@@ -0,0 +1,271 @@
+//! Выгрузка данных `/export`: CSV, TSV (открывается в Excel), JSON, LAS 2.0,
+//! Arrow IPC stream и Parquet.
+//!
+//! Ответ формируется потоком по блокам строк, чтобы большие файлы
+//! не собирались в памяти целиком.
+
+use crate::arrow::{ColumnarWriter, ROWS_PER_BATCH};
+use crate::las::{format_number, LasFile};
+use anyhow::{bail, Result};
+use bytes::Bytes;
+use futures::stream::{self, LocalBoxStream, StreamExt};
+use serde_json::{json, Value};
+use std::sync::Arc;
+
+/// Количество строк данных в одном блоке потока
+const ROWS_PER_CHUNK: usize = 1000;
+
+/// Формат выгрузки `/export` (`format=`)
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ExportFormat {
+    Csv,
+    Tsv,
+    Json,
+    Las,
+    Arrow,
+    Parquet,
+}
+
+impl ExportFormat {
+    pub fn parse(s: &str) -> Result<Self> {
+        match s.to_lowercase().as_str() {
+            "csv" => Ok(ExportFormat::Csv),
+            "tsv" => Ok(ExportFormat::Tsv),
+            "json" => Ok(ExportFormat::Json),
+            "las" => Ok(ExportFormat::Las),
+            "arrow" | "ipc" => Ok(ExportFormat::Arrow),
+            "parquet" => Ok(ExportFormat::Parquet),
+            other => bail!(
+                "Unknown export format '{}' (expected csv, tsv, json, las, arrow or parquet)",
+                other
+            ),
+        }
+    }
+
+    pub fn content_type(&self) -> &'static str {
+        match self {
+            ExportFormat::Csv => "text/csv; charset=utf-8",
+            ExportFormat::Tsv => "text/tab-separated-values; charset=utf-8",
+            ExportFormat::Json => "application/json",
+            ExportFormat::Las => "text/plain; charset=utf-8",
+            ExportFormat::Arrow => "application/vnd.apache.arrow.stream",
+            ExportFormat::Parquet => "application/vnd.apache.parquet",
+        }
+    }
+
+    pub fn extension(&self) -> &'static str {
+        match self {
+            ExportFormat::Csv => "csv",
+            ExportFormat::Tsv => "tsv",
+            ExportFormat::Json => "json",
+            ExportFormat::Las => "las",
+            ExportFormat::Arrow => "arrows",
+            ExportFormat::Parquet => "parquet",
+        }
+    }
+}
+
+/// Как записывать пропущенные значения (`null=`)
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum NullRepr {
+    /// Пустая ячейка (в JSON - `null`)
+    Empty,
+    /// `NaN` (в JSON - `null`, т.к. NaN там недопустим)
+    NaN,
+    /// Значение NULL из заголовка LAS файла
+    Original,
+}
+
+impl NullRepr {
+    pub fn parse(s: &str) -> Result<Self> {
+        match s.to_lowercase().as_str() {
+            "" | "empty" => Ok(NullRepr::Empty),
+            "nan" => Ok(NullRepr::NaN),
+            "original" | "null" => Ok(NullRepr::Original),
+            other => bail!("Unknown null representation '{}' (expected empty, nan or original)", other),
+        }
+    }
+}
+
+/// Поток с содержимым выгрузки. В LAS пропуски всегда пишутся значением NULL файла,
+/// в Arrow/Parquet - как null.
+pub fn export_stream(
+    las_file: LasFile,
+    format: ExportFormat,
+    null_repr: NullRepr,
+) -> LocalBoxStream<'static, Result<Bytes>> {
+    match format {
+        ExportFormat::Arrow | ExportFormat::Parquet => columnar_stream(las_file, format),
+        _ => text_stream(las_file, format, null_repr),
+    }
+}
+
+fn text_stream(
+    las_file: LasFile,
+    format: ExportFormat,
+    null_repr: NullRepr,
+) -> LocalBoxStream<'static, Result<Bytes>> {
+    let las_file = Arc::new(las_file);
+    let row_count = las_file.data.len();
+
+    let header = match format {
+        ExportFormat::Csv => delimited_header(&las_file, ','),
+        ExportFormat::Tsv => delimited_header(&las_file, '\t'),
+        ExportFormat::Json => json_header(&las_file),
+        _ => las_file.write_header(),
+    };
+    let footer = match format {
+        ExportFormat::Json => "\n]}\n",
+        _ => "",
+    };
+
+    let chunks = stream::iter((0..row_count).step_by(ROWS_PER_CHUNK)).map(move |start| {
+        let rows = start..(start + ROWS_PER_CHUNK).min(row_count);
+        let chunk = match format {
+            ExportFormat::Csv => delimited_rows(&las_file, rows, ',', null_repr),
+            ExportFormat::Tsv => delimited_rows(&las_file, rows, '\t', null_repr),
+            ExportFormat::Json => json_rows(&las_file, rows, null_repr),
+            _ => las_file.write_data_rows(rows),
+        };
+        Ok(Bytes::from(chunk))
+    });
+
+    stream::once(async move { Ok(Bytes::from(header)) })
+        .chain(chunks)
+        .chain(stream::once(async move { Ok(Bytes::from_static(footer.as_bytes())) }))
+        .boxed_local()
+}
+
+/// Arrow IPC / Parquet: по батчу на блок строк, байты отдаются по мере записи
+fn columnar_stream(las_file: LasFile, format: ExportFormat) -> LocalBoxStream<'static, Result<Bytes>> {
+    let schema = las_file.arrow_schema();
+    let writer = match format {
+        ExportFormat::Parquet => ColumnarWriter::parquet(&schema),
+        _ => ColumnarWriter::ipc_stream(&schema),
+    };
+    let mut writer = match writer {
+        Ok(writer) => writer,
+        Err(e) => return stream::once(async move { Err(e) }).boxed_local(),
+    };
+    let row_count = las_file.data.len();
+
+    // Состояние: следующая строка; None - запись завершена
+    stream::unfold(Some(0usize), move |next_row| {
+        let result = next_row.map(|start| -> Result<(Bytes, Option<usize>)> {
+            if start < row_count {
+                let rows = start..(start + ROWS_PER_BATCH).min(row_count);
+                let end = rows.end;
+                writer.write(&las_file.to_record_batch(schema.clone(), rows)?)?;
+                Ok((Bytes::from(writer.take_output()), Some(end)))
+            } else {
+                writer.finish()?;
+                Ok((Bytes::from(writer.take_output()), None))
+            }
+        });
+        async move {
+            match result? {
+                Ok((bytes, next_row)) => Some((Ok(bytes), next_row)),
+                Err(e) => Some((Err(e), None)),
+            }
+        }
+    })
+    .boxed_local()
+}
+
+/// Значение ячейки или `None`, если это пропуск
+fn cell_value(las_file: &LasFile, value: f64) -> Option<f64> {
+    if value == las_file.null_value || value.is_nan() {
+        None
+    } else {
+        Some(value)
+    }
+}
+
+fn delimited_field(s: &str, delimiter: char) -> String {
+    if s.contains(delimiter) || s.contains('"') || s.contains('\n') {
+        format!("\"{}\"", s.replace('"', "\"\""))
+    } else {
+        s.to_string()
+    }
+}
+
+/// Заголовок CSV/TSV: `MNEM [UNIT]` для каждой кривой
+fn delimited_header(las_file: &LasFile, delimiter: char) -> String {
+    let names: Vec<String> = las_file
+        .curves
+        .iter()
+        .map(|c| {
+            let name = if c.unit.is_empty() {
+                c.mnemonic.clone()
+            } else {
+                format!("{} [{}]", c.mnemonic, c.unit)
+            };
+            delimited_field(&name, delimiter)
+        })
+        .collect();
+    names.join(&delimiter.to_string()) + "\n"
+}
+
+fn delimited_rows(
+    las_file: &LasFile,
+    rows: std::ops::Range<usize>,
+    delimiter: char,
+    null_repr: NullRepr,
+) -> String {
+    let mut out = String::new();
+    for row in &las_file.data[rows] {
+        for idx in 0..las_file.curves.len() {
+            if idx > 0 {
+                out.push(delimiter);
+            }
+            let value = row.values.get(idx).copied().unwrap_or(las_file.null_value);
+            match (cell_value(las_file, value), null_repr) {
+                (Some(v), _) => out.push_str(&format_number(v)),
+                (None, NullRepr::Empty) => {}
+                (None, NullRepr::NaN) => out.push_str("NaN"),
+                (None, NullRepr::Original) => out.push_str(&format_number(las_file.null_value)),
+            }
+        }
+        out.push('\n');
+    }
+    out
+}
+
+/// Начало JSON документа: описание кривых и открытие массива строк `rows`
+fn json_header(las_file: &LasFile) -> String {
+    let curves: Vec<Value> = las_file
+        .curves
+        .iter()
+        .map(|c| json!({ "mnemonic": c.mnemonic, "unit": c.unit, "description": c.description }))
+        .collect();
+    let header = json!({
+        "index": las_file.curves.first().map(|c| c.mnemonic.clone()),
+        "null_value": las_file.null_value,
+        "curves": curves,
+    })
+    .to_string();
+
+    // Дописываем массив строк вручную, чтобы отдавать его по частям
+    format!("{},\"rows\":[\n", header.trim_end_matches('}'))
+}
+
+fn json_rows(las_file: &LasFile, rows: std::ops::Range<usize>, null_repr: NullRepr) -> String {
+    let mut out = String::new();
+    for (offset, row) in las_file.data[rows.clone()].iter().enumerate() {
+        let values: Vec<Value> = (0..las_file.curves.len())
+            .map(|idx| {
+                let value = row.values.get(idx).copied().unwrap_or(las_file.null_value);
+                match (cell_value(las_file, value), null_repr) {
+                    (Some(v), _) => json!(v),
+                    (None, NullRepr::Original) => json!(las_file.null_value),
+                    (None, _) => Value::Null,
+                }
+            })
+            .collect();
+        if rows.start + offset > 0 {
+            out.push_str(",\n");
+        }
+        out.push_str(&Value::Array(values).to_string());
+    }
+    out
+}
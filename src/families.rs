@@ -0,0 +1,289 @@
+//! Семейства кривых: нормализация мнемоник разных сервисных компаний (GR/GRC/SGR/GAM,
+//! RHOB/RHOZ/DEN, NPHI/TNPH/NPOR, ILD/RILD/AT90...) по словарю синонимов.
+//!
+//! Встроенный словарь дополняется секциями `[curve_aliases.<семейство>]` в lasplot.toml.
+//! Семейство задаёт колонку диаграммы, тип шкалы и цвет кривой.
+
+use crate::las::CurveInfo;
+use crate::plot::ScaleType;
+use anyhow::{bail, Result};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::sync::LazyLock;
+
+/// Каноническое семейство кривой
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum CurveFamily {
+    Depth,
+    GammaRay,
+    SpontaneousPotential,
+    Caliper,
+    BitSize,
+    Resistivity,
+    DensityCorrection,
+    Neutron,
+    Porosity,
+    Density,
+    PhotoElectric,
+    Sonic,
+    Temperature,
+}
+
+/// Колонка диаграммы; пустые колонки не показываются
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Track {
+    /// ГК, ПС, кавернометрия
+    Correlation,
+    Resistivity,
+    /// Плотность, нейтронный, акустика
+    Porosity,
+    Other,
+}
+
+impl CurveFamily {
+    pub const ALL: [CurveFamily; 13] = [
+        CurveFamily::Depth,
+        CurveFamily::GammaRay,
+        CurveFamily::SpontaneousPotential,
+        CurveFamily::Caliper,
+        CurveFamily::BitSize,
+        CurveFamily::Resistivity,
+        CurveFamily::DensityCorrection,
+        CurveFamily::Neutron,
+        CurveFamily::Porosity,
+        CurveFamily::Density,
+        CurveFamily::PhotoElectric,
+        CurveFamily::Sonic,
+        CurveFamily::Temperature,
+    ];
+
+    /// Имя семейства в конфигурации и API
+    pub fn name(&self) -> &'static str {
+        match self {
+            CurveFamily::Depth => "depth",
+            CurveFamily::GammaRay => "gamma_ray",
+            CurveFamily::SpontaneousPotential => "spontaneous_potential",
+            CurveFamily::Caliper => "caliper",
+            CurveFamily::BitSize => "bit_size",
+            CurveFamily::Resistivity => "resistivity",
+            CurveFamily::DensityCorrection => "density_correction",
+            CurveFamily::Neutron => "neutron",
+            CurveFamily::Porosity => "porosity",
+            CurveFamily::Density => "density",
+            CurveFamily::PhotoElectric => "photoelectric",
+            CurveFamily::Sonic => "sonic",
+            CurveFamily::Temperature => "temperature",
+        }
+    }
+
+    pub fn parse(s: &str) -> Result<Self> {
+        let name = s.trim().to_lowercase();
+        match CurveFamily::ALL.iter().find(|family| family.name() == name) {
+            Some(family) => Ok(*family),
+            None => {
+                let known: Vec<&str> = CurveFamily::ALL.iter().map(|f| f.name()).collect();
+                bail!("Unknown curve family '{}' (expected one of: {})", s, known.join(", "))
+            }
+        }
+    }
+
+    pub fn track(&self) -> Track {
+        match self {
+            CurveFamily::GammaRay
+            | CurveFamily::SpontaneousPotential
+            | CurveFamily::Caliper
+            | CurveFamily::BitSize => Track::Correlation,
+            CurveFamily::Resistivity => Track::Resistivity,
+            CurveFamily::DensityCorrection
+            | CurveFamily::Neutron
+            | CurveFamily::Porosity
+            | CurveFamily::Density
+            | CurveFamily::PhotoElectric
+            | CurveFamily::Sonic => Track::Porosity,
+            CurveFamily::Depth | CurveFamily::Temperature => Track::Other,
+        }
+    }
+
+    pub fn scale_type(&self) -> ScaleType {
+        match self {
+            CurveFamily::Resistivity => ScaleType::Logarithmic,
+            _ => ScaleType::Linear,
+        }
+    }
+
+    /// Шкала растёт справа налево (пористость, нейтронный, интервальное время)
+    pub fn reversed(&self) -> bool {
+        matches!(self, CurveFamily::Neutron | CurveFamily::Porosity | CurveFamily::Sonic)
+    }
+
+    /// Привычный цвет кривой (hex)
+    pub fn color(&self) -> &'static str {
+        match self {
+            CurveFamily::Depth => "000000",
+            CurveFamily::GammaRay => "008000",
+            CurveFamily::SpontaneousPotential => "000080",
+            CurveFamily::Caliper => "000000",
+            CurveFamily::BitSize => "808080",
+            CurveFamily::Resistivity => "FF0000",
+            CurveFamily::DensityCorrection => "A0522D",
+            CurveFamily::Neutron => "0000FF",
+            CurveFamily::Porosity => "008080",
+            CurveFamily::Density => "FF0000",
+            CurveFamily::PhotoElectric => "FF00FF",
+            CurveFamily::Sonic => "800080",
+            CurveFamily::Temperature => "FFA500",
+        }
+    }
+}
+
+/// Признаки семейства: мнемоники (`*` в конце - любой суффикс), единицы
+/// и слова описания; сравнение без учёта регистра
+#[derive(Debug, Clone, Default, PartialEq, Deserialize, Serialize)]
+#[serde(deny_unknown_fields)]
+pub struct AliasRule {
+    #[serde(default)]
+    pub mnemonics: Vec<String>,
+    #[serde(default)]
+    pub units: Vec<String>,
+    #[serde(default)]
+    pub descriptions: Vec<String>,
+}
+
+/// Встроенный словарь: (семейство, мнемоники, единицы, слова описания).
+/// Порядок важен для описаний: "neutron porosity" - нейтронный, а не пористость.
+#[allow(clippy::type_complexity)]
+static BUILTIN_ALIASES: &[(CurveFamily, &[&str], &[&str], &[&str])] = &[
+    (CurveFamily::Depth, &["DEPT", "DEPTH", "MD", "TDEP", "DEPTH_MD"], &[], &["MEASURED DEPTH"]),
+    (
+        CurveFamily::GammaRay,
+        &["GR", "GRC", "SGR", "CGR", "GAM", "GAMMA", "GRD", "GRS", "GR_EDTC", "HSGR", "HCGR", "ECGR", "GKS", "GK"],
+        &["GAPI", "API"],
+        &["GAMMA"],
+    ),
+    (CurveFamily::SpontaneousPotential, &["SP", "SPBL", "SPC", "PS"], &["MV"], &["SPONTANEOUS", "SELF POTENTIAL"]),
+    (CurveFamily::Caliper, &["CALI", "CAL", "CALS", "CALX", "CALY", "HCAL", "C1", "C2", "DCAL"], &[], &["CALIPER", "HOLE DIAMETER"]),
+    (CurveFamily::BitSize, &["BS", "BIT", "BITSIZE"], &[], &["BIT SIZE"]),
+    (
+        CurveFamily::Resistivity,
+        &[
+            "ILD", "RILD", "ILM", "RILM", "LLD", "LLS", "LL3", "LL8", "MSFL", "SFL", "SFLU", "RT", "RXO", "RDEP", "RMED",
+            "RSHAL", "RES*", "AT10", "AT20", "AT30", "AT60", "AT90", "AHT*", "AO*", "HDRS", "HMRS", "RLA*", "IK", "BK",
+        ],
+        &["OHMM", "OHM.M", "OHM-M", "OHM_M", "OHMFT", "OHM.FT", "OHM-FT"],
+        &["RESISTIV", "INDUCTION", "LATEROLOG"],
+    ),
+    (CurveFamily::DensityCorrection, &["DRHO", "HDRA", "ZCOR", "DCOR", "DRH"], &[], &["DENSITY CORRECTION", "DELTA RHO"]),
+    (
+        CurveFamily::Neutron,
+        &["NPHI", "TNPH", "NPOR", "NPHS", "NPLS", "CNC", "CNCF", "CN", "NEU", "HNPO", "NPHZ", "APLC"],
+        &[],
+        &["NEUTRON"],
+    ),
+    (CurveFamily::Porosity, &["PHIT", "PHIE", "DPHI", "SPHI", "PHID", "PHIN", "PHIND", "PORO"], &[], &["POROSITY"]),
+    (
+        CurveFamily::Density,
+        &["RHOB", "RHOZ", "DEN", "DENS", "ZDEN", "RHO8", "HDEN", "ZDNC", "RHOM"],
+        &["G/C3", "G/CC", "GM/CC", "G/CM3", "KG/M3"],
+        &["DENSITY"],
+    ),
+    (CurveFamily::PhotoElectric, &["PE", "PEF", "PEFZ", "PEF8", "PDPE", "HPEF"], &["B/E", "B/EL"], &["PHOTOELECTRIC", "PHOTO-ELECTRIC"]),
+    (
+        CurveFamily::Sonic,
+        &["DT", "DTC", "DTCO", "DT24", "DTLN", "DTLF", "DT4P", "AC", "DTS", "DTSM", "DT4S"],
+        &["US/F", "US/FT", "USEC/F", "USEC/FT", "US/M", "USEC/M"],
+        &["SONIC", "SLOWNESS", "TRANSIT TIME", "ACOUSTIC"],
+    ),
+    (CurveFamily::Temperature, &["TEMP", "MTEM", "BHT", "HTEM"], &["DEGC", "DEGF"], &["TEMPERATURE"]),
+];
+
+/// Словарь синонимов: правила проверяются по порядку, сначала по мнемонике,
+/// затем по описанию, затем по единице
+#[derive(Debug, Clone)]
+pub struct AliasDictionary {
+    rules: Vec<(CurveFamily, AliasRule)>,
+}
+
+/// Встроенный словарь
+impl Default for AliasDictionary {
+    fn default() -> Self {
+        BUILTIN.clone()
+    }
+}
+
+static BUILTIN: LazyLock<AliasDictionary> = LazyLock::new(AliasDictionary::builtin);
+
+fn to_upper(values: &[&str]) -> Vec<String> {
+    values.iter().map(|v| v.to_uppercase()).collect()
+}
+
+fn mnemonic_matches(pattern: &str, mnemonic: &str) -> bool {
+    match pattern.strip_suffix('*') {
+        Some(prefix) => mnemonic.starts_with(prefix),
+        None => mnemonic == pattern,
+    }
+}
+
+impl AliasDictionary {
+    /// Только встроенный словарь
+    pub fn builtin() -> Self {
+        let rules = BUILTIN_ALIASES
+            .iter()
+            .map(|(family, mnemonics, units, descriptions)| {
+                let rule = AliasRule {
+                    mnemonics: to_upper(mnemonics),
+                    units: to_upper(units),
+                    descriptions: to_upper(descriptions),
+                };
+                (*family, rule)
+            })
+            .collect();
+        AliasDictionary { rules }
+    }
+
+    /// Правила из конфигурации (ключ - имя семейства) перед встроенными
+    pub fn with_overrides(overrides: &BTreeMap<String, AliasRule>) -> Result<Self> {
+        let mut rules = Vec::new();
+        for (name, rule) in overrides {
+            let family = CurveFamily::parse(name)?;
+            let normalize = |values: &[String]| -> Vec<String> {
+                values.iter().map(|v| v.trim().to_uppercase()).filter(|v| !v.is_empty()).collect()
+            };
+            let rule = AliasRule {
+                mnemonics: normalize(&rule.mnemonics),
+                units: normalize(&rule.units),
+                descriptions: normalize(&rule.descriptions),
+            };
+            rules.push((family, rule));
+        }
+        rules.extend(BUILTIN.rules.iter().cloned());
+        Ok(AliasDictionary { rules })
+    }
+
+    /// Семейство кривой; None - кривая не распознана
+    pub fn classify(&self, curve: &CurveInfo) -> Option<CurveFamily> {
+        let mnemonic = curve.mnemonic.trim().to_uppercase();
+        let unit = curve.unit.trim().to_uppercase();
+        let description = curve.description.trim().to_uppercase();
+
+        let by_mnemonic = || {
+            self.rules
+                .iter()
+                .find(|(_, rule)| rule.mnemonics.iter().any(|pattern| mnemonic_matches(pattern, &mnemonic)))
+        };
+        let by_description = || {
+            self.rules.iter().find(|(_, rule)| {
+                !description.is_empty() && rule.descriptions.iter().any(|word| description.contains(word.as_str()))
+            })
+        };
+        let by_unit = || self.rules.iter().find(|(_, rule)| !unit.is_empty() && rule.units.contains(&unit));
+
+        by_mnemonic().or_else(by_description).or_else(by_unit).map(|(family, _)| *family)
+    }
+}
+
+impl CurveInfo {
+    /// Семейство кривой по встроенному словарю (синонимы из конфигурации - [`AliasDictionary::classify`])
+    pub fn family(&self) -> Option<CurveFamily> {
+        BUILTIN.classify(self)
+    }
+}
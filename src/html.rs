@@ -0,0 +1,121 @@
+//! Экранирование текста для вставки в HTML.
+//!
+//! Всё, что пришло из LAS файла (мнемоники, единицы, описания, ~Well),
+//! из laslist или из параметров запроса, должно проходить через эти функции
+//! перед тем, как попасть в генерируемую страницу.
+
+use std::collections::hash_map::RandomState;
+use std::hash::{BuildHasher, Hasher};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// Экранирует текст для содержимого элемента (`<td>...</td>`, `<title>`)
+pub fn escape_html(s: &str) -> String {
+    let mut out = String::with_capacity(s.len());
+    for ch in s.chars() {
+        match ch {
+            '&' => out.push_str("&amp;"),
+            '<' => out.push_str("&lt;"),
+            '>' => out.push_str("&gt;"),
+            '"' => out.push_str("&quot;"),
+            '\'' => out.push_str("&#39;"),
+            _ => out.push(ch),
+        }
+    }
+    out
+}
+
+/// Экранирует значение атрибута, заключённого в кавычки (одинарные или двойные).
+/// Кроме HTML-спецсимволов кодирует `` ` ``, `=` и управляющие символы,
+/// чтобы значение нельзя было "вытащить" из атрибута.
+pub fn escape_attr(s: &str) -> String {
+    let mut out = String::with_capacity(s.len());
+    for ch in s.chars() {
+        match ch {
+            '&' => out.push_str("&amp;"),
+            '<' => out.push_str("&lt;"),
+            '>' => out.push_str("&gt;"),
+            '"' => out.push_str("&quot;"),
+            '\'' => out.push_str("&#39;"),
+            '`' => out.push_str("&#96;"),
+            '=' => out.push_str("&#61;"),
+            c if c.is_control() => out.push_str(&format!("&#{};", c as u32)),
+            c => out.push(c),
+        }
+    }
+    out
+}
+
+/// Кодирует значение для подстановки в параметр URL (`/?file=...`).
+/// Результат всё равно нужно пропустить через [`escape_attr`] при вставке в атрибут.
+pub fn encode_query_value(s: &str) -> String {
+    url::form_urlencoded::byte_serialize(s.as_bytes()).collect()
+}
+
+/// Заголовок Content-Security-Policy для генерируемых страниц.
+/// Страницы используют только встроенные стили и картинки `data:`;
+/// скрипты разрешены лишь с указанным nonce (`None` - скрипты запрещены).
+pub fn content_security_policy(script_nonce: Option<&str>) -> String {
+    let script_src = match script_nonce {
+        Some(nonce) => format!("script-src 'nonce-{}'; connect-src 'self'", nonce),
+        None => "script-src 'none'".to_string(),
+    };
+    format!(
+        "default-src 'none'; img-src 'self' data:; style-src 'unsafe-inline'; {}; \
+         base-uri 'none'; form-action 'self'; frame-ancestors 'none'",
+        script_src
+    )
+}
+
+/// Одноразовый nonce для `script-src` (новый на каждый ответ)
+pub fn generate_nonce() -> String {
+    let mut hasher = RandomState::new().build_hasher();
+    hasher.write_u128(
+        SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_nanos())
+            .unwrap_or(0),
+    );
+    format!("{:016x}", hasher.finish())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn escape_html_replaces_special_characters() {
+        assert_eq!(escape_html("<script>alert('x')</script>"), "&lt;script&gt;alert(&#39;x&#39;)&lt;/script&gt;");
+        assert_eq!(escape_html("A & \"B\""), "A &amp; &quot;B&quot;");
+        assert_eq!(escape_html("Скважина 1"), "Скважина 1");
+    }
+
+    #[test]
+    fn escape_attr_keeps_value_inside_quotes() {
+        assert_eq!(escape_attr("x\" onerror=alert(1)"), "x&quot; onerror&#61;alert(1)");
+        assert_eq!(escape_attr("`a'b`"), "&#96;a&#39;b&#96;");
+        assert_eq!(escape_attr("a\nb\t&"), "a&#10;b&#9;&amp;");
+    }
+
+    #[test]
+    fn encode_query_value_encodes_reserved_characters() {
+        assert_eq!(encode_query_value("a b&c=d/e.las"), "a+b%26c%3Dd%2Fe.las");
+        assert_eq!(encode_query_value("\"><script>"), "%22%3E%3Cscript%3E");
+        assert_eq!(encode_query_value("скв.las"), "%D1%81%D0%BA%D0%B2.las");
+    }
+
+    #[test]
+    fn content_security_policy_allows_only_nonce_scripts() {
+        let policy = content_security_policy(Some("abc123"));
+        assert!(policy.contains("script-src 'nonce-abc123'"));
+        assert!(!policy.contains("unsafe-eval"));
+        assert!(content_security_policy(None).contains("script-src 'none'"));
+    }
+
+    #[test]
+    fn nonce_is_hex_and_changes() {
+        let nonce = generate_nonce();
+        assert_eq!(nonce.len(), 16);
+        assert!(nonce.chars().all(|c| c.is_ascii_hexdigit()));
+        assert_ne!(nonce, generate_nonce());
+    }
+}
@@ -1,17 +1,50 @@
-use anyhow::Result;
+//! Разбор и запись LAS файлов (Log ASCII Standard 1.2/2.0; из LAS 3.0 - кровли `~Tops` и инклинометрия).
+
+use crate::survey::Survey;
+use crate::tops::{parse_las_tops, FormationTop};
+use anyhow::{Context, Result};
 use std::collections::HashMap;
 use std::str::FromStr;
 
+/// Разобранный LAS файл: заголовок и строки данных.
+/// Пропуски в данных хранятся как `null_value` (как в файле).
 #[derive(Debug, Clone)]
 pub struct LasFile {
     pub version: String,
+    /// Значения секции ~Well по мнемонике (строка после точки, вместе с единицей)
     pub well_info: HashMap<String, String>,
+    /// Строки секции ~Well с разобранными единицами и описаниями (в порядке файла)
+    pub well_items: Vec<HeaderItem>,
     pub curves: Vec<CurveInfo>,
-    pub parameters: HashMap<String, String>,
+    /// Строки секции ~Parameter (в порядке файла)
+    pub parameters: Vec<HeaderItem>,
     pub data: Vec<DataRow>,
     pub null_value: f64,
+    /// Кровли пластов из `~Tops_Data` (LAS 3.0) или из файла кровель (`tops=`)
+    pub tops: Vec<FormationTop>,
+    /// Инклинометрия из `~Survey`/`~Inclinometry_Data` или из отдельного файла (`survey=`)
+    pub survey: Option<Survey>,
+    /// Строки секции ~Other (произвольный текст); сюда же дописывается история правок
+    pub other: Vec<String>,
 }
 
+/// Строка заголовочной секции: `MNEM.UNIT  VALUE : DESCRIPTION`
+#[derive(Debug, Clone)]
+pub struct HeaderItem {
+    pub mnemonic: String,
+    pub unit: String,
+    pub value: String,
+    pub description: String,
+}
+
+impl HeaderItem {
+    /// Значение как число, если оно числовое
+    pub fn numeric_value(&self) -> Option<f64> {
+        f64::from_str(self.value.trim()).ok()
+    }
+}
+
+/// Описание кривой из секции ~Curve
 #[derive(Debug, Clone)]
 pub struct CurveInfo {
     pub mnemonic: String,
@@ -20,20 +53,34 @@ pub struct CurveInfo {
     pub api_codes: Option<String>,
 }
 
+/// Строка секции ~ASCII: по значению на кривую
 #[derive(Debug, Clone)]
 pub struct DataRow {
     pub values: Vec<f64>,
 }
 
 impl LasFile {
+    /// Читает и разбирает файл с диска.
+    /// LAS файлы нередко в однобайтовых кодировках, поэтому текст читается с заменой невалидных байт.
+    pub fn read(path: &std::path::Path) -> Result<Self> {
+        let bytes = std::fs::read(path).with_context(|| format!("Failed to read file: {:?}", path))?;
+        Self::parse(&String::from_utf8_lossy(&bytes))
+    }
+
     pub fn parse(content: &str) -> Result<Self> {
         let lines: Vec<&str> = content.lines().collect();
         let mut version = String::new();
         let mut well_info = HashMap::new();
+        let mut well_items = Vec::new();
         let mut curves = Vec::new();
-        let mut parameters = HashMap::new();
+        let mut parameters = Vec::new();
         let mut data = Vec::new();
         let mut null_value = -999.25;
+        let mut tops_definition = Vec::new();
+        let mut tops_lines = Vec::new();
+        let mut survey_definition = Vec::new();
+        let mut survey_lines = Vec::new();
+        let mut other = Vec::new();
 
         let mut i = 0;
         let mut in_section = None;
@@ -49,7 +96,31 @@ impl LasFile {
             // Определяем секцию
             if line.starts_with('~') {
                 let section = line.to_uppercase();
-                if section.contains("VERSION") {
+                // ~Tops_Parameter не должна попасть в ~Parameter; заголовок данных - `~Tops_Data | Tops_Definition`
+                if section.starts_with("~TOPS") {
+                    in_section = if section.contains("DATA") {
+                        Some("tops")
+                    } else if section.contains("DEFINITION") {
+                        Some("tops_definition")
+                    } else if section.contains("PARAMETER") {
+                        None
+                    } else {
+                        Some("tops")
+                    };
+                } else if section.starts_with("~SURVEY") || section.starts_with("~INCLINOMETRY") {
+                    // Как и у кровель: `~Survey` (LAS 2.0) или `~Inclinometry_Data | Inclinometry_Definition`
+                    in_section = if section.contains("DATA") {
+                        Some("survey")
+                    } else if section.contains("DEFINITION") {
+                        Some("survey_definition")
+                    } else if section.contains("PARAMETER") {
+                        None
+                    } else {
+                        Some("survey")
+                    };
+                } else if section.starts_with("~O") {
+                    in_section = Some("other");
+                } else if section.contains("VERSION") {
                     in_section = Some("version");
                 } else if section.contains("WELL") {
                     in_section = Some("well");
@@ -67,25 +138,23 @@ impl LasFile {
             }
 
             match in_section {
-                Some("version") => {
-                    // Версия находится в части перед двоеточием
-                    // Формат: VERS.                 2.0:   описание
-                    // Нужно взять значение из части до двоеточия после "VERS."
-                    if line.to_uppercase().contains("VERS") {
-                        if let Some(colon_pos) = line.find(':') {
-                            let before_colon = &line[..colon_pos].trim();
-                            // Ищем "VERS." или "VERS" (без учета регистра)
-                            let vers_upper = before_colon.to_uppercase();
-                            if let Some(vers_pos) = vers_upper.find("VERS") {
-                                let after_vers = &before_colon[vers_pos + 4..]; // +4 для "VERS"
-                                // Пропускаем точку и пробелы, берем первое слово/значение
-                                let version_value = after_vers.trim_start_matches('.').trim();
-                                // Берем первое слово (до пробела или до конца)
-                                if let Some(space_pos) = version_value.find(char::is_whitespace) {
-                                    version = version_value[..space_pos].to_string();
-                                } else {
-                                    version = version_value.to_string();
-                                }
+                // Версия находится в части перед двоеточием
+                // Формат: VERS.                 2.0:   описание
+                // Нужно взять значение из части до двоеточия после "VERS."
+                Some("version") if line.to_uppercase().contains("VERS") => {
+                    if let Some(colon_pos) = line.find(':') {
+                        let before_colon = &line[..colon_pos].trim();
+                        // Ищем "VERS." или "VERS" (без учета регистра)
+                        let vers_upper = before_colon.to_uppercase();
+                        if let Some(vers_pos) = vers_upper.find("VERS") {
+                            let after_vers = &before_colon[vers_pos + 4..]; // +4 для "VERS"
+                            // Пропускаем точку и пробелы, берем первое слово/значение
+                            let version_value = after_vers.trim_start_matches('.').trim();
+                            // Берем первое слово (до пробела или до конца)
+                            if let Some(space_pos) = version_value.find(char::is_whitespace) {
+                                version = version_value[..space_pos].to_string();
+                            } else {
+                                version = version_value.to_string();
                             }
                         }
                     }
@@ -109,6 +178,9 @@ impl LasFile {
                             well_info.insert(key, value);
                         }
                     }
+                    if let Some(item) = Self::parse_header_line(line) {
+                        well_items.push(item);
+                    }
                 }
                 Some("curve") => {
                     if line.starts_with('#') {
@@ -124,8 +196,8 @@ impl LasFile {
                         i += 1;
                         continue;
                     }
-                    if let Some((key, value)) = Self::parse_key_value(line) {
-                        parameters.insert(key.to_string(), value.trim().to_string());
+                    if let Some(item) = Self::parse_header_line(line) {
+                        parameters.push(item);
                     }
                 }
                 Some("data") => {
@@ -137,6 +209,19 @@ impl LasFile {
                         data.push(row);
                     }
                 }
+                Some("tops_definition") => {
+                    if let Some(column) = Self::parse_curve_line(line) {
+                        tops_definition.push((column.mnemonic, column.unit));
+                    }
+                }
+                Some("tops") => tops_lines.push(line),
+                Some("survey_definition") => {
+                    if let Some(column) = Self::parse_curve_line(line) {
+                        survey_definition.push((column.mnemonic, column.unit));
+                    }
+                }
+                Some("survey") => survey_lines.push(line),
+                Some("other") => other.push(lines[i].trim_end().to_string()),
                 _ => {}
             }
             i += 1;
@@ -145,24 +230,45 @@ impl LasFile {
         Ok(LasFile {
             version,
             well_info,
+            well_items,
             curves,
             parameters,
             data,
             null_value,
+            tops: parse_las_tops(&tops_definition, &tops_lines),
+            survey: Survey::from_las_section(&survey_definition, &survey_lines).ok(),
+            other,
         })
     }
 
-    fn parse_key_value(line: &str) -> Option<(String, String)> {
-        let parts: Vec<&str> = line.splitn(3, ':').collect();
-        if parts.len() >= 2 {
-            let key_part = parts[0].trim();
-            let value_part = parts[1].trim();
-            if let Some(space_idx) = key_part.find(char::is_whitespace) {
-                let key = key_part[..space_idx].to_string();
-                return Some((key, value_part.to_string()));
-            }
+    /// Разбирает строку `MNEM.UNIT  VALUE : DESCRIPTION`.
+    /// Единица идёт сразу после точки до первого пробела (может быть пустой),
+    /// описание - после последнего двоеточия (значение может содержать время `12:30`).
+    fn parse_header_line(line: &str) -> Option<HeaderItem> {
+        let dot_idx = line.find('.')?;
+        let mnemonic = line[..dot_idx].trim().to_string();
+        if mnemonic.is_empty() {
+            return None;
         }
-        None
+
+        let after_dot = &line[dot_idx + 1..];
+        let unit_end = after_dot
+            .find(|c: char| c.is_whitespace() || c == ':')
+            .unwrap_or(after_dot.len());
+        let unit = after_dot[..unit_end].to_string();
+
+        let rest = &after_dot[unit_end..];
+        let (value, description) = match rest.rfind(':') {
+            Some(colon_idx) => (rest[..colon_idx].trim(), rest[colon_idx + 1..].trim()),
+            None => (rest.trim(), ""),
+        };
+
+        Some(HeaderItem {
+            mnemonic,
+            unit,
+            value: value.to_string(),
+            description: description.to_string(),
+        })
     }
 
     fn parse_curve_line(line: &str) -> Option<CurveInfo> {
@@ -256,5 +362,225 @@ impl LasFile {
         
         Some((min, max))
     }
+
+    /// Ищет строку секции ~Well по мнемонике (без учёта регистра)
+    pub fn get_well_item(&self, mnemonic: &str) -> Option<&HeaderItem> {
+        self.well_items
+            .iter()
+            .find(|item| item.mnemonic.eq_ignore_ascii_case(mnemonic))
+    }
+
+    /// Проверяет согласованность файла и возвращает список предупреждений
+    pub fn warnings(&self) -> Vec<String> {
+        let mut warnings = Vec::new();
+
+        if self.version.is_empty() {
+            warnings.push("VERS not found in ~Version section".to_string());
+        }
+        if self.curves.is_empty() {
+            warnings.push("No curves in ~Curve section".to_string());
+        }
+        if self.data.is_empty() {
+            warnings.push("No data rows in ~ASCII section".to_string());
+        }
+
+        let wrapped = self
+            .well_info
+            .iter()
+            .any(|(key, value)| key.eq_ignore_ascii_case("WRAP") && value.trim().eq_ignore_ascii_case("YES"));
+        if wrapped {
+            warnings.push("Wrapped data (WRAP = YES) is not supported".to_string());
+        }
+
+        let bad_rows = self
+            .data
+            .iter()
+            .filter(|row| row.values.len() != self.curves.len())
+            .count();
+        if bad_rows > 0 {
+            warnings.push(format!(
+                "{} data rows do not have {} values (one per curve)",
+                bad_rows,
+                self.curves.len()
+            ));
+        }
+
+        for (idx, curve) in self.curves.iter().enumerate() {
+            if !self.data.is_empty() && self.get_curve_stats(idx).is_none() {
+                warnings.push(format!("Curve {} has no valid values", curve.mnemonic));
+            }
+        }
+
+        if !self.curves.is_empty() {
+            let index: Vec<f64> = self.get_curve_data(0).into_iter().flatten().collect();
+            let increasing = index.windows(2).all(|w| w[1] > w[0]);
+            let decreasing = index.windows(2).all(|w| w[1] < w[0]);
+            if !increasing && !decreasing {
+                warnings.push(format!("Index curve {} is not monotonic", self.curves[0].mnemonic));
+            }
+
+            // STRT/STOP из ~Well должны совпадать с первым/последним значением индекса
+            for (key, actual) in [("STRT", index.first()), ("STOP", index.last())] {
+                let declared = self.get_well_item(key).and_then(|item| item.numeric_value());
+                if let (Some(declared), Some(&actual)) = (declared, actual) {
+                    if (declared - actual).abs() > 1e-6 * declared.abs().max(1.0) {
+                        warnings.push(format!(
+                            "{} = {} does not match index value {}",
+                            key, declared, actual
+                        ));
+                    }
+                }
+            }
+        }
+
+        warnings
+    }
+
+    /// Новый файл только с указанными кривыми и строками данных.
+    /// STRT/STOP/STEP в ~Well пересчитываются по первой из выбранных кривых (индексу).
+    pub fn subset(&self, curve_indices: &[usize], rows: &[usize]) -> LasFile {
+        let mut result = self.clone();
+        result.curves = curve_indices.iter().map(|&idx| self.curves[idx].clone()).collect();
+        result.data = rows
+            .iter()
+            .map(|&row| DataRow {
+                values: curve_indices
+                    .iter()
+                    .map(|&idx| self.data[row].values.get(idx).copied().unwrap_or(self.null_value))
+                    .collect(),
+            })
+            .collect();
+        result.update_index_header();
+        result
+    }
+
+    /// Обновляет STRT/STOP/STEP в ~Well по первой кривой (индексу).
+    /// STEP = 0, если шаг нерегулярный (как требует стандарт LAS 2.0).
+    pub fn update_index_header(&mut self) {
+        if self.curves.is_empty() {
+            return;
+        }
+
+        let index: Vec<f64> = self.get_curve_data(0).into_iter().flatten().collect();
+        let (Some(&start), Some(&stop)) = (index.first(), index.last()) else {
+            return;
+        };
+        let step = match index.windows(2).map(|w| w[1] - w[0]).collect::<Vec<_>>().as_slice() {
+            [] => 0.0,
+            [first, rest @ ..] => {
+                let regular = rest.iter().all(|d| (d - first).abs() <= 1e-6 * first.abs().max(1e-9));
+                if regular { round_significant(*first, 12) } else { 0.0 }
+            }
+        };
+
+        let unit = self.curves[0].unit.clone();
+        for (key, value, description) in [
+            ("STRT", start, "START DEPTH"),
+            ("STOP", stop, "STOP DEPTH"),
+            ("STEP", step, "STEP"),
+        ] {
+            let value = format_number(value);
+            match self.well_items.iter_mut().find(|item| item.mnemonic.eq_ignore_ascii_case(key)) {
+                Some(item) => {
+                    item.unit = unit.clone();
+                    item.value = value.clone();
+                }
+                None => self.well_items.push(HeaderItem {
+                    mnemonic: key.to_string(),
+                    unit: unit.clone(),
+                    value: value.clone(),
+                    description: description.to_string(),
+                }),
+            }
+            self.well_info.insert(key.to_string(), format!("{} {}", unit, value).trim().to_string());
+        }
+    }
+
+    /// Заголовок LAS 2.0 (секции ~V, ~W, ~C, ~P, ~O и строка ~A)
+    pub fn write_header(&self) -> String {
+        let mut out = String::new();
+
+        out.push_str("~Version Information\n");
+        out.push_str(&format_header_line("VERS", "", "2.0", "CWLS LOG ASCII STANDARD - VERSION 2.0"));
+        out.push_str(&format_header_line("WRAP", "", "NO", "ONE LINE PER DEPTH STEP"));
+
+        out.push_str("~Well Information\n");
+        let mut has_null = false;
+        for item in &self.well_items {
+            if item.mnemonic.eq_ignore_ascii_case("NULL") {
+                has_null = true;
+                out.push_str(&format_header_line(&item.mnemonic, &item.unit, &format_number(self.null_value), &item.description));
+            } else {
+                out.push_str(&format_header_line(&item.mnemonic, &item.unit, &item.value, &item.description));
+            }
+        }
+        if !has_null {
+            out.push_str(&format_header_line("NULL", "", &format_number(self.null_value), "NULL VALUE"));
+        }
+
+        out.push_str("~Curve Information\n");
+        for curve in &self.curves {
+            let api_codes = curve.api_codes.as_deref().unwrap_or("");
+            out.push_str(&format_header_line(&curve.mnemonic, &curve.unit, api_codes, &curve.description));
+        }
+
+        if !self.parameters.is_empty() {
+            out.push_str("~Parameter Information\n");
+            for item in &self.parameters {
+                out.push_str(&format_header_line(&item.mnemonic, &item.unit, &item.value, &item.description));
+            }
+        }
+
+        if !self.other.is_empty() {
+            out.push_str("~Other Information\n");
+            for line in &self.other {
+                out.push_str(line);
+                out.push('\n');
+            }
+        }
+
+        let names: Vec<&str> = self.curves.iter().map(|c| c.mnemonic.as_str()).collect();
+        out.push_str(&format!("~A  {}\n", names.join(" ")));
+        out
+    }
+
+    /// Строки секции ~A для диапазона строк данных; пропуски записываются как NULL
+    pub fn write_data_rows(&self, rows: std::ops::Range<usize>) -> String {
+        let mut out = String::new();
+        for row in &self.data[rows] {
+            for (idx, &value) in row.values.iter().enumerate() {
+                let value = if value.is_nan() { self.null_value } else { value };
+                if idx > 0 {
+                    out.push(' ');
+                }
+                out.push_str(&format!("{:>12}", format_number(value)));
+            }
+            out.push('\n');
+        }
+        out
+    }
 }
 
+/// Округление до `digits` значащих цифр - убирает хвосты вроде `0.1524000000000001` после пересчётов
+pub fn round_significant(value: f64, digits: i32) -> f64 {
+    if value == 0.0 || !value.is_finite() {
+        return value;
+    }
+    let scale = 10_f64.powi(digits - 1 - value.abs().log10().floor() as i32);
+    (value * scale).round() / scale
+}
+
+/// Число без лишних нулей (`100`, `2.35`, `-999.25`)
+pub fn format_number(value: f64) -> String {
+    format!("{}", value)
+}
+
+/// Строка заголовочной секции в формате `MNEM.UNIT  VALUE : DESCRIPTION`.
+/// Точки в мнемонике и пробелы/двоеточия в единице недопустимы - заменяются на `_`.
+fn format_header_line(mnemonic: &str, unit: &str, value: &str, description: &str) -> String {
+    let mnemonic = mnemonic.replace(['.', ':'], "_");
+    let unit = unit.replace(|c: char| c.is_whitespace() || c == ':', "_");
+    let description = description.replace(['\r', '\n'], " ");
+    let value = value.replace(['\r', '\n'], " ");
+    format!(" {:<8}.{:<10} {:>20} : {}\n", mnemonic, unit, value, description)
+}
@@ -0,0 +1,182 @@
+//! Список LAS файлов для тестовой страницы: laslist_file или содержимое samples_dir.
+
+use anyhow::{Context, Result};
+use lasplot::config::Config;
+use std::path::PathBuf;
+
+/// Путь к списку файлов; по умолчанию "lasfiles.txt"
+pub fn laslist_path(config: &Config) -> PathBuf {
+    if config.laslist_file.is_empty() {
+        PathBuf::from("lasfiles.txt")
+    } else {
+        PathBuf::from(&config.laslist_file)
+    }
+}
+
+fn get_files_from_samples(config: &Config) -> Vec<String> {
+    let samples_path = config.get_samples_path();
+    let mut files = Vec::new();
+    
+    if let Ok(entries) = std::fs::read_dir(&samples_path) {
+        for entry in entries.flatten() {
+            let path = entry.path();
+            if path.is_file() {
+                if let Some(ext) = path.extension() {
+                    if ext == "las" || ext == "LAS" {
+                        if let Some(name) = path.file_name() {
+                            if let Some(name_str) = name.to_str() {
+                                files.push(name_str.to_string());
+                            }
+                        }
+                    }
+                }
+            }
+        }
+    }
+    
+    files.sort();
+    files
+}
+
+/// Строка списка файлов для тестовой страницы
+#[derive(Debug, Clone)]
+pub struct LasFileInfo {
+    pub url: String,
+    pub operator: Option<String>,
+    pub lease: Option<String>,
+    pub depth_start: Option<String>,
+    pub depth_stop: Option<String>,
+}
+
+fn parse_csv_line(line: &str) -> Vec<String> {
+    let mut fields = Vec::new();
+    let mut current = String::new();
+    let mut in_quotes = false;
+    
+    for ch in line.chars() {
+        match ch {
+            '"' => {
+                in_quotes = !in_quotes;
+            }
+            ',' if !in_quotes => {
+                fields.push(current.trim_matches('"').to_string());
+                current.clear();
+            }
+            _ => {
+                current.push(ch);
+            }
+        }
+    }
+    if !current.is_empty() {
+        fields.push(current.trim_matches('"').to_string());
+    }
+    fields
+}
+
+fn is_csv_format(content: &str) -> bool {
+    // Проверяем, является ли файл CSV форматом
+    // CSV формат обычно начинается с заголовка в кавычках
+    let first_line = content.lines().next().unwrap_or("").trim();
+    first_line.starts_with('"') && first_line.contains(',')
+}
+
+/// Список файлов из laslist_file (простой список или CSV с колонками URL, Operator, Lease,
+/// Depth_start, Depth_stop); если файла нет - LAS файлы из samples_dir
+pub fn read_laslist_file_with_info(config: &Config) -> Result<Vec<LasFileInfo>> {
+    let laslist_path = laslist_path(config);
+
+    // Если файл не существует, используем список из samples
+    if !laslist_path.exists() {
+        let files = get_files_from_samples(config);
+        return Ok(files.into_iter().map(|url| LasFileInfo {
+            url,
+            operator: None,
+            lease: None,
+            depth_start: None,
+            depth_stop: None,
+        }).collect());
+    }
+    
+    let content = std::fs::read_to_string(&laslist_path)
+        .with_context(|| format!("Failed to read {:?}", laslist_path))?;
+    
+    // Определяем формат файла
+    if is_csv_format(&content) {
+        // CSV формат
+        let lines: Vec<&str> = content.lines().collect();
+        if lines.is_empty() {
+            return Ok(Vec::new());
+        }
+        
+        // Парсим заголовок
+        let header = parse_csv_line(lines[0]);
+        let url_idx = header.iter().position(|s| s == "URL").unwrap_or(usize::MAX);
+        let operator_idx = header.iter().position(|s| s == "Operator").unwrap_or(usize::MAX);
+        let lease_idx = header.iter().position(|s| s == "Lease").unwrap_or(usize::MAX);
+        let depth_start_idx = header.iter().position(|s| s == "Depth_start").unwrap_or(usize::MAX);
+        let depth_stop_idx = header.iter().position(|s| s == "Depth_stop").unwrap_or(usize::MAX);
+        
+        if url_idx == usize::MAX {
+            return Ok(Vec::new());
+        }
+        
+        let mut files_info = Vec::new();
+        for line in lines.iter().skip(1) {
+            let trimmed = line.trim();
+            if trimmed.is_empty() || trimmed.starts_with('#') {
+                continue;
+            }
+            
+            let fields = parse_csv_line(trimmed);
+            if url_idx < fields.len() {
+                let url = fields[url_idx].clone();
+                if !url.is_empty() {
+                    files_info.push(LasFileInfo {
+                        url,
+                        operator: if operator_idx < fields.len() && !fields[operator_idx].is_empty() {
+                            Some(fields[operator_idx].clone())
+                        } else {
+                            None
+                        },
+                        lease: if lease_idx < fields.len() && !fields[lease_idx].is_empty() {
+                            Some(fields[lease_idx].clone())
+                        } else {
+                            None
+                        },
+                        depth_start: if depth_start_idx < fields.len() && !fields[depth_start_idx].is_empty() {
+                            Some(fields[depth_start_idx].clone())
+                        } else {
+                            None
+                        },
+                        depth_stop: if depth_stop_idx < fields.len() && !fields[depth_stop_idx].is_empty() {
+                            Some(fields[depth_stop_idx].clone())
+                        } else {
+                            None
+                        },
+                    });
+                }
+            }
+        }
+        
+        Ok(files_info)
+    } else {
+        // Простой формат - список файлов
+        let files: Vec<String> = content
+            .lines()
+            .map(|s| s.trim())
+            .filter(|s| {
+                // Игнорируем пустые строки и комментарии
+                !s.is_empty() && !s.starts_with('#')
+            })
+            .map(|s| s.to_string())
+            .collect();
+        
+        Ok(files.into_iter().map(|url| LasFileInfo {
+            url,
+            operator: None,
+            lease: None,
+            depth_start: None,
+            depth_stop: None,
+        }).collect())
+    }
+}
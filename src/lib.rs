@@ -0,0 +1,58 @@
+//! Разбор LAS файлов (Log ASCII Standard 1.2/2.0) и отрисовка каротажных диаграмм.
+//!
+//! Основные части:
+//! - [`las::LasFile`] - разбор, выборки и запись LAS, [`resample`] - перевод на другую сетку индекса,
+//!   [`units`] - перевод единиц (`units=metric|imperial`), [`families`] - семейства кривых по словарю синонимов,
+//!   [`petrophysics`] - расчётные кривые (глинистость, пористость, водонасыщенность),
+//!   [`derived`] - производные кривые по формулам (`RATIO = ILD / MSFL`), [`tops`] - кровли пластов,
+//!   [`stats`] - статистика по зонам и эффективные толщины, [`survey`] - инклинометрия и TVD,
+//!   [`edits`] - правка кривых (сдвиг, растяжение, обнуление интервала, удаление выбросов);
+//! - [`plot`] - отрисовка кривых и шкал в картинку ([`plot::PlotConfig`], [`plot::generate_plot_png`]);
+//! - [`view`] - подготовка файла к отображению ([`view::LogView`]) и потоковая генерация
+//!   HTML страницы ([`view::generate_html`]);
+//! - [`render`] - готовые PNG/SVG/PDF/HTML документы, [`pool`] - пул потоков отрисовки;
+//! - [`charts`] - кросс-плоты и гистограммы кривых (PNG/SVG), [`correlate`] - корреляция нескольких скважин,
+//!   [`compare`] - сравнение двух файлов;
+//! - [`api`], [`export`], [`arrow`] - выборки данных и выгрузка в CSV/JSON/LAS/Arrow/Parquet.
+//!
+//! HTTP сервер и командная строка живут в бинарнике `lasplot` (feature `server`),
+//! библиотека от actix/reqwest/tokio не зависит.
+//!
+//! ```no_run
+//! use lasplot::las::LasFile;
+//! use lasplot::render::{render, RenderFormat};
+//! use lasplot::view::{LogView, PageLayout};
+//!
+//! # fn main() -> anyhow::Result<()> {
+//! let las_file = LasFile::read("well.las".as_ref())?;
+//! let view = LogView::new(&las_file, None, &["FF0000".to_string()])?;
+//! let pdf = render(view, &PageLayout::default(), RenderFormat::Pdf, "well.las")?;
+//! std::fs::write("well.pdf", pdf)?;
+//! # Ok(())
+//! # }
+//! ```
+
+pub mod api;
+pub mod arrow;
+pub mod charts;
+pub mod compare;
+pub mod config;
+pub mod convert;
+pub mod correlate;
+pub mod derived;
+pub mod edits;
+pub mod export;
+pub mod families;
+pub mod html;
+pub mod las;
+pub mod pdf;
+pub mod petrophysics;
+pub mod plot;
+pub mod pool;
+pub mod render;
+pub mod resample;
+pub mod stats;
+pub mod survey;
+pub mod tops;
+pub mod units;
+pub mod view;
@@ -1,244 +1,99 @@
-mod config;
-mod las;
-mod plot;
+mod cli;
+mod laslist;
+mod state;
+mod upload;
 
 use actix_web::{web, App, HttpRequest, HttpResponse, HttpServer, Result as ActixResult};
-use actix_web::web::Bytes;
 use anyhow::{Context, Result};
-use base64::Engine;
-use config::Config;
-use futures::future::ok;
-use futures::stream::{self, once, StreamExt};
+use clap::Parser;
+use cli::{Cli, Command};
 use futures::TryStreamExt;
-use las::LasFile;
-use plot::{hex_to_rgb, generate_plot_png, PlotConfig, RGBColor};
-use std::collections::hash_map::DefaultHasher;
-use std::hash::{Hash, Hasher};
+use lasplot::charts::{self, ChartFormat, CrossplotSpec, HistogramSpec};
+use lasplot::compare::{self, CompareSpec};
+use lasplot::config::Config;
+use lasplot::correlate::{self, Correlation, CorrelationSpec};
+use lasplot::html::{content_security_policy, encode_query_value, escape_attr, escape_html, generate_nonce};
+use lasplot::derived::DerivedCurve;
+use lasplot::edits::Edit;
+use lasplot::las::LasFile;
+use lasplot::petrophysics::Computation;
+use lasplot::stats::{ReportTable, Zone, ZoneReport};
+use lasplot::survey::Survey;
+use lasplot::tops::TopsFile;
+use lasplot::view::{self, LogView};
+use lasplot::{api, export};
+use state::AppState;
 use std::sync::Arc;
+use std::time::Duration;
 use url::Url;
 
-#[actix_web::main]
-async fn main() -> std::io::Result<()> {
-    let config = Config::load().expect("Failed to load config");
-    let config = Arc::new(config);
+fn main() {
+    let cli = Cli::parse();
+    let config_path = cli.config.as_deref();
+    let result = match cli.command.unwrap_or(Command::Serve) {
+        Command::Serve => Config::load(config_path).and_then(|config| {
+            actix_web::rt::System::new().block_on(serve(config, cli.config.clone()))
+        }),
+        Command::Render(args) => cli::run_render(&args, config_path),
+        Command::Info(args) => cli::run_info(&args),
+        Command::Convert(args) => cli::run_convert(&args),
+        Command::CheckConfig => cli::run_check_config(config_path),
+    };
+    if let Err(e) = result {
+        eprintln!("Error: {:#}", e);
+        std::process::exit(1);
+    }
+}
 
+async fn serve(config: Config, config_path: Option<std::path::PathBuf>) -> Result<()> {
+    config.check_directories()?;
     let bind_addr = format!("{}:{}", config.bind_address, config.bind_port);
+    let state = web::Data::new(AppState::new(config, config_path));
     println!("Starting lasplot server on http://{}", bind_addr);
-    
-    HttpServer::new(move || {
-        let config = Arc::clone(&config);
-        App::new()
-            .app_data(web::Data::new(config.clone()))
-            .route("/", web::get().to(handle_request))
-            .route("/test", web::get().to(handle_test_page))
-            .route("/list", web::get().to(handle_list_files))
-    })
-    .bind(&bind_addr)?
-    .run()
-    .await
-}
 
-fn get_files_from_samples(config: &Config) -> Vec<String> {
-    let samples_path = config.get_samples_path();
-    let mut files = Vec::new();
-    
-    if let Ok(entries) = std::fs::read_dir(&samples_path) {
-        for entry in entries {
-            if let Ok(entry) = entry {
-                let path = entry.path();
-                if path.is_file() {
-                    if let Some(ext) = path.extension() {
-                        if ext == "las" || ext == "LAS" {
-                            if let Some(name) = path.file_name() {
-                                if let Some(name_str) = name.to_str() {
-                                    files.push(name_str.to_string());
-                                }
-                            }
-                        }
-                    }
+    // Перезагрузка lasplot.toml и списка файлов при изменениях
+    state::spawn_watcher(state.clone());
+
+    // Периодически удаляем устаревшие загрузки
+    {
+        let state = state.clone();
+        actix_web::rt::spawn(async move {
+            let mut interval = actix_web::rt::time::interval(Duration::from_secs(600));
+            loop {
+                interval.tick().await;
+                let config = state.config();
+                let removed = web::block(move || upload::cleanup_expired(&config)).await.unwrap_or(0);
+                if removed > 0 {
+                    println!("Removed {} expired uploads", removed);
                 }
             }
-        }
-    }
-    
-    files.sort();
-    files
-}
-
-#[derive(Debug, Clone)]
-struct LasFileInfo {
-    url: String,
-    operator: Option<String>,
-    lease: Option<String>,
-    depth_start: Option<String>,
-    depth_stop: Option<String>,
-}
-
-fn parse_csv_line(line: &str) -> Vec<String> {
-    let mut fields = Vec::new();
-    let mut current = String::new();
-    let mut in_quotes = false;
-    
-    for ch in line.chars() {
-        match ch {
-            '"' => {
-                in_quotes = !in_quotes;
-            }
-            ',' if !in_quotes => {
-                fields.push(current.trim_matches('"').to_string());
-                current.clear();
-            }
-            _ => {
-                current.push(ch);
-            }
-        }
-    }
-    if !current.is_empty() {
-        fields.push(current.trim_matches('"').to_string());
+        });
     }
-    fields
-}
-
-fn is_csv_format(content: &str) -> bool {
-    // Проверяем, является ли файл CSV форматом
-    // CSV формат обычно начинается с заголовка в кавычках
-    let first_line = content.lines().next().unwrap_or("").trim();
-    first_line.starts_with('"') && first_line.contains(',')
-}
 
-fn read_laslist_file(config: &Config) -> Result<Vec<String>> {
-    let laslist_path = &config.laslist_file;
-    
-    // Если файл не существует, используем список из samples
-    if !std::path::Path::new(laslist_path).exists() {
-        return Ok(get_files_from_samples(config));
-    }
-    
-    let content = std::fs::read_to_string(laslist_path)
-        .context("Failed to read laslist.txt")?;
-    
-    // Определяем формат файла
-    if is_csv_format(&content) {
-        // CSV формат - возвращаем пустой список, так как нужна дополнительная информация
-        return Ok(Vec::new());
-    }
-    
-    // Простой формат - список файлов
-    let files: Vec<String> = content
-        .lines()
-        .map(|s| s.trim())
-        .filter(|s| {
-            // Игнорируем пустые строки и комментарии
-            !s.is_empty() && !s.starts_with('#')
-        })
-        .map(|s| s.to_string())
-        .collect();
-    
-    Ok(files)
+    HttpServer::new(move || {
+        App::new()
+            .app_data(state.clone())
+            .configure(routes)
+    })
+    .bind(&bind_addr)?
+    .run()
+    .await?;
+    Ok(())
 }
 
-fn read_laslist_file_with_info(config: &Config) -> Result<Vec<LasFileInfo>> {
-    // Используем laslist_file из конфигурации, или по умолчанию "lasfiles.txt"
-    let laslist_path = if config.laslist_file.is_empty() {
-        "lasfiles.txt"
-    } else {
-        &config.laslist_file
-    };
-    
-    // Если файл не существует, используем список из samples
-    if !std::path::Path::new(laslist_path).exists() {
-        let files = get_files_from_samples(config);
-        return Ok(files.into_iter().map(|url| LasFileInfo {
-            url,
-            operator: None,
-            lease: None,
-            depth_start: None,
-            depth_stop: None,
-        }).collect());
-    }
-    
-    let content = std::fs::read_to_string(laslist_path)
-        .context("Failed to read laslist.txt")?;
-    
-    // Определяем формат файла
-    if is_csv_format(&content) {
-        // CSV формат
-        let lines: Vec<&str> = content.lines().collect();
-        if lines.is_empty() {
-            return Ok(Vec::new());
-        }
-        
-        // Парсим заголовок
-        let header = parse_csv_line(lines[0]);
-        let url_idx = header.iter().position(|s| s == "URL").unwrap_or(usize::MAX);
-        let operator_idx = header.iter().position(|s| s == "Operator").unwrap_or(usize::MAX);
-        let lease_idx = header.iter().position(|s| s == "Lease").unwrap_or(usize::MAX);
-        let depth_start_idx = header.iter().position(|s| s == "Depth_start").unwrap_or(usize::MAX);
-        let depth_stop_idx = header.iter().position(|s| s == "Depth_stop").unwrap_or(usize::MAX);
-        
-        if url_idx == usize::MAX {
-            return Ok(Vec::new());
-        }
-        
-        let mut files_info = Vec::new();
-        for line in lines.iter().skip(1) {
-            let trimmed = line.trim();
-            if trimmed.is_empty() || trimmed.starts_with('#') {
-                continue;
-            }
-            
-            let fields = parse_csv_line(trimmed);
-            if url_idx < fields.len() {
-                let url = fields[url_idx].clone();
-                if !url.is_empty() {
-                    files_info.push(LasFileInfo {
-                        url,
-                        operator: if operator_idx < fields.len() && !fields[operator_idx].is_empty() {
-                            Some(fields[operator_idx].clone())
-                        } else {
-                            None
-                        },
-                        lease: if lease_idx < fields.len() && !fields[lease_idx].is_empty() {
-                            Some(fields[lease_idx].clone())
-                        } else {
-                            None
-                        },
-                        depth_start: if depth_start_idx < fields.len() && !fields[depth_start_idx].is_empty() {
-                            Some(fields[depth_start_idx].clone())
-                        } else {
-                            None
-                        },
-                        depth_stop: if depth_stop_idx < fields.len() && !fields[depth_stop_idx].is_empty() {
-                            Some(fields[depth_stop_idx].clone())
-                        } else {
-                            None
-                        },
-                    });
-                }
-            }
-        }
-        
-        Ok(files_info)
-    } else {
-        // Простой формат - список файлов
-        let files: Vec<String> = content
-            .lines()
-            .map(|s| s.trim())
-            .filter(|s| {
-                // Игнорируем пустые строки и комментарии
-                !s.is_empty() && !s.starts_with('#')
-            })
-            .map(|s| s.to_string())
-            .collect();
-        
-        Ok(files.into_iter().map(|url| LasFileInfo {
-            url,
-            operator: None,
-            lease: None,
-            depth_start: None,
-            depth_stop: None,
-        }).collect())
-    }
+fn routes(cfg: &mut web::ServiceConfig) {
+    cfg.route("/", web::get().to(handle_request))
+        .route("/test", web::get().to(handle_test_page))
+        .route("/list", web::get().to(handle_list_files))
+        .route("/upload", web::post().to(handle_upload))
+        .route("/api/las", web::get().to(handle_api_las))
+        .route("/api/data", web::get().to(handle_api_data))
+        .route("/api/zones", web::get().to(handle_api_zones))
+        .route("/crossplot", web::get().to(handle_crossplot))
+        .route("/histogram", web::get().to(handle_histogram))
+        .route("/correlate", web::get().to(handle_correlate))
+        .route("/compare", web::get().to(handle_compare))
+        .route("/export", web::get().to(handle_export));
 }
 
 fn is_url(s: &str) -> bool {
@@ -261,28 +116,28 @@ async fn get_las_version(file_path: &str, config: &Config) -> Option<String> {
 }
 
 async fn handle_test_page(
-    config: web::Data<Arc<Config>>,
+    state: web::Data<AppState>,
 ) -> ActixResult<HttpResponse> {
-    let files_info = read_laslist_file_with_info(&config)
-        .map_err(|e| actix_web::error::ErrorInternalServerError(format!("Failed to read laslist: {}", e)))?;
-    
+    let config = state.config();
+    let files_info = state.laslist();
+
     // Создаем список файлов с информацией
     let mut file_items = Vec::new();
-    for file_info in &files_info {
+    for file_info in files_info.iter() {
         let url = &file_info.url;
         let mut extra_info = Vec::new();
         
         // Добавляем информацию об Operator и Lease
         if let Some(ref operator) = file_info.operator {
-            extra_info.push(format!("Operator: {}", operator));
+            extra_info.push(format!("Operator: {}", escape_html(operator)));
         }
         if let Some(ref lease) = file_info.lease {
-            extra_info.push(format!("Lease: {}", lease));
+            extra_info.push(format!("Lease: {}", escape_html(lease)));
         }
         
         // Добавляем диапазон глубины
         if let (Some(ref start), Some(ref stop)) = (&file_info.depth_start, &file_info.depth_stop) {
-            extra_info.push(format!("Depth: {} - {}", start, stop));
+            extra_info.push(format!("Depth: {} - {}", escape_html(start), escape_html(stop)));
         }
         
         let extra_info_str = if !extra_info.is_empty() {
@@ -291,26 +146,29 @@ async fn handle_test_page(
             String::new()
         };
         
+        // Ссылка открывает график в новой вкладке; значение кодируется для URL и атрибута
+        let href = escape_attr(&format!("/?file={}", encode_query_value(url)));
         if is_url(url) {
             let server_name = get_server_name(url);
             file_items.push(format!(
-                "<li><a href=\"#\" onclick=\"openFile('{}'); return false;\">{}</a> <span style='font-size: 0.8em; color: #666;'>({})</span>{}</li>",
-                url, url, server_name, extra_info_str
+                "<li><a href=\"{}\" target=\"_blank\" rel=\"noopener\">{}</a> <span style='font-size: 0.8em; color: #666;'>({})</span>{}</li>",
+                href, escape_html(url), escape_html(&server_name), extra_info_str
             ));
         } else {
             // Локальный файл - получаем версию LAS
             let version_info = if let Some(version) = get_las_version(url, &config).await {
-                format!(" <span style='font-size: 0.8em; color: #666;'>(LAS {})</span>", version)
+                format!(" <span style='font-size: 0.8em; color: #666;'>(LAS {})</span>", escape_html(&version))
             } else {
                 String::new()
             };
             file_items.push(format!(
-                "<li><a href=\"#\" onclick=\"openFile('{}'); return false;\">{}</a>{}{}</li>",
-                url, url, version_info, extra_info_str
+                "<li><a href=\"{}\" target=\"_blank\" rel=\"noopener\">{}</a>{}{}</li>",
+                href, escape_html(url), version_info, extra_info_str
             ));
         }
     }
     
+    let nonce = generate_nonce();
     let html = format!(r#"
 <!DOCTYPE html>
 <html>
@@ -339,10 +197,27 @@ async fn handle_test_page(
         .file-list a:hover {{
             text-decoration: underline;
         }}
+        #drop-zone {{
+            margin-top: 20px;
+            padding: 30px;
+            border: 2px dashed #999;
+            border-radius: 6px;
+            text-align: center;
+            color: #666;
+        }}
+        #drop-zone.dragover {{
+            border-color: #0066cc;
+            background-color: #eef5ff;
+        }}
     </style>
 </head>
 <body>
     <h1>LAS Plot Test Page</h1>
+
+    <div id="drop-zone">
+        Drop a LAS file here or <input type="file" id="file-input" accept=".las,.LAS">
+        <div id="upload-status" style="margin-top: 10px;"></div>
+    </div>
     
     <div class="file-list">
         <h2>All LAS Files:</h2>
@@ -350,29 +225,59 @@ async fn handle_test_page(
             {}
         </ul>
     </div>
-    
-    <script>
-        function openFile(file) {{
-            window.open('/?file=' + encodeURIComponent(file), '_blank');
+
+    <script nonce="{}">
+        const zone = document.getElementById('drop-zone');
+        const input = document.getElementById('file-input');
+        const status = document.getElementById('upload-status');
+
+        function uploadFile(file) {{
+            status.textContent = 'Uploading ' + file.name + '...';
+            fetch('/upload', {{
+                method: 'POST',
+                headers: {{ 'Accept': 'application/json' }},
+                body: file
+            }})
+                .then(resp => resp.ok
+                    ? resp.json()
+                    : resp.text().then(text => {{ throw new Error(text || resp.statusText); }}))
+                .then(result => {{ window.location = result.url; }})
+                .catch(err => {{ status.textContent = 'Upload failed: ' + err.message; }});
         }}
+
+        zone.addEventListener('dragover', e => {{ e.preventDefault(); zone.classList.add('dragover'); }});
+        zone.addEventListener('dragleave', () => zone.classList.remove('dragover'));
+        zone.addEventListener('drop', e => {{
+            e.preventDefault();
+            zone.classList.remove('dragover');
+            if (e.dataTransfer.files.length > 0) {{
+                uploadFile(e.dataTransfer.files[0]);
+            }}
+        }});
+        input.addEventListener('change', () => {{
+            if (input.files.length > 0) {{
+                uploadFile(input.files[0]);
+            }}
+        }});
     </script>
 </body>
 </html>
 "#,
-        file_items.join("\n            ")
+        file_items.join("\n            "),
+        nonce
     );
     
     Ok(HttpResponse::Ok()
         .content_type("text/html; charset=utf-8")
+        .insert_header(("Content-Security-Policy", content_security_policy(Some(&nonce))))
+        .insert_header(("X-Content-Type-Options", "nosniff"))
         .body(html))
 }
 
 async fn handle_list_files(
-    config: web::Data<Arc<Config>>,
+    state: web::Data<AppState>,
 ) -> ActixResult<HttpResponse> {
-    let files_info = read_laslist_file_with_info(&config)
-        .map_err(|e| actix_web::error::ErrorInternalServerError(format!("Failed to read laslist: {}", e)))?;
-    
+    let files_info = state.laslist();
     let files: Vec<String> = files_info.iter().map(|f| f.url.clone()).collect();
     
     let json = serde_json::json!({
@@ -384,10 +289,407 @@ async fn handle_list_files(
         .body(json.to_string()))
 }
 
+fn query_params(req: &HttpRequest) -> std::collections::HashMap<String, String> {
+    url::form_urlencoded::parse(req.query_string().as_bytes())
+        .into_owned()
+        .collect()
+}
+
+/// Загружает и разбирает LAS файл по параметру `file=` и применяет правки (`edits=`); кровли (`tops=`)
+/// и инклинометрия (`survey=`) из отдельных файлов заменяют секции самого LAS файла
+async fn load_and_parse_las(
+    params: &std::collections::HashMap<String, String>,
+    config: &Config,
+) -> ActixResult<LasFile> {
+    let file_param = params
+        .get("file")
+        .ok_or_else(|| actix_web::error::ErrorBadRequest("Missing 'file' parameter"))?;
+
+    let las_content = load_las_file(file_param, config)
+        .await
+        .map_err(|e| actix_web::error::ErrorInternalServerError(format!("Failed to load LAS: {}", e)))?;
+    let tops_file = load_tops(params, config).await?;
+    let survey = load_survey(params, config).await?;
+    let edits = load_edits(params, config).await?;
+
+    Ok(parse_las_with(&las_content, &edits, tops_file.as_ref(), survey)?)
+}
+
+/// Ошибка разбора и подготовки данных (в том числе внутри `web::block`, куда ошибки actix
+/// не передаются): файл не разобрался - 500, параметры запроса не подходят к файлу - 400
+enum PrepareError {
+    Parse(anyhow::Error),
+    Request(anyhow::Error),
+}
+
+impl From<anyhow::Error> for PrepareError {
+    fn from(e: anyhow::Error) -> Self {
+        PrepareError::Request(e)
+    }
+}
+
+impl From<PrepareError> for actix_web::Error {
+    fn from(e: PrepareError) -> Self {
+        match e {
+            PrepareError::Parse(e) => actix_web::error::ErrorInternalServerError(format!("Failed to parse LAS: {}", e)),
+            PrepareError::Request(e) => actix_web::error::ErrorBadRequest(format!("{:#}", e)),
+        }
+    }
+}
+
+/// Разбирает LAS файл и применяет правки (`edits=`); кровли (`tops=`) и инклинометрия (`survey=`)
+/// из отдельных файлов заменяют секции самого файла
+fn parse_las_with(
+    content: &str,
+    edits: &[Edit],
+    tops_file: Option<&TopsFile>,
+    survey: Option<Survey>,
+) -> Result<LasFile, PrepareError> {
+    let mut las_file = LasFile::parse(content).map_err(PrepareError::Parse)?;
+    las_file.apply_edits(edits)?;
+    if let Some(tops_file) = tops_file {
+        las_file.tops = tops_file.for_well(&las_file)?;
+    }
+    if survey.is_some() {
+        las_file.survey = survey;
+    }
+    Ok(las_file)
+}
+
+async fn handle_api_las(
+    req: HttpRequest,
+    state: web::Data<AppState>,
+) -> ActixResult<HttpResponse> {
+    let config = state.config();
+    let params = query_params(&req);
+    let las_file = load_and_parse_las(&params, &config).await?;
+
+    Ok(HttpResponse::Ok()
+        .content_type("application/json")
+        .body(api::las_metadata(&las_file).to_string()))
+}
+
+async fn handle_api_data(
+    req: HttpRequest,
+    state: web::Data<AppState>,
+) -> ActixResult<HttpResponse> {
+    let config = state.config();
+    let params = query_params(&req);
+    let format = api::DataFormat::parse(params.get("format").map_or("json", |s| s.as_str()))
+        .map_err(actix_web::error::ErrorBadRequest)?;
+    let query = api::DataQuery::with_config(&config, &params).map_err(actix_web::error::ErrorBadRequest)?;
+
+    let las_file = load_and_parse_las(&params, &config).await?;
+    let selection = api::select_data(&las_file, &query).map_err(actix_web::error::ErrorBadRequest)?;
+
+    let body = match format {
+        api::DataFormat::Json => selection.to_json().to_string(),
+        api::DataFormat::Csv => selection.to_csv(),
+        api::DataFormat::Ndjson => selection.to_ndjson(),
+    };
+
+    Ok(HttpResponse::Ok()
+        .content_type(format.content_type())
+        .body(body))
+}
+
+/// Статистика по зонам и эффективные толщины (JSON, CSV или NDJSON).
+/// Зоны - из `zones=` или между кровлями (`tops=` либо ~Tops файла); без них - весь файл одной зоной.
+async fn handle_api_zones(
+    req: HttpRequest,
+    state: web::Data<AppState>,
+) -> ActixResult<HttpResponse> {
+    let config = state.config();
+    let params = query_params(&req);
+    let format = api::DataFormat::parse(params.get("format").map_or("json", |s| s.as_str()))
+        .map_err(actix_web::error::ErrorBadRequest)?;
+    let table = ReportTable::parse(params.get("table").map_or("", |s| s.as_str()))
+        .map_err(actix_web::error::ErrorBadRequest)?;
+    let query = api::DataQuery::with_config(&config, &params).map_err(actix_web::error::ErrorBadRequest)?;
+    let zones = Zone::parse_list(params.get("zones").map_or("", |s| s.as_str()))
+        .map_err(actix_web::error::ErrorBadRequest)?;
+    let cutoffs = config.cutoffs.with_params(&params).map_err(actix_web::error::ErrorBadRequest)?;
+
+    let las_file = load_and_parse_las(&params, &config).await?;
+    let selected = api::select_las(&las_file, &query).map_err(actix_web::error::ErrorBadRequest)?;
+
+    // Кровли заданы по индексу файла - зоны по ним только для основного параметра-индекса
+    let by_index = selected.curves.first().map(|c| &c.mnemonic) == las_file.curves.first().map(|c| &c.mnemonic);
+    let zones = match zones {
+        zones if !zones.is_empty() => zones,
+        _ => {
+            let zones = if by_index { Zone::from_tops(&selected, 0) } else { Vec::new() };
+            if zones.is_empty() {
+                let (top, bottom) = selected.get_curve_stats(0).unwrap_or((0.0, 0.0));
+                vec![Zone { name: "All".to_string(), top, bottom }]
+            } else {
+                zones
+            }
+        }
+    };
+    let report = ZoneReport::compute(&selected, 0, &zones, &cutoffs).map_err(actix_web::error::ErrorBadRequest)?;
+
+    let body = match format {
+        api::DataFormat::Json => report.to_json().to_string(),
+        api::DataFormat::Csv => report.to_csv(table),
+        api::DataFormat::Ndjson => report.to_ndjson(),
+    };
+
+    Ok(HttpResponse::Ok()
+        .content_type(format.content_type())
+        .body(body))
+}
+
+/// Кросс-плот двух кривых (PNG или SVG), см. [`CrossplotSpec`]
+async fn handle_crossplot(
+    req: HttpRequest,
+    state: web::Data<AppState>,
+) -> ActixResult<HttpResponse> {
+    let config = state.config();
+    let params = query_params(&req);
+    let format = ChartFormat::parse(params.get("format").map_or("", |s| s.as_str()))
+        .map_err(actix_web::error::ErrorBadRequest)?;
+    let spec = CrossplotSpec::from_params(&params).map_err(actix_web::error::ErrorBadRequest)?;
+    let mut query = api::DataQuery::with_config(&config, &params).map_err(actix_web::error::ErrorBadRequest)?;
+    query.curves = Some(spec.curves());
+
+    let las_file = load_and_parse_las(&params, &config).await?;
+    let body = web::block(move || -> Result<Vec<u8>> {
+        let selected = api::select_las(&las_file, &query)?;
+        charts::crossplot(&selected, &spec, &query.petrophysics, format)
+    })
+    .await
+    .map_err(actix_web::error::ErrorInternalServerError)?
+    .map_err(|e| actix_web::error::ErrorBadRequest(format!("{:#}", e)))?;
+
+    Ok(HttpResponse::Ok()
+        .content_type(format.content_type())
+        .body(body))
+}
+
+/// Гистограмма кривой (PNG или SVG), см. [`HistogramSpec`]
+async fn handle_histogram(
+    req: HttpRequest,
+    state: web::Data<AppState>,
+) -> ActixResult<HttpResponse> {
+    let config = state.config();
+    let params = query_params(&req);
+    let format = ChartFormat::parse(params.get("format").map_or("", |s| s.as_str()))
+        .map_err(actix_web::error::ErrorBadRequest)?;
+    let spec = HistogramSpec::from_params(&params).map_err(actix_web::error::ErrorBadRequest)?;
+    let mut query = api::DataQuery::with_config(&config, &params).map_err(actix_web::error::ErrorBadRequest)?;
+    query.curves = Some(vec![spec.axis.curve.clone()]);
+
+    let las_file = load_and_parse_las(&params, &config).await?;
+    let body = web::block(move || -> Result<Vec<u8>> {
+        let selected = api::select_las(&las_file, &query)?;
+        charts::histogram(&selected, &spec, format)
+    })
+    .await
+    .map_err(actix_web::error::ErrorInternalServerError)?
+    .map_err(|e| actix_web::error::ErrorBadRequest(format!("{:#}", e)))?;
+
+    Ok(HttpResponse::Ok()
+        .content_type(format.content_type())
+        .body(body))
+}
+
+/// Корреляция скважин на общей оси (HTML потоком), см. [`CorrelationSpec`];
+/// кровли - из ~Tops файлов или общего файла кровель `tops=` (по названию скважины)
+async fn handle_correlate(
+    req: HttpRequest,
+    state: web::Data<AppState>,
+) -> ActixResult<HttpResponse> {
+    let config = state.config();
+    let params = query_params(&req);
+    let spec = CorrelationSpec::from_params(&params).map_err(actix_web::error::ErrorBadRequest)?;
+    let (layout, colors) = view::layout_for_request(&config, &params)
+        .map_err(actix_web::error::ErrorBadRequest)?;
+    let units = api::units_param(&params).map_err(actix_web::error::ErrorBadRequest)?;
+
+    let contents = futures::future::try_join_all(spec.files.iter().map(|file| load_las_file(file, &config)))
+        .await
+        .map_err(|e| actix_web::error::ErrorInternalServerError(format!("Failed to load LAS: {}", e)))?;
+    let tops_file = load_tops(&params, &config).await?;
+
+    let aliases = config.alias_dictionary().map_err(actix_web::error::ErrorInternalServerError)?;
+    let layout_aliases = config.curve_families.then(|| aliases.clone());
+    let tops_colors = config.tops_colors.clone();
+    let correlation = web::block(move || -> Result<Correlation> {
+        let mut wells = Vec::new();
+        for (file, content) in spec.files.iter().zip(contents) {
+            let mut las_file = LasFile::parse(&content).with_context(|| format!("Failed to parse LAS {}", file))?;
+            if let Some(tops_file) = &tops_file {
+                las_file.tops = tops_file.for_well(&las_file)?;
+            }
+            las_file.add_trajectory(None)?;
+            if let Some(system) = units {
+                las_file.convert_units(system, None);
+            }
+            wells.push((file.clone(), las_file));
+        }
+        Correlation::build(wells, &spec, &colors, &aliases, layout_aliases.as_ref(), &tops_colors)
+    })
+    .await
+    .map_err(actix_web::error::ErrorInternalServerError)?
+    .map_err(|e| actix_web::error::ErrorBadRequest(format!("{:#}", e)))?;
+
+    let stream = correlate::generate_html(correlation, layout, state.render_pool())
+        .map_err(|e| actix_web::error::ErrorInternalServerError(format!("Failed to generate HTML: {}", e)))?
+        .map_err(actix_web::error::ErrorInternalServerError);
+
+    Ok(HttpResponse::Ok()
+        .content_type("text/html; charset=utf-8")
+        .insert_header(("Content-Security-Policy", content_security_policy(None)))
+        .insert_header(("X-Content-Type-Options", "nosniff"))
+        .streaming(stream))
+}
+
+/// Сравнение двух файлов (`a=`, `b=`, `shift=`): B на сетке индекса A, разности и различия заголовков,
+/// см. [`CompareSpec`]
+async fn handle_compare(
+    req: HttpRequest,
+    state: web::Data<AppState>,
+) -> ActixResult<HttpResponse> {
+    let config = state.config();
+    let params = query_params(&req);
+    let spec = CompareSpec::from_params(&params).map_err(actix_web::error::ErrorBadRequest)?;
+    let (layout, colors) = view::layout_for_request(&config, &params)
+        .map_err(actix_web::error::ErrorBadRequest)?;
+    let units = api::units_param(&params).map_err(actix_web::error::ErrorBadRequest)?;
+
+    let (content_a, content_b) = futures::future::try_join(load_las_file(&spec.a, &config), load_las_file(&spec.b, &config))
+        .await
+        .map_err(|e| actix_web::error::ErrorInternalServerError(format!("Failed to load LAS: {}", e)))?;
+
+    let aliases = config.alias_dictionary().map_err(actix_web::error::ErrorInternalServerError)?;
+    let layout_aliases = config.curve_families.then_some(aliases);
+    let title = format!("{} vs {}", spec.a, spec.b);
+    let view = web::block(move || -> Result<LogView> {
+        let mut files = Vec::new();
+        for (name, content) in [(&spec.a, content_a), (&spec.b, content_b)] {
+            let mut las_file = LasFile::parse(&content).with_context(|| format!("Failed to parse LAS {}", name))?;
+            if let Some(system) = units {
+                las_file.convert_units(system, None);
+            }
+            files.push(las_file);
+        }
+        let (combined, report) = compare::compare(&files[0], &files[1], &spec)?;
+        compare::comparison_view(&combined, report, &colors, layout_aliases.as_ref())
+    })
+    .await
+    .map_err(actix_web::error::ErrorInternalServerError)?
+    .map_err(|e| actix_web::error::ErrorBadRequest(format!("{:#}", e)))?;
+
+    let stream = view::generate_html(view, layout, &title, state.render_pool())
+        .map_err(|e| actix_web::error::ErrorInternalServerError(format!("Failed to generate HTML: {}", e)))?
+        .map_err(actix_web::error::ErrorInternalServerError);
+
+    Ok(HttpResponse::Ok()
+        .content_type("text/html; charset=utf-8")
+        .insert_header(("Content-Security-Policy", content_security_policy(None)))
+        .insert_header(("X-Content-Type-Options", "nosniff"))
+        .streaming(stream))
+}
+
+async fn handle_export(
+    req: HttpRequest,
+    state: web::Data<AppState>,
+) -> ActixResult<HttpResponse> {
+    let config = state.config();
+    let params = query_params(&req);
+    let format = export::ExportFormat::parse(params.get("format").map_or("csv", |s| s.as_str()))
+        .map_err(actix_web::error::ErrorBadRequest)?;
+    let null_repr = export::NullRepr::parse(params.get("null").map_or("", |s| s.as_str()))
+        .map_err(actix_web::error::ErrorBadRequest)?;
+    let query = api::DataQuery::with_config(&config, &params).map_err(actix_web::error::ErrorBadRequest)?;
+
+    let las_file = load_and_parse_las(&params, &config).await?;
+    let selected = api::select_las(&las_file, &query).map_err(actix_web::error::ErrorBadRequest)?;
+
+    // Имя файла для сохранения: последний сегмент пути/URL без расширения
+    let file_param = params.get("file").map_or("export", |s| s.as_str());
+    let base_name = file_param
+        .rsplit(['/', '\\', ':'])
+        .next()
+        .and_then(|name| name.split('.').next())
+        .filter(|name| !name.is_empty())
+        .unwrap_or("export");
+    let base_name: String = base_name
+        .chars()
+        .map(|c| if c.is_ascii_alphanumeric() || c == '-' || c == '_' { c } else { '_' })
+        .collect();
+
+    let stream = export::export_stream(selected, format, null_repr)
+        .map_err(actix_web::error::ErrorInternalServerError);
+
+    Ok(HttpResponse::Ok()
+        .content_type(format.content_type())
+        .insert_header((
+            "Content-Disposition",
+            format!("attachment; filename=\"{}.{}\"", base_name, format.extension()),
+        ))
+        .streaming(stream))
+}
+
+async fn handle_upload(
+    req: HttpRequest,
+    mut payload: web::Payload,
+    state: web::Data<AppState>,
+) -> ActixResult<HttpResponse> {
+    // Предел берётся из текущей конфигурации, чтобы max_upload_size менялся без перезапуска
+    let config = state.config();
+    let too_large = || {
+        actix_web::error::ErrorPayloadTooLarge(format!("Upload is larger than max_upload_size ({} bytes)", config.max_upload_size))
+    };
+    let mut body = web::BytesMut::new();
+    while let Some(chunk) = payload.try_next().await? {
+        if body.len() + chunk.len() > config.max_upload_size {
+            return Err(too_large());
+        }
+        body.extend_from_slice(&chunk);
+    }
+    if body.is_empty() {
+        return Err(actix_web::error::ErrorBadRequest("Empty upload"));
+    }
+
+    let store_config = Arc::clone(&config);
+    let id = web::block(move || {
+        upload::cleanup_expired(&store_config);
+        upload::store_upload(&store_config, &body)
+    })
+    .await?
+    .map_err(|e| actix_web::error::ErrorBadRequest(format!("Invalid LAS file: {}", e)))?;
+
+    let location = format!("/?file={}{}", upload::UPLOAD_PREFIX, id);
+
+    // Скрипт страницы /test просит JSON, обычные клиенты получают редирект
+    let wants_json = req
+        .headers()
+        .get(actix_web::http::header::ACCEPT)
+        .and_then(|v| v.to_str().ok())
+        .is_some_and(|v| v.contains("application/json"));
+    if wants_json {
+        let json = serde_json::json!({
+            "id": id,
+            "url": location,
+        });
+        return Ok(HttpResponse::Created()
+            .content_type("application/json")
+            .append_header(("Location", location))
+            .body(json.to_string()));
+    }
+
+    Ok(HttpResponse::SeeOther()
+        .append_header(("Location", location))
+        .finish())
+}
+
 async fn handle_request(
     req: HttpRequest,
-    config: web::Data<Arc<Config>>,
+    state: web::Data<AppState>,
 ) -> ActixResult<HttpResponse> {
+    let config = state.config();
     let query = req.query_string();
     let params: std::collections::HashMap<String, String> = 
         url::form_urlencoded::parse(query.as_bytes())
@@ -404,540 +706,262 @@ async fn handle_request(
         }
     };
 
-    // Получаем цвета из параметров или используем дефолтные
-    let colors: Vec<String> = if let Some(colors_param) = params.get("colors") {
-        colors_param.split(',').map(|s| s.trim().to_string()).collect()
-    } else {
-        config.default_colors.clone()
-    };
+    // Параметры отображения: конфигурация, пресет (preset=) и параметры запроса
+    let (layout, colors) = view::layout_for_request(&config, &params)
+        .map_err(actix_web::error::ErrorBadRequest)?;
+    let units = api::units_param(&params).map_err(actix_web::error::ErrorBadRequest)?;
+    // Расчётные кривые (compute=vsh,phid,...) с параметрами из [petrophysics] и запроса
+    let compute = Computation::parse_list(params.get("compute").map_or("", |s| s.as_str()))
+        .map_err(actix_web::error::ErrorBadRequest)?;
+    let petro_params = config.petrophysics.with_params(&params).map_err(actix_web::error::ErrorBadRequest)?;
+    // Производные кривые: сначала derived_curves из конфигурации, затем derive=RATIO=ILD/MSFL;...
+    let mut derived = config.derived_curves().map_err(actix_web::error::ErrorInternalServerError)?;
+    derived.extend(
+        DerivedCurve::parse_list(params.get("derive").map_or("", |s| s.as_str()))
+            .map_err(actix_web::error::ErrorBadRequest)?,
+    );
 
     // Загружаем LAS файл
     let las_content = load_las_file(file_param, &config)
         .await
         .map_err(|e| actix_web::error::ErrorInternalServerError(format!("Failed to load LAS: {}", e)))?;
 
-    let las_file = LasFile::parse(&las_content)
-        .map_err(|e| actix_web::error::ErrorInternalServerError(format!("Failed to parse LAS: {}", e)))?;
-
-    // Определяем основной параметр: из GET-параметра или первый параметр из LAS
-    let main_param_name = params.get("main_param")
-        .map(|s| s.as_str())
-        .unwrap_or_else(|| {
-            // По умолчанию - первый параметр из LAS файла
-            las_file.curves.first()
-                .map(|c| c.mnemonic.as_str())
-                .unwrap_or("DEPT")
-        });
-
-    // Находим индекс основного параметра
-    let main_param_idx = las_file
-        .get_main_parameter_index(main_param_name)
-        .ok_or_else(|| actix_web::error::ErrorInternalServerError(format!("Main parameter '{}' not found", main_param_name)))?;
-
-    // Получаем данные глубины
-    let depth_data = las_file.get_curve_data(main_param_idx);
-    
-    // Находим диапазон глубины
-    let (depth_min, depth_max) = las_file
-        .get_curve_stats(main_param_idx)
-        .ok_or_else(|| actix_web::error::ErrorInternalServerError("No depth data"))?;
-
-    // Подготавливаем данные кривых (исключаем основной параметр)
-    let mut curves_data = Vec::new();
-    let mut x_ranges = Vec::new();
-    let mut plot_colors = Vec::new();
-    let mut color_hex_strings = colors.clone();
-
-    // Генерируем случайные цвета для недостающих параметров
-    let curves = Arc::new(las_file.curves.clone());
-    for (idx, curve) in curves.iter().enumerate() {
-        if idx == main_param_idx {
-            continue; // Пропускаем основной параметр
+    let tops_file = load_tops(&params, &config).await?;
+    let survey = load_survey(&params, &config).await?;
+    let edits = load_edits(&params, &config).await?;
+    let kb = api::number_param(&params, "kb").map_err(actix_web::error::ErrorBadRequest)?;
+    // Отчёт по зонам: zones=Верх:1000-1050;... или зоны между кровлями
+    let zones = Zone::parse_list(params.get("zones").map_or("", |s| s.as_str()))
+        .map_err(actix_web::error::ErrorBadRequest)?;
+    let cutoffs = config.cutoffs.with_params(&params).map_err(actix_web::error::ErrorBadRequest)?;
+
+    // Разбор и подготовка данных тоже не должны занимать поток actix
+    let main_param = params.get("main_param").cloned();
+    let aliases = config.alias_dictionary().map_err(actix_web::error::ErrorInternalServerError)?;
+    let layout_aliases = config.curve_families.then(|| aliases.clone());
+    let tops_colors = config.tops_colors.clone();
+    let view = web::block(move || -> Result<LogView, PrepareError> {
+        let mut las_file = parse_las_with(&las_content, &edits, tops_file.as_ref(), survey)?;
+        // TVD, TVDSS, NORTH, EAST по инклинометрии - можно выбрать основным параметром
+        las_file.add_trajectory(kb)?;
+        if let Some(system) = units {
+            las_file.convert_units(system, None);
         }
-
-        let curve_data = las_file.get_curve_data(idx);
-        if let Some((min, max)) = las_file.get_curve_stats(idx) {
-            curves_data.push((curve_data, curve.mnemonic.clone()));
-            x_ranges.push((min, max));
-            
-            let color_idx = curves_data.len() - 1;
-            if color_idx < color_hex_strings.len() {
-                plot_colors.push(hex_to_rgb(&color_hex_strings[color_idx]));
-            } else {
-                // Генерируем случайный цвет на основе индекса (фиксированный для данной обработки)
-                let mut hasher = DefaultHasher::new();
-                (idx, &curve.mnemonic).hash(&mut hasher);
-                let rng_seed = hasher.finish();
-                
-                // Простой генератор псевдослучайных чисел
-                let r = ((rng_seed >> 0) & 0xFF) as u8;
-                let g = ((rng_seed >> 8) & 0xFF) as u8;
-                let b = ((rng_seed >> 16) & 0xFF) as u8;
-                
-                // Убеждаемся, что цвет не слишком темный
-                let r = r.max(50);
-                let g = g.max(50);
-                let b = b.max(50);
-                
-                let hex_color = format!("{:02X}{:02X}{:02X}", r, g, b);
-                color_hex_strings.push(hex_color.clone());
-                plot_colors.push(hex_to_rgb(&hex_color));
-            }
-        }
-    }
-
-    if curves_data.is_empty() {
-        return Err(actix_web::error::ErrorInternalServerError("No curves to plot"));
-    }
-
-    // Вычисляем количество строк
-    // Высота = html_row_steps * pixels_per_step + 1
-    let row_height = config.html_row_steps * config.pixels_per_step + 1;
-
-    // Подготавливаем данные для заголовка (клонируем нужные части)
-    let curves_info: Vec<_> = curves.iter().map(|c| (c.mnemonic.clone(), c.unit.clone(), c.description.clone())).collect();
-    let curves_stats: Vec<_> = (0..curves.len())
-        .map(|i| las_file.get_curve_stats(i))
-        .collect();
-
-    // Создаем маппинг: индекс кривой -> hex цвет для всех кривых (кроме основного параметра)
-    let mut curve_to_color: std::collections::HashMap<usize, String> = std::collections::HashMap::new();
-    let mut color_idx = 0;
-    for (idx, _curve) in curves.iter().enumerate() {
-        if idx == main_param_idx {
-            continue; // Пропускаем основной параметр
-        }
-        
-        if color_idx < color_hex_strings.len() {
-            curve_to_color.insert(idx, color_hex_strings[color_idx].clone());
-            color_idx += 1;
-        } else {
-            // Генерируем случайный цвет для этой кривой (должно быть уже в color_hex_strings, но на всякий случай)
-            let mut hasher = DefaultHasher::new();
-            (idx, &curves[idx].mnemonic).hash(&mut hasher);
-            let rng_seed = hasher.finish();
-            
-            let r = ((rng_seed >> 0) & 0xFF) as u8;
-            let g = ((rng_seed >> 8) & 0xFF) as u8;
-            let b = ((rng_seed >> 16) & 0xFF) as u8;
-            
-            let r = r.max(50);
-            let g = g.max(50);
-            let b = b.max(50);
-            
-            let hex_color = format!("{:02X}{:02X}{:02X}", r, g, b);
-            curve_to_color.insert(idx, hex_color);
+        las_file.add_computed(&compute, &petro_params, &aliases)?;
+        las_file.add_derived(&derived)?;
+        let view = LogView::with_aliases(&las_file, main_param.as_deref(), &colors, layout_aliases.as_ref())?;
+        let zones = match zones {
+            zones if !zones.is_empty() => zones,
+            _ if view.main_param_idx == 0 => Zone::from_tops(&las_file, 0),
+            _ => Vec::new(),
+        };
+        let view = view.with_top_colors(&tops_colors);
+        if zones.is_empty() {
+            return Ok(view);
         }
-    }
-
-    // Извлекаем информацию из секции ~Well
-    // Формат: (ключ, описание) - описание используется как заголовок
-    let well_info_keys = vec![
-        ("COMP", "COMPANY"),
-        ("WELL", "WELL"),
-        ("FLD", "FIELD"),
-        ("LOC", "LOCATION"),
-        ("SRVC", "SERVICE COMPANY"),
-        ("DATE", "LOG DATE"),
-        ("PROV", "PROVINCE"),
-    ];
-    
-    let well_info_text: Vec<(String, String)> = well_info_keys
-        .iter()
-        .filter_map(|(key, description)| {
-            // Пробуем найти ключ с точкой и без точки
-            las_file.well_info.get(*key)
-                .or_else(|| las_file.well_info.get(&format!("{}.", key)))
-                .map(|value| (description.to_string(), value.clone()))
-        })
-        .collect();
+        let report = ZoneReport::compute(&las_file, view.main_param_idx, &zones, &cutoffs)?;
+        Ok(view.with_zone_report(report))
+    })
+    .await
+    .map_err(actix_web::error::ErrorInternalServerError)??;
 
-    // Генерируем HTML stream
-    let stream = generate_html(
-        curves_info,
-        curves_stats,
-        curves_data.into(),
-        x_ranges,
-        plot_colors,
-        depth_data,//.into(),
-        depth_min,
-        depth_max,
-        config.html_row_steps,
-        config.pixels_per_step,
-        row_height,
-        config.image_width,
-        config.separate_depth_column,
-        main_param_idx,
-        curve_to_color,
-        file_param,
-        &well_info_text,
-        config.scale_spacing,
-        config.max_scales,
-        config.tick_size_major,
-        config.tick_size_minor,
-    ).map_err(|e| actix_web::error::ErrorInternalServerError(e))
-    .map_err(|e| actix_web::error::ErrorInternalServerError(format!("Failed to generate HTML: {}", e)))?;
+    // Генерируем HTML stream; картинки рисуются в общем пуле
+    let stream = view::generate_html(view, layout, file_param, state.render_pool())
+        .map_err(|e| actix_web::error::ErrorInternalServerError(format!("Failed to generate HTML: {}", e)))?
+        .map_err(actix_web::error::ErrorInternalServerError);
 
     let response =
         HttpResponse::Ok()
             .content_type("text/html; charset=utf-8")
+            .insert_header(("Content-Security-Policy", content_security_policy(None)))
+            .insert_header(("X-Content-Type-Options", "nosniff"))
             .streaming(stream);
     Ok(response)
 }
 
+/// Кровли пластов из отдельного файла (`tops=`) - вместо секции ~Tops самого LAS файла
+async fn load_tops(params: &std::collections::HashMap<String, String>, config: &Config) -> ActixResult<Option<TopsFile>> {
+    let Some(tops_param) = params.get("tops").map(|s| s.trim()).filter(|s| !s.is_empty()) else {
+        return Ok(None);
+    };
+    let content = load_las_file(tops_param, config)
+        .await
+        .map_err(|e| actix_web::error::ErrorBadRequest(format!("Failed to load tops: {}", e)))?;
+    TopsFile::parse(&content).map(Some).map_err(actix_web::error::ErrorBadRequest)
+}
+
+/// Инклинометрия из отдельного файла (`survey=`) - вместо секции ~Survey самого LAS файла
+async fn load_survey(params: &std::collections::HashMap<String, String>, config: &Config) -> ActixResult<Option<Survey>> {
+    let Some(survey_param) = params.get("survey").map(|s| s.trim()).filter(|s| !s.is_empty()) else {
+        return Ok(None);
+    };
+    let content = load_las_file(survey_param, config)
+        .await
+        .map_err(|e| actix_web::error::ErrorBadRequest(format!("Failed to load survey: {}", e)))?;
+    Survey::parse(&content).map(Some).map_err(actix_web::error::ErrorBadRequest)
+}
+
+/// Правки кривых (`edits=`): JSON-массив прямо в параметре или файл с ним (URL, загрузка, путь
+/// в samples_dir) - файл ищется так же, как `file=`, и не может лежать вне samples_dir и uploads_dir
+async fn load_edits(params: &std::collections::HashMap<String, String>, config: &Config) -> ActixResult<Vec<Edit>> {
+    let Some(edits_param) = params.get("edits").map(|s| s.trim()).filter(|s| !s.is_empty()) else {
+        return Ok(Vec::new());
+    };
+    let content = if edits_param.starts_with('[') {
+        edits_param.to_string()
+    } else {
+        load_las_file(edits_param, config)
+            .await
+            .map_err(|e| actix_web::error::ErrorBadRequest(format!("Failed to load edits: {}", e)))?
+    };
+    Edit::parse_list(&content).map_err(|e| actix_web::error::ErrorBadRequest(format!("{:#}", e)))
+}
+
+/// Текст файла по значению параметра (`file=`, `tops=`, `survey=`, `edits=`): URL, загрузка (`upload:`)
+/// или путь в samples_dir, не выходящий за него
 async fn load_las_file(file_param: &str, config: &Config) -> Result<String> {
     if file_param.starts_with("http://") || file_param.starts_with("https://") {
         // Загружаем по URL
         let response = reqwest::get(file_param).await?;
         let content = response.text().await?;
         Ok(content)
+    } else if let Some(id) = file_param.strip_prefix(upload::UPLOAD_PREFIX) {
+        // Загруженный пользователем файл
+        let path = upload::upload_path(config, id)
+            .ok_or_else(|| anyhow::anyhow!("Invalid upload id: {}", id))?;
+        let content = std::fs::read(&path)
+            .with_context(|| format!("Upload not found or expired: {}", id))?;
+        Ok(String::from_utf8_lossy(&content).into_owned())
     } else {
         // Загружаем из локальной папки
-        let path = config.get_samples_path().join(file_param);
+        let path = config.resolve_sample_path(file_param)?;
         let content = std::fs::read_to_string(&path)
-            .with_context(|| format!("Failed to read file: {:?}", path))?;
+            .map_err(|_| anyhow::anyhow!("Failed to read file: {}", file_param))?;
         Ok(content)
     }
 }
 
-async fn generate_html_row(
-    plot_config: &PlotConfig,
-    curves_data: Vec<(Vec<Option<f64>>, String)>,
-    depth_data: &[Option<f64>],
-    start_block_value: usize,
-    end_block_value: usize,
-    row_height: usize,
-    image_width: usize,
-    image_height: usize,
-    separate_depth_column: bool,
-    depth_min: f64,
-) -> Result<String> {
-    let start_depth = depth_data.get(start_block_value)
-        .and_then(|&d| d)
-        .unwrap_or(depth_min);
-
-    let png_data = generate_plot_png(
-        plot_config,
-        curves_data.into(),
-        depth_data,
-        start_block_value,
-        end_block_value,
-    )?;
-
-    let base64_img = base64::engine::general_purpose::STANDARD.encode(&png_data);
-    
-    let row_html = if separate_depth_column {
-        format!(
-            "<tr height='{}' style='vertical-align: top; margin: 0; padding: 0;'><td valign='top' style='padding: 0; margin: 0; border: 1px solid #ccc;'>{:.2}</td><td style='padding: 0; margin: 0; border: 1px solid #ccc; vertical-align: top;'><img src='data:image/png;base64,{}' alt='Plot' width='{}' height='{}' style='display: block; margin: 0; padding: 0;'></td></tr>\n",
-            row_height, start_depth, base64_img, image_width, image_height
-        )
-    } else {
-        format!(
-            "<tr height='{}' style='vertical-align: top; margin: 0; padding: 0;'><td style='padding: 0; margin: 0; border: 1px solid #ccc; vertical-align: top;'><div style='position:relative; margin: 0; padding: 0;'><div style='position:absolute;left:5px;top:5px'>{:.2}</div><img src='data:image/png;base64,{}' alt='Plot' width='{}' height='{}' style='display: block; margin: 0; padding: 0;'></div></td></tr>\n",
-            row_height, start_depth, base64_img, image_width, image_height
-        )
-    };
-    
-    Ok(row_html)
-}
-
-fn generate_html(
-    curves_info: Vec<(String, String, String)>,
-    curves_stats: Vec<Option<(f64, f64)>>,
-    curves_data: Arc<Vec<(Vec<Option<f64>>, String)>>,
-    x_ranges: Vec<(f64, f64)>,
-    colors: Vec<RGBColor>,
-    depth_data: Vec<Option<f64>>,
-    depth_min: f64,
-    depth_max: f64,
-    html_row_steps: usize,
-    pixels_per_step: usize,
-    row_height: usize,
-    image_width: usize,
-    separate_depth_column: bool,
-    main_param_idx: usize,
-    curve_to_color: std::collections::HashMap<usize, String>,
-    file_name: &str,
-    well_info: &[(String, String)],
-    scale_spacing: usize,
-    max_scales: usize,
-    tick_size_major: usize,
-    tick_size_minor: usize,
-) -> Result<impl futures::Stream<Item = Result<Bytes, actix_web::Error>> + 'static, actix_web::Error> {
-    //let curves_data = Arc::new(curves_data);
-    let depth_data = Arc::new(depth_data);
-
-    let total_steps = depth_data.len();
-    let num_rows = (total_steps + html_row_steps - 1) / html_row_steps;
-
-    // HTML над строки таблицы со шкалой
-    let mut html_before_scale = String::new();
-    html_before_scale.push_str(&format!("<html><head><meta charset='utf-8'><title>LAS Plot - {}</title></head><body>\n", file_name));
-    html_before_scale.push_str(&format!("<h2>LAS Plot - {}</h2>\n", file_name));
-    html_before_scale.push_str("<p style='font-size: 0.9em; color: #666; margin-top: 5px; margin-bottom: 15px;'>Free for non-commercial use. License: <a href='https://github.com/shestero/lasplot/blob/main/LICENSE' target='_blank'>https://github.com/shestero/lasplot/blob/main/LICENSE</a></p>\n");
-    
-    // Генерируем HTML для двух верхних таблиц
-    let mut curves_table_html = String::new();
-    curves_table_html.push_str("<table border='1' cellpadding='5' style='border-collapse: collapse; border: 1px solid #ccc; font-family: monospace;'>\n");
-    curves_table_html.push_str("<style>table th, table td { border: 1px solid #ccc; }</style>\n");
-    curves_table_html.push_str("<tr><th>Color</th><th>Mnemonic</th><th>Measure</th><th>Description</th><th>min</th><th>max</th></tr>\n");
-
-    for (idx, (mnemonic, unit, description)) in curves_info.iter().enumerate() {
-        if let Some((min, max)) = curves_stats.get(idx).and_then(|s| *s) {
-            // Определяем цвет для этой кривой
-            let color_cell = if idx == main_param_idx {
-                // Для основного параметра - пустая ячейка
-                "<td></td>".to_string()
-            } else if let Some(hex_color) = curve_to_color.get(&idx) {
-                // Для остальных кривых - ячейка с цветом
-                format!(
-                    "<td style='background-color: #{}; color: black; text-align: center; font-weight: bold;'>{}</td>",
-                    hex_color, hex_color
-                )
-            } else {
-                // Если цвет не найден - пустая ячейка (не должно происходить)
-                "<td></td>".to_string()
-            };
-            
-            curves_table_html.push_str(&format!(
-                "<tr>{}<td>{}</td><td>{}</td><td>{}</td><td style='text-align: right;'>{:.2}</td><td style='text-align: right;'>{:.2}</td></tr>\n",
-                color_cell, mnemonic, unit, description, min, max
-            ));
-        }
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use actix_web::test;
+    use std::path::PathBuf;
+
+    const HOSTILE_LAS: &str = include_str!("../tests/fixtures/hostile.las");
+
+    /// Временная папка с samples/evil.las и списком файлов, в котором есть враждебное имя
+    fn test_config(name: &str) -> (Config, PathBuf) {
+        let dir = std::env::temp_dir().join(format!("lasplot-test-{}-{}", name, std::process::id()));
+        let samples = dir.join("samples");
+        std::fs::create_dir_all(&samples).unwrap();
+        std::fs::write(samples.join("evil.las"), HOSTILE_LAS).unwrap();
+        std::fs::write(dir.join("laslist.txt"), "evil.las\n\"><script>alert(5)</script>.las\n").unwrap();
+        let config = Config {
+            samples_dir: samples.to_string_lossy().into_owned(),
+            uploads_dir: dir.join("uploads").to_string_lossy().into_owned(),
+            laslist_file: dir.join("laslist.txt").to_string_lossy().into_owned(),
+            ..Config::default()
+        };
+        (config, dir)
     }
-    curves_table_html.push_str("</table>\n");
-    
-    let mut well_table_html = String::new();
-    // Выводим информацию из секции ~Well в таблице с 2 колонками
-    if !well_info.is_empty() {
-        well_table_html.push_str("<table style='border: none; border-style: none; border-collapse: collapse; font-family: monospace; border-spacing: 0;'>\n");
-        for (key, value) in well_info {
-            // Разделяем на часть до двоеточия и после
-            if let Some(colon_pos) = value.find(':') {
-                let before_colon = &value[..colon_pos].trim(); // Без двоеточия
-                let after_colon = &value[colon_pos + 1..].trim();
-                well_table_html.push_str(&format!(
-                    "<tr style='border: none'><td style='text-align: right; padding-right: 5px; border: none'>{}{}</td><td style='text-align: left; padding-left: 5px; border: none'>{}</td></tr>\n",
-                    before_colon, ":", after_colon
-                ));
-            } else {
-                // Если нет двоеточия, выводим ключ в первой колонке с двоеточием, значение во второй
-                well_table_html.push_str(&format!(
-                    "<tr style='border: none'><td style='text-align: right; padding-right: 5px; border: none'>{}{}</td><td style='text-align: left; padding-left: 5px; border: none'>{}</td></tr>\n",
-                    key, ":", value
-                ));
-            }
-        }
-        well_table_html.push_str("</table>\n");
+
+    async fn get(config: Config, uri: &str) -> (String, String) {
+        let state = web::Data::new(AppState::new(config, None));
+        let app = test::init_service(App::new().app_data(state).configure(routes)).await;
+        let response = test::call_service(&app, test::TestRequest::get().uri(uri).to_request()).await;
+        assert!(response.status().is_success(), "{} -> {}", uri, response.status());
+        let policy = response
+            .headers()
+            .get("Content-Security-Policy")
+            .and_then(|v| v.to_str().ok())
+            .unwrap_or_default()
+            .to_string();
+        let body = test::read_body(response).await;
+        (policy, String::from_utf8(body.to_vec()).unwrap())
     }
-    
-    // Начинаем таблицу с графиками
-    html_before_scale.push_str("<table border='0' cellspacing='0' cellpadding='0' style='border-collapse: collapse; border-spacing: 0; margin: 0; padding: 0; font-family: monospace;'>\n");
-    
-    // Первая строка с объединённой ячейкой для верхних таблиц
-    let colspan = if separate_depth_column { 2 } else { 1 };
-    html_before_scale.push_str(&format!(
-        "<tr style='border: none; border-width: 0; border-collapse: collapse;'><td colspan='{}' style='padding: 10px 10px 10px 0; border: none; border-width: 0; border-collapse: collapse; vertical-align: top;'><div style='display: flex; gap: 20px; align-items: flex-start;'><div style='vertical-align: top;'>{}</div><div style='vertical-align: top; margin-left: auto; text-align: right;'>{}</div></div></td></tr>\n",
-        colspan, curves_table_html, well_table_html
-    ));
-
-    // HTML строки таблицы со шкалой
-    // Ограничиваем количество кривых для шкалы до max_scales
-    let scale_curves_data: Vec<_> = curves_data.iter().take(max_scales).cloned().collect();
-    let scale_colors: Vec<_> = colors.iter().take(max_scales).cloned().collect();
-    let scale_x_ranges: Vec<_> = x_ranges.iter().take(max_scales).cloned().collect();
-    
-    // Вычисляем реальное количество отображаемых шкал
-    let actual_scales_count = scale_curves_data.len();
-    
-    // Вычисляем высоту изображения на основе количества шкал:
-    // Первая шкала начинается с отступом scale_spacing сверху
-    // Последняя шкала находится на позиции: actual_scales_count * scale_spacing
-    // Нижняя точка засечки последней шкалы: actual_scales_count * scale_spacing + tick_size_major
-    // Добавляем такой же отступ снизу (scale_spacing) для симметрии
-    let scale_height = if actual_scales_count > 0 {
-        // Позиция последней шкалы + нижняя засечка + отступ снизу
-        (actual_scales_count * scale_spacing) + tick_size_major + scale_spacing
-    } else {
-        scale_spacing * 2
-    };
-    
-    let scale_config = PlotConfig {
-        width: image_width as u32,
-        height: scale_height as u32,
-        colors: scale_colors,
-        x_ranges: scale_x_ranges,
-        y_range: (depth_min, depth_max),
-        show_scales: true,
-        pixels_per_step,
-        html_row_steps,
-        scale_spacing,
-        tick_size_major,
-        tick_size_minor,
-        max_scales,
-    };
 
-    let scale_png = generate_plot_png(
-        &scale_config,
-        scale_curves_data,
-        &depth_data,
-        0,
-        html_row_steps.min(depth_data.len()),
-    ).map_err(|e| actix_web::error::ErrorInternalServerError(e))?;
-
-    let scale_base64 = base64::engine::general_purpose::STANDARD.encode(&scale_png);
-    let html_scale_row = if separate_depth_column {
-        format!(
-            "<tr height='{}' style='vertical-align: top; margin: 0; padding: 0;'><td style='padding: 0; margin: 0; border: 1px solid #ccc;'></td><td style='padding: 0; margin: 0; border: 1px solid #ccc; vertical-align: top;'><img src='data:image/png;base64,{}' alt='Scales' width='{}' height='{}' style='display: block; margin: 0; padding: 0;'></td></tr>\n",
-            scale_height, scale_base64, image_width, scale_height
-        )
-    } else {
-        format!(
-            "<tr height='{}' style='vertical-align: top; margin: 0; padding: 0;'><td style='padding: 0; margin: 0; border: 1px solid #ccc; vertical-align: top;'><img src='data:image/png;base64,{}' alt='Scales' width='{}' height='{}' style='display: block; margin: 0; padding: 0;'></td></tr>\n",
-            scale_height, scale_base64, image_width, scale_height
-        )
-    };
+    /// Статус и текст ответа с ошибкой
+    async fn get_error(config: Config, uri: &str) -> (actix_web::http::StatusCode, String) {
+        let state = web::Data::new(AppState::new(config, None));
+        let app = test::init_service(App::new().app_data(state).configure(routes)).await;
+        let response = test::call_service(&app, test::TestRequest::get().uri(uri).to_request()).await;
+        let status = response.status();
+        let body = test::read_body(response).await;
+        (status, String::from_utf8_lossy(&body).into_owned())
+    }
 
-    // HTML строк с изображением
-    // Высота каждого блока: html_row_steps * pixels_per_step + 1
-    let block_height = (1+html_row_steps) * pixels_per_step;
-    
-    let plot_config = Arc::new(PlotConfig {
-        width: image_width as u32,
-        height: block_height as u32,
-        colors: colors.clone(),
-        x_ranges: x_ranges.clone(),
-        y_range: (depth_min, depth_max),
-        show_scales: false,
-        pixels_per_step,
-        html_row_steps,
-        scale_spacing,
-        tick_size_major,
-        tick_size_minor,
-        max_scales,
-    });
+    #[actix_web::test]
+    async fn sidecar_files_stay_inside_samples_dir() {
+        let (config, dir) = test_config("contain");
+        let secret = dir.join("secret.csv");
+        std::fs::write(&secret, "W-1, TOP_SECRET, 1000\nW-1, SECRET_TOO, abc\n").unwrap();
+        let secret_uri = secret.to_string_lossy().into_owned();
+        let mut errors = Vec::new();
+        for uri in [
+            "/?file=evil.las&tops=../secret.csv".to_string(),
+            format!("/?file=evil.las&tops={}", secret_uri),
+            format!("/?file=evil.las&survey={}", secret_uri),
+            "/?file=evil.las&survey=../secret.csv".to_string(),
+            format!("/?file=evil.las&edits={}", secret_uri),
+            "/?file=evil.las&edits=../secret.csv".to_string(),
+            "/?file=../secret.csv".to_string(),
+            "/?file=missing.las".to_string(),
+        ] {
+            errors.push((uri.clone(), get_error(config.clone(), &uri).await));
+        }
+        std::fs::remove_dir_all(&dir).ok();
 
-    /*
-    let mut html_plot_rows = String::new();
-    for row_idx in 0..num_rows {
-        let start_block_value = row_idx * html_row_steps;
-        let end_block_value = (start_block_value + html_row_steps).min(depth_data.len());
+        for (uri, (status, body)) in errors {
+            assert!(status.is_client_error() || status.is_server_error(), "{} -> {}", uri, status);
+            assert!(!body.contains("SECRET"), "{} leaked file contents: {}", uri, body);
+            assert!(!body.contains(&config.samples_dir), "{} leaked a server path: {}", uri, body);
+            assert!(body.contains("File not found"), "{}: {}", uri, body);
+        }
+    }
 
-        if start_block_value >= depth_data.len() {
-            break;
+    #[actix_web::test]
+    async fn plot_page_rejects_parameters_that_do_not_fit_the_file() {
+        let (config, dir) = test_config("badreq");
+        let mut errors = Vec::new();
+        for uri in ["/?file=evil.las&derive=Y%3DFOO*2", "/?file=evil.las&main_param=FOO", "/?file=evil.las&main_param=TVD", "/?file=evil.las&compute=sw"] {
+            errors.push((uri, get_error(config.clone(), uri).await));
         }
+        std::fs::remove_dir_all(dir).ok();
 
-        // Вычисляем реальное количество шагов в этом блоке для правильной высоты изображения
-        let actual_steps = end_block_value - start_block_value;
-        let image_height = actual_steps * pixels_per_step;
-
-        let row_html = generate_html_row(
-            &plot_config,
-            &curves_data,
-            &depth_data,
-            start_block_value,
-            end_block_value,
-            block_height, // Высота строки таблицы
-            image_width,
-            image_height, // Высота изображения = actual_steps * pixels_per_step
-            separate_depth_column,
-            depth_min,
-        ).await?;
-        
-        html_plot_rows.push_str(&row_html);
+        for (uri, (status, body)) in errors {
+            assert_eq!(status, actix_web::http::StatusCode::BAD_REQUEST, "{}: {}", uri, body);
+        }
     }
-    */
-    // 1) поток индексов
-    let depth_len = depth_data.len();
-    let html_plot_rows = stream::iter(0..num_rows)
-        // 2) превращаем каждый индекс в future
-        .map(move |row_idx| {
-            let start_block_value = row_idx * html_row_steps;
-            let end_block_value = (start_block_value + html_row_steps).min(depth_data.len());
-
-            // Arc clones
-            let plot_config = plot_config.clone();
-            let curves_data = curves_data.clone();
-            let depth_data = depth_data.clone();
-            let num_rows = num_rows;
-
-            async move {
-                if start_block_value >= depth_len {
-                    return Ok(String::new());
-                }
-
-                // Для всех строк кроме последней добавляем еще один шаг из следующей строки
-                // (если он существует и не None)
-                let is_last_row = row_idx == num_rows - 1;
-                let actual_end = if is_last_row {
-                    end_block_value
-                } else {
-                    // Добавляем еще один шаг, если он существует и не None
-                    if end_block_value < depth_len {
-                        // Проверяем, что следующий шаг не None
-                        if depth_data.get(end_block_value).and_then(|&d| d).is_some() {
-                            end_block_value + 1
-                        } else {
-                            end_block_value
-                        }
-                    } else {
-                        end_block_value
-                    }
-                };
-                
-                // Высота изображения должна быть равна высоте строки (block_height)
-                // независимо от количества шагов в этом блоке
-                let image_height = block_height;
-
-                // Создаем PlotConfig с правильной высотой для этого блока
-                let block_plot_config = PlotConfig {
-                    width: plot_config.width,
-                    height: block_height as u32, // Используем фиксированную высоту блока
-                    colors: plot_config.colors.clone(),
-                    x_ranges: plot_config.x_ranges.clone(),
-                    y_range: plot_config.y_range,
-                    show_scales: false,
-                    pixels_per_step: plot_config.pixels_per_step,
-                    html_row_steps: plot_config.html_row_steps,
-                    scale_spacing: plot_config.scale_spacing,
-                    tick_size_major: plot_config.tick_size_major,
-                    tick_size_minor: plot_config.tick_size_minor,
-                    max_scales: plot_config.max_scales,
-                };
-                
-                generate_html_row(
-                    &block_plot_config,
-                    curves_data.to_vec(),
-                    &depth_data,
-                    start_block_value,
-                    actual_end,
-                    block_height,
-                    image_width,
-                    image_height,
-                    separate_depth_column,
-                    depth_min,
-                )
-                    .await
-            }
-        })
-        // 3) параллельность
-        .buffered(2)
-        // 4) String → Bytes
-        .map(|res| res.map(Bytes::from))
-        // 5) приведение типа ошибки
-        .map_err(|e| actix_web::error::ErrorInternalServerError(e));
-
-    // HTML конца таблицы и документа
-    let html_end = "</table>\n</body></html>\n";
-
-    // Слепляем все части
-    //let html = format!("{}{}{}{}", html_before_scale, html_scale_row, html_plot_rows, html_end);
-    
-    // Оборачиваем в stream через once (фиктивно)
-    //let stream = once(ok::<_, actix_web::Error>(Bytes::from(html)));
 
-    let before = once(ok::<_, actix_web::Error>(Bytes::from(html_before_scale + &html_scale_row)));
-    let after = once(ok::<_, actix_web::Error>(Bytes::from(html_end)));
+    #[actix_web::test]
+    async fn plot_page_escapes_las_header() {
+        let (config, dir) = test_config("plot");
+        let (policy, page) = get(config, "/?file=evil.las").await;
+        std::fs::remove_dir_all(dir).ok();
+
+        assert!(policy.contains("script-src 'none'"));
+        assert!(!page.contains("<script"), "raw script tag in page");
+        assert!(!page.contains("<img src=x"), "raw img tag in page");
+        assert!(!page.contains("GR<b>"), "raw mnemonic markup in page");
+        assert!(page.contains("&lt;script&gt;alert(1)&lt;/script&gt;"));
+        assert!(page.contains("&lt;script&gt;alert(3)&lt;/script&gt;"));
+        assert!(page.contains("&amp; Co"));
+    }
 
-    Ok( before.chain(html_plot_rows.chain(after)) )
+    #[actix_web::test]
+    async fn test_page_script_carries_csp_nonce() {
+        let (config, dir) = test_config("list");
+        let (policy, page) = get(config, "/test").await;
+        std::fs::remove_dir_all(dir).ok();
+
+        let nonce = policy
+            .split("'nonce-")
+            .nth(1)
+            .and_then(|rest| rest.split('\'').next())
+            .expect("nonce in Content-Security-Policy");
+        assert_eq!(page.matches("<script").count(), 1);
+        assert!(page.contains(&format!("<script nonce=\"{}\">", nonce)));
+        assert!(page.contains("&quot;&gt;&lt;script&gt;alert(5)&lt;/script&gt;.las"));
+        assert!(page.contains("href=\"/?file&#61;%22%3E%3Cscript%3Ealert%285%29%3C%2Fscript%3E.las\""));
+    }
 }
@@ -0,0 +1,158 @@
+//! Минимальная запись PDF: страницы с JPEG картинками (DCTDecode) и текстом Helvetica.
+//! Достаточно для печати графиков из `lasplot render --format pdf`.
+
+use anyhow::Result;
+use image::codecs::jpeg::JpegEncoder;
+use image::{DynamicImage, RgbaImage};
+use std::fmt::Write as _;
+
+/// Размер страницы A4 в пунктах
+pub const A4_WIDTH: f32 = 595.0;
+pub const A4_HEIGHT: f32 = 842.0;
+
+const JPEG_QUALITY: u8 = 92;
+
+struct PdfImage {
+    jpeg: Vec<u8>,
+    width: u32,
+    height: u32,
+}
+
+/// Страница; координаты отсчитываются от левого верхнего угла, в пунктах
+pub struct PdfPage {
+    width: f32,
+    height: f32,
+    images: Vec<PdfImage>,
+    content: String,
+}
+
+impl PdfPage {
+    pub fn new(width: f32, height: f32) -> Self {
+        PdfPage { width, height, images: Vec::new(), content: String::new() }
+    }
+
+    /// Размещает картинку в прямоугольнике (x, y, w, h)
+    pub fn image(&mut self, img: &RgbaImage, x: f32, y: f32, w: f32, h: f32) -> Result<()> {
+        let mut jpeg = Vec::new();
+        let rgb = DynamicImage::ImageRgba8(img.clone()).to_rgb8();
+        JpegEncoder::new_with_quality(&mut jpeg, JPEG_QUALITY).encode_image(&rgb)?;
+
+        let name = self.images.len();
+        self.images.push(PdfImage { jpeg, width: img.width(), height: img.height() });
+        let _ = writeln!(
+            self.content,
+            "q {:.2} 0 0 {:.2} {:.2} {:.2} cm /Im{} Do Q",
+            w, h, x, self.height - y - h, name
+        );
+        Ok(())
+    }
+
+    /// Текст размером `size`; `y` - базовая линия
+    pub fn text(&mut self, x: f32, y: f32, size: f32, text: &str) {
+        let _ = writeln!(
+            self.content,
+            "BT /F1 {:.1} Tf {:.2} {:.2} Td ({}) Tj ET",
+            size, x, self.height - y, escape_text(text)
+        );
+    }
+}
+
+/// Строка PDF: экранирование скобок и обратной косой черты, не-ASCII заменяется на '?'
+fn escape_text(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for ch in text.chars() {
+        match ch {
+            '(' | ')' | '\\' => {
+                escaped.push('\\');
+                escaped.push(ch);
+            }
+            ' '..='~' => escaped.push(ch),
+            _ => escaped.push('?'),
+        }
+    }
+    escaped
+}
+
+/// Документ: страницы копятся в памяти, [`PdfDocument::finish`] собирает файл
+#[derive(Default)]
+pub struct PdfDocument {
+    pages: Vec<PdfPage>,
+}
+
+impl PdfDocument {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn add_page(&mut self, page: PdfPage) {
+        self.pages.push(page);
+    }
+
+    pub fn finish(self) -> Vec<u8> {
+        // Объекты: 1 - каталог, 2 - дерево страниц, 3 - шрифт, далее страницы
+        let mut objects: Vec<Vec<u8>> = vec![
+            b"<< /Type /Catalog /Pages 2 0 R >>".to_vec(),
+            Vec::new(),
+            b"<< /Type /Font /Subtype /Type1 /BaseFont /Helvetica /Encoding /WinAnsiEncoding >>".to_vec(),
+        ];
+        let mut kids = Vec::new();
+
+        for page in self.pages {
+            let page_id = objects.len() + 1;
+            let content_id = page_id + 1;
+            let first_image_id = content_id + 1;
+            kids.push(format!("{} 0 R", page_id));
+
+            let xobjects: String = (0..page.images.len())
+                .map(|i| format!("/Im{} {} 0 R ", i, first_image_id + i))
+                .collect();
+            objects.push(
+                format!(
+                    "<< /Type /Page /Parent 2 0 R /MediaBox [0 0 {:.2} {:.2}] /Resources << /Font << /F1 3 0 R >> /XObject << {}>> >> /Contents {} 0 R >>",
+                    page.width, page.height, xobjects, content_id
+                )
+                .into_bytes(),
+            );
+            objects.push(stream_object("", page.content.as_bytes()));
+            for image in page.images {
+                let dict = format!(
+                    "/Type /XObject /Subtype /Image /Width {} /Height {} /ColorSpace /DeviceRGB /BitsPerComponent 8 /Filter /DCTDecode ",
+                    image.width, image.height
+                );
+                objects.push(stream_object(&dict, &image.jpeg));
+            }
+        }
+
+        objects[1] = format!("<< /Type /Pages /Kids [{}] /Count {} >>", kids.join(" "), kids.len()).into_bytes();
+
+        let mut out = b"%PDF-1.4\n%\xE2\xE3\xCF\xD3\n".to_vec();
+        let mut offsets = Vec::with_capacity(objects.len());
+        for (i, object) in objects.iter().enumerate() {
+            offsets.push(out.len());
+            out.extend_from_slice(format!("{} 0 obj\n", i + 1).as_bytes());
+            out.extend_from_slice(object);
+            out.extend_from_slice(b"\nendobj\n");
+        }
+
+        let xref_offset = out.len();
+        let mut xref = format!("xref\n0 {}\n0000000000 65535 f \n", objects.len() + 1);
+        for offset in offsets {
+            let _ = writeln!(xref, "{:010} 00000 n ", offset);
+        }
+        let _ = write!(
+            xref,
+            "trailer\n<< /Size {} /Root 1 0 R >>\nstartxref\n{}\n%%EOF\n",
+            objects.len() + 1,
+            xref_offset
+        );
+        out.extend_from_slice(xref.as_bytes());
+        out
+    }
+}
+
+fn stream_object(dict: &str, data: &[u8]) -> Vec<u8> {
+    let mut object = format!("<< {}/Length {} >>\nstream\n", dict, data.len()).into_bytes();
+    object.extend_from_slice(data);
+    object.extend_from_slice(b"\nendstream");
+    object
+}
@@ -0,0 +1,516 @@
+//! Петрофизические расчёты поверх `LasFile`: глинистость по ГК, пористость по плотности,
+//! нейтронно-плотностная пористость и водонасыщенность по Арчи.
+//!
+//! Результаты добавляются в файл расчётными кривыми (VSH, PHID, PHIND, SW), поэтому их можно
+//! показать, выгрузить и выбрать через `curves=` так же, как измеренные.
+//! Исходные кривые ищутся по семействам ([`crate::families`]), если не заданы явно.
+
+use crate::api::number_param;
+use crate::families::{AliasDictionary, CurveFamily};
+use crate::las::{CurveInfo, LasFile};
+use crate::stats::percentile;
+use crate::units::{find_unit, Quantity};
+use anyhow::{anyhow, bail, Result};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+
+/// Расчётная кривая
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Computation {
+    /// Глинистость по ГК
+    Vshale,
+    /// Пористость по плотности
+    DensityPorosity,
+    /// Нейтронно-плотностная пористость
+    NeutronDensityPorosity,
+    /// Водонасыщенность по Арчи
+    WaterSaturation,
+}
+
+impl Computation {
+    pub fn parse(s: &str) -> Result<Self> {
+        match s.trim().to_lowercase().as_str() {
+            "vsh" | "vshale" => Ok(Computation::Vshale),
+            "phid" => Ok(Computation::DensityPorosity),
+            "phind" | "phi_nd" => Ok(Computation::NeutronDensityPorosity),
+            "sw" => Ok(Computation::WaterSaturation),
+            other => bail!("Unknown computation '{}' (expected vsh, phid, phind or sw)", other),
+        }
+    }
+
+    /// Список через запятую (`compute=vsh,phid,sw`)
+    pub fn parse_list(s: &str) -> Result<Vec<Self>> {
+        let mut computations = Vec::new();
+        for name in s.split(',').map(str::trim).filter(|name| !name.is_empty()) {
+            let computation = Computation::parse(name)?;
+            if !computations.contains(&computation) {
+                computations.push(computation);
+            }
+        }
+        Ok(computations)
+    }
+
+    /// Мнемоника расчётной кривой
+    pub fn mnemonic(&self) -> &'static str {
+        match self {
+            Computation::Vshale => "VSH",
+            Computation::DensityPorosity => "PHID",
+            Computation::NeutronDensityPorosity => "PHIND",
+            Computation::WaterSaturation => "SW",
+        }
+    }
+}
+
+/// Способ пересчёта индекса ГК в глинистость
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum VshaleMethod {
+    #[default]
+    Linear,
+    /// Ларионов для древних (дотретичных) пород
+    LarionovOlder,
+    /// Ларионов для третичных пород
+    LarionovTertiary,
+}
+
+impl VshaleMethod {
+    pub fn parse(s: &str) -> Result<Self> {
+        match s.trim().to_lowercase().as_str() {
+            "linear" => Ok(VshaleMethod::Linear),
+            "larionov_older" | "older" => Ok(VshaleMethod::LarionovOlder),
+            "larionov_tertiary" | "tertiary" => Ok(VshaleMethod::LarionovTertiary),
+            other => bail!("Unknown vsh_method '{}' (expected linear, larionov_older or larionov_tertiary)", other),
+        }
+    }
+
+    fn name(&self) -> &'static str {
+        match self {
+            VshaleMethod::Linear => "linear",
+            VshaleMethod::LarionovOlder => "Larionov older rocks",
+            VshaleMethod::LarionovTertiary => "Larionov tertiary",
+        }
+    }
+
+    /// Глинистость по индексу ГК (0..1)
+    fn vshale(&self, igr: f64) -> f64 {
+        match self {
+            VshaleMethod::Linear => igr,
+            VshaleMethod::LarionovOlder => 0.33 * (2_f64.powf(2.0 * igr) - 1.0),
+            VshaleMethod::LarionovTertiary => 0.083 * (2_f64.powf(3.7 * igr) - 1.0),
+        }
+    }
+}
+
+/// Параметры расчётов: секция `[petrophysics]` в lasplot.toml, поверх неё - параметры запроса
+/// с теми же именами. Незаданные кривые ищутся по семействам.
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct PetroParams {
+    pub vsh_method: VshaleMethod,
+    /// ГК чистого песчаника и глины; по умолчанию - 5-й и 95-й процентили кривой
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub gr_clean: Option<f64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub gr_shale: Option<f64>,
+    /// Плотность матрицы и флюида, г/см3
+    pub rho_matrix: f64,
+    pub rho_fluid: f64,
+    /// Коэффициенты Арчи: извилистость, цементация, насыщение
+    pub archie_a: f64,
+    pub archie_m: f64,
+    pub archie_n: f64,
+    /// Сопротивление пластовой воды, Ом·м; по умолчанию - RW из ~Parameter
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub rw: Option<f64>,
+    /// Мнемоники исходных кривых
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub gr_curve: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub rhob_curve: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub nphi_curve: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub rt_curve: Option<String>,
+}
+
+impl Default for PetroParams {
+    fn default() -> Self {
+        PetroParams {
+            vsh_method: VshaleMethod::default(),
+            gr_clean: None,
+            gr_shale: None,
+            rho_matrix: 2.65,
+            rho_fluid: 1.0,
+            archie_a: 1.0,
+            archie_m: 2.0,
+            archie_n: 2.0,
+            rw: None,
+            gr_curve: None,
+            rhob_curve: None,
+            nphi_curve: None,
+            rt_curve: None,
+        }
+    }
+}
+
+/// Сопротивления с наибольшей глубиной исследования - для Sw выбираются первыми
+const DEEP_RESISTIVITY: &[&str] = &["AT90", "AHT90", "ILD", "RILD", "LLD", "RT", "RDEP", "HDRS", "IK", "BK"];
+
+impl PetroParams {
+    /// Параметры запроса поверх этих значений
+    pub fn with_params(&self, params: &HashMap<String, String>) -> Result<Self> {
+        let number = |name: &str| number_param(params, name);
+        let curve = |name: &str| params.get(name).map(|s| s.trim().to_string()).filter(|s| !s.is_empty());
+
+        let mut result = self.clone();
+        if let Some(method) = params.get("vsh_method").filter(|s| !s.trim().is_empty()) {
+            result.vsh_method = VshaleMethod::parse(method)?;
+        }
+        result.gr_clean = number("gr_clean")?.or(result.gr_clean);
+        result.gr_shale = number("gr_shale")?.or(result.gr_shale);
+        result.rho_matrix = number("rho_matrix")?.unwrap_or(result.rho_matrix);
+        result.rho_fluid = number("rho_fluid")?.unwrap_or(result.rho_fluid);
+        result.archie_a = number("archie_a")?.unwrap_or(result.archie_a);
+        result.archie_m = number("archie_m")?.unwrap_or(result.archie_m);
+        result.archie_n = number("archie_n")?.unwrap_or(result.archie_n);
+        result.rw = number("rw")?.or(result.rw);
+        result.gr_curve = curve("gr_curve").or(result.gr_curve);
+        result.rhob_curve = curve("rhob_curve").or(result.rhob_curve);
+        result.nphi_curve = curve("nphi_curve").or(result.nphi_curve);
+        result.rt_curve = curve("rt_curve").or(result.rt_curve);
+
+        let errors = result.errors("");
+        if !errors.is_empty() {
+            bail!("{}", errors.join("; "));
+        }
+        Ok(result)
+    }
+
+    /// Ошибки значений; `prefix` - путь к полям (для конфигурации)
+    pub fn errors(&self, prefix: &str) -> Vec<String> {
+        let mut errors = Vec::new();
+        if let (Some(clean), Some(shale)) = (self.gr_clean, self.gr_shale) {
+            if clean >= shale {
+                errors.push(format!("{}gr_clean = {} must be less than gr_shale = {}", prefix, clean, shale));
+            }
+        }
+        if self.rho_matrix <= self.rho_fluid {
+            errors.push(format!(
+                "{}rho_matrix = {} must be greater than rho_fluid = {}",
+                prefix, self.rho_matrix, self.rho_fluid
+            ));
+        }
+        for (name, value) in [("archie_a", self.archie_a), ("archie_m", self.archie_m), ("archie_n", self.archie_n)] {
+            if value <= 0.0 {
+                errors.push(format!("{}{} = {} must be positive", prefix, name, value));
+            }
+        }
+        if self.rw.is_some_and(|rw| rw <= 0.0) {
+            errors.push(format!("{}rw must be positive", prefix));
+        }
+        errors
+    }
+}
+
+/// Множитель перевода пористости в доли: 0.01 для кривых в процентах
+pub(crate) fn porosity_scale(unit: &str) -> f64 {
+    let unit = unit.trim().to_uppercase();
+    if matches!(unit.as_str(), "PU" | "P.U." | "%" | "PERCENT") { 0.01 } else { 1.0 }
+}
+
+impl LasFile {
+    /// Исходная кривая: заданная мнемоника или первая кривая семейства (`preferred` - раньше остальных)
+    fn petro_source(
+        &self,
+        name: Option<&str>,
+        family: CurveFamily,
+        preferred: &[&str],
+        aliases: &AliasDictionary,
+        purpose: &str,
+    ) -> Result<usize> {
+        if let Some(name) = name {
+            return self.get_curve_index(name).ok_or_else(|| anyhow!("Curve '{}' not found", name));
+        }
+        let candidates: Vec<usize> = (0..self.curves.len())
+            .filter(|&idx| aliases.classify(&self.curves[idx]) == Some(family))
+            .collect();
+        preferred
+            .iter()
+            .find_map(|name| candidates.iter().copied().find(|&idx| self.curves[idx].mnemonic.eq_ignore_ascii_case(name)))
+            .or_else(|| candidates.first().copied())
+            .ok_or_else(|| anyhow!("{} needs a {} curve", purpose, family.name()))
+    }
+
+    /// Плотность в г/см3 (кривая может быть в кг/м3 или, после перевода единиц, в фунтах на фут3)
+    fn density_gcc(&self, idx: usize) -> Vec<Option<f64>> {
+        let data = self.get_curve_data(idx);
+        let (Some(from), Some(to)) = (find_unit(&self.curves[idx].unit), find_unit("G/C3")) else {
+            return data;
+        };
+        if from.quantity != Quantity::Density {
+            return data;
+        }
+        data.into_iter().map(|v| v.map(|v| from.convert(v, to))).collect()
+    }
+
+    /// Нейтронная пористость в долях (кривая может быть в процентах)
+    fn neutron_fraction(&self, idx: usize) -> Vec<Option<f64>> {
+        let scale = porosity_scale(&self.curves[idx].unit);
+        self.get_curve_data(idx).into_iter().map(|v| v.map(|v| v * scale)).collect()
+    }
+
+    /// Сопротивление пластовой воды: `rw=` или RW из ~Parameter; `purpose` - для сообщения об ошибке
+    pub(crate) fn formation_water_resistivity(&self, params: &PetroParams, purpose: &str) -> Result<f64> {
+        params
+            .rw
+            .or_else(|| self.parameters.iter().find(|p| p.mnemonic.eq_ignore_ascii_case("RW"))?.numeric_value())
+            .filter(|rw| *rw > 0.0)
+            .ok_or_else(|| anyhow!("{} needs formation water resistivity: set rw= or RW in ~Parameter", purpose))
+    }
+
+    fn density_porosity(&self, params: &PetroParams, aliases: &AliasDictionary) -> Result<(Vec<Option<f64>>, String)> {
+        let rhob_idx = self.petro_source(params.rhob_curve.as_deref(), CurveFamily::Density, &[], aliases, "PHID")?;
+        let values = self
+            .density_gcc(rhob_idx)
+            .into_iter()
+            .map(|rhob| rhob.map(|rhob| (params.rho_matrix - rhob) / (params.rho_matrix - params.rho_fluid)))
+            .collect();
+        let description = format!(
+            "Density porosity from {} (matrix {} g/cc, fluid {} g/cc)",
+            self.curves[rhob_idx].mnemonic, params.rho_matrix, params.rho_fluid
+        );
+        Ok((values, description))
+    }
+
+    fn neutron_density_porosity(&self, params: &PetroParams, aliases: &AliasDictionary) -> Result<(Vec<Option<f64>>, String)> {
+        let (phid, _) = self.density_porosity(params, aliases)?;
+        let nphi_idx = self.petro_source(params.nphi_curve.as_deref(), CurveFamily::Neutron, &[], aliases, "PHIND")?;
+        let values = phid
+            .into_iter()
+            .zip(self.neutron_fraction(nphi_idx))
+            .map(|(phid, phin)| {
+                let (phid, phin) = (phid?, phin?);
+                // Газ (PHID > PHIN) - среднеквадратичное, иначе среднее
+                Some(if phid > phin { ((phid * phid + phin * phin) / 2.0).sqrt() } else { (phid + phin) / 2.0 })
+            })
+            .collect();
+        let description = format!("Neutron-density porosity from {} and PHID", self.curves[nphi_idx].mnemonic);
+        Ok((values, description))
+    }
+
+    fn computed_curve(
+        &self,
+        computation: Computation,
+        params: &PetroParams,
+        aliases: &AliasDictionary,
+    ) -> Result<(Vec<Option<f64>>, String)> {
+        match computation {
+            Computation::Vshale => {
+                let gr_idx = self.petro_source(params.gr_curve.as_deref(), CurveFamily::GammaRay, &[], aliases, "VSH")?;
+                let gr = self.get_curve_data(gr_idx);
+                let mut sorted: Vec<f64> = gr.iter().flatten().copied().collect();
+                sorted.sort_by(f64::total_cmp);
+                let clean = params.gr_clean.or_else(|| percentile(&sorted, 0.05));
+                let shale = params.gr_shale.or_else(|| percentile(&sorted, 0.95));
+                let (Some(clean), Some(shale)) = (clean, shale) else {
+                    bail!("VSH: curve {} has no data", self.curves[gr_idx].mnemonic);
+                };
+                if clean >= shale {
+                    bail!("VSH: clean GR {} must be less than shale GR {}", clean, shale);
+                }
+                let values = gr
+                    .into_iter()
+                    .map(|gr| {
+                        let igr = ((gr? - clean) / (shale - clean)).clamp(0.0, 1.0);
+                        Some(params.vsh_method.vshale(igr).clamp(0.0, 1.0))
+                    })
+                    .collect();
+                let description = format!(
+                    "Shale volume from {} ({}, GR clean {}, shale {})",
+                    self.curves[gr_idx].mnemonic,
+                    params.vsh_method.name(),
+                    clean,
+                    shale
+                );
+                Ok((values, description))
+            }
+            Computation::DensityPorosity => self.density_porosity(params, aliases),
+            Computation::NeutronDensityPorosity => self.neutron_density_porosity(params, aliases),
+            Computation::WaterSaturation => {
+                let rw = self.formation_water_resistivity(params, "SW")?;
+                let rt_idx = self.petro_source(
+                    params.rt_curve.as_deref(),
+                    CurveFamily::Resistivity,
+                    DEEP_RESISTIVITY,
+                    aliases,
+                    "SW",
+                )?;
+                // Пористость: нейтронно-плотностная, если есть нейтронный, иначе по плотности
+                let (phi, phi_name) = match self.neutron_density_porosity(params, aliases) {
+                    Ok((phi, _)) => (phi, "PHIND"),
+                    Err(_) => (self.density_porosity(params, aliases)?.0, "PHID"),
+                };
+                let values = phi
+                    .into_iter()
+                    .zip(self.get_curve_data(rt_idx))
+                    .map(|(phi, rt)| {
+                        let (phi, rt) = (phi?, rt?);
+                        if phi <= 0.0 || rt <= 0.0 {
+                            return None;
+                        }
+                        let sw = (params.archie_a * rw / (phi.powf(params.archie_m) * rt)).powf(1.0 / params.archie_n);
+                        Some(sw.clamp(0.0, 1.0))
+                    })
+                    .collect();
+                let description = format!(
+                    "Archie water saturation from {} and {} (a {}, m {}, n {}, Rw {})",
+                    self.curves[rt_idx].mnemonic, phi_name, params.archie_a, params.archie_m, params.archie_n, rw
+                );
+                Ok((values, description))
+            }
+        }
+    }
+
+    /// Добавляет расчётные кривые в конец списка кривых; возвращает их индексы.
+    /// Ошибка, если исходной кривой нет; если в файле уже есть кривая с той же мнемоникой
+    /// (например, VSH из интерпретации), расчётная получает суффикс: `VSH_1`, `VSH_2`, ...
+    pub fn add_computed(
+        &mut self,
+        computations: &[Computation],
+        params: &PetroParams,
+        aliases: &AliasDictionary,
+    ) -> Result<Vec<usize>> {
+        let mut added = Vec::new();
+        for &computation in computations {
+            let mnemonic = (0..)
+                .map(|n| if n == 0 { computation.mnemonic().to_string() } else { format!("{}_{}", computation.mnemonic(), n) })
+                .find(|name| self.get_curve_index(name).is_none())
+                .expect("a free mnemonic exists");
+            let (values, description) = self.computed_curve(computation, params, aliases)?;
+
+            let null_value = self.null_value;
+            let curve_idx = self.curves.len();
+            for (row, value) in self.data.iter_mut().zip(values) {
+                // Короткие строки дополняются пропусками, чтобы значение попало в свою колонку
+                row.values.resize(curve_idx, null_value);
+                row.values.push(value.filter(|v| v.is_finite()).unwrap_or(null_value));
+            }
+            self.curves.push(CurveInfo {
+                mnemonic,
+                unit: "V/V".to_string(),
+                description,
+                api_codes: None,
+            });
+            added.push(self.curves.len() - 1);
+        }
+        Ok(added)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// GR 20..120, RHOB в г/см3, NPHI в процентах, ILD; RW в ~Parameter
+    fn sample_file() -> LasFile {
+        LasFile::parse(
+            "~Version Information
+ VERS.  2.0:
+~Well Information
+ NULL.  -999.25:
+~Curve Information
+ DEPT.M  :  DEPTH
+ GR.GAPI  :  GAMMA RAY
+ RHOB.G/C3  :  BULK DENSITY
+ NPHI.PU  :  NEUTRON POROSITY
+ ILD.OHMM  :  DEEP RESISTIVITY
+~Parameter Information
+ RW.OHMM  0.05:  FORMATION WATER RESISTIVITY
+~ASCII
+100  20   2.3    30  5
+101  70   2.32   10  5
+102  120  -999.25 20  5
+",
+        )
+        .unwrap()
+    }
+
+    fn computed(las_file: &mut LasFile, computation: Computation, params: &PetroParams) -> Vec<Option<f64>> {
+        let added = las_file.add_computed(&[computation], params, &AliasDictionary::builtin()).unwrap();
+        las_file.get_curve_data(added[0])
+    }
+
+    fn assert_close(actual: Option<f64>, expected: f64) {
+        let actual = actual.expect("value");
+        assert!((actual - expected).abs() < 1e-9, "{} != {}", actual, expected);
+    }
+
+    #[test]
+    fn larionov_curves_give_known_values() {
+        assert_close(Some(VshaleMethod::Linear.vshale(0.5)), 0.5);
+        assert_close(Some(VshaleMethod::LarionovOlder.vshale(0.5)), 0.33);
+        assert_close(Some(VshaleMethod::LarionovTertiary.vshale(0.5)), 0.083 * (2_f64.powf(1.85) - 1.0));
+        assert_close(Some(VshaleMethod::LarionovOlder.vshale(1.0)), 0.99);
+
+        let params = PetroParams { gr_clean: Some(20.0), gr_shale: Some(120.0), vsh_method: VshaleMethod::LarionovOlder, ..Default::default() };
+        let vsh = computed(&mut sample_file(), Computation::Vshale, &params);
+        assert_close(vsh[0], 0.0);
+        assert_close(vsh[1], 0.33);
+    }
+
+    #[test]
+    fn density_and_neutron_density_porosity() {
+        let params = PetroParams::default();
+        let phid = computed(&mut sample_file(), Computation::DensityPorosity, &params);
+        assert_close(phid[0], 0.35 / 1.65);
+        assert_close(phid[1], 0.2);
+        assert_eq!(phid[2], None);
+
+        // PHID 0.2121 < PHIN 0.3 - среднее; PHID 0.2 > PHIN 0.1 (газ) - среднеквадратичное
+        let phind = computed(&mut sample_file(), Computation::NeutronDensityPorosity, &params);
+        assert_close(phind[0], (0.35 / 1.65 + 0.3) / 2.0);
+        assert_close(phind[1], ((0.04 + 0.01) / 2.0_f64).sqrt());
+
+        // Плотность в кг/м3 пересчитывается в г/см3
+        let mut las_file = sample_file();
+        las_file.curves[2].unit = "KG/M3".to_string();
+        for row in &mut las_file.data {
+            if row.values[2] != las_file.null_value {
+                row.values[2] *= 1000.0;
+            }
+        }
+        assert_close(computed(&mut las_file, Computation::DensityPorosity, &params)[1], 0.2);
+    }
+
+    #[test]
+    fn archie_saturation() {
+        // a=1, m=2, n=2: Sw = sqrt(Rw / (phi^2 * Rt))
+        let params = PetroParams { rw: Some(0.05), nphi_curve: None, ..Default::default() };
+        let mut las_file = sample_file();
+        las_file.curves.retain(|c| c.mnemonic != "NPHI");
+        for row in &mut las_file.data {
+            row.values.remove(3);
+        }
+        let sw = computed(&mut las_file, Computation::WaterSaturation, &params);
+        let phi: f64 = 0.35 / 1.65;
+        assert_close(sw[0], (0.05 / (phi * phi * 5.0)).sqrt());
+        assert_close(sw[1], 0.5);
+
+        // Без rw= берётся RW из ~Parameter; без RW - ошибка
+        let from_header = computed(&mut sample_file(), Computation::WaterSaturation, &PetroParams::default());
+        assert!(from_header[1].is_some());
+        let mut no_rw = sample_file();
+        no_rw.parameters.clear();
+        assert!(no_rw.add_computed(&[Computation::WaterSaturation], &PetroParams::default(), &AliasDictionary::builtin()).is_err());
+    }
+
+    #[test]
+    fn existing_curve_keeps_its_name() {
+        let mut las_file = sample_file();
+        let params = PetroParams::default();
+        computed(&mut las_file, Computation::DensityPorosity, &params);
+        computed(&mut las_file, Computation::DensityPorosity, &params);
+        let names: Vec<&str> = las_file.curves.iter().skip(5).map(|c| c.mnemonic.as_str()).collect();
+        assert_eq!(names, ["PHID", "PHID_1"]);
+    }
+}
@@ -1,12 +1,15 @@
+//! Отрисовка кривых и горизонтальных шкал в картинки (raqote).
+
 use anyhow::Result;
 use image::{ImageEncoder, Rgba, RgbaImage};
 use raqote::{
-    DrawTarget, PathBuilder, Source, SolidSource, StrokeStyle, DrawOptions, LineCap, LineJoin
+    DrawTarget, PathBuilder, Source, SolidSource, StrokeStyle, LineCap, LineJoin
 };
 
 #[cfg(target_arch = "x86_64")]
 use std::arch::x86_64::*;
 
+/// Цвет RGB
 pub type RGBColor = [u8; 3];
 
 /// Конвертирует BGRA данные в RGBA используя SIMD-оптимизацию
@@ -88,19 +91,185 @@ pub fn hex_to_rgb(hex: &str) -> RGBColor {
     }
 }
 
+/// Тип горизонтальной шкалы кривой
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ScaleType {
+    #[default]
+    Linear,
+    /// Десятичный логарифм (сопротивление); диапазон должен быть положительным
+    Logarithmic,
+}
+
+/// Стиль линии кривой
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum LineStyle {
+    #[default]
+    Solid,
+    /// Штрихи по DASH_LENGTH пикселей через DASH_PERIOD (вторая запись при сравнении)
+    Dashed,
+}
+
+/// Штрих и период штриховой линии, пикселей
+const DASH_LENGTH: f64 = 6.0;
+const DASH_PERIOD: f64 = 10.0;
+
+/// Отступ слева под подписи основного параметра
+const PLOT_X_START: u32 = 100;
+
+/// Цвет границ между колонками
+const TRACK_BORDER_COLOR: RGBColor = [0xC0, 0xC0, 0xC0];
+
+/// Горизонтальная линия с подписью поперёк всех колонок (кровля пласта)
+#[derive(Debug, Clone)]
+pub struct PlotTop {
+    pub depth: f64,
+    pub label: String,
+    pub color: RGBColor,
+}
+
+/// Параметры отрисовки одной картинки: шкал (`show_scales`) или фрагмента графиков.
+/// `x_ranges` и `colors` - по одному на кривую, `y_range` - диапазон основного параметра
+/// от верхнего до нижнего края картинки.
 pub struct PlotConfig {
     pub width: u32,
     pub height: u32,
     pub colors: Vec<RGBColor>,
+    /// Значения у левого и правого края колонки; левое больше правого - шкала справа налево
     pub x_ranges: Vec<(f64, f64)>,
+    /// Колонка кривой (`0..track_count`, ширина делится поровну); нет значения - колонка 0
+    pub tracks: Vec<usize>,
+    pub track_count: usize,
+    /// Тип шкалы кривой; нет значения - линейная
+    pub scale_types: Vec<ScaleType>,
+    /// Стиль линии кривой; нет значения - сплошная
+    pub line_styles: Vec<LineStyle>,
     pub y_range: (f64, f64),
+    /// Линия прерывается между отсчётами, основной параметр которых отличается больше чем на `max_gap`
+    pub max_gap: f64,
     pub show_scales: bool,
-    pub pixels_per_step: usize,
-    pub html_row_steps: usize,
     pub scale_spacing: usize,
     pub tick_size_major: usize,
     pub tick_size_minor: usize,
     pub max_scales: usize,
+    /// Кровли (рисуются только на картинках графиков)
+    pub tops: Vec<PlotTop>,
+}
+
+impl PlotConfig {
+    fn track_of(&self, idx: usize) -> usize {
+        self.tracks.get(idx).copied().unwrap_or(0).min(self.track_count.max(1) - 1)
+    }
+
+    fn scale_type_of(&self, idx: usize) -> ScaleType {
+        self.scale_types.get(idx).copied().unwrap_or_default()
+    }
+
+    fn line_style_of(&self, idx: usize) -> LineStyle {
+        self.line_styles.get(idx).copied().unwrap_or_default()
+    }
+
+    /// Левый край и ширина колонки в пикселях
+    fn track_span(&self, track: usize) -> (f64, f64) {
+        let plot_width = self.width.saturating_sub(PLOT_X_START) as f64;
+        let track_width = plot_width / self.track_count.max(1) as f64;
+        (PLOT_X_START as f64 + track as f64 * track_width, track_width)
+    }
+
+    /// Положение значения на шкале кривой: 0 - левый край колонки, 1 - правый.
+    /// Постоянная кривая (нулевой диапазон) рисуется посередине;
+    /// None - диапазона нет (или он не положительный для логарифмической шкалы).
+    fn scale_position(&self, idx: usize, value: f64) -> Option<f64> {
+        let (left, right) = *self.x_ranges.get(idx)?;
+        if !left.is_finite() || !right.is_finite() {
+            return None;
+        }
+        if left == right {
+            return Some(0.5);
+        }
+        match self.scale_type_of(idx) {
+            ScaleType::Linear => Some((value - left) / (right - left)),
+            ScaleType::Logarithmic => {
+                if left <= 0.0 || right <= 0.0 {
+                    return None;
+                }
+                // Нули и отрицательные значения прижимаются к минимуму шкалы
+                let value = value.max(left.min(right));
+                Some((value.log10() - left.log10()) / (right.log10() - left.log10()))
+            }
+        }
+    }
+
+    /// X-координата значения в колонке кривой
+    fn curve_x(&self, idx: usize, value: f64) -> Option<f64> {
+        let (track_left, track_width) = self.track_span(self.track_of(idx));
+        self.scale_position(idx, value).map(|t| track_left + t * track_width)
+    }
+
+    /// Засечки шкалы кривой: (положение 0..=1, длинная ли засечка).
+    /// Линейная - длинные через старший разряд диапазона, короткие через десятую его часть;
+    /// логарифмическая - длинные на степенях 10, короткие на 2..9 внутри декады.
+    fn scale_ticks(&self, idx: usize) -> Vec<(f64, bool)> {
+        let Some(&(left, right)) = self.x_ranges.get(idx) else {
+            return Vec::new();
+        };
+        if left == right || self.scale_position(idx, left).is_none() {
+            return Vec::new();
+        }
+        let (x_min, x_max) = (left.min(right), left.max(right));
+        let mut values = Vec::new();
+
+        match self.scale_type_of(idx) {
+            ScaleType::Linear => {
+                let range = x_max - x_min;
+                // Определяем порядок старшего разряда
+                let order = range.log10().floor();
+                let major_step = 10_f64.powf(order);
+                let minor_step = 10_f64.powf(order - 1.0);
+
+                // Позиции длинных засечек исключаются из коротких
+                let mut major_positions = std::collections::HashSet::new();
+                let mut major_value = (x_min / major_step).ceil() * major_step;
+                while major_value <= x_max {
+                    values.push((major_value, true));
+                    major_positions.insert((major_value / minor_step).round() as i64);
+                    major_value += major_step;
+                }
+
+                let mut minor_value = (x_min / minor_step).ceil() * minor_step;
+                while minor_value <= x_max {
+                    if !major_positions.contains(&((minor_value / minor_step).round() as i64)) {
+                        values.push((minor_value, false));
+                    }
+                    minor_value += minor_step;
+                }
+            }
+            ScaleType::Logarithmic => {
+                let first_decade = x_min.log10().floor() as i32;
+                let last_decade = x_max.log10().ceil() as i32;
+                for decade in first_decade..=last_decade {
+                    let base = 10_f64.powi(decade);
+                    for mantissa in 1..=9 {
+                        let value = mantissa as f64 * base;
+                        // Допуск на погрешность log10/powi у краёв диапазона
+                        if value >= x_min * (1.0 - 1e-9) && value <= x_max * (1.0 + 1e-9) {
+                            values.push((value, mantissa == 1));
+                        }
+                    }
+                }
+            }
+        }
+
+        values
+            .into_iter()
+            .filter_map(|(value, major)| self.scale_position(idx, value).map(|t| (t, major)))
+            .filter(|(t, _)| (0.0..=1.0).contains(t))
+            .collect()
+    }
+
+    /// X-координаты границ между колонками
+    fn track_borders(&self) -> Vec<u32> {
+        (1..self.track_count.max(1)).map(|track| self.track_span(track).0.round() as u32).collect()
+    }
 }
 
 /// Пара: массив X-координат засечек в пикселях и цвет шкалы
@@ -109,39 +278,25 @@ pub type ScaleTickPositions = Vec<(Vec<u32>, RGBColor)>;
 /// Вычисляет позиции длинных засечек для первых max_scales кривых
 fn calculate_scale_tick_positions(
     config: &PlotConfig,
-    curves_data: &[(Vec<Option<f64>>, String)],
+    curves_data: &[CurveData],
 ) -> ScaleTickPositions {
-    let plot_x_start = 100u32;
-    let plot_width = config.width.saturating_sub(plot_x_start);
     let mut result = Vec::new();
     
     let max_curves = config.max_scales.min(curves_data.len()).min(config.colors.len()).min(config.x_ranges.len());
     
     for idx in 0..max_curves {
-        let (x_min, x_max) = config.x_ranges[idx];
         let color = config.colors[idx];
-        let mut tick_positions = Vec::new();
-        
-        if x_max > x_min && x_max.is_finite() && x_min.is_finite() {
-            let range = x_max - x_min;
-            let order = range.log10().floor();
-            let major_step = 10_f64.powf(order);
-            let first_major = (x_min / major_step).ceil() * major_step;
-            
-            let mut major_value = first_major;
-            while major_value <= x_max {
-                // Вычисляем X-координату в пикселях (целое число)
-                let t = (major_value - x_min) / range;
-                let x_pixel_f64 = plot_x_start as f64 + t * plot_width as f64;
-                let x_pixel = x_pixel_f64.round() as u32;
-                
-                // Проверяем, что координата в пределах области графика
-                if x_pixel >= plot_x_start && x_pixel < plot_x_start + plot_width {
-                    tick_positions.push(x_pixel);
-                }
-                major_value += major_step;
-            }
-        }
+        let (track_left, track_width) = config.track_span(config.track_of(idx));
+        let track_right = track_left + track_width;
+        let tick_positions = config
+            .scale_ticks(idx)
+            .into_iter()
+            .filter(|&(_, major)| major)
+            // X-координата в пикселях (целое число) в пределах колонки
+            .map(|(t, _)| (track_left + t * track_width).round())
+            .filter(|&x| x >= track_left && x < track_right)
+            .map(|x| x as u32)
+            .collect();
         
         result.push((tick_positions, color));
     }
@@ -149,13 +304,29 @@ fn calculate_scale_tick_positions(
     result
 }
 
+/// Данные кривой для отрисовки: значения по строкам и мнемоника
+pub type CurveData = (Vec<Option<f64>>, String);
+
+/// Рисует картинку (см. [`render_plot_image`]) для строк `depth_start_idx..depth_end_idx` и кодирует её в PNG
 pub fn generate_plot_png(
     config: &PlotConfig,
-    curves_data: Vec<(Vec<Option<f64>>, String)>,
+    curves_data: &[CurveData],
     depth_data: &[Option<f64>],
     depth_start_idx: usize,
     depth_end_idx: usize,
 ) -> Result<Vec<u8>> {
+    let img = render_plot_image(config, curves_data, depth_data, depth_start_idx, depth_end_idx)?;
+    encode_png(&img)
+}
+
+/// Рисует шкалы или графики (в зависимости от `config.show_scales`) в RgbaImage
+pub fn render_plot_image(
+    config: &PlotConfig,
+    curves_data: &[CurveData],
+    depth_data: &[Option<f64>],
+    depth_start_idx: usize,
+    depth_end_idx: usize,
+) -> Result<RgbaImage> {
     let mut img = RgbaImage::new(config.width, config.height);
     
     // Заполняем белым фоном с непрозрачным альфа-каналом
@@ -164,7 +335,7 @@ pub fn generate_plot_png(
     }
 
     // Вычисляем позиции засечек для первых max_scales кривых
-    let scale_tick_positions = calculate_scale_tick_positions(config, &curves_data);
+    let scale_tick_positions = calculate_scale_tick_positions(config, curves_data);
 
     if config.show_scales {
         // Рисуем шкалы для каждого параметра
@@ -174,14 +345,18 @@ pub fn generate_plot_png(
         draw_curves(&mut img, config, curves_data, depth_data, depth_start_idx, depth_end_idx, &scale_tick_positions)?;
     }
 
-    // Конвертируем в PNG
+    Ok(img)
+}
+
+/// Кодирует изображение в PNG
+pub fn encode_png(img: &RgbaImage) -> Result<Vec<u8>> {
     let mut png_data = Vec::new();
     {
         let encoder = image::codecs::png::PngEncoder::new(&mut png_data);
         encoder.write_image(
-            &img.into_raw(),
-            config.width,
-            config.height,
+            img.as_raw(),
+            img.width(),
+            img.height(),
             image::ColorType::Rgba8.into(),
         )?;
     }
@@ -192,28 +367,41 @@ pub fn generate_plot_png(
 fn draw_scales(
     img: &mut RgbaImage,
     config: &PlotConfig,
-    curves_data: Vec<(Vec<Option<f64>>, String)>,
+    curves_data: &[CurveData],
 ) -> Result<()> {
-    let plot_x_start = 100u32;
-    let plot_width = (config.width as i32 - plot_x_start as i32) as u32;
-    
     // Создаём DrawTarget для антиалиасинга
     let mut dt = DrawTarget::new(config.width as i32, config.height as i32);
     dt.clear(SolidSource::from_unpremultiplied_argb(0xFF, 0xFF, 0xFF, 0xFF));
 
-    let mut y_pos = config.scale_spacing as u32;
-    
+    let stroke = StrokeStyle {
+        width: 1.0,
+        cap: LineCap::Round,
+        join: LineJoin::Round,
+        miter_limit: 10.0,
+        ..StrokeStyle::default()
+    };
+
+    // Шкалы каждой колонки идут сверху вниз с шагом scale_spacing
+    let mut scales_in_track = vec![0u32; config.track_count.max(1)];
+
     for (idx, (_, _name)) in curves_data.iter().enumerate() {
         if idx >= config.x_ranges.len() || idx >= config.colors.len() {
             continue;
         }
 
-        let (x_min, x_max) = config.x_ranges[idx];
+        let track = config.track_of(idx);
+        scales_in_track[track] += 1;
+        let y_pos = scales_in_track[track] * config.scale_spacing as u32;
+        if y_pos >= config.height {
+            continue;
+        }
+
         let rgb = config.colors[idx];
         
-        // Рисуем горизонтальную линию шкалы
-        let x_start = plot_x_start as f32;
-        let x_end = (plot_x_start + plot_width) as f32;
+        // Рисуем горизонтальную линию шкалы на ширину колонки
+        let (track_left, track_width) = config.track_span(track);
+        let x_start = track_left as f32;
+        let x_end = (track_left + track_width) as f32;
         let y = y_pos as f32;
         
         let mut pb = PathBuilder::new();
@@ -228,81 +416,21 @@ fn draw_scales(
             a: 255,
         });
         
-        let stroke = StrokeStyle {
-            width: 1.0,
-            cap: LineCap::Round,
-            join: LineJoin::Round,
-            miter_limit: 10.0,
-            ..StrokeStyle::default()
-        };
-        
         dt.stroke(&path, &source, &stroke, &raqote::DrawOptions::new());
         
-        // Рисуем засечки только если диапазон валиден
-        if x_max > x_min && x_max.is_finite() && x_min.is_finite() {
-            let range = x_max - x_min;
-            
-            // Определяем порядок старшего разряда
-            let order = range.log10().floor();
-            let major_step = 10_f64.powf(order);
-            let minor_step = 10_f64.powf(order - 1.0);
-            
-            // Находим первую длинную засечку
-            let first_major = (x_min / major_step).ceil() * major_step;
-            
-            // Собираем позиции длинных засечек для исключения их из коротких
-            let mut major_positions = std::collections::HashSet::new();
-            
-            // Рисуем длинные засечки
-            let mut major_value = first_major;
-            while major_value <= x_max {
-                let t = ((major_value - x_min) / range) as f32;
-                if t >= 0.0 && t <= 1.0 {
-                    let tick_x = x_start + t * (x_end - x_start);
-                    let tick_y_start = (y_pos.saturating_sub(config.tick_size_major as u32)) as f32;
-                    let tick_y_end = (y_pos + config.tick_size_major as u32) as f32;
-                    
-                    let mut pb_tick = PathBuilder::new();
-                    pb_tick.move_to(tick_x, tick_y_start);
-                    pb_tick.line_to(tick_x, tick_y_end);
-                    let path_tick = pb_tick.finish();
-                    
-                    dt.stroke(&path_tick, &source, &stroke, &raqote::DrawOptions::new());
-                    
-                    // Сохраняем позицию для исключения из коротких засечек
-                    major_positions.insert((major_value / minor_step).round() as i64);
-                }
-                major_value += major_step;
-            }
-            
-            // Рисуем короткие засечки
-            let first_minor = (x_min / minor_step).ceil() * minor_step;
-            let mut minor_value = first_minor;
-            while minor_value <= x_max {
-                // Пропускаем места, где уже есть длинные засечки
-                let minor_index = (minor_value / minor_step).round() as i64;
-                if !major_positions.contains(&minor_index) {
-                    let t = ((minor_value - x_min) / range) as f32;
-                    if t >= 0.0 && t <= 1.0 {
-                        let tick_x = x_start + t * (x_end - x_start);
-                        let tick_y_start = (y_pos.saturating_sub(config.tick_size_minor as u32)) as f32;
-                        let tick_y_end = (y_pos + config.tick_size_minor as u32) as f32;
-                        
-                        let mut pb_tick = PathBuilder::new();
-                        pb_tick.move_to(tick_x, tick_y_start);
-                        pb_tick.line_to(tick_x, tick_y_end);
-                        let path_tick = pb_tick.finish();
-                        
-                        dt.stroke(&path_tick, &source, &stroke, &raqote::DrawOptions::new());
-                    }
-                }
-                minor_value += minor_step;
-            }
-        }
-
-        y_pos += config.scale_spacing as u32;
-        if y_pos >= config.height {
-            break;
+        // Засечки (пусто, если диапазон невалиден)
+        for (t, major) in config.scale_ticks(idx) {
+            let tick_size = if major { config.tick_size_major } else { config.tick_size_minor } as u32;
+            let tick_x = x_start + t as f32 * (x_end - x_start);
+            let tick_y_start = (y_pos.saturating_sub(tick_size)) as f32;
+            let tick_y_end = (y_pos + tick_size) as f32;
+
+            let mut pb_tick = PathBuilder::new();
+            pb_tick.move_to(tick_x, tick_y_start);
+            pb_tick.line_to(tick_x, tick_y_end);
+            let path_tick = pb_tick.finish();
+
+            dt.stroke(&path_tick, &source, &stroke, &raqote::DrawOptions::new());
         }
     }
     
@@ -311,65 +439,57 @@ fn draw_scales(
     let dst = img.as_mut();
     
     convert_bgra_to_rgba(data_u8, dst);
+    draw_track_borders(img, config);
 
     Ok(())
 }
 
+/// Сплошные вертикальные линии между колонками на всю высоту картинки
+fn draw_track_borders(img: &mut RgbaImage, config: &PlotConfig) {
+    let [r, g, b] = TRACK_BORDER_COLOR;
+    for x in config.track_borders() {
+        if x >= config.width {
+            continue;
+        }
+        for y in 0..config.height {
+            img.put_pixel(x, y, Rgba([r, g, b, 255]));
+        }
+    }
+}
+
 fn draw_curves(
     img: &mut RgbaImage,
     config: &PlotConfig,
-    curves_data: Vec<(Vec<Option<f64>>, String)>,
+    curves_data: &[CurveData],
     depth_data: &[Option<f64>],
     depth_start_idx: usize,
     depth_end_idx: usize,
     scale_tick_positions: &ScaleTickPositions,
 ) -> Result<()> {
-    let plot_width = config.width as f64;
     let plot_height = config.height as f64; // (config.height as f64 * 1.04) as f64; // TODO: coef!
-    let plot_x_start = 100 as f64;
-    let plot_y_start = 0 as f64;
-    let (mut y_min, mut y_max) = config.y_range;
+    let plot_y_start = 0_f64;
+    let (y_min, y_max) = config.y_range;
 
     // Собираем данные для текущего диапазона
     let mut depth_slice = Vec::new();
     let mut valid_indices = Vec::new();
 
-    let (mut ymin, mut ymax): (Option::<f64>, Option::<f64>) = (None, None);
-    // depth_end_idx может быть на 1 больше для включения общего шага со следующей строкой
-    // Включаем все индексы от depth_start_idx до depth_end_idx (включительно)
-    // Но не выходим за границы массива
+    // Диапазон depth_start_idx..depth_end_idx уже включает общий шаг со следующей строкой;
+    // не выходим за границы массива
     let actual_end = depth_end_idx.min(depth_data.len());
-    // Используем ..= для включения последнего индекса, если он в пределах массива
     for i in depth_start_idx..actual_end {
         if let Some(depth) = depth_data.get(i).and_then(|&d| d) {
-            ymin = Some(ymin.map_or(depth, |m| m.min(depth)));
-            ymax = Some(ymax.map_or(depth, |m| m.max(depth)));
             depth_slice.push(depth);
             valid_indices.push(i);
         }
     }
 
-    // Если depth_end_idx указывает на следующий шаг (html_row_steps+1) и он в пределах массива, включаем его
-    // Это нужно для того, чтобы последний шаг был общим со следующей строкой
-    /*
-    if depth_end_idx < depth_data.len() && depth_end_idx >= actual_end {
-        if let Some(depth) = depth_data.get(depth_end_idx).and_then(|&d| d) {
-            ymin = Some(ymin.map_or(depth, |m| m.min(depth)));
-            ymax = Some(ymax.map_or(depth, |m| m.max(depth)));
-            depth_slice.push(depth);
-            valid_indices.push(depth_end_idx);
-        }
-    }
-    */
-
     // avoid div to zero
-    y_min = ymin.unwrap_or(y_min);
-    y_max = ymax.unwrap_or(y_max);
-
-    if depth_slice.is_empty() {
+    if y_max <= y_min {
         return Ok(());
     }
 
+    // Пустая строка (разрыв в данных) остаётся пустой, но с вертикальными линиями шкал
     // Создаём DrawTarget того же размера (ARGB backing)
     let mut dt = DrawTarget::new(config.width as i32, config.height as i32);
 
@@ -384,10 +504,11 @@ fn draw_curves(
             continue;
         }
 
-        let (x_min, x_max) = config.x_ranges[curve_idx];
-            let rgb = config.colors[curve_idx];
+        let rgb = config.colors[curve_idx];
 
-        let mut last_point: Option<(u32, u32)> = None;
+        // Точки кривой в пикселях; None - разрыв линии (NaN, null или пропуск по глубине)
+        let mut points: Vec<PixelPoint> = Vec::with_capacity(valid_indices.len());
+        let mut last_depth: Option<f64> = None;
 
         // Обрабатываем все индексы из valid_indices (включая последний шаг html_row_steps+1, если он есть)
         for (slice_idx, &data_idx) in valid_indices.iter().enumerate() {
@@ -395,10 +516,14 @@ fn draw_curves(
                 continue;
             }
 
-            if let Some(value) = data[data_idx] {
-                let depth = depth_slice[slice_idx];
-                
-                let x = plot_x_start + ((value - x_min) / (x_max - x_min)) * plot_width;
+            let depth = depth_slice[slice_idx];
+            if last_depth.is_some_and(|last| (depth - last).abs() > config.max_gap) {
+                points.push(None);
+            }
+            last_depth = Some(depth);
+
+            // Значение без шкалы (пустой диапазон) не рисуется
+            if let Some(x) = data[data_idx].and_then(|value| config.curve_x(curve_idx, value)) {
                 // Исправляем формулу: y_min (меньшая глубина) должна быть вверху (y=0), y_max (большая глубина) - внизу (y=height)
                 let y = plot_y_start + ((depth - y_min) / (y_max - y_min)) * plot_height;
 
@@ -406,18 +531,31 @@ fn draw_curves(
                 let y_int = y as u32;
 
                 if x_int < config.width /* && y_int < config.height */ {
-                    // Рисуем линию от предыдущей точки
-                    if let Some((last_x, last_y)) = last_point {
-                        draw_line_dt(&mut dt, last_x, last_y, x_int, y_int, rgb);
-                    }
-
-                    last_point = Some((x_int, y_int));
+                    points.push(Some((x_int, y_int)));
                 }
             } else {
-                // NaN или null - разрыв линии
-                last_point = None;
+                points.push(None);
             }
         }
+
+        // Отсчётов больше, чем строк пикселей - лишние точки всё равно легли бы друг на друга
+        if valid_indices.len() > config.height as usize {
+            points = decimate_m4(&points);
+        }
+
+        // Штриховая линия: отрезки рисуются, только пока пройденная длина в начале периода
+        let dashed = config.line_style_of(curve_idx) == LineStyle::Dashed;
+        let mut length = 0.0;
+        let mut last_point: Option<(u32, u32)> = None;
+        for point in points {
+            if let (Some((last_x, last_y)), Some((x, y))) = (last_point, point) {
+                if !dashed || length % DASH_PERIOD < DASH_LENGTH {
+                    draw_line_dt(&mut dt, last_x, last_y, x, y, rgb);
+                }
+                length += (x as f64 - last_x as f64).hypot(y as f64 - last_y as f64);
+            }
+            last_point = point;
+        }
     }
 
     // Получаем сырые байты BGRA (u8) из DrawTarget
@@ -436,8 +574,8 @@ fn draw_curves(
     // Рисуем вертикальные линии под длинными засечками шкал напрямую на RgbaImage
     // Рисуем их после графиков, чтобы они были под графиками
     // Используем прямое рисование пикселей, чтобы избежать проблем с raqote
-    let plot_x_start_u32 = plot_x_start as u32;
-    let plot_width_u32 = (plot_width - plot_x_start) as u32;
+    let plot_x_start_u32 = PLOT_X_START;
+    let plot_width_u32 = config.width.saturating_sub(PLOT_X_START);
 
     for (tick_positions, color) in scale_tick_positions.iter() {
         if tick_positions.is_empty() {
@@ -484,37 +622,176 @@ fn draw_curves(
         }
     }
     
+    draw_track_borders(img, config);
+    draw_tops(img, config);
+
     Ok(())
 }
 
-// todo: delete
-fn draw_line(img: &mut RgbaImage, x1: u32, y1: u32, x2: u32, y2: u32, color: [u8; 3]) {
-    let dx = (x2 as i32 - x1 as i32).abs();
-    let dy = (y2 as i32 - y1 as i32).abs();
-    let sx = if x1 < x2 { 1 } else { -1 };
-    let sy = if y1 < y2 { 1 } else { -1 };
-    let mut err = dx - dy;
-    let mut x = x1 as i32;
-    let mut y = y1 as i32;
-
-    loop {
-        if x >= 0 && x < img.width() as i32 && y >= 0 && y < img.height() as i32 {
-            img.put_pixel(x as u32, y as u32, Rgba([color[0], color[1], color[2], 255]));
+/// Линии кровель поверх кривых; подпись над линией (у верхнего края картинки - под ней)
+fn draw_tops(img: &mut RgbaImage, config: &PlotConfig) {
+    let (y_min, y_max) = config.y_range;
+    if y_max <= y_min {
+        return;
+    }
+    for top in &config.tops {
+        let y = ((top.depth - y_min) / (y_max - y_min) * config.height as f64).round();
+        if !(0.0..config.height as f64).contains(&y) {
+            continue;
+        }
+        let y = y as u32;
+        let [r, g, b] = top.color;
+        for x in PLOT_X_START.min(config.width)..config.width {
+            img.put_pixel(x, y, Rgba([r, g, b, 255]));
         }
+        let label_y = if y >= GLYPH_HEIGHT + 2 { y - GLYPH_HEIGHT - 2 } else { y + 3 };
+        draw_label(img, PLOT_X_START + 3, label_y, &top.label, 1, top.color);
+    }
+}
 
-        if x == x2 as i32 && y == y2 as i32 {
-            break;
+/// Точка кривой в пикселях; `None` - разрыв линии
+type PixelPoint = Option<(u32, u32)>;
+
+/// Прореживание M4: на каждой строке пикселей непрерывного участка кривой остаются
+/// первая, последняя, крайняя левая и крайняя правая точки. Ломаная через них
+/// рисуется так же, как через все точки, выбросы не теряются.
+fn decimate_m4(points: &[PixelPoint]) -> Vec<PixelPoint> {
+    let mut result = Vec::with_capacity(points.len().min(1024));
+    // Индексы точек текущей строки пикселей: первая, min X, max X, последняя
+    let mut bucket: Option<[usize; 4]> = None;
+
+    let flush = |bucket: [usize; 4], result: &mut Vec<PixelPoint>| {
+        let mut indices = bucket;
+        indices.sort_unstable();
+        let mut previous = None;
+        for idx in indices {
+            if previous != Some(idx) {
+                result.push(points[idx]);
+                previous = Some(idx);
+            }
         }
+    };
+
+    for (idx, point) in points.iter().enumerate() {
+        let Some((x, y)) = *point else {
+            if let Some(bucket) = bucket.take() {
+                flush(bucket, &mut result);
+            }
+            if result.last().is_some_and(|p| p.is_some()) {
+                result.push(None);
+            }
+            continue;
+        };
 
-        let e2 = 2 * err;
-        if e2 > -dy {
-            err -= dy;
-            x += sx;
+        match bucket.as_mut() {
+            Some(b) if points[b[0]].is_some_and(|(_, bucket_y)| bucket_y == y) => {
+                let x_of = |i: usize| points[i].map_or(0, |(x, _)| x);
+                if x < x_of(b[1]) {
+                    b[1] = idx;
+                }
+                if x > x_of(b[2]) {
+                    b[2] = idx;
+                }
+                b[3] = idx;
+            }
+            _ => {
+                if let Some(bucket) = bucket.replace([idx; 4]) {
+                    flush(bucket, &mut result);
+                }
+            }
         }
-        if e2 < dx {
-            err += dx;
-            y += sy;
+    }
+    if let Some(bucket) = bucket {
+        flush(bucket, &mut result);
+    }
+    result
+}
+
+/// Высота глифа растрового шрифта в точках
+const GLYPH_HEIGHT: u32 = 7;
+
+/// Растровый шрифт 5x7 для подписей глубины и кровель (цифры, латинские буквы без учёта
+/// регистра, знаки препинания). Каждая строка глифа - 5 бит, старший бит слева.
+fn glyph_5x7(ch: char) -> Option<[u8; 7]> {
+    let glyph = match ch.to_ascii_uppercase() {
+        '0' => [0b01110, 0b10001, 0b10011, 0b10101, 0b11001, 0b10001, 0b01110],
+        '1' => [0b00100, 0b01100, 0b00100, 0b00100, 0b00100, 0b00100, 0b01110],
+        '2' => [0b01110, 0b10001, 0b00001, 0b00010, 0b00100, 0b01000, 0b11111],
+        '3' => [0b11111, 0b00010, 0b00100, 0b00010, 0b00001, 0b10001, 0b01110],
+        '4' => [0b00010, 0b00110, 0b01010, 0b10010, 0b11111, 0b00010, 0b00010],
+        '5' => [0b11111, 0b10000, 0b11110, 0b00001, 0b00001, 0b10001, 0b01110],
+        '6' => [0b00110, 0b01000, 0b10000, 0b11110, 0b10001, 0b10001, 0b01110],
+        '7' => [0b11111, 0b00001, 0b00010, 0b00100, 0b01000, 0b01000, 0b01000],
+        '8' => [0b01110, 0b10001, 0b10001, 0b01110, 0b10001, 0b10001, 0b01110],
+        '9' => [0b01110, 0b10001, 0b10001, 0b01111, 0b00001, 0b00010, 0b01100],
+        'A' => [0b01110, 0b10001, 0b10001, 0b11111, 0b10001, 0b10001, 0b10001],
+        'B' => [0b11110, 0b10001, 0b10001, 0b11110, 0b10001, 0b10001, 0b11110],
+        'C' => [0b01110, 0b10001, 0b10000, 0b10000, 0b10000, 0b10001, 0b01110],
+        'D' => [0b11100, 0b10010, 0b10001, 0b10001, 0b10001, 0b10010, 0b11100],
+        'E' => [0b11111, 0b10000, 0b10000, 0b11110, 0b10000, 0b10000, 0b11111],
+        'F' => [0b11111, 0b10000, 0b10000, 0b11110, 0b10000, 0b10000, 0b10000],
+        'G' => [0b01110, 0b10001, 0b10000, 0b10111, 0b10001, 0b10001, 0b01111],
+        'H' => [0b10001, 0b10001, 0b10001, 0b11111, 0b10001, 0b10001, 0b10001],
+        'I' => [0b01110, 0b00100, 0b00100, 0b00100, 0b00100, 0b00100, 0b01110],
+        'J' => [0b00111, 0b00010, 0b00010, 0b00010, 0b00010, 0b10010, 0b01100],
+        'K' => [0b10001, 0b10010, 0b10100, 0b11000, 0b10100, 0b10010, 0b10001],
+        'L' => [0b10000, 0b10000, 0b10000, 0b10000, 0b10000, 0b10000, 0b11111],
+        'M' => [0b10001, 0b11011, 0b10101, 0b10101, 0b10001, 0b10001, 0b10001],
+        'N' => [0b10001, 0b10001, 0b11001, 0b10101, 0b10011, 0b10001, 0b10001],
+        'O' => [0b01110, 0b10001, 0b10001, 0b10001, 0b10001, 0b10001, 0b01110],
+        'P' => [0b11110, 0b10001, 0b10001, 0b11110, 0b10000, 0b10000, 0b10000],
+        'Q' => [0b01110, 0b10001, 0b10001, 0b10001, 0b10101, 0b10010, 0b01101],
+        'R' => [0b11110, 0b10001, 0b10001, 0b11110, 0b10100, 0b10010, 0b10001],
+        'S' => [0b01111, 0b10000, 0b10000, 0b01110, 0b00001, 0b00001, 0b11110],
+        'T' => [0b11111, 0b00100, 0b00100, 0b00100, 0b00100, 0b00100, 0b00100],
+        'U' => [0b10001, 0b10001, 0b10001, 0b10001, 0b10001, 0b10001, 0b01110],
+        'V' => [0b10001, 0b10001, 0b10001, 0b10001, 0b10001, 0b01010, 0b00100],
+        'W' => [0b10001, 0b10001, 0b10001, 0b10101, 0b10101, 0b10101, 0b01010],
+        'X' => [0b10001, 0b10001, 0b01010, 0b00100, 0b01010, 0b10001, 0b10001],
+        'Y' => [0b10001, 0b10001, 0b10001, 0b01010, 0b00100, 0b00100, 0b00100],
+        'Z' => [0b11111, 0b00001, 0b00010, 0b00100, 0b01000, 0b10000, 0b11111],
+        '.' => [0b00000, 0b00000, 0b00000, 0b00000, 0b00000, 0b01100, 0b01100],
+        ',' => [0b00000, 0b00000, 0b00000, 0b00000, 0b01100, 0b00100, 0b01000],
+        ':' => [0b00000, 0b01100, 0b01100, 0b00000, 0b01100, 0b01100, 0b00000],
+        '-' => [0b00000, 0b00000, 0b00000, 0b11111, 0b00000, 0b00000, 0b00000],
+        '+' => [0b00000, 0b00100, 0b00100, 0b11111, 0b00100, 0b00100, 0b00000],
+        '_' => [0b00000, 0b00000, 0b00000, 0b00000, 0b00000, 0b00000, 0b11111],
+        '/' => [0b00000, 0b00001, 0b00010, 0b00100, 0b01000, 0b10000, 0b00000],
+        '(' => [0b00010, 0b00100, 0b01000, 0b01000, 0b01000, 0b00100, 0b00010],
+        ')' => [0b01000, 0b00100, 0b00010, 0b00010, 0b00010, 0b00100, 0b01000],
+        '\'' => [0b01100, 0b00100, 0b01000, 0b00000, 0b00000, 0b00000, 0b00000],
+        '&' => [0b01100, 0b10010, 0b10100, 0b01000, 0b10101, 0b10010, 0b01101],
+        ' ' => [0; 7],
+        _ => return None,
+    };
+    Some(glyph)
+}
+
+/// Пишет подпись растровым шрифтом (масштаб `scale` пикселей на точку глифа).
+/// Неподдерживаемые символы пропускаются.
+pub fn draw_label(img: &mut RgbaImage, x: u32, y: u32, text: &str, scale: u32, color: RGBColor) {
+    let mut cursor_x = x;
+    for ch in text.chars() {
+        let Some(glyph) = glyph_5x7(ch) else {
+            continue;
+        };
+        for (row, bits) in glyph.iter().enumerate() {
+            for col in 0..5u32 {
+                if bits & (0b10000 >> col) == 0 {
+                    continue;
+                }
+                for dy in 0..scale {
+                    for dx in 0..scale {
+                        let px = cursor_x + col * scale + dx;
+                        let py = y + row as u32 * scale + dy;
+                        if px < img.width() && py < img.height() {
+                            img.put_pixel(px, py, Rgba([color[0], color[1], color[2], 255]));
+                        }
+                    }
+                }
+            }
         }
+        cursor_x += 6 * scale;
     }
 }
 
@@ -554,54 +831,45 @@ pub fn draw_line_dt(
     dt.stroke(&path, &source, &stroke, &raqote::DrawOptions::new());
 }
 
-// todo: delete
-pub fn draw_line_new(
-    img: &mut RgbaImage,
-    x1: u32,
-    y1: u32,
-    x2: u32,
-    y2: u32,
-    color: [u8; 3],
-) {
-    let w = img.width() as i32;
-    let h = img.height() as i32;
-
-    // Создаём DrawTarget того же размера (ARGB backing)
-    let mut dt = DrawTarget::new(w as i32, h as i32);
-
-    // (Опционально) очистим фон прозрачным/белым
-    dt.clear(SolidSource::from_unpremultiplied_argb(0xFF, 0xFF, 0xFF, 0xFF));
-
-    // Построим путь: простая линия от (x1,y1) -> (x2,y2)
-    let mut pb = PathBuilder::new();
-    pb.move_to(x1 as f32, y1 as f32);
-    pb.line_to(x2 as f32, y2 as f32);
-    let path = pb.finish();
-
-    // Цвет (raqote использует premultiplied ARGB helpers)
-    let src = Source::Solid(SolidSource::from_unpremultiplied_argb(
-        0xFF, color[0], color[1], color[2],
-    ));
-
-    // Параметры обводки (толщина линии в пикселях)
-    let stroke_style = StrokeStyle {
-        width: 1.5, // меняй толщину
-        ..StrokeStyle::default()
-    };
-
-    // Рендерим stroke
-    dt.stroke(&path, &src, &stroke_style, &DrawOptions::new());
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn m4_keeps_first_last_and_extremes_per_row() {
+        // Строка y=5: первая x=10, min x=2, max x=30, последняя x=12; строка y=6 - одна точка
+        let points = vec![
+            Some((10, 5)),
+            Some((20, 5)),
+            Some((2, 5)),
+            Some((30, 5)),
+            Some((15, 5)),
+            Some((12, 5)),
+            Some((7, 6)),
+        ];
+        let result = decimate_m4(&points);
+        assert_eq!(result, vec![Some((10, 5)), Some((2, 5)), Some((30, 5)), Some((12, 5)), Some((7, 6))]);
+    }
 
-    // Получаем сырые байты BGRA (u8) из DrawTarget
-    // docs.rs: get_data_u8() / get_data_u8_mut() дают BGRA порядок (little endian).
-    // Мы прочитаем их и конвертируем в image::RgbaImage (RGBA).
-    let data_u8 = dt.get_data_u8(); // &[u8], порядок BGRA для каждого пикселя
-    // data_u8.len() == (w*h*4)
+    #[test]
+    fn m4_keeps_min_max_of_every_bucket() {
+        let points: Vec<PixelPoint> = (0..1000u32).map(|i| Some(((i * 37) % 101, i / 100))).collect();
+        let result = decimate_m4(&points);
+        assert!(result.len() <= 40);
+        for y in 0..10 {
+            let row = |pts: &[PixelPoint]| -> Vec<u32> { pts.iter().flatten().filter(|p| p.1 == y).map(|p| p.0).collect() };
+            let (all, kept) = (row(&points), row(&result));
+            assert_eq!(kept.iter().min(), all.iter().min(), "min x in row {}", y);
+            assert_eq!(kept.iter().max(), all.iter().max(), "max x in row {}", y);
+            assert_eq!(kept.first(), all.first());
+            assert_eq!(kept.last(), all.last());
+        }
+    }
 
-    // Копируем в RgbaImage (RGBA) с SIMD-оптимизацией
-    // Raqote: BGRA per-pixel (b,g,r,a) on little-endian. Конвертируем в RGBA.
-    let dst = img.as_mut();
-    // dst.len() == w*h*4
-    
-    convert_bgra_to_rgba(data_u8, dst);
-}
\ No newline at end of file
+    #[test]
+    fn m4_breaks_line_at_nulls() {
+        let points = vec![Some((1, 1)), Some((3, 1)), None, None, Some((5, 1)), None];
+        assert_eq!(decimate_m4(&points), vec![Some((1, 1)), Some((3, 1)), None, Some((5, 1)), None]);
+        assert!(decimate_m4(&[None, None]).is_empty());
+    }
+}
@@ -0,0 +1,86 @@
+//! Ограниченный пул потоков для тяжёлой отрисовки (картинки строк и шкал).
+//!
+//! Задачи выполняются в порядке поступления на фиксированном числе потоков,
+//! результат приходит через future, так что асинхронные обработчики не блокируются.
+//! Каждый поток HTML держит в пуле не больше [`RenderPool::threads`] задач,
+//! поэтому большой запрос не занимает очередь целиком и не вытесняет остальных.
+
+use anyhow::{anyhow, Result};
+use futures::channel::oneshot;
+use std::future::Future;
+use std::panic::{self, AssertUnwindSafe};
+use std::sync::mpsc;
+use std::sync::{Arc, Mutex};
+
+type Job = Box<dyn FnOnce() + Send>;
+
+/// Пул потоков отрисовки; клоны используют одни и те же потоки
+#[derive(Clone)]
+pub struct RenderPool {
+    sender: mpsc::Sender<Job>,
+    threads: usize,
+}
+
+impl RenderPool {
+    /// Пул из `threads` потоков (0 - по числу ядер)
+    pub fn new(threads: usize) -> Self {
+        let threads = if threads == 0 {
+            std::thread::available_parallelism().map_or(1, |n| n.get())
+        } else {
+            threads
+        };
+
+        let (sender, receiver) = mpsc::channel::<Job>();
+        let receiver = Arc::new(Mutex::new(receiver));
+        for idx in 0..threads {
+            let receiver = receiver.clone();
+            std::thread::Builder::new()
+                .name(format!("lasplot-render-{}", idx))
+                .spawn(move || loop {
+                    // Блокировка держится только на время получения задачи
+                    let job = receiver.lock().unwrap().recv();
+                    match job {
+                        Ok(job) => job(),
+                        // Все клоны пула удалены
+                        Err(_) => break,
+                    }
+                })
+                .expect("failed to spawn render thread");
+        }
+
+        RenderPool { sender, threads }
+    }
+
+    /// Количество потоков
+    pub fn threads(&self) -> usize {
+        self.threads
+    }
+
+    /// Ставит `f` в очередь сразу при вызове; future завершается с его результатом.
+    /// Если future удалён до начала выполнения (клиент отключился), задача пропускается.
+    pub fn run<T, F>(&self, f: F) -> impl Future<Output = Result<T>> + Send + 'static
+    where
+        F: FnOnce() -> Result<T> + Send + 'static,
+        T: Send + 'static,
+    {
+        let (tx, rx) = oneshot::channel();
+        let job: Job = Box::new(move || {
+            if tx.is_canceled() {
+                return;
+            }
+            let _ = tx.send(panic::catch_unwind(AssertUnwindSafe(f)));
+        });
+        let queued = self.sender.send(job).is_ok();
+
+        async move {
+            if !queued {
+                return Err(anyhow!("Render pool is shut down"));
+            }
+            match rx.await {
+                Ok(Ok(result)) => result,
+                Ok(Err(_)) => Err(anyhow!("Render task panicked")),
+                Err(_) => Err(anyhow!("Render task was dropped")),
+            }
+        }
+    }
+}
@@ -0,0 +1,161 @@
+//! Отрисовка LAS файла в готовый документ PNG/SVG/PDF/HTML без сервера.
+//!
+//! Используются те же `LogView`/`PageLayout`, что и на странице сервера:
+//! картинка со шкалами, затем строки с графиками.
+
+use crate::pdf::{PdfDocument, PdfPage, A4_HEIGHT, A4_WIDTH};
+use crate::plot::{draw_label, encode_png};
+use crate::pool::RenderPool;
+use crate::view::{generate_html, LogView, PageLayout};
+use anyhow::{anyhow, bail, Result};
+use base64::Engine;
+use futures::TryStreamExt;
+use image::{imageops, RgbaImage};
+
+/// Максимальная высота одного PNG; для длинных записей лучше pdf или html
+const MAX_PNG_HEIGHT: usize = 65_535;
+
+/// Поля страницы PDF в пунктах
+const PDF_MARGIN: f32 = 20.0;
+
+/// Формат документа для [`render`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RenderFormat {
+    Png,
+    Svg,
+    Pdf,
+    Html,
+}
+
+impl RenderFormat {
+    pub fn parse(s: &str) -> Result<Self> {
+        match s.to_lowercase().as_str() {
+            "png" => Ok(RenderFormat::Png),
+            "svg" => Ok(RenderFormat::Svg),
+            "pdf" => Ok(RenderFormat::Pdf),
+            "html" | "htm" => Ok(RenderFormat::Html),
+            other => bail!("Unknown format '{}' (expected png, svg, pdf or html)", other),
+        }
+    }
+
+    pub fn extension(&self) -> &'static str {
+        match self {
+            RenderFormat::Png => "png",
+            RenderFormat::Svg => "svg",
+            RenderFormat::Pdf => "pdf",
+            RenderFormat::Html => "html",
+        }
+    }
+}
+
+/// Документ в формате `format`: картинка со шкалами, затем строки с графиками.
+/// `title` - заголовок (имя файла) для HTML и PDF.
+pub fn render(view: LogView, layout: &PageLayout, format: RenderFormat, title: &str) -> Result<Vec<u8>> {
+    match format {
+        RenderFormat::Png => render_png(&view, layout),
+        RenderFormat::Svg => Ok(render_svg(&view, layout)?.into_bytes()),
+        RenderFormat::Pdf => render_pdf(&view, layout, title),
+        RenderFormat::Html => {
+            // Файлы и так обрабатываются параллельно (lasplot render --jobs), строкам хватит одного потока
+            let stream = generate_html(view, layout.clone(), title, &RenderPool::new(1))?;
+            let chunks: Vec<_> = futures::executor::block_on(stream.try_collect())?;
+            Ok(chunks.concat())
+        }
+    }
+}
+
+/// Подпись глубины в начале строки - как на HTML странице
+fn depth_label(view: &LogView, layout: &PageLayout, row_idx: usize) -> String {
+    format!("{:.2}", view.row_bounds(layout, row_idx).2)
+}
+
+/// Одна картинка: шкалы, под ними строки с подписями глубины
+fn render_png(view: &LogView, layout: &PageLayout) -> Result<Vec<u8>> {
+    let scale_height = view.scale_height(layout);
+    let block_height = layout.block_height();
+    let rows = view.row_count(layout);
+    let total_height = scale_height + rows * block_height;
+    if total_height > MAX_PNG_HEIGHT {
+        bail!(
+            "Plot is too tall for PNG ({} px, limit {}); render to PDF or HTML instead",
+            total_height,
+            MAX_PNG_HEIGHT
+        );
+    }
+
+    let mut img = RgbaImage::new(layout.image_width as u32, total_height as u32);
+    imageops::replace(&mut img, &view.render_scale(layout)?, 0, 0);
+    for row_idx in 0..rows {
+        let y = scale_height + row_idx * block_height;
+        imageops::replace(&mut img, &view.render_row(layout, row_idx)?, 0, y as i64);
+        draw_label(&mut img, 5, y as u32 + 5, &depth_label(view, layout, row_idx), 2, [0, 0, 0]);
+    }
+    encode_png(&img)
+}
+
+/// SVG с картинками строк (PNG в data URI) и текстовыми подписями глубины
+fn render_svg(view: &LogView, layout: &PageLayout) -> Result<String> {
+    let scale_height = view.scale_height(layout);
+    let block_height = layout.block_height();
+    let rows = view.row_count(layout);
+    let width = layout.image_width;
+    let total_height = scale_height + rows * block_height;
+
+    let image_tag = |img: &RgbaImage, y: usize, height: usize| -> Result<String> {
+        let png = base64::engine::general_purpose::STANDARD.encode(encode_png(img)?);
+        Ok(format!(
+            "<image x='0' y='{}' width='{}' height='{}' href='data:image/png;base64,{}'/>\n",
+            y, width, height, png
+        ))
+    };
+
+    let mut svg = format!(
+        "<?xml version='1.0' encoding='UTF-8'?>\n<svg xmlns='http://www.w3.org/2000/svg' width='{}' height='{}' viewBox='0 0 {} {}'>\n",
+        width, total_height, width, total_height
+    );
+    svg.push_str(&image_tag(&view.render_scale(layout)?, 0, scale_height)?);
+    for row_idx in 0..rows {
+        let y = scale_height + row_idx * block_height;
+        svg.push_str(&image_tag(&view.render_row(layout, row_idx)?, y, block_height)?);
+        svg.push_str(&format!(
+            "<text x='5' y='{}' font-family='monospace' font-size='12'>{}</text>\n",
+            y + 15,
+            depth_label(view, layout, row_idx)
+        ));
+    }
+    svg.push_str("</svg>\n");
+    Ok(svg)
+}
+
+/// PDF формата A4: заголовок и шкалы на первой странице, строки переносятся по страницам
+fn render_pdf(view: &LogView, layout: &PageLayout, title: &str) -> Result<Vec<u8>> {
+    let scale = (A4_WIDTH - 2.0 * PDF_MARGIN) / layout.image_width as f32;
+    let width = layout.image_width as f32 * scale;
+    let block_height = layout.block_height() as f32 * scale;
+    if block_height > A4_HEIGHT - 2.0 * PDF_MARGIN {
+        return Err(anyhow!("Row is taller than a PDF page; reduce html_row_steps"));
+    }
+
+    let mut document = PdfDocument::new();
+    let mut page = PdfPage::new(A4_WIDTH, A4_HEIGHT);
+    page.text(PDF_MARGIN, PDF_MARGIN + 12.0, 14.0, &format!("LAS Plot - {}", title));
+
+    let scale_height = view.scale_height(layout) as f32 * scale;
+    let mut y = PDF_MARGIN + 24.0;
+    page.image(&view.render_scale(layout)?, PDF_MARGIN, y, width, scale_height)?;
+    y += scale_height;
+
+    for row_idx in 0..view.row_count(layout) {
+        if y + block_height > A4_HEIGHT - PDF_MARGIN {
+            document.add_page(page);
+            page = PdfPage::new(A4_WIDTH, A4_HEIGHT);
+            y = PDF_MARGIN;
+        }
+        page.image(&view.render_row(layout, row_idx)?, PDF_MARGIN, y, width, block_height)?;
+        page.text(PDF_MARGIN + 3.0, y + 9.0, 7.0, &depth_label(view, layout, row_idx));
+        y += block_height;
+    }
+    document.add_page(page);
+
+    Ok(document.finish())
+}
@@ -0,0 +1,356 @@
+//! Перевод кривых на другую сетку индекса: регулярный шаг или индекс другого файла.
+//!
+//! Методы: ближайший отсчёт, линейная интерполяция, среднее и медиана по интервалу узла.
+//! Пропуски (null) не заполняются через интервалы длиннее допуска `max_gap`.
+
+use crate::las::{round_significant, DataRow, LasFile};
+use anyhow::{bail, Result};
+
+/// Допуск на пропуск по умолчанию - в типичных шагах исходного индекса
+const DEFAULT_GAP_STEPS: f64 = 1.5;
+
+/// Предел числа узлов регулярной сетки: шаг из запроса (`step=`) не должен заставить сервер
+/// выделить память под миллиарды строк
+pub const MAX_GRID_NODES: usize = 1_000_000;
+
+/// Число узлов сетки с шагом `step` на интервале `start`..`stop`; ошибка, если их больше [`MAX_GRID_NODES`]
+pub fn grid_size(start: f64, stop: f64, step: f64) -> Result<usize> {
+    if !(step > 0.0 && step.is_finite()) {
+        bail!("Step must be a positive number, got {}", step);
+    }
+    let intervals = ((stop - start).abs() / step + 1e-9).floor();
+    if intervals.is_nan() || intervals >= MAX_GRID_NODES as f64 {
+        bail!(
+            "Step {} gives more than {} nodes over {}..{}; use a larger step",
+            step, MAX_GRID_NODES, start.min(stop), start.max(stop)
+        );
+    }
+    Ok(intervals as usize + 1)
+}
+
+/// Способ получения значения в узле новой сетки
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ResampleMethod {
+    /// Ближайший отсчёт
+    #[default]
+    Nearest,
+    /// Линейная интерполяция между соседними отсчётами
+    Linear,
+    /// Среднее отсчётов интервала узла (половина расстояния до соседних узлов)
+    Average,
+    /// Медиана отсчётов интервала узла
+    Median,
+}
+
+impl ResampleMethod {
+    pub fn parse(s: &str) -> Result<Self> {
+        match s.to_lowercase().as_str() {
+            "nearest" => Ok(ResampleMethod::Nearest),
+            "linear" => Ok(ResampleMethod::Linear),
+            "average" | "mean" | "avg" => Ok(ResampleMethod::Average),
+            "median" => Ok(ResampleMethod::Median),
+            other => bail!("Unknown resample method '{}' (expected nearest, linear, average or median)", other),
+        }
+    }
+}
+
+/// Типичный шаг: медиана положительных разностей соседних значений отсортированного индекса.
+/// Не зависит от STEP в заголовке, поэтому подходит и для неравномерных записей (STEP = 0).
+pub fn median_step(sorted: &[f64]) -> Option<f64> {
+    let mut diffs: Vec<f64> = sorted.windows(2).map(|w| w[1] - w[0]).filter(|d| *d > 0.0).collect();
+    if diffs.is_empty() {
+        return None;
+    }
+    let mid = diffs.len() / 2;
+    let (_, median, _) = diffs.select_nth_unstable_by(mid, f64::total_cmp);
+    Some(*median)
+}
+
+/// Значение кривой в узле `node`; `samples` - непустые отсчёты кривой (индекс, значение) по возрастанию,
+/// `block` - интервал узла для Average/Median
+fn node_value(samples: &[(f64, f64)], node: f64, block: (f64, f64), method: ResampleMethod, max_gap: f64) -> Option<f64> {
+    let pos = samples.partition_point(|s| s.0 < node);
+    let before = pos.checked_sub(1).map(|p| samples[p]);
+    let after = samples.get(pos).copied();
+
+    match method {
+        ResampleMethod::Nearest => {
+            let nearest = match (before, after) {
+                (Some(b), Some(a)) => if node - b.0 <= a.0 - node { b } else { a },
+                (Some(b), None) => b,
+                (None, Some(a)) => a,
+                (None, None) => return None,
+            };
+            ((nearest.0 - node).abs() <= max_gap).then_some(nearest.1)
+        }
+        ResampleMethod::Linear => match (before, after) {
+            (_, Some(a)) if a.0 == node => Some(a.1),
+            (Some(b), Some(a)) if a.0 - b.0 <= max_gap => {
+                Some(b.1 + (a.1 - b.1) * (node - b.0) / (a.0 - b.0))
+            }
+            // Без экстраполяции за края и через длинные пропуски
+            _ => None,
+        },
+        ResampleMethod::Average | ResampleMethod::Median => {
+            let start = samples.partition_point(|s| s.0 < block.0);
+            let end = samples.partition_point(|s| s.0 < block.1);
+            let mut values: Vec<f64> = samples[start..end].iter().map(|s| s.1).collect();
+            if values.is_empty() {
+                return None;
+            }
+            if method == ResampleMethod::Average {
+                return Some(values.iter().sum::<f64>() / values.len() as f64);
+            }
+            values.sort_by(f64::total_cmp);
+            let mid = values.len() / 2;
+            Some(if values.len().is_multiple_of(2) { (values[mid - 1] + values[mid]) / 2.0 } else { values[mid] })
+        }
+    }
+}
+
+/// Значения кривой, отсчёты которой `samples` (индекс, значение) оказались не в узлах `grid`
+/// (после сдвига или растяжения), в узлах сетки линейной интерполяцией; допуск на пропуск -
+/// как по умолчанию у [`LasFile::resample`], по типичному шагу отсчётов
+pub(crate) fn interpolate_samples(mut samples: Vec<(f64, f64)>, grid: &[Option<f64>]) -> Vec<Option<f64>> {
+    samples.sort_by(|a, b| a.0.total_cmp(&b.0));
+    let sorted: Vec<f64> = samples.iter().map(|s| s.0).collect();
+    let max_gap = median_step(&sorted).map_or(0.0, |step| step * DEFAULT_GAP_STEPS);
+    grid.iter()
+        .map(|node| node_value(&samples, (*node)?, (0.0, 0.0), ResampleMethod::Linear, max_gap))
+        .collect()
+}
+
+impl LasFile {
+    /// Переводит все кривые на сетку `grid` по кривой-индексу `index_idx` (порядок узлов сохраняется).
+    /// `max_gap` - наибольшее расстояние по индексу, через которое переносится значение
+    /// (до ближайшего отсчёта для Nearest, между соседними отсчётами для Linear);
+    /// по умолчанию полтора типичных шага исходного индекса.
+    pub fn resample(&self, index_idx: usize, grid: &[f64], method: ResampleMethod, max_gap: Option<f64>) -> LasFile {
+        let index_data = self.get_curve_data(index_idx);
+        let mut order: Vec<usize> = (0..index_data.len()).filter(|&row| index_data[row].is_some()).collect();
+        order.sort_by(|&a, &b| index_data[a].unwrap_or(0.0).total_cmp(&index_data[b].unwrap_or(0.0)));
+        let sorted_index: Vec<f64> = order.iter().filter_map(|&row| index_data[row]).collect();
+        let max_gap = max_gap
+            .or_else(|| median_step(&sorted_index).map(|step| step * DEFAULT_GAP_STEPS))
+            .unwrap_or(0.0);
+
+        // Интервалы узлов для Average/Median: до середины между соседними узлами
+        let mut grid_order: Vec<usize> = (0..grid.len()).collect();
+        grid_order.sort_by(|&a, &b| grid[a].total_cmp(&grid[b]));
+        let mut blocks = vec![(0.0, 0.0); grid.len()];
+        for (pos, &node_idx) in grid_order.iter().enumerate() {
+            let node = grid[node_idx];
+            let prev = pos.checked_sub(1).map(|p| grid[grid_order[p]]);
+            let next = grid_order.get(pos + 1).map(|&n| grid[n]);
+            let half_before = prev.or(next.map(|n| 2.0 * node - n)).map_or(0.0, |p| (node - p) / 2.0);
+            let half_after = next.or(prev.map(|p| 2.0 * node - p)).map_or(0.0, |n| (n - node) / 2.0);
+            // Единственный узел без соседей забирает все отсчёты
+            blocks[node_idx] = if prev.is_none() && next.is_none() {
+                (f64::NEG_INFINITY, f64::INFINITY)
+            } else {
+                (node - half_before, node + half_after)
+            };
+        }
+
+        let mut resampled = self.clone();
+        resampled.data = grid
+            .iter()
+            .map(|&node| {
+                let mut values = vec![self.null_value; self.curves.len()];
+                if index_idx < values.len() {
+                    values[index_idx] = node;
+                }
+                DataRow { values }
+            })
+            .collect();
+
+        for curve_idx in (0..self.curves.len()).filter(|&idx| idx != index_idx) {
+            let curve_data = self.get_curve_data(curve_idx);
+            let samples: Vec<(f64, f64)> = order
+                .iter()
+                .filter_map(|&row| Some((index_data[row]?, curve_data[row]?)))
+                .collect();
+            for (row, &node) in grid.iter().enumerate() {
+                if let Some(value) = node_value(&samples, node, blocks[row], method, max_gap) {
+                    resampled.data[row].values[curve_idx] = value;
+                }
+            }
+        }
+
+        // STRT/STOP/STEP пересчитываются по первой кривой - только если индекс и есть первая
+        if index_idx == 0 {
+            resampled.update_index_header();
+        }
+        resampled
+    }
+
+    /// Регулярная сетка с шагом `step` от минимума до максимума индекса;
+    /// направление (возрастание/убывание) как в исходном файле.
+    /// Ошибка, если узлов получается больше [`MAX_GRID_NODES`].
+    pub fn resample_step(&self, index_idx: usize, step: f64, method: ResampleMethod, max_gap: Option<f64>) -> Result<LasFile> {
+        self.resample_step_within(index_idx, step, f64::NEG_INFINITY, f64::INFINITY, method, max_gap)
+    }
+
+    /// Как [`LasFile::resample_step`], но только узлы в интервале `low..=high`, обрезанном по данным.
+    /// Узлы совпадают с узлами полной сетки; предел [`MAX_GRID_NODES`] проверяется по обрезанному интервалу.
+    pub fn resample_step_within(
+        &self,
+        index_idx: usize,
+        step: f64,
+        low: f64,
+        high: f64,
+        method: ResampleMethod,
+        max_gap: Option<f64>,
+    ) -> Result<LasFile> {
+        let index: Vec<f64> = self.get_curve_data(index_idx).into_iter().flatten().collect();
+        let (Some(&first), Some(&last)) = (index.first(), index.last()) else {
+            return Ok(self.resample(index_idx, &[], method, max_gap));
+        };
+
+        let start = index.iter().copied().fold(f64::INFINITY, f64::min);
+        let stop = index.iter().copied().fold(f64::NEG_INFINITY, f64::max);
+        // Первый узел полной сетки не выше `low`, последний - не ниже `high`
+        let skipped = if low > start { ((low - start) / step - 1e-9).ceil() } else { 0.0 };
+        let (grid_start, grid_stop) = (start + skipped * step, stop.min(high));
+        if grid_start > grid_stop {
+            grid_size(start, start, step)?; // шаг проверяется и для пустой сетки
+            return Ok(self.resample(index_idx, &[], method, max_gap));
+        }
+        let count = grid_size(grid_start, grid_stop, step)?;
+        let mut grid: Vec<f64> = (0..count).map(|i| round_significant(grid_start + i as f64 * step, 12)).collect();
+        if first > last {
+            grid.reverse();
+        }
+        Ok(self.resample(index_idx, &grid, method, max_gap))
+    }
+
+    /// Шаг сетки для кривой `idx`, если её значения по порядку строк не монотонны; иначе `None`.
+    /// Берётся шаг индекса: TVD между отсчётами меняется не больше, чем MD, и узлы сетки не пустуют.
+    pub fn non_monotonic_step(&self, idx: usize) -> Option<f64> {
+        let values: Vec<f64> = self.get_curve_data(idx).into_iter().flatten().collect();
+        let increasing = values.windows(2).all(|w| w[1] >= w[0]);
+        let decreasing = values.windows(2).all(|w| w[1] <= w[0]);
+        if increasing || decreasing {
+            return None;
+        }
+        let mut index: Vec<f64> = self.get_curve_data(0).into_iter().flatten().collect();
+        index.sort_by(f64::total_cmp);
+        let range = values.iter().copied().fold(f64::NEG_INFINITY, f64::max)
+            - values.iter().copied().fold(f64::INFINITY, f64::min);
+        let fallback = range / values.len() as f64;
+        Some(median_step(&index).unwrap_or(fallback).max(fallback))
+    }
+
+    /// Сетка - индекс другого файла (`other_index_idx` в `other`), например для сравнения записей
+    pub fn resample_to(
+        &self,
+        index_idx: usize,
+        other: &LasFile,
+        other_index_idx: usize,
+        method: ResampleMethod,
+        max_gap: Option<f64>,
+    ) -> LasFile {
+        let grid: Vec<f64> = other.get_curve_data(other_index_idx).into_iter().flatten().collect();
+        self.resample(index_idx, &grid, method, max_gap)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// DEPT 100..104 с шагом 1, GR = 10 * (DEPT - 100), пропуск GR на 102
+    fn sample_file() -> LasFile {
+        LasFile::parse(
+            "~Version Information
+ VERS.  2.0:
+~Well Information
+ NULL.  -999.25:
+~Curve Information
+ DEPT.M  :  DEPTH
+ GR.GAPI  :  GAMMA
+~ASCII
+100  0
+101  10
+102  -999.25
+103  30
+104  40
+",
+        )
+        .unwrap()
+    }
+
+    fn column(las_file: &LasFile, idx: usize) -> Vec<Option<f64>> {
+        las_file.get_curve_data(idx)
+    }
+
+    #[test]
+    fn linear_resampling_interpolates_between_samples() {
+        let resampled = sample_file().resample_step(0, 0.5, ResampleMethod::Linear, None).unwrap();
+        assert_eq!(column(&resampled, 0), (0..9).map(|i| Some(100.0 + i as f64 * 0.5)).collect::<Vec<_>>());
+        // 101..103 - пропуск в два шага длиннее допуска (1.5 шага), узлы внутри пустые
+        assert_eq!(
+            column(&resampled, 1),
+            vec![Some(0.0), Some(5.0), Some(10.0), None, None, None, Some(30.0), Some(35.0), Some(40.0)]
+        );
+        // С допуском в два шага пропуск перекрывается
+        let bridged = sample_file().resample_step(0, 0.5, ResampleMethod::Linear, Some(2.0)).unwrap();
+        assert_eq!(column(&bridged, 1)[4], Some(20.0));
+    }
+
+    #[test]
+    fn nearest_resampling_takes_closest_sample() {
+        let resampled = sample_file().resample(0, &[99.0, 100.4, 100.6, 102.0, 103.9, 106.0], ResampleMethod::Nearest, None);
+        assert_eq!(column(&resampled, 1), vec![Some(0.0), Some(0.0), Some(10.0), Some(10.0), Some(40.0), None]);
+    }
+
+    #[test]
+    fn step_resampling_averages_node_intervals() {
+        let average = sample_file().resample_step(0, 2.0, ResampleMethod::Average, None).unwrap();
+        assert_eq!(column(&average, 0), vec![Some(100.0), Some(102.0), Some(104.0)]);
+        // Интервалы узлов: [99, 101), [101, 103), [103, 105); пропуск в среднее не входит
+        assert_eq!(column(&average, 1), vec![Some(0.0), Some(10.0), Some(35.0)]);
+        let median = sample_file().resample_step(0, 4.0, ResampleMethod::Median, None).unwrap();
+        assert_eq!(column(&median, 1), vec![Some(5.0), Some(35.0)]);
+    }
+
+    #[test]
+    fn resampling_keeps_index_direction() {
+        let mut reversed = sample_file();
+        reversed.data.reverse();
+        let resampled = reversed.resample_step(0, 1.0, ResampleMethod::Nearest, None).unwrap();
+        assert_eq!(column(&resampled, 0), vec![Some(104.0), Some(103.0), Some(102.0), Some(101.0), Some(100.0)]);
+        // На месте пропуска - ближайший отсчёт (при равном расстоянии - меньший по индексу)
+        assert_eq!(column(&resampled, 1), vec![Some(40.0), Some(30.0), Some(10.0), Some(10.0), Some(0.0)]);
+    }
+
+    #[test]
+    fn grid_size_is_limited() {
+        assert_eq!(grid_size(100.0, 104.0, 0.5).unwrap(), 9);
+        assert_eq!(grid_size(104.0, 100.0, 3.0).unwrap(), 2);
+        assert!(grid_size(100.0, 104.0, 0.0).is_err());
+        assert!(grid_size(1000.0, 1100.0, 1e-12).is_err());
+        assert!(sample_file().resample_step(0, 1e-12, ResampleMethod::Linear, None).is_err());
+    }
+
+    #[test]
+    fn step_grid_is_clamped_to_interval_and_data() {
+        let within = |low, high, step| sample_file().resample_step_within(0, step, low, high, ResampleMethod::Linear, None);
+        // Узлы полной сетки 100, 100.5, ... внутри 101.2..=102.6
+        let resampled = within(101.2, 102.6, 0.5).unwrap();
+        assert_eq!(column(&resampled, 0), vec![Some(101.5), Some(102.0), Some(102.5)]);
+        // Огромный интервал запроса при обычном шаге - узлы только по данным
+        let clamped = within(-1e9, 1e9, 0.5).unwrap();
+        assert_eq!(column(&clamped, 0).len(), 9);
+        // Предел узлов - по обрезанному интервалу
+        assert!(within(101.0, 101.000_1, 1e-9).is_ok());
+        assert!(within(100.0, 104.0, 1e-9).is_err());
+        assert!(column(&within(200.0, 300.0, 0.5).unwrap(), 0).is_empty());
+    }
+
+    #[test]
+    fn median_step_ignores_repeats() {
+        assert_eq!(median_step(&[1.0, 1.0, 1.5, 2.0, 2.5, 10.0]), Some(0.5));
+        assert_eq!(median_step(&[3.0]), None);
+    }
+}
@@ -0,0 +1,166 @@
+//! Состояние сервера с горячей перезагрузкой.
+//!
+//! Конфигурация и список файлов лежат в `ArcSwap`: обработчики берут текущую версию
+//! без блокировок, а фоновая задача раз в `RELOAD_INTERVAL` сверяет время изменения
+//! lasplot.toml, laslist_file и папки samples_dir и подменяет данные целиком.
+
+use crate::laslist::{laslist_path, read_laslist_file_with_info, LasFileInfo};
+use actix_web::web;
+use arc_swap::ArcSwap;
+use lasplot::config::{Config, DEFAULT_CONFIG_FILE};
+use lasplot::pool::RenderPool;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, SystemTime};
+
+/// Период проверки файлов на изменения
+const RELOAD_INTERVAL: Duration = Duration::from_secs(2);
+
+/// Поля, которые применяются только при запуске сервера
+const RESTART_ONLY_FIELDS: &[&str] = &["bind_address", "bind_port", "render_concurrency"];
+
+/// Время изменения отслеживаемых файлов при последней загрузке
+#[derive(Debug, Default, PartialEq)]
+struct Fingerprint {
+    config: Option<SystemTime>,
+    laslist: Option<SystemTime>,
+    samples: Option<SystemTime>,
+}
+
+pub struct AppState {
+    config: ArcSwap<Config>,
+    laslist: ArcSwap<Vec<LasFileInfo>>,
+    /// Путь из `--config` (None - `LASPLOT_CONFIG` или ./lasplot.toml)
+    config_arg: Option<PathBuf>,
+    fingerprint: Mutex<Fingerprint>,
+    /// Пул отрисовки, общий для всех запросов (размер - render_concurrency)
+    render_pool: RenderPool,
+}
+
+fn modified(path: &Path) -> Option<SystemTime> {
+    std::fs::metadata(path).and_then(|m| m.modified()).ok()
+}
+
+fn load_laslist(config: &Config) -> Vec<LasFileInfo> {
+    read_laslist_file_with_info(config).unwrap_or_else(|e| {
+        eprintln!("Failed to read file list: {:#}", e);
+        Vec::new()
+    })
+}
+
+/// Изменённые поля конфигурации в виде `поле: старое -> новое`
+fn config_changes(old: &Config, new: &Config) -> Vec<(String, String)> {
+    let (Ok(old), Ok(new)) = (toml::Table::try_from(old), toml::Table::try_from(new)) else {
+        return Vec::new();
+    };
+    new.iter()
+        .filter(|(key, value)| old.get(*key) != Some(*value))
+        .map(|(key, value)| {
+            let old_value = old.get(key).map_or_else(|| "-".to_string(), |v| v.to_string());
+            (key.clone(), format!("{} -> {}", old_value, value))
+        })
+        .collect()
+}
+
+impl AppState {
+    pub fn new(config: Config, config_arg: Option<PathBuf>) -> Self {
+        let laslist = load_laslist(&config);
+        let render_pool = RenderPool::new(config.render_concurrency);
+        println!("Render pool: {} threads", render_pool.threads());
+        let state = AppState {
+            config: ArcSwap::from_pointee(config),
+            laslist: ArcSwap::from_pointee(laslist),
+            config_arg,
+            fingerprint: Mutex::new(Fingerprint::default()),
+            render_pool,
+        };
+        *state.fingerprint.lock().unwrap() = state.current_fingerprint();
+        state
+    }
+
+    /// Текущая конфигурация
+    pub fn config(&self) -> Arc<Config> {
+        self.config.load_full()
+    }
+
+    /// Текущий список файлов для тестовой страницы
+    pub fn laslist(&self) -> Arc<Vec<LasFileInfo>> {
+        self.laslist.load_full()
+    }
+
+    pub fn render_pool(&self) -> &RenderPool {
+        &self.render_pool
+    }
+
+    fn watched_config_path(&self) -> PathBuf {
+        Config::resolve_path(self.config_arg.as_deref()).unwrap_or_else(|| PathBuf::from(DEFAULT_CONFIG_FILE))
+    }
+
+    fn current_fingerprint(&self) -> Fingerprint {
+        let config = self.config();
+        Fingerprint {
+            config: modified(&self.watched_config_path()),
+            laslist: modified(&laslist_path(&config)),
+            samples: modified(&config.get_samples_path()),
+        }
+    }
+
+    /// Проверяет файлы и перезагружает изменившееся.
+    /// Невалидная новая конфигурация (в том числе не прошедшая проверку папок, как при запуске)
+    /// не применяется - остаётся предыдущая.
+    pub fn reload_if_changed(&self) {
+        let current = self.current_fingerprint();
+        let mut fingerprint = self.fingerprint.lock().unwrap();
+        if *fingerprint == current {
+            return;
+        }
+
+        let mut reload_laslist = current.laslist != fingerprint.laslist || current.samples != fingerprint.samples;
+        if current.config != fingerprint.config {
+            let loaded = Config::load(self.config_arg.as_deref())
+                .and_then(|config| config.check_directories().map(|_| config));
+            match loaded {
+                Ok(new_config) => {
+                    let changes = config_changes(&self.config(), &new_config);
+                    if changes.is_empty() {
+                        println!("Config reloaded: no changes");
+                    }
+                    for (field, change) in &changes {
+                        if RESTART_ONLY_FIELDS.contains(&field.as_str()) {
+                            println!("Config reloaded: {}: {} (applies after restart)", field, change);
+                        } else {
+                            println!("Config reloaded: {}: {}", field, change);
+                        }
+                    }
+                    // Список файлов зависит от laslist_file и samples_dir
+                    reload_laslist |= changes
+                        .iter()
+                        .any(|(field, _)| field == "laslist_file" || field == "samples_dir");
+                    self.config.store(Arc::new(new_config));
+                }
+                Err(e) => eprintln!("Config reload failed, keeping previous config: {:#}", e),
+            }
+        }
+
+        if reload_laslist {
+            let laslist = load_laslist(&self.config());
+            println!("File list reloaded: {} files", laslist.len());
+            self.laslist.store(Arc::new(laslist));
+        }
+
+        // Отпечаток берём заново: после смены конфигурации могли измениться пути
+        *fingerprint = self.current_fingerprint();
+    }
+}
+
+/// Фоновая задача, периодически вызывающая [`AppState::reload_if_changed`]
+pub fn spawn_watcher(state: web::Data<AppState>) {
+    actix_web::rt::spawn(async move {
+        let mut interval = actix_web::rt::time::interval(RELOAD_INTERVAL);
+        loop {
+            interval.tick().await;
+            let state = state.clone();
+            let _ = web::block(move || state.reload_if_changed()).await;
+        }
+    });
+}
@@ -0,0 +1,531 @@
+//! Статистика кривых по зонам и эффективные толщины (net/gross, net pay).
+//!
+//! Зоны - интервалы между кровлями пластов ([`crate::tops`]) или заданные в запросе
+//! (`zones=Верх:1000-1050;Низ:1050-1100`). По каждой кривой в зоне считаются среднее, медиана,
+//! P10/P50/P90, стандартное отклонение и доля пропусков; по граничным значениям
+//! ([`Cutoffs`]) - толщины коллектора и продуктивной части.
+
+use crate::api::{csv_field, number_param};
+use crate::las::{round_significant, LasFile};
+use crate::resample::median_step;
+use anyhow::{anyhow, bail, Result};
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use std::collections::HashMap;
+
+/// Значение по процентилю `p` (0..1) отсортированных значений, с линейной интерполяцией
+pub fn percentile(sorted: &[f64], p: f64) -> Option<f64> {
+    let last = sorted.len().checked_sub(1)?;
+    let pos = last as f64 * p.clamp(0.0, 1.0);
+    let (lo, hi) = (pos.floor() as usize, pos.ceil() as usize);
+    Some(sorted[lo] + (sorted[hi] - sorted[lo]) * (pos - lo as f64))
+}
+
+/// Интервал основного параметра `[top, bottom)`; зона, доходящая до конца данных,
+/// включает и последний отсчёт (`[top, bottom]`)
+#[derive(Debug, Clone, PartialEq)]
+pub struct Zone {
+    pub name: String,
+    pub top: f64,
+    pub bottom: f64,
+}
+
+impl Zone {
+    pub fn thickness(&self) -> f64 {
+        round_significant(self.bottom - self.top, 12)
+    }
+
+    /// Разбирает `name:top-bottom` (имя необязательно; для отрицательных значений - `top..bottom`)
+    fn parse(s: &str) -> Result<Self> {
+        let (name, range) = match s.rsplit_once(':') {
+            Some((name, range)) => (name.trim(), range.trim()),
+            None => ("", s.trim()),
+        };
+        let bounds = |a: &str, b: &str| Some((a.trim().parse::<f64>().ok()?, b.trim().parse::<f64>().ok()?));
+        let parsed = match range.split_once("..") {
+            Some((a, b)) => bounds(a, b),
+            None => range.match_indices('-').filter(|(i, _)| *i > 0).find_map(|(i, _)| bounds(&range[..i], &range[i + 1..])),
+        };
+        let (a, b) = parsed
+            .filter(|(a, b)| a.is_finite() && b.is_finite() && a != b)
+            .ok_or_else(|| anyhow!("Invalid zone '{}' (expected name:top-bottom)", s.trim()))?;
+        Ok(Zone {
+            name: if name.is_empty() { range.to_string() } else { name.to_string() },
+            top: a.min(b),
+            bottom: a.max(b),
+        })
+    }
+
+    /// Зоны из параметра `zones=` через `;`
+    pub fn parse_list(s: &str) -> Result<Vec<Self>> {
+        s.split(';').filter(|z| !z.trim().is_empty()).map(Zone::parse).collect()
+    }
+
+    /// Зоны между кровлями файла: от кровли до следующей кровли или до конца данных.
+    /// Кровли берутся в единицах кривой `index_idx`; зоны обрезаются по диапазону данных.
+    pub fn from_tops(las_file: &LasFile, index_idx: usize) -> Vec<Self> {
+        let Some(index) = las_file.curves.get(index_idx) else {
+            return Vec::new();
+        };
+        let Some((depth_min, depth_max)) = las_file.get_curve_stats(index_idx) else {
+            return Vec::new();
+        };
+        let mut tops: Vec<(String, f64)> =
+            las_file.tops.iter().map(|top| (top.name.clone(), top.depth_in(&index.unit))).collect();
+        tops.sort_by(|a, b| a.1.total_cmp(&b.1));
+
+        tops.iter()
+            .enumerate()
+            .map(|(i, (name, depth))| Zone {
+                name: name.clone(),
+                top: depth.max(depth_min),
+                bottom: tops.get(i + 1).map_or(depth_max, |next| next.1).min(depth_max),
+            })
+            .filter(|zone| zone.bottom > zone.top)
+            .collect()
+    }
+}
+
+/// Граничные значения для эффективных толщин: секция `[cutoffs]` в lasplot.toml,
+/// поверх неё - параметры запроса с теми же именами
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Cutoffs {
+    /// Коллектор: глинистость меньше `vsh_max` и пористость больше `phi_min`
+    pub vsh_max: f64,
+    pub phi_min: f64,
+    /// Продуктивная часть коллектора: водонасыщенность меньше `sw_max`
+    pub sw_max: f64,
+    /// Мнемоники кривых; по умолчанию - первая найденная из привычных (VSH, PHIE/PHIND/PHID, SW)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub vsh_curve: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub phi_curve: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub sw_curve: Option<String>,
+}
+
+impl Default for Cutoffs {
+    fn default() -> Self {
+        Cutoffs { vsh_max: 0.4, phi_min: 0.08, sw_max: 0.6, vsh_curve: None, phi_curve: None, sw_curve: None }
+    }
+}
+
+const VSH_CURVES: &[&str] = &["VSH", "VCL", "VSHALE", "VSH_GR"];
+const PHI_CURVES: &[&str] = &["PHIE", "PHIND", "PHID", "PHIT", "PHI"];
+const SW_CURVES: &[&str] = &["SW", "SWE", "SWT", "SWA"];
+
+impl Cutoffs {
+    /// Параметры запроса поверх этих значений
+    pub fn with_params(&self, params: &HashMap<String, String>) -> Result<Self> {
+        let number = |name: &str| number_param(params, name);
+        let curve = |name: &str| params.get(name).map(|s| s.trim().to_string()).filter(|s| !s.is_empty());
+
+        let mut result = self.clone();
+        result.vsh_max = number("vsh_max")?.unwrap_or(result.vsh_max);
+        result.phi_min = number("phi_min")?.unwrap_or(result.phi_min);
+        result.sw_max = number("sw_max")?.unwrap_or(result.sw_max);
+        result.vsh_curve = curve("vsh_curve").or(result.vsh_curve);
+        result.phi_curve = curve("phi_curve").or(result.phi_curve);
+        result.sw_curve = curve("sw_curve").or(result.sw_curve);
+
+        let errors = result.errors("");
+        if !errors.is_empty() {
+            bail!("{}", errors.join("; "));
+        }
+        Ok(result)
+    }
+
+    /// Ошибки значений; `prefix` - путь к полям (для конфигурации)
+    pub fn errors(&self, prefix: &str) -> Vec<String> {
+        [("vsh_max", self.vsh_max), ("phi_min", self.phi_min), ("sw_max", self.sw_max)]
+            .into_iter()
+            .filter(|(_, value)| !(0.0..=1.0).contains(value))
+            .map(|(name, value)| format!("{}{} = {} is out of range 0..=1 (fraction)", prefix, name, value))
+            .collect()
+    }
+
+    /// Кривая граничного условия: заданная явно (её отсутствие - ошибка) или первая из привычных
+    fn resolve(las_file: &LasFile, explicit: Option<&str>, defaults: &[&str]) -> Result<Option<usize>> {
+        match explicit {
+            Some(name) => las_file
+                .get_curve_index(name)
+                .map(Some)
+                .ok_or_else(|| anyhow!("Cutoff curve '{}' not found", name)),
+            None => Ok(defaults.iter().find_map(|name| las_file.get_curve_index(name))),
+        }
+    }
+}
+
+/// Статистика кривой в зоне (None - нет значений)
+#[derive(Debug, Clone)]
+pub struct CurveStats {
+    pub mnemonic: String,
+    pub unit: String,
+    pub count: usize,
+    pub nulls: usize,
+    pub mean: Option<f64>,
+    pub median: Option<f64>,
+    pub p10: Option<f64>,
+    pub p50: Option<f64>,
+    pub p90: Option<f64>,
+    /// Стандартное отклонение по всем значениям зоны
+    pub std_dev: Option<f64>,
+    pub min: Option<f64>,
+    pub max: Option<f64>,
+}
+
+impl CurveStats {
+    fn compute(mnemonic: &str, unit: &str, values: &[Option<f64>]) -> Self {
+        let mut sorted: Vec<f64> = values.iter().flatten().copied().collect();
+        sorted.sort_by(f64::total_cmp);
+        let count = sorted.len();
+        let mean = (count > 0).then(|| sorted.iter().sum::<f64>() / count as f64);
+        let std_dev = mean.map(|mean| (sorted.iter().map(|v| (v - mean).powi(2)).sum::<f64>() / count as f64).sqrt());
+        CurveStats {
+            mnemonic: mnemonic.to_string(),
+            unit: unit.to_string(),
+            count,
+            nulls: values.len() - count,
+            mean,
+            median: percentile(&sorted, 0.5),
+            p10: percentile(&sorted, 0.1),
+            p50: percentile(&sorted, 0.5),
+            p90: percentile(&sorted, 0.9),
+            std_dev,
+            min: sorted.first().copied(),
+            max: sorted.last().copied(),
+        }
+    }
+
+    /// Доля пропусков среди отсчётов зоны
+    pub fn null_fraction(&self) -> Option<f64> {
+        let total = self.count + self.nulls;
+        (total > 0).then(|| self.nulls as f64 / total as f64)
+    }
+}
+
+/// Эффективные толщины зоны (в единицах основного параметра): отсчёт, прошедший
+/// граничные условия, добавляет к толщине шаг основного параметра
+#[derive(Debug, Clone)]
+pub struct NetPay {
+    pub gross: f64,
+    pub net_reservoir: f64,
+    /// None - нет кривой водонасыщенности
+    pub net_pay: Option<f64>,
+    /// Средние глинистость, пористость и водонасыщенность в продуктивной части (в коллекторе без Sw)
+    pub avg_vsh: Option<f64>,
+    pub avg_phi: Option<f64>,
+    pub avg_sw: Option<f64>,
+}
+
+impl NetPay {
+    pub fn net_to_gross(&self) -> Option<f64> {
+        (self.gross > 0.0).then(|| self.net_reservoir / self.gross)
+    }
+
+    pub fn pay_to_gross(&self) -> Option<f64> {
+        let net_pay = self.net_pay?;
+        (self.gross > 0.0).then(|| net_pay / self.gross)
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct ZoneStats {
+    pub zone: Zone,
+    pub samples: usize,
+    pub curves: Vec<CurveStats>,
+    /// None - в файле нет кривых глинистости и пористости
+    pub net: Option<NetPay>,
+}
+
+/// Кривая граничного условия отчёта: мнемоника и граница
+#[derive(Debug, Clone)]
+pub struct CutoffCurve {
+    pub mnemonic: String,
+    pub limit: f64,
+}
+
+/// Отчёт по зонам для одного файла
+#[derive(Debug, Clone)]
+pub struct ZoneReport {
+    pub index: String,
+    pub index_unit: String,
+    /// Толщина одного отсчёта (типичный шаг основного параметра)
+    pub step: f64,
+    pub vsh: Option<CutoffCurve>,
+    pub phi: Option<CutoffCurve>,
+    pub sw: Option<CutoffCurve>,
+    pub zones: Vec<ZoneStats>,
+}
+
+impl ZoneReport {
+    /// Статистика всех кривых, кроме `index_idx`, по зонам `zones` (интервалы кривой `index_idx`)
+    pub fn compute(las_file: &LasFile, index_idx: usize, zones: &[Zone], cutoffs: &Cutoffs) -> Result<Self> {
+        let index_curve = las_file.curves.get(index_idx).ok_or_else(|| anyhow!("LAS file has no curves"))?;
+        let index = las_file.get_curve_data(index_idx);
+        let mut sorted_index: Vec<f64> = index.iter().flatten().copied().collect();
+        sorted_index.sort_by(f64::total_cmp);
+        let step = median_step(&sorted_index).map_or(0.0, |step| round_significant(step, 10));
+
+        let vsh_idx = Cutoffs::resolve(las_file, cutoffs.vsh_curve.as_deref(), VSH_CURVES)?;
+        let phi_idx = Cutoffs::resolve(las_file, cutoffs.phi_curve.as_deref(), PHI_CURVES)?;
+        let sw_idx = Cutoffs::resolve(las_file, cutoffs.sw_curve.as_deref(), SW_CURVES)?;
+        let cutoff_curve = |idx: Option<usize>, limit: f64| {
+            idx.map(|idx| CutoffCurve { mnemonic: las_file.curves[idx].mnemonic.clone(), limit })
+        };
+
+        let columns: Vec<Vec<Option<f64>>> = (0..las_file.curves.len()).map(|idx| las_file.get_curve_data(idx)).collect();
+        let column = |idx: Option<usize>| idx.map(|idx| &columns[idx]);
+        let (vsh, phi, sw) = (column(vsh_idx), column(phi_idx), column(sw_idx));
+
+        let zones = zones
+            .iter()
+            .map(|zone| {
+                let to_end = sorted_index.last().is_some_and(|&last| zone.bottom >= last);
+                let rows: Vec<usize> = (0..index.len())
+                    .filter(|&row| index[row].is_some_and(|d| d >= zone.top && (d < zone.bottom || to_end && d == zone.bottom)))
+                    .collect();
+                let curves = (0..las_file.curves.len())
+                    .filter(|&idx| idx != index_idx)
+                    .map(|idx| {
+                        let values: Vec<Option<f64>> = rows.iter().map(|&row| columns[idx][row]).collect();
+                        let curve = &las_file.curves[idx];
+                        CurveStats::compute(&curve.mnemonic, &curve.unit, &values)
+                    })
+                    .collect();
+
+                // Условие по кривой выполнено; кривой нет - условие не проверяется, пропуск - не выполнено
+                let passes = |data: Option<&Vec<Option<f64>>>, row: usize, test: &dyn Fn(f64) -> bool| {
+                    data.is_none_or(|data| data[row].is_some_and(test))
+                };
+                let net = (vsh.is_some() || phi.is_some()).then(|| {
+                    let reservoir: Vec<usize> = rows
+                        .iter()
+                        .copied()
+                        .filter(|&row| {
+                            passes(vsh, row, &|v| v < cutoffs.vsh_max) && passes(phi, row, &|v| v > cutoffs.phi_min)
+                        })
+                        .collect();
+                    let pay: Vec<usize> =
+                        reservoir.iter().copied().filter(|&row| passes(sw, row, &|v| v < cutoffs.sw_max)).collect();
+                    let gross = zone.thickness();
+                    let thickness = |samples: usize| round_significant(samples as f64 * step, 12).min(gross);
+                    let average = |data: Option<&Vec<Option<f64>>>| {
+                        let values: Vec<f64> = data.map_or(Vec::new(), |data| pay.iter().filter_map(|&row| data[row]).collect());
+                        (!values.is_empty()).then(|| values.iter().sum::<f64>() / values.len() as f64)
+                    };
+                    NetPay {
+                        gross,
+                        net_reservoir: thickness(reservoir.len()),
+                        net_pay: sw.map(|_| thickness(pay.len())),
+                        avg_vsh: average(vsh),
+                        avg_phi: average(phi),
+                        avg_sw: average(sw),
+                    }
+                });
+
+                ZoneStats { zone: zone.clone(), samples: rows.len(), curves, net }
+            })
+            .collect();
+
+        Ok(ZoneReport {
+            index: index_curve.mnemonic.clone(),
+            index_unit: index_curve.unit.clone(),
+            step,
+            vsh: cutoff_curve(vsh_idx, cutoffs.vsh_max),
+            phi: cutoff_curve(phi_idx, cutoffs.phi_min),
+            sw: cutoff_curve(sw_idx, cutoffs.sw_max),
+            zones,
+        })
+    }
+
+    fn zone_json(zone: &ZoneStats) -> Value {
+        let curves: Vec<Value> = zone
+            .curves
+            .iter()
+            .map(|c| {
+                json!({
+                    "mnemonic": c.mnemonic,
+                    "unit": c.unit,
+                    "count": c.count,
+                    "nulls": c.nulls,
+                    "null_fraction": c.null_fraction(),
+                    "mean": c.mean,
+                    "median": c.median,
+                    "p10": c.p10,
+                    "p50": c.p50,
+                    "p90": c.p90,
+                    "std_dev": c.std_dev,
+                    "min": c.min,
+                    "max": c.max,
+                })
+            })
+            .collect();
+        let net = zone.net.as_ref().map(|net| {
+            json!({
+                "gross": net.gross,
+                "net_reservoir": net.net_reservoir,
+                "net_pay": net.net_pay,
+                "net_to_gross": net.net_to_gross(),
+                "pay_to_gross": net.pay_to_gross(),
+                "avg_vsh": net.avg_vsh,
+                "avg_phi": net.avg_phi,
+                "avg_sw": net.avg_sw,
+            })
+        });
+        json!({
+            "name": zone.zone.name,
+            "top": zone.zone.top,
+            "bottom": zone.zone.bottom,
+            "thickness": zone.zone.thickness(),
+            "samples": zone.samples,
+            "net": net,
+            "curves": curves,
+        })
+    }
+
+    pub fn to_json(&self) -> Value {
+        let cutoff = |c: &Option<CutoffCurve>| c.as_ref().map(|c| json!({ "curve": c.mnemonic, "limit": c.limit }));
+        json!({
+            "index": self.index,
+            "unit": self.index_unit,
+            "step": self.step,
+            "cutoffs": {
+                "vsh_max": cutoff(&self.vsh),
+                "phi_min": cutoff(&self.phi),
+                "sw_max": cutoff(&self.sw),
+            },
+            "zones": self.zones.iter().map(Self::zone_json).collect::<Vec<_>>(),
+        })
+    }
+
+    /// По одному JSON объекту зоны на строку
+    pub fn to_ndjson(&self) -> String {
+        self.zones.iter().map(|zone| format!("{}\n", Self::zone_json(zone))).collect()
+    }
+
+    /// CSV: статистика кривых по зонам (`table=curves`) или эффективные толщины (`table=net`)
+    pub fn to_csv(&self, table: ReportTable) -> String {
+        let number = |v: Option<f64>| v.map(|v| v.to_string()).unwrap_or_default();
+        let mut out = String::new();
+        match table {
+            ReportTable::Curves => {
+                out.push_str("zone,top,bottom,thickness,curve,unit,count,null_fraction,mean,median,p10,p50,p90,std_dev,min,max\n");
+                for zone in &self.zones {
+                    for c in &zone.curves {
+                        let fields = [
+                            csv_field(&zone.zone.name),
+                            zone.zone.top.to_string(),
+                            zone.zone.bottom.to_string(),
+                            zone.zone.thickness().to_string(),
+                            csv_field(&c.mnemonic),
+                            csv_field(&c.unit),
+                            c.count.to_string(),
+                            number(c.null_fraction()),
+                            number(c.mean),
+                            number(c.median),
+                            number(c.p10),
+                            number(c.p50),
+                            number(c.p90),
+                            number(c.std_dev),
+                            number(c.min),
+                            number(c.max),
+                        ];
+                        out.push_str(&fields.join(","));
+                        out.push('\n');
+                    }
+                }
+            }
+            ReportTable::Net => {
+                out.push_str("zone,top,bottom,gross,net_reservoir,net_pay,net_to_gross,pay_to_gross,avg_vsh,avg_phi,avg_sw\n");
+                for zone in &self.zones {
+                    let net = zone.net.as_ref();
+                    let fields = [
+                        csv_field(&zone.zone.name),
+                        zone.zone.top.to_string(),
+                        zone.zone.bottom.to_string(),
+                        zone.zone.thickness().to_string(),
+                        number(net.map(|n| n.net_reservoir)),
+                        number(net.and_then(|n| n.net_pay)),
+                        number(net.and_then(|n| n.net_to_gross())),
+                        number(net.and_then(|n| n.pay_to_gross())),
+                        number(net.and_then(|n| n.avg_vsh)),
+                        number(net.and_then(|n| n.avg_phi)),
+                        number(net.and_then(|n| n.avg_sw)),
+                    ];
+                    out.push_str(&fields.join(","));
+                    out.push('\n');
+                }
+            }
+        }
+        out
+    }
+}
+
+/// Таблица CSV отчёта (`table=`)
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ReportTable {
+    #[default]
+    Curves,
+    Net,
+}
+
+impl ReportTable {
+    pub fn parse(s: &str) -> Result<Self> {
+        match s.to_lowercase().as_str() {
+            "" | "curves" => Ok(ReportTable::Curves),
+            "net" | "net_pay" => Ok(ReportTable::Net),
+            other => bail!("Unknown table '{}' (expected curves or net)", other),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tops::FormationTop;
+
+    fn sample_file() -> LasFile {
+        LasFile::parse(
+            "~Version Information
+ VERS.  2.0:
+~Well Information
+ NULL.  -999.25:
+~Curve Information
+ DEPT.M  :  DEPTH
+ GR.GAPI  :  GAMMA
+~ASCII
+100  10
+101  20
+102  30
+103  40
+104  50
+",
+        )
+        .unwrap()
+    }
+
+    fn samples(las_file: &LasFile, zones: &[Zone]) -> Vec<usize> {
+        let report = ZoneReport::compute(las_file, 0, zones, &Cutoffs::default()).unwrap();
+        report.zones.iter().map(|zone| zone.samples).collect()
+    }
+
+    #[test]
+    fn last_zone_includes_bottom_sample() {
+        let mut las_file = sample_file();
+        las_file.tops = ["A", "B"]
+            .iter()
+            .zip([100.0, 102.0])
+            .map(|(name, depth)| FormationTop { name: name.to_string(), depth, unit: "M".to_string() })
+            .collect();
+        let zones = Zone::from_tops(&las_file, 0);
+        assert_eq!(zones.iter().map(|z| (z.top, z.bottom)).collect::<Vec<_>>(), [(100.0, 102.0), (102.0, 104.0)]);
+        assert_eq!(samples(&las_file, &zones), [2, 3]);
+
+        let all = Zone { name: "All".to_string(), top: 100.0, bottom: 104.0 };
+        assert_eq!(samples(&las_file, &[all]), [5]);
+        // Зона внутри данных по-прежнему без нижней границы
+        assert_eq!(samples(&las_file, &Zone::parse_list("101-103").unwrap()), [2]);
+    }
+}
@@ -0,0 +1,366 @@
+//! Инклинометрия: замеры MD, INC, AZI из CSV (`survey=`) или секции `~Survey`/`~Inclinometry` LAS файла.
+//!
+//! По замерам методом минимальной кривизны считаются TVD, TVDSS (по альтитуде стола ротора
+//! из ~Well/~Parameter или `kb=`) и смещения на север и восток; они добавляются в файл кривыми,
+//! после чего их можно выбрать основным параметром (`main_param=TVD`).
+
+use crate::las::{CurveInfo, LasFile};
+use crate::tops::{detect_delimiter, split_fields};
+use crate::units::find_unit;
+use anyhow::{bail, Result};
+
+/// Мнемоники колонок замеров
+const MD_COLUMNS: &[&str] = &["MD", "DEPT", "DEPTH", "DMEA", "MDEPTH"];
+const INC_COLUMNS: &[&str] = &["INC", "INCL", "INCLINATION", "DEVI", "DEV", "ANG"];
+const AZI_COLUMNS: &[&str] = &["AZI", "AZIM", "AZIMUTH", "HAZI", "AZ", "DAZI"];
+
+/// Альтитуда точки отсчёта глубин (стол ротора, буровая площадка, ...): первая найденная
+const ELEVATION_ITEMS: &[&str] = &["EKB", "KB", "EDF", "DF", "EREF", "ELZ", "EGL", "GL"];
+
+/// Мнемоники добавляемых кривых
+pub const TVD: &str = "TVD";
+pub const TVDSS: &str = "TVDSS";
+pub const NORTH: &str = "NORTH";
+pub const EAST: &str = "EAST";
+
+/// Замер: глубина по стволу, зенитный угол и азимут в градусах
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct SurveyStation {
+    pub md: f64,
+    pub inc: f64,
+    pub azi: f64,
+}
+
+/// Положение точки ствола относительно устья
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Position {
+    pub tvd: f64,
+    pub north: f64,
+    pub east: f64,
+}
+
+/// Инклинометрия скважины; замеры упорядочены по MD
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Survey {
+    pub stations: Vec<SurveyStation>,
+    /// Единица MD (и результатов); пустая - единица индекса LAS файла
+    pub unit: String,
+}
+
+/// Единичный вектор направления ствола (север, восток, вниз)
+fn direction(inc: f64, azi: f64) -> [f64; 3] {
+    let (inc, azi) = (inc.to_radians(), azi.to_radians());
+    [inc.sin() * azi.cos(), inc.sin() * azi.sin(), inc.cos()]
+}
+
+fn dot(a: [f64; 3], b: [f64; 3]) -> f64 {
+    a[0] * b[0] + a[1] * b[1] + a[2] * b[2]
+}
+
+/// Приращение положения по дуге окружности между направлениями `t1` и `t2` длиной `length`
+fn arc_step(t1: [f64; 3], t2: [f64; 3], length: f64) -> [f64; 3] {
+    let dogleg = dot(t1, t2).clamp(-1.0, 1.0).acos();
+    let ratio = if dogleg < 1e-9 { 1.0 } else { 2.0 / dogleg * (dogleg / 2.0).tan() };
+    [0, 1, 2].map(|i| length / 2.0 * (t1[i] + t2[i]) * ratio)
+}
+
+/// Направление в доле `f` дуги от `t1` к `t2` (сферическая интерполяция)
+fn slerp(t1: [f64; 3], t2: [f64; 3], f: f64) -> [f64; 3] {
+    let dogleg = dot(t1, t2).clamp(-1.0, 1.0).acos();
+    if dogleg < 1e-9 {
+        return t1;
+    }
+    let (a, b) = (((1.0 - f) * dogleg).sin() / dogleg.sin(), (f * dogleg).sin() / dogleg.sin());
+    [0, 1, 2].map(|i| a * t1[i] + b * t2[i])
+}
+
+impl Survey {
+    /// Замеры из строк секции LAS: `definition` - (мнемоника, единица) колонок из `~..._Definition`;
+    /// без определения колонки MD, INC, AZI по порядку. Строки без чисел пропускаются.
+    pub(crate) fn from_las_section(definition: &[(String, String)], lines: &[&str]) -> Result<Self> {
+        let column = |names: &[&str], default: usize| {
+            definition
+                .iter()
+                .position(|(m, _)| names.iter().any(|n| m.eq_ignore_ascii_case(n)))
+                .unwrap_or(default)
+        };
+        let columns = [column(MD_COLUMNS, 0), column(INC_COLUMNS, 1), column(AZI_COLUMNS, 2)];
+        let unit = definition.get(columns[0]).map(|(_, unit)| unit.clone()).unwrap_or_default();
+        let rows: Vec<Vec<String>> = lines
+            .iter()
+            .map(|line| split_fields(line, detect_delimiter(line)))
+            .filter(|row| columns.iter().all(|&col| row.get(col).is_some_and(|f| f.parse::<f64>().is_ok())))
+            .collect();
+        Self::from_rows(&rows, columns, unit)
+    }
+
+    /// Инклинометрия из CSV `md, inc, azi` (необязательная строка заголовка задаёт порядок колонок)
+    /// или LAS файла: секция `~Survey`/`~Inclinometry` либо кривые MD, INC, AZI
+    pub fn parse(content: &str) -> Result<Self> {
+        let content = content.trim_start_matches('\u{feff}');
+        if content.trim_start().starts_with('~') {
+            let las_file = LasFile::parse(content)?;
+            if let Some(survey) = las_file.survey {
+                return Ok(survey);
+            }
+            let find = |names: &[&str]| {
+                las_file.curves.iter().position(|c| names.iter().any(|n| c.mnemonic.eq_ignore_ascii_case(n)))
+            };
+            let (Some(inc), Some(azi)) = (find(INC_COLUMNS), find(AZI_COLUMNS)) else {
+                bail!("LAS file has no ~Survey section or INC/AZI curves");
+            };
+            let md = find(MD_COLUMNS).unwrap_or(0);
+            let (md_data, inc_data, azi_data) =
+                (las_file.get_curve_data(md), las_file.get_curve_data(inc), las_file.get_curve_data(azi));
+            let stations = (0..md_data.len())
+                .filter_map(|row| Some(SurveyStation { md: md_data[row]?, inc: inc_data[row]?, azi: azi_data[row]? }))
+                .collect();
+            return Self::validated(stations, las_file.curves[md].unit.clone());
+        }
+
+        let lines: Vec<&str> = content
+            .lines()
+            .map(str::trim)
+            .filter(|line| !line.is_empty() && !line.starts_with('#'))
+            .collect();
+        let delimiter = lines.first().and_then(|line| detect_delimiter(line));
+        let mut rows: Vec<Vec<String>> = lines.iter().map(|line| split_fields(line, delimiter)).collect();
+
+        // Первая строка без чисел - заголовок с названиями колонок
+        let mut columns = [0, 1, 2];
+        if rows.first().is_some_and(|row| row.iter().all(|f| f.parse::<f64>().is_err())) {
+            let header = rows.remove(0);
+            let column = |names: &[&str], what: &str| {
+                header
+                    .iter()
+                    .position(|h| {
+                        let name = h.split(['(', '[', '.', ',', ' ']).next().unwrap_or("").trim();
+                        names.iter().any(|n| name.eq_ignore_ascii_case(n))
+                    })
+                    .ok_or_else(|| anyhow::anyhow!("Survey header has no {} column (expected one of {})", what, names.join(", ")))
+            };
+            columns = [column(MD_COLUMNS, "MD")?, column(INC_COLUMNS, "INC")?, column(AZI_COLUMNS, "AZI")?];
+        }
+        Self::from_rows(&rows, columns, String::new())
+    }
+
+    fn from_rows(rows: &[Vec<String>], [md, inc, azi]: [usize; 3], unit: String) -> Result<Self> {
+        let mut stations = Vec::new();
+        for (pos, row) in rows.iter().enumerate() {
+            let value = |col: usize| row.get(col).and_then(|s| s.trim().parse::<f64>().ok()).filter(|v| v.is_finite());
+            let (Some(md), Some(inc), Some(azi)) = (value(md), value(inc), value(azi)) else {
+                bail!("Survey row {}: expected numeric MD, INC and AZI", pos + 1);
+            };
+            stations.push(SurveyStation { md, inc, azi });
+        }
+        Self::validated(stations, unit)
+    }
+
+    /// Проверка углов, упорядочивание по MD; повторы MD отбрасываются
+    fn validated(mut stations: Vec<SurveyStation>, unit: String) -> Result<Self> {
+        if stations.is_empty() {
+            bail!("Survey has no stations");
+        }
+        if let Some(pos) = stations.iter().position(|s| !(0.0..=180.0).contains(&s.inc)) {
+            bail!("Survey station {}: inclination is out of range 0..=180", pos + 1);
+        }
+        for station in &mut stations {
+            station.azi = station.azi.rem_euclid(360.0);
+        }
+        stations.sort_by(|a, b| a.md.total_cmp(&b.md));
+        stations.dedup_by(|b, a| a.md == b.md);
+        Ok(Survey { stations, unit })
+    }
+
+    /// Положения по глубинам `md` (в единицах замеров) методом минимальной кривизны.
+    /// Если первый замер ниже устья, от устья до него ствол считается вертикальным;
+    /// выше первого и ниже последнего замера - прямая по крайнему направлению.
+    pub fn positions(&self, md: &[Option<f64>]) -> Vec<Option<Position>> {
+        let mut stations = self.stations.clone();
+        match stations.first().copied() {
+            Some(first) if first.md > 0.0 => stations.insert(0, SurveyStation { md: 0.0, inc: 0.0, azi: first.azi }),
+            Some(_) => {}
+            None => return vec![None; md.len()],
+        }
+
+        // Точки замеров: (север, восток, вниз) и направление; отсчёт вниз - от MD первого замера
+        let mut nodes = vec![([0.0, 0.0, stations[0].md], direction(stations[0].inc, stations[0].azi))];
+        for pair in stations.windows(2) {
+            let (position, t1) = nodes[nodes.len() - 1];
+            let t2 = direction(pair[1].inc, pair[1].azi);
+            let step = arc_step(t1, t2, pair[1].md - pair[0].md);
+            nodes.push(([0, 1, 2].map(|i| position[i] + step[i]), t2));
+        }
+
+        md.iter()
+            .map(|&md| {
+                let md = md?;
+                let next = stations.partition_point(|s| s.md <= md);
+                let from = next.saturating_sub(1);
+                let ((position, t1), length) = (nodes[from], md - stations[from].md);
+                let step = if next == 0 || next == stations.len() {
+                    t1.map(|c| c * length)
+                } else {
+                    let f = length / (stations[next].md - stations[from].md);
+                    arc_step(t1, slerp(t1, nodes[next].1, f), length)
+                };
+                let [north, east, tvd] = [0, 1, 2].map(|i| position[i] + step[i]);
+                Some(Position { tvd, north, east })
+            })
+            .collect()
+    }
+}
+
+/// Коэффициент перевода из единицы `from` в `to`; неизвестные единицы - без перевода
+fn length_factor(from: &str, to: &str) -> f64 {
+    match (find_unit(from), find_unit(to)) {
+        (Some(from), Some(to)) if from.quantity == to.quantity => from.convert(1.0, to),
+        _ => 1.0,
+    }
+}
+
+impl LasFile {
+    /// Альтитуда точки отсчёта глубин из ~Well или ~Parameter (EKB, KB, EDF, ...) в единицах индекса
+    pub fn elevation(&self) -> Option<f64> {
+        let index_unit = self.curves.first().map_or("", |c| c.unit.as_str());
+        ELEVATION_ITEMS.iter().find_map(|name| {
+            let item = self
+                .well_items
+                .iter()
+                .chain(&self.parameters)
+                .find(|item| item.mnemonic.eq_ignore_ascii_case(name))?;
+            let value = item.numeric_value()?;
+            let unit = if item.unit.is_empty() { index_unit } else { item.unit.as_str() };
+            Some(value * length_factor(unit, index_unit))
+        })
+    }
+
+    /// Добавляет по инклинометрии кривые TVD, TVDSS (если известна альтитуда - `elevation` или
+    /// из заголовка), NORTH и EAST в единицах индекса. Без инклинометрии или если TVD уже есть -
+    /// ничего не делает. Возвращает индексы новых кривых.
+    pub fn add_trajectory(&mut self, elevation: Option<f64>) -> Result<Vec<usize>> {
+        let Some(survey) = &self.survey else {
+            return Ok(Vec::new());
+        };
+        if self.get_curve_index(TVD).is_some() {
+            return Ok(Vec::new());
+        }
+        let Some(index) = self.curves.first() else {
+            bail!("LAS file has no curves");
+        };
+        let index_unit = index.unit.clone();
+        let survey_unit = if survey.unit.is_empty() { index_unit.clone() } else { survey.unit.clone() };
+        let (to_survey, from_survey) = (length_factor(&index_unit, &survey_unit), length_factor(&survey_unit, &index_unit));
+
+        let md: Vec<Option<f64>> = self.get_curve_data(0).into_iter().map(|v| v.map(|v| v * to_survey)).collect();
+        let positions = survey.positions(&md);
+        let elevation = elevation.or_else(|| self.elevation());
+
+        let column = |value: &dyn Fn(&Position) -> f64| -> Vec<Option<f64>> {
+            positions.iter().map(|p| p.as_ref().map(value)).collect()
+        };
+        let mut columns = vec![(TVD, "True vertical depth (minimum curvature)", column(&|p| p.tvd * from_survey))];
+        if let Some(elevation) = elevation {
+            columns.push((TVDSS, "True vertical depth subsea", column(&|p| p.tvd * from_survey - elevation)));
+        }
+        columns.push((NORTH, "Northing from wellhead", column(&|p| p.north * from_survey)));
+        columns.push((EAST, "Easting from wellhead", column(&|p| p.east * from_survey)));
+
+        let mut added = Vec::new();
+        for (mnemonic, description, values) in columns {
+            if self.get_curve_index(mnemonic).is_some() {
+                continue;
+            }
+            let (curve_idx, null_value) = (self.curves.len(), self.null_value);
+            for (row, value) in self.data.iter_mut().zip(values) {
+                row.values.resize(curve_idx, null_value);
+                row.values.push(value.unwrap_or(null_value));
+            }
+            self.curves.push(CurveInfo {
+                mnemonic: mnemonic.to_string(),
+                unit: index_unit.clone(),
+                description: description.to_string(),
+                api_codes: None,
+            });
+            added.push(self.curves.len() - 1);
+        }
+        Ok(added)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn survey(stations: &[(f64, f64, f64)]) -> Survey {
+        Survey { stations: stations.iter().map(|&(md, inc, azi)| SurveyStation { md, inc, azi }).collect(), unit: String::new() }
+    }
+
+    fn assert_close(actual: f64, expected: f64) {
+        assert!((actual - expected).abs() < 1e-6, "{} != {}", actual, expected);
+    }
+
+    #[test]
+    fn vertical_well_has_tvd_equal_to_md() {
+        let positions = survey(&[(0.0, 0.0, 0.0), (1000.0, 0.0, 120.0)]).positions(&[Some(0.0), Some(500.0), Some(1200.0), None]);
+        for (position, md) in positions.iter().zip([0.0, 500.0, 1200.0]) {
+            let position = position.unwrap();
+            assert_close(position.tvd, md);
+            assert_close(position.north, 0.0);
+            assert_close(position.east, 0.0);
+        }
+        assert_eq!(positions[3], None);
+    }
+
+    #[test]
+    fn constant_build_follows_circular_arc() {
+        // 30° на 300 м по северу: радиус 300 / (π/6), TVD = R·sin(INC), смещение = R·(1 - cos(INC))
+        let radius = 300.0 / 30f64.to_radians();
+        let positions = survey(&[(0.0, 0.0, 0.0), (300.0, 30.0, 0.0)]).positions(&[Some(150.0), Some(300.0)]);
+        for (position, inc) in positions.iter().zip([15f64, 30.0]) {
+            let position = position.unwrap();
+            assert_close(position.tvd, radius * inc.to_radians().sin());
+            assert_close(position.north, radius * (1.0 - inc.to_radians().cos()));
+            assert_close(position.east, 0.0);
+        }
+        // Тот же набор на восток
+        let east = survey(&[(0.0, 0.0, 90.0), (300.0, 30.0, 90.0)]).positions(&[Some(300.0)])[0].unwrap();
+        assert_close(east.east, 76.761_789_251_210_33);
+        assert_close(east.north, 0.0);
+    }
+
+    #[test]
+    fn tvdss_is_tvd_minus_elevation() {
+        let mut las_file = LasFile::parse(
+            "~Version Information
+ VERS.  2.0:
+~Well Information
+ NULL.  -999.25:
+ EKB.M  25.0:  KELLY BUSHING
+~Curve Information
+ DEPT.M  :  DEPTH
+~ASCII
+100.0
+200.0
+",
+        )
+        .unwrap();
+        las_file.survey = Some(survey(&[(0.0, 0.0, 0.0), (300.0, 30.0, 0.0)]));
+        let added = las_file.add_trajectory(None).unwrap();
+        let names: Vec<&str> = added.iter().map(|&idx| las_file.curves[idx].mnemonic.as_str()).collect();
+        assert_eq!(names, [TVD, TVDSS, NORTH, EAST]);
+        let tvd = las_file.get_curve_data(las_file.get_curve_index(TVD).unwrap());
+        let tvdss = las_file.get_curve_data(las_file.get_curve_index(TVDSS).unwrap());
+        for (tvd, tvdss) in tvd.iter().zip(&tvdss) {
+            assert_close(tvdss.unwrap(), tvd.unwrap() - 25.0);
+        }
+    }
+
+    #[test]
+    fn csv_columns_follow_header() {
+        let survey = Survey::parse("AZI;MD;INC\n10;100;5\n370;0;0\n").unwrap();
+        assert_eq!(survey.stations, [SurveyStation { md: 0.0, inc: 0.0, azi: 10.0 }, SurveyStation { md: 100.0, inc: 5.0, azi: 10.0 }]);
+        let error = Survey::parse("0,0,0\n100,190.5,0\n").unwrap_err().to_string();
+        assert!(!error.contains("190.5"), "{}", error);
+    }
+}
@@ -0,0 +1,206 @@
+//! Кровли пластов: из секции `~Tops` LAS 3.0 или из CSV `well, top_name, depth` (`tops=`).
+//!
+//! Кровли рисуются горизонтальными линиями с подписями поперёк всех колонок
+//! и перечисляются в таблице над графиками (см. [`crate::view`]).
+
+use crate::las::LasFile;
+use crate::units::find_unit;
+use anyhow::{anyhow, bail, Result};
+use std::collections::hash_map::DefaultHasher;
+use std::collections::{BTreeMap, BTreeSet};
+use std::hash::{Hash, Hasher};
+
+/// Цвета кровель, для которых не задан цвет в `[tops_colors]` (выбираются по названию)
+const TOP_PALETTE: &[&str] = &["8B4513", "006400", "00008B", "8B008B", "B8860B", "2F4F4F", "8B0000", "008B8B"];
+
+/// Кровля пласта
+#[derive(Debug, Clone, PartialEq)]
+pub struct FormationTop {
+    pub name: String,
+    pub depth: f64,
+    /// Единица глубины; пустая - единица индекса файла
+    pub unit: String,
+}
+
+impl FormationTop {
+    /// Глубина в единице `unit`; если одна из единиц неизвестна - как есть
+    pub fn depth_in(&self, unit: &str) -> f64 {
+        match (find_unit(&self.unit), find_unit(unit)) {
+            (Some(from), Some(to)) if from.quantity == to.quantity => from.convert(self.depth, to),
+            _ => self.depth,
+        }
+    }
+}
+
+/// Цвет линии кровли: из `[tops_colors]` (название без учёта регистра) или из палитры по названию
+pub fn top_color(name: &str, configured: &BTreeMap<String, String>) -> String {
+    if let Some((_, color)) = configured.iter().find(|(key, _)| key.eq_ignore_ascii_case(name.trim())) {
+        return color.trim_start_matches('#').to_string();
+    }
+    let mut hasher = DefaultHasher::new();
+    name.trim().to_uppercase().hash(&mut hasher);
+    TOP_PALETTE[(hasher.finish() % TOP_PALETTE.len() as u64) as usize].to_string()
+}
+
+/// Разбивает строку на поля: по `delimiter` или, если он не задан, по пробелам;
+/// поле в двойных кавычках может содержать разделитель (`""` - кавычка)
+pub(crate) fn split_fields(line: &str, delimiter: Option<char>) -> Vec<String> {
+    let mut fields = Vec::new();
+    let mut field = String::new();
+    let mut quoted = false;
+    let mut chars = line.chars().peekable();
+    while let Some(ch) = chars.next() {
+        match ch {
+            '"' if quoted && chars.peek() == Some(&'"') => {
+                field.push('"');
+                chars.next();
+            }
+            '"' => quoted = !quoted,
+            ch if !quoted && delimiter.map_or(ch.is_whitespace(), |d| ch == d) => {
+                if delimiter.is_some() || !field.is_empty() {
+                    fields.push(field.trim().to_string());
+                }
+                field.clear();
+            }
+            ch => field.push(ch),
+        }
+    }
+    if delimiter.is_some() || !field.is_empty() {
+        fields.push(field.trim().to_string());
+    }
+    fields
+}
+
+/// Разделитель строки данных: запятая, точка с запятой или табуляция; иначе пробелы
+pub(crate) fn detect_delimiter(line: &str) -> Option<char> {
+    [',', ';', '\t'].into_iter().find(|d| line.contains(*d))
+}
+
+fn parse_depth(value: &str) -> Option<f64> {
+    value.trim().parse::<f64>().ok().filter(|v| v.is_finite())
+}
+
+/// Кровли из строк `~Tops_Data` LAS 3.0. `definition` - (мнемоника, единица) колонок
+/// из `~Tops_Definition`; без определения первая колонка - название, вторая - глубина.
+pub(crate) fn parse_las_tops(definition: &[(String, String)], lines: &[&str]) -> Vec<FormationTop> {
+    let column = |names: &[&str]| definition.iter().position(|(m, _)| names.iter().any(|n| m.eq_ignore_ascii_case(n)));
+    let name_col = column(&["TOPT", "TOPN", "TOPNAME", "NAME", "FORMATION"]).unwrap_or(0);
+    let depth_col = column(&["TOPDEPTH", "TOPD", "TOPMD", "TOP", "DEPTH", "DEPT", "MD"])
+        .unwrap_or(if name_col == 0 { 1 } else { 0 });
+    let unit = definition.get(depth_col).map(|(_, unit)| unit.clone()).unwrap_or_default();
+
+    lines
+        .iter()
+        .filter_map(|line| {
+            let fields = split_fields(line, detect_delimiter(line));
+            let (mut name, mut depth) = (fields.get(name_col)?, fields.get(depth_col)?);
+            // Без определения допускается и порядок "глубина название"
+            if definition.is_empty() && parse_depth(depth).is_none() && parse_depth(name).is_some() {
+                std::mem::swap(&mut name, &mut depth);
+            }
+            Some(FormationTop { name: name.clone(), depth: parse_depth(depth)?, unit: unit.clone() })
+        })
+        .filter(|top| !top.name.is_empty())
+        .collect()
+}
+
+/// Файл кровель: CSV `well, top_name, depth` (или `top_name, depth` для одной скважины)
+/// с необязательной строкой заголовка, либо LAS файл с секцией `~Tops`
+#[derive(Debug, Clone, Default)]
+pub struct TopsFile {
+    /// (скважина, кровля); пустая скважина - кровля подходит к любому файлу
+    pub tops: Vec<(String, FormationTop)>,
+}
+
+impl TopsFile {
+    pub fn parse(content: &str) -> Result<Self> {
+        let content = content.trim_start_matches('\u{feff}');
+        if content.trim_start().starts_with('~') {
+            let las_file = LasFile::parse(content)?;
+            if las_file.tops.is_empty() {
+                bail!("LAS file has no ~Tops section");
+            }
+            let well = las_well_names(&las_file).into_iter().next().unwrap_or_default();
+            return Ok(TopsFile { tops: las_file.tops.into_iter().map(|top| (well.clone(), top)).collect() });
+        }
+
+        let lines: Vec<(usize, &str)> = content
+            .lines()
+            .enumerate()
+            .map(|(idx, line)| (idx + 1, line.trim()))
+            .filter(|(_, line)| !line.is_empty() && !line.starts_with('#'))
+            .collect();
+        let delimiter = lines.first().and_then(|(_, line)| detect_delimiter(line));
+
+        let mut tops = Vec::new();
+        for (pos, (line_no, line)) in lines.iter().enumerate() {
+            let fields = split_fields(line, delimiter);
+            let (well, name, depth) = match fields.as_slice() {
+                [well, name, depth, ..] => (well.as_str(), name.as_str(), depth.as_str()),
+                [name, depth] => ("", name.as_str(), depth.as_str()),
+                _ => bail!("Tops file line {}: expected 'well, top_name, depth'", line_no),
+            };
+            let Some(depth) = parse_depth(depth) else {
+                // Первая строка без числа - заголовок
+                if pos == 0 {
+                    continue;
+                }
+                bail!("Tops file line {}: invalid depth", line_no);
+            };
+            if name.is_empty() {
+                bail!("Tops file line {}: empty top name", line_no);
+            }
+            tops.push((well.to_string(), FormationTop { name: name.to_string(), depth, unit: String::new() }));
+        }
+        if tops.is_empty() {
+            bail!("Tops file has no tops");
+        }
+        Ok(TopsFile { tops })
+    }
+
+    /// Кровли скважины `las_file` (по WELL или UWI из ~Well, без учёта регистра) и кровли без скважины.
+    /// Если в файле кровель одна скважина, её кровли подходят к любому LAS файлу.
+    /// Глубины без единицы считаются в единицах индекса `las_file`.
+    pub fn for_well(&self, las_file: &LasFile) -> Result<Vec<FormationTop>> {
+        let names = las_well_names(las_file);
+        let matches = |well: &str| names.iter().any(|name| name.eq_ignore_ascii_case(well.trim()));
+        let wells: BTreeSet<&str> = self.tops.iter().map(|(well, _)| well.trim()).filter(|w| !w.is_empty()).collect();
+
+        let selected: Vec<&FormationTop> = if wells.len() <= 1 || wells.iter().any(|well| matches(well)) {
+            self.tops
+                .iter()
+                .filter(|(well, _)| wells.len() <= 1 || well.trim().is_empty() || matches(well))
+                .map(|(_, top)| top)
+                .collect()
+        } else {
+            let listed: Vec<&str> = wells.iter().take(10).copied().collect();
+            return Err(anyhow!(
+                "Tops file has no tops for well '{}' (wells: {}{})",
+                names.first().map_or("", |name| name.as_str()),
+                listed.join(", "),
+                if wells.len() > listed.len() { ", ..." } else { "" }
+            ));
+        };
+
+        let index_unit = las_file.curves.first().map(|curve| curve.unit.clone()).unwrap_or_default();
+        let mut tops: Vec<FormationTop> = selected
+            .into_iter()
+            .map(|top| FormationTop {
+                unit: if top.unit.is_empty() { index_unit.clone() } else { top.unit.clone() },
+                ..top.clone()
+            })
+            .collect();
+        tops.sort_by(|a, b| a.depth_in(&index_unit).total_cmp(&b.depth_in(&index_unit)));
+        Ok(tops)
+    }
+}
+
+/// Названия скважины из ~Well: WELL, затем UWI и API
+fn las_well_names(las_file: &LasFile) -> Vec<String> {
+    ["WELL", "UWI", "API"]
+        .iter()
+        .filter_map(|key| las_file.get_well_item(key))
+        .map(|item| item.value.trim().to_string())
+        .filter(|value| !value.is_empty())
+        .collect()
+}
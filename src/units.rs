@@ -0,0 +1,298 @@
+//! Единицы измерения кривых и перевод между метрической и американской системами.
+//!
+//! Единица в LAS - произвольная строка; здесь собраны распространённые написания
+//! для глубины, плотности, интервального времени, температуры, давления и сопротивления.
+
+use crate::las::{format_number, round_significant, HeaderItem, LasFile};
+use anyhow::{bail, Result};
+
+/// Точность пересчитанных значений
+const SIGNIFICANT_DIGITS: i32 = 12;
+
+/// Физическая величина; переводятся только единицы одной величины
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Quantity {
+    Length,
+    Density,
+    Slowness,
+    Temperature,
+    Pressure,
+    Resistivity,
+}
+
+/// Единица: `value_si = value * scale + offset`
+#[derive(Debug)]
+pub struct Unit {
+    /// Написания в LAS (в верхнем регистре), первое - каноническое
+    pub names: &'static [&'static str],
+    pub quantity: Quantity,
+    pub scale: f64,
+    pub offset: f64,
+}
+
+impl Unit {
+    pub fn name(&self) -> &'static str {
+        self.names[0]
+    }
+
+    /// Значение в единице `to` (той же величины)
+    pub fn convert(&self, value: f64, to: &Unit) -> f64 {
+        round_significant((value * self.scale + self.offset - to.offset) / to.scale, SIGNIFICANT_DIGITS)
+    }
+}
+
+const fn unit(names: &'static [&'static str], quantity: Quantity, scale: f64, offset: f64) -> Unit {
+    Unit { names, quantity, scale, offset }
+}
+
+/// Известные единицы; базовые единицы величин - м, кг/м3, мкс/м, °C, кПа, Ом·м
+static UNITS: &[Unit] = &[
+    unit(&["M", "METER", "METERS", "METRE", "METRES"], Quantity::Length, 1.0, 0.0),
+    unit(&["FT", "F", "FEET", "FOOT"], Quantity::Length, 0.3048, 0.0),
+    unit(&["CM"], Quantity::Length, 0.01, 0.0),
+    unit(&["MM"], Quantity::Length, 0.001, 0.0),
+    unit(&["IN", "INCH", "INCHES"], Quantity::Length, 0.0254, 0.0),
+    unit(&["KG/M3", "K/M3"], Quantity::Density, 1.0, 0.0),
+    unit(&["G/C3", "G/CC", "GM/CC", "G/CM3", "GRAM/CC"], Quantity::Density, 1000.0, 0.0),
+    unit(&["LB/FT3"], Quantity::Density, 16.018_463, 0.0),
+    unit(&["US/M", "USEC/M"], Quantity::Slowness, 1.0, 0.0),
+    unit(&["US/F", "US/FT", "USEC/F", "USEC/FT"], Quantity::Slowness, 1.0 / 0.3048, 0.0),
+    unit(&["DEGC", "DEG_C", "C"], Quantity::Temperature, 1.0, 0.0),
+    unit(&["DEGF", "DEG_F"], Quantity::Temperature, 5.0 / 9.0, -32.0 * 5.0 / 9.0),
+    unit(&["KPA"], Quantity::Pressure, 1.0, 0.0),
+    unit(&["MPA"], Quantity::Pressure, 1000.0, 0.0),
+    unit(&["PA"], Quantity::Pressure, 0.001, 0.0),
+    unit(&["BAR"], Quantity::Pressure, 100.0, 0.0),
+    unit(&["PSI", "PSIA", "PSIG"], Quantity::Pressure, 6.894_757, 0.0),
+    unit(&["OHMM", "OHM.M", "OHM-M", "OHM_M"], Quantity::Resistivity, 1.0, 0.0),
+    unit(&["OHMFT", "OHM.FT", "OHM-FT", "OHM_FT"], Quantity::Resistivity, 0.3048, 0.0),
+];
+
+/// Кривые и параметры с глубинами и отметками; остальные длины (диаметры, толщины)
+/// переводятся в мм/дюймы
+const DEPTH_MNEMONICS: &[&str] = &[
+    "DEPT", "DEPTH", "MD", "TVD", "TVDSS", "TVDKB", "NORTH", "EAST", "STRT", "STOP", "STEP", "EKB", "EDF", "EGL",
+    "ELEV", "EPD", "APD", "KB", "DF", "GL", "TD", "TDD", "TDL", "BLI", "TLI",
+];
+
+fn is_depth_mnemonic(mnemonic: &str) -> bool {
+    DEPTH_MNEMONICS.iter().any(|key| mnemonic.trim().eq_ignore_ascii_case(key))
+}
+
+/// Единица по написанию из LAS (без учёта регистра)
+pub fn find_unit(name: &str) -> Option<&'static Unit> {
+    let name = name.trim().to_uppercase();
+    if name.is_empty() {
+        return None;
+    }
+    UNITS.iter().find(|unit| unit.names.contains(&name.as_str()))
+}
+
+/// Система единиц для `units=`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum UnitSystem {
+    Metric,
+    Imperial,
+}
+
+impl UnitSystem {
+    pub fn parse(s: &str) -> Result<Self> {
+        match s.to_lowercase().as_str() {
+            "metric" | "si" => Ok(UnitSystem::Metric),
+            "imperial" | "us" | "english" => Ok(UnitSystem::Imperial),
+            other => bail!("Unknown unit system '{}' (expected metric or imperial)", other),
+        }
+    }
+
+    /// Единица величины в этой системе; `depth` - длина является глубиной или отметкой
+    /// (м/фт), иначе малой длиной вроде диаметра скважины (мм/дюймы)
+    pub fn unit_for(&self, quantity: Quantity, depth: bool) -> &'static Unit {
+        let name = match (self, quantity) {
+            (UnitSystem::Metric, Quantity::Length) if depth => "M",
+            (UnitSystem::Imperial, Quantity::Length) if depth => "FT",
+            (UnitSystem::Metric, Quantity::Length) => "MM",
+            (UnitSystem::Imperial, Quantity::Length) => "IN",
+            // Плотность в метрической системе - промысловые г/см3, а не кг/м3: на них рассчитаны
+            // привычные шкалы планшетов и параметры пористости (RHOB 2.3, а не 2300)
+            (UnitSystem::Metric, Quantity::Density) => "G/C3",
+            // Г/см3 - не американская единица; расчёты пористости переводят плотность
+            // в г/см3 сами, так что в выдаче она в фунтах на фут3
+            (UnitSystem::Imperial, Quantity::Density) => "LB/FT3",
+            (UnitSystem::Metric, Quantity::Slowness) => "US/M",
+            (UnitSystem::Imperial, Quantity::Slowness) => "US/F",
+            (UnitSystem::Metric, Quantity::Temperature) => "DEGC",
+            (UnitSystem::Imperial, Quantity::Temperature) => "DEGF",
+            (UnitSystem::Metric, Quantity::Pressure) => "KPA",
+            (UnitSystem::Imperial, Quantity::Pressure) => "PSI",
+            // Сопротивление в обеих системах принято давать в Ом·м
+            (_, Quantity::Resistivity) => "OHMM",
+        };
+        find_unit(name).expect("target units are in the registry")
+    }
+}
+
+/// Единица, в которую переводится `unit`, если она известна и отличается от целевой
+fn conversion(unit: &str, system: UnitSystem, depth: bool) -> Option<(&'static Unit, &'static Unit)> {
+    let from = find_unit(unit)?;
+    let to = system.unit_for(from.quantity, depth);
+    (!std::ptr::eq(from, to)).then_some((from, to))
+}
+
+fn is_index_item(mnemonic: &str) -> bool {
+    ["STRT", "STOP", "STEP"].iter().any(|key| mnemonic.eq_ignore_ascii_case(key))
+}
+
+/// Переводит числовое значение строки заголовка; false - единица неизвестна или уже целевая
+fn convert_header_item(item: &mut HeaderItem, system: UnitSystem, null_value: f64) -> bool {
+    let depth = is_depth_mnemonic(&item.mnemonic);
+    let (Some((from, to)), Some(value)) = (conversion(&item.unit, system, depth), item.numeric_value()) else {
+        return false;
+    };
+    if value == null_value {
+        return false;
+    }
+    // STEP - разность, смещение шкалы (°C/°F) к нему не применяется
+    let converted = if item.mnemonic.eq_ignore_ascii_case("STEP") {
+        round_significant(value * from.scale / to.scale, SIGNIFICANT_DIGITS)
+    } else {
+        from.convert(value, to)
+    };
+    item.unit = to.name().to_string();
+    item.value = format_number(converted);
+    true
+}
+
+impl LasFile {
+    /// Переводит кривые `curve_indices` (None - все) с известными единицами в систему `system`.
+    /// Длины индекса и кривых глубин (MD, TVD, ...) идут в м/фт, прочие длины - в мм/дюймы.
+    /// STRT/STOP/STEP в ~Well и кровли переводятся вместе с первой кривой (индексом),
+    /// числовые значения ~Parameter - при переводе всего файла.
+    /// Возвращает количество переведённых кривых.
+    pub fn convert_units(&mut self, system: UnitSystem, curve_indices: Option<&[usize]>) -> usize {
+        let mut converted = 0;
+        for curve_idx in 0..self.curves.len() {
+            if curve_indices.is_some_and(|indices| !indices.contains(&curve_idx)) {
+                continue;
+            }
+            let depth = curve_idx == 0 || is_depth_mnemonic(&self.curves[curve_idx].mnemonic);
+            let Some((from, to)) = conversion(&self.curves[curve_idx].unit, system, depth) else {
+                continue;
+            };
+            for row in &mut self.data {
+                if let Some(value) = row.values.get_mut(curve_idx) {
+                    if *value != self.null_value && !value.is_nan() {
+                        *value = from.convert(*value, to);
+                    }
+                }
+            }
+            self.curves[curve_idx].unit = to.name().to_string();
+            converted += 1;
+        }
+
+        let null_value = self.null_value;
+        let index_included = curve_indices.is_none_or(|indices| indices.contains(&0));
+        for item in self.well_items.iter_mut().filter(|item| index_included && is_index_item(&item.mnemonic)) {
+            if convert_header_item(item, system, null_value) {
+                let value = format!("{} {}", item.unit, item.value).trim().to_string();
+                self.well_info.insert(item.mnemonic.clone(), value);
+            }
+        }
+        // Кровли - вместе с индексом
+        for top in self.tops.iter_mut().filter(|_| index_included) {
+            if let Some((from, to)) = conversion(&top.unit, system, true) {
+                top.depth = from.convert(top.depth, to);
+                top.unit = to.name().to_string();
+            }
+        }
+        if curve_indices.is_none() {
+            for item in &mut self.parameters {
+                convert_header_item(item, system, null_value);
+            }
+        }
+
+        converted
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sample_file() -> LasFile {
+        LasFile::parse(
+            "~Version Information
+ VERS.  2.0:
+~Well Information
+ STRT.FT  1000.0:
+ STOP.FT  1001.0:
+ STEP.FT  1.0:
+ NULL.  -999.25:
+~Curve Information
+ DEPT.FT  :  DEPTH
+ TVD.FT  :  TRUE VERTICAL DEPTH
+ CALI.IN  :  CALIPER
+ RHOB.G/C3  :  BULK DENSITY
+~Parameter Information
+ BS.IN  8.5:  BIT SIZE
+ EKB.FT  100.0:  KELLY BUSHING
+~ASCII
+1000.0  990.0  8.5  2.65
+1001.0  991.0  -999.25  2.30
+",
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn depths_go_to_meters_and_small_lengths_to_millimeters() {
+        let mut las_file = sample_file();
+        assert_eq!(las_file.convert_units(UnitSystem::Metric, None), 3);
+        let units: Vec<&str> = las_file.curves.iter().map(|c| c.unit.as_str()).collect();
+        assert_eq!(units, ["M", "M", "MM", "G/C3"]);
+        assert_eq!(las_file.get_curve_data(0), vec![Some(304.8), Some(305.1048)]);
+        assert_eq!(las_file.get_curve_data(1)[0], Some(301.752));
+        assert_eq!(las_file.get_curve_data(2), vec![Some(215.9), None]);
+        assert_eq!(las_file.get_curve_data(3)[0], Some(2.65));
+
+        let param = |name: &str| las_file.parameters.iter().find(|p| p.mnemonic == name).unwrap();
+        assert_eq!((param("BS").unit.as_str(), param("BS").value.as_str()), ("MM", "215.9"));
+        assert_eq!((param("EKB").unit.as_str(), param("EKB").value.as_str()), ("M", "30.48"));
+        let step = las_file.well_items.iter().find(|item| item.mnemonic == "STEP").unwrap();
+        assert_eq!((step.unit.as_str(), step.value.as_str()), ("M", "0.3048"));
+    }
+
+    #[test]
+    fn imperial_uses_feet_inches_and_pounds_per_cubic_foot() {
+        let mut las_file = sample_file();
+        las_file.convert_units(UnitSystem::Metric, None);
+        las_file.convert_units(UnitSystem::Imperial, None);
+        let units: Vec<&str> = las_file.curves.iter().map(|c| c.unit.as_str()).collect();
+        assert_eq!(units, ["FT", "FT", "IN", "LB/FT3"]);
+        assert_eq!(las_file.get_curve_data(0)[0], Some(1000.0));
+        assert_eq!(las_file.get_curve_data(2)[0], Some(8.5));
+        let rhob = las_file.get_curve_data(3)[0].unwrap();
+        assert!((rhob - 165.435).abs() < 0.001, "{}", rhob);
+    }
+
+    #[test]
+    fn metric_density_is_grams_per_cubic_centimeter() {
+        let mut las_file = sample_file();
+        las_file.convert_units(UnitSystem::Imperial, None);
+        las_file.convert_units(UnitSystem::Metric, None);
+        assert_eq!(las_file.curves[3].unit, "G/C3");
+        assert_eq!(las_file.get_curve_data(3), vec![Some(2.65), Some(2.3)]);
+
+        las_file.curves[3].unit = "K/M3".to_string();
+        las_file.convert_units(UnitSystem::Metric, Some(&[3]));
+        assert_eq!(las_file.curves[3].unit, "G/C3");
+        assert_eq!(las_file.get_curve_data(3)[0], Some(0.00265));
+    }
+
+    #[test]
+    fn unit_spellings_are_case_insensitive() {
+        assert_eq!(find_unit("k/m3").map(Unit::name), Some("KG/M3"));
+        assert_eq!(find_unit(" g/cc ").map(Unit::name), Some("G/C3"));
+        assert!(find_unit("").is_none());
+        assert!(find_unit("GAPI").is_none());
+    }
+}
@@ -0,0 +1,85 @@
+//! Хранилище загруженных пользователями LAS файлов.
+//!
+//! Файлы лежат в `uploads_dir` под именем `<sha256>.las`, на них ссылаются
+//! как `upload:<sha256>`. Устаревшие файлы удаляются по времени изменения.
+
+use lasplot::config::Config;
+use lasplot::las::LasFile;
+use anyhow::{bail, Context, Result};
+use sha2::{Digest, Sha256};
+use std::path::PathBuf;
+use std::time::{Duration, SystemTime};
+
+/// Префикс параметра `file=`, указывающий на загруженный файл
+pub const UPLOAD_PREFIX: &str = "upload:";
+
+/// Проверяет, что идентификатор - это SHA-256 в hex (и не может содержать путь)
+pub fn is_valid_id(id: &str) -> bool {
+    id.len() == 64 && id.bytes().all(|b| b.is_ascii_digit() || (b'a'..=b'f').contains(&b))
+}
+
+/// Путь к загруженному файлу по идентификатору
+pub fn upload_path(config: &Config, id: &str) -> Option<PathBuf> {
+    if is_valid_id(id) {
+        Some(config.get_uploads_path().join(format!("{}.las", id)))
+    } else {
+        None
+    }
+}
+
+/// Проверяет содержимое и сохраняет файл байт в байт; возвращает идентификатор загрузки
+/// (SHA-256 исходных байт). Повторная загрузка того же файла возвращает тот же идентификатор.
+pub fn store_upload(config: &Config, body: &[u8]) -> Result<String> {
+    // LAS файлы бывают не в UTF-8: для проверки разбираем с заменой невалидных байт,
+    // как при чтении (LasFile::read), но храним то, что прислали
+    let las_file = LasFile::parse(&String::from_utf8_lossy(body))?;
+    if las_file.curves.is_empty() {
+        bail!("No ~Curve section found");
+    }
+    if las_file.data.is_empty() {
+        bail!("No data rows found");
+    }
+
+    let id = format!("{:x}", Sha256::digest(body));
+    let uploads_path = config.get_uploads_path();
+    std::fs::create_dir_all(&uploads_path)
+        .with_context(|| format!("Failed to create uploads dir: {:?}", uploads_path))?;
+
+    let path = uploads_path.join(format!("{}.las", id));
+    std::fs::write(&path, body)
+        .with_context(|| format!("Failed to write upload: {:?}", path))?;
+
+    Ok(id)
+}
+
+/// Удаляет загрузки старше `upload_ttl_hours`; возвращает количество удалённых файлов
+pub fn cleanup_expired(config: &Config) -> usize {
+    let ttl = Duration::from_secs(config.upload_ttl_hours * 3600);
+    let now = SystemTime::now();
+    let mut removed = 0;
+
+    if let Ok(entries) = std::fs::read_dir(config.get_uploads_path()) {
+        for entry in entries.flatten() {
+            let path = entry.path();
+            let is_upload = path
+                .file_stem()
+                .and_then(|s| s.to_str())
+                .is_some_and(is_valid_id);
+            if !is_upload {
+                continue;
+            }
+
+            let expired = entry
+                .metadata()
+                .and_then(|m| m.modified())
+                .ok()
+                .and_then(|modified| now.duration_since(modified).ok())
+                .is_some_and(|age| age > ttl);
+            if expired && std::fs::remove_file(&path).is_ok() {
+                removed += 1;
+            }
+        }
+    }
+
+    removed
+}
@@ -0,0 +1,1145 @@
+//! Подготовка LAS файла к отображению и генерация HTML страницы с графиками.
+//!
+//! [`LogView`] собирает из `LasFile` всё, что нужно для отрисовки
+//! (кривые, диапазоны, цвета, заголовок), [`PageLayout`] задаёт геометрию страницы.
+//! Семейства кривых ([`crate::families`]) раскладывают кривые по колонкам и задают их шкалы и цвета,
+//! кровли пластов ([`crate::tops`]) рисуются линиями поперёк колонок и перечисляются в таблице.
+//! Используется и сервером, и командой `lasplot render`.
+
+use crate::api::flag_param;
+use crate::compare::ComparisonReport;
+use crate::config::{is_hex_color, Config};
+use crate::families::{AliasDictionary, CurveFamily, Track};
+use crate::html::escape_html;
+use crate::las::LasFile;
+use crate::pool::RenderPool;
+use crate::resample::{median_step, ResampleMethod};
+use crate::stats::ZoneReport;
+use crate::plot::{generate_plot_png, hex_to_rgb, render_plot_image, CurveData, LineStyle, PlotConfig, PlotTop, RGBColor, ScaleType};
+use crate::tops::top_color;
+use anyhow::{anyhow, bail, Result};
+use base64::Engine;
+use bytes::Bytes;
+use futures::future::ok;
+use futures::stream::{self, once, Stream, StreamExt};
+use image::RgbaImage;
+use serde::{Deserialize, Serialize};
+use std::collections::hash_map::DefaultHasher;
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::borrow::Cow;
+use std::hash::{Hash, Hasher};
+use std::sync::{Arc, OnceLock};
+
+/// Геометрия страницы: размеры строк, картинок и шкал
+#[derive(Debug, Clone)]
+pub struct PageLayout {
+    pub html_row_steps: usize,
+    pub pixels_per_step: usize,
+    pub image_width: usize,
+    pub separate_depth_column: bool,
+    pub scale_spacing: usize,
+    pub max_scales: usize,
+    pub tick_size_major: usize,
+    pub tick_size_minor: usize,
+}
+
+/// Значения по умолчанию - как в [`Config::default`]
+impl Default for PageLayout {
+    fn default() -> Self {
+        PageLayout::from_config(&Config::default())
+    }
+}
+
+impl PageLayout {
+    pub fn from_config(config: &Config) -> Self {
+        PageLayout {
+            html_row_steps: config.html_row_steps,
+            pixels_per_step: config.pixels_per_step,
+            image_width: config.image_width,
+            separate_depth_column: config.separate_depth_column,
+            scale_spacing: config.scale_spacing,
+            max_scales: config.max_scales,
+            tick_size_major: config.tick_size_major,
+            tick_size_minor: config.tick_size_minor,
+        }
+    }
+
+    /// Количество шагов на строку (не меньше одного, даже если задан 0)
+    pub fn row_steps(&self) -> usize {
+        self.html_row_steps.max(1)
+    }
+
+    /// Высота картинки одной строки: html_row_steps * pixels_per_step + один общий шаг
+    pub fn block_height(&self) -> usize {
+        (1 + self.row_steps()) * self.pixels_per_step
+    }
+}
+
+/// Верхние границы параметров, заданных в запросе (`/?image_width=...`), чтобы один запрос
+/// не мог заставить сервер рисовать гигантские картинки: (поле, минимум, максимум)
+const REQUEST_LIMITS: &[(&str, usize, usize)] = &[
+    ("html_row_steps", 1, 500),
+    ("pixels_per_step", 1, 50),
+    ("image_width", 200, 4000),
+    ("scale_spacing", 5, 100),
+    ("max_scales", 1, 20),
+    ("tick_size_major", 1, 50),
+    ("tick_size_minor", 1, 50),
+];
+
+/// Максимальная высота картинки строки при параметрах из запроса
+const REQUEST_MAX_BLOCK_HEIGHT: usize = 4000;
+
+/// Переопределение параметров отображения: пресет из `[presets.<имя>]` в lasplot.toml
+/// или параметры запроса с теми же именами. Незаданные поля берутся из конфигурации.
+#[derive(Debug, Clone, Default, PartialEq, Deserialize, Serialize)]
+#[serde(deny_unknown_fields)]
+pub struct LayoutOverrides {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub html_row_steps: Option<usize>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub pixels_per_step: Option<usize>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub image_width: Option<usize>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub separate_depth_column: Option<bool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub scale_spacing: Option<usize>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub max_scales: Option<usize>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub tick_size_major: Option<usize>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub tick_size_minor: Option<usize>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub colors: Option<Vec<String>>,
+}
+
+impl LayoutOverrides {
+    /// Разбирает параметры запроса; значения проверяются по `REQUEST_LIMITS`
+    pub fn from_params(params: &HashMap<String, String>) -> Result<Self> {
+        let number = |name: &str| -> Result<Option<usize>> {
+            let Some(raw) = params.get(name) else {
+                return Ok(None);
+            };
+            let value: usize = raw
+                .trim()
+                .parse()
+                .map_err(|_| anyhow!("{} must be a non-negative integer, got '{}'", name, raw))?;
+            if let Some(&(_, min, max)) = REQUEST_LIMITS.iter().find(|(field, _, _)| *field == name) {
+                if !(min..=max).contains(&value) {
+                    bail!("{} = {} is out of range {}..={}", name, value, min, max);
+                }
+            }
+            Ok(Some(value))
+        };
+
+        let separate_depth_column = flag_param(params, "separate_depth_column")?;
+
+        let colors = match params.get("colors") {
+            Some(colors_param) => {
+                let colors: Vec<String> =
+                    colors_param.split(',').map(|s| s.trim().to_string()).filter(|s| !s.is_empty()).collect();
+                if let Some(color) = colors.iter().find(|color| !is_hex_color(color)) {
+                    bail!("colors: '{}' is not a 6-digit hex color (e.g. FF0000)", color);
+                }
+                Some(colors).filter(|colors| !colors.is_empty())
+            }
+            None => None,
+        };
+
+        Ok(LayoutOverrides {
+            html_row_steps: number("html_row_steps")?,
+            pixels_per_step: number("pixels_per_step")?,
+            image_width: number("image_width")?,
+            separate_depth_column,
+            scale_spacing: number("scale_spacing")?,
+            max_scales: number("max_scales")?,
+            tick_size_major: number("tick_size_major")?,
+            tick_size_minor: number("tick_size_minor")?,
+            colors,
+        })
+    }
+
+    /// Поля `other` поверх заданных здесь (например, параметры запроса поверх пресета)
+    pub fn merge(&self, other: &LayoutOverrides) -> LayoutOverrides {
+        LayoutOverrides {
+            html_row_steps: other.html_row_steps.or(self.html_row_steps),
+            pixels_per_step: other.pixels_per_step.or(self.pixels_per_step),
+            image_width: other.image_width.or(self.image_width),
+            separate_depth_column: other.separate_depth_column.or(self.separate_depth_column),
+            scale_spacing: other.scale_spacing.or(self.scale_spacing),
+            max_scales: other.max_scales.or(self.max_scales),
+            tick_size_major: other.tick_size_major.or(self.tick_size_major),
+            tick_size_minor: other.tick_size_minor.or(self.tick_size_minor),
+            colors: other.colors.clone().or_else(|| self.colors.clone()),
+        }
+    }
+
+    /// Применяет заданные поля к `layout`
+    pub fn apply(&self, layout: &mut PageLayout) {
+        if let Some(v) = self.html_row_steps {
+            layout.html_row_steps = v;
+        }
+        if let Some(v) = self.pixels_per_step {
+            layout.pixels_per_step = v;
+        }
+        if let Some(v) = self.image_width {
+            layout.image_width = v;
+        }
+        if let Some(v) = self.separate_depth_column {
+            layout.separate_depth_column = v;
+        }
+        if let Some(v) = self.scale_spacing {
+            layout.scale_spacing = v;
+        }
+        if let Some(v) = self.max_scales {
+            layout.max_scales = v;
+        }
+        if let Some(v) = self.tick_size_major {
+            layout.tick_size_major = v;
+        }
+        if let Some(v) = self.tick_size_minor {
+            layout.tick_size_minor = v;
+        }
+    }
+}
+
+/// Итоговые параметры отображения для запроса: конфигурация, затем пресет `preset=`,
+/// затем отдельные параметры запроса. Возвращает геометрию и палитру.
+pub fn layout_for_request(config: &Config, params: &HashMap<String, String>) -> Result<(PageLayout, Vec<String>)> {
+    let request = LayoutOverrides::from_params(params)?;
+    let overrides = match params.get("preset").map(|s| s.trim()).filter(|s| !s.is_empty()) {
+        Some(name) => {
+            let preset = config.presets.get(name).ok_or_else(|| {
+                let known: Vec<&str> = config.presets.keys().map(|k| k.as_str()).collect();
+                if known.is_empty() {
+                    anyhow!("Unknown preset '{}' (no [presets] defined in config)", name)
+                } else {
+                    anyhow!("Unknown preset '{}' (available: {})", name, known.join(", "))
+                }
+            })?;
+            preset.merge(&request)
+        }
+        None => request.clone(),
+    };
+
+    let mut layout = PageLayout::from_config(config);
+    overrides.apply(&mut layout);
+    let colors = overrides.colors.unwrap_or_else(|| config.default_colors.clone());
+
+    if request != LayoutOverrides::default() {
+        if layout.tick_size_minor > layout.tick_size_major {
+            bail!(
+                "tick_size_minor = {} must not exceed tick_size_major = {}",
+                layout.tick_size_minor,
+                layout.tick_size_major
+            );
+        }
+        if layout.block_height() > REQUEST_MAX_BLOCK_HEIGHT {
+            bail!(
+                "Row image is too tall: (html_row_steps + 1) * pixels_per_step = {} exceeds {}",
+                layout.block_height(),
+                REQUEST_MAX_BLOCK_HEIGHT
+            );
+        }
+    }
+
+    Ok((layout, colors))
+}
+
+/// Данные LAS файла, подготовленные для отрисовки
+pub struct LogView {
+    /// (мнемоника, единица, описание) для всех кривых
+    pub curves_info: Vec<(String, String, String)>,
+    pub curves_stats: Vec<Option<(f64, f64)>>,
+    /// Семейство каждой кривой (None - не распознана или словарь не задан)
+    pub curves_family: Vec<Option<CurveFamily>>,
+    /// Отображаемые кривые (без основного параметра и пустых кривых)
+    pub curves_data: Vec<CurveData>,
+    pub x_ranges: Vec<(f64, f64)>,
+    pub colors: Vec<RGBColor>,
+    /// Колонка и тип шкалы каждой отображаемой кривой
+    pub tracks: Vec<usize>,
+    pub track_count: usize,
+    pub scale_types: Vec<ScaleType>,
+    /// Стиль линии каждой отображаемой кривой (все сплошные, штриховые - у сравнения)
+    pub line_styles: Vec<LineStyle>,
+    /// Значения основного параметра по возрастанию (строки без значения отброшены);
+    /// `curves_data` упорядочены так же
+    pub depth_data: Vec<Option<f64>>,
+    pub depth_min: f64,
+    pub depth_max: f64,
+    /// Типичный шаг основного параметра, задаёт масштаб по вертикали
+    pub depth_step: f64,
+    pub main_param_idx: usize,
+    /// Индекс кривой -> hex цвет (для таблицы кривых)
+    pub curve_to_color: HashMap<usize, String>,
+    /// (заголовок, значение) из секции ~Well
+    pub well_info: Vec<(String, String)>,
+    /// Кровли по глубине (в единицах основного параметра); только если основной параметр - индекс файла
+    pub tops: Vec<PlotTop>,
+    /// Отчёт по зонам для раздела над графиками
+    pub zone_report: Option<ZoneReport>,
+    /// Итоги сравнения двух файлов (`/compare`) для раздела над графиками
+    pub comparison: Option<ComparisonReport>,
+    /// Строки диаграммы для `html_row_steps` (см. [`LogView::rows`]), считаются при первом обращении
+    rows: OnceLock<(usize, Vec<usize>)>,
+}
+
+/// Линия кривой прерывается, если соседние отсчёты дальше друг от друга, чем столько шагов
+const GAP_STEPS: f64 = 3.0;
+
+/// Логарифмическая шкала охватывает не больше стольких декад вниз от максимума
+const LOG_SCALE_DECADES: i32 = 4;
+
+/// Цвет кривой, для которой не хватило палитры: псевдослучайный по индексу и мнемонике
+fn generated_color(idx: usize, mnemonic: &str) -> String {
+    let mut hasher = DefaultHasher::new();
+    (idx, mnemonic).hash(&mut hasher);
+    let rng_seed = hasher.finish();
+
+    // Простой генератор псевдослучайных чисел
+    let r = (rng_seed & 0xFF) as u8;
+    let g = ((rng_seed >> 8) & 0xFF) as u8;
+    let b = ((rng_seed >> 16) & 0xFF) as u8;
+
+    // Убеждаемся, что цвет не слишком темный
+    let r = r.max(50);
+    let g = g.max(50);
+    let b = b.max(50);
+
+    format!("{:02X}{:02X}{:02X}", r, g, b)
+}
+
+impl LogView {
+    /// `main_param` - мнемоника основного параметра (по умолчанию первая кривая),
+    /// `colors` - палитра в hex; недостающие цвета генерируются.
+    /// Семейства кривых определяются по встроенному словарю синонимов.
+    pub fn new(las_file: &LasFile, main_param: Option<&str>, colors: &[String]) -> Result<Self> {
+        Self::with_aliases(las_file, main_param, colors, Some(&AliasDictionary::builtin()))
+    }
+
+    /// Как [`LogView::new`], но со своим словарём синонимов; `None` - без семейств:
+    /// все кривые в одной колонке с линейными шкалами и цветами из палитры.
+    /// Распознанные кривые раскладываются по колонкам своих семейств, первая кривая
+    /// семейства получает его цвет, остальные - цвета палитры по порядку.
+    pub fn with_aliases(
+        las_file: &LasFile,
+        main_param: Option<&str>,
+        colors: &[String],
+        aliases: Option<&AliasDictionary>,
+    ) -> Result<Self> {
+        // Определяем основной параметр: из параметра или первый параметр из LAS
+        let main_param_name = main_param.unwrap_or_else(|| {
+            las_file.curves.first()
+                .map(|c| c.mnemonic.as_str())
+                .unwrap_or("DEPT")
+        });
+
+        // Находим индекс основного параметра
+        let main_param_idx = las_file
+            .get_main_parameter_index(main_param_name)
+            .ok_or_else(|| anyhow!("Main parameter '{}' not found", main_param_name))?;
+
+        // Неиндексный основной параметр может идти немонотонно (TVD на участке, где ствол
+        // поднимается): такие участки усредняются на регулярной сетке, иначе ветви перемешаются
+        let resampled;
+        let las_file = match las_file.non_monotonic_step(main_param_idx) {
+            Some(step) if main_param_idx != 0 => {
+                resampled = las_file.resample_step(main_param_idx, step, ResampleMethod::Average, None)?;
+                &resampled
+            }
+            _ => las_file,
+        };
+
+        // Отсчёты упорядочиваются по основному параметру (STEP < 0, неупорядоченные записи);
+        // строки без значения основного параметра на диаграмме не показать
+        let raw_depth = las_file.get_curve_data(main_param_idx);
+        let mut order: Vec<usize> = (0..raw_depth.len())
+            .filter(|&i| raw_depth[i].is_some_and(f64::is_finite))
+            .collect();
+        order.sort_by(|&a, &b| raw_depth[a].unwrap_or(0.0).total_cmp(&raw_depth[b].unwrap_or(0.0)));
+        let depth_data: Vec<Option<f64>> = order.iter().map(|&i| raw_depth[i]).collect();
+
+        // Находим диапазон глубины
+        let (depth_min, depth_max) = match (depth_data.first(), depth_data.last()) {
+            (Some(&Some(min)), Some(&Some(max))) => (min, max),
+            _ => return Err(anyhow!("No depth data")),
+        };
+        let sorted_depth: Vec<f64> = depth_data.iter().flatten().copied().collect();
+        let depth_step = median_step(&sorted_depth).unwrap_or(1.0);
+
+        let curves_family: Vec<Option<CurveFamily>> = las_file
+            .curves
+            .iter()
+            .map(|curve| aliases.and_then(|aliases| aliases.classify(curve)))
+            .collect();
+
+        // Подготавливаем данные кривых (исключаем основной параметр)
+        let mut curves_data = Vec::new();
+        let mut x_ranges = Vec::new();
+        let mut curve_tracks = Vec::new();
+        let mut scale_types = Vec::new();
+        // Индексы отображаемых кривых в LAS файле
+        let mut displayed = Vec::new();
+
+        for (idx, curve) in las_file.curves.iter().enumerate() {
+            if idx == main_param_idx {
+                continue; // Пропускаем основной параметр
+            }
+
+            let Some((min, max)) = las_file.get_curve_stats(idx) else {
+                continue;
+            };
+            let raw_data = las_file.get_curve_data(idx);
+            let family = curves_family[idx];
+
+            // Логарифмическая шкала - по положительным значениям, иначе линейная
+            let mut scale_type = family.map_or(ScaleType::Linear, |f| f.scale_type());
+            let mut range = (min, max);
+            if scale_type == ScaleType::Logarithmic {
+                let min_positive = raw_data.iter().flatten().copied().filter(|v| *v > 0.0).fold(f64::INFINITY, f64::min);
+                if max > 0.0 && min_positive.is_finite() {
+                    range = (min_positive.max(max / 10_f64.powi(LOG_SCALE_DECADES)), max);
+                } else {
+                    scale_type = ScaleType::Linear;
+                }
+            }
+            if family.is_some_and(|f| f.reversed()) {
+                range = (range.1, range.0);
+            }
+
+            let curve_data = order.iter().map(|&i| raw_data.get(i).copied().flatten()).collect();
+            curves_data.push((curve_data, curve.mnemonic.clone()));
+            x_ranges.push(range);
+            scale_types.push(scale_type);
+            curve_tracks.push(match (aliases, family) {
+                (None, _) => Track::Correlation,
+                (Some(_), Some(family)) => family.track(),
+                (Some(_), None) => Track::Other,
+            });
+
+            displayed.push(idx);
+        }
+
+        if curves_data.is_empty() {
+            return Err(anyhow!("No curves to plot"));
+        }
+
+        // Цвета: первая кривая семейства - цвет семейства, остальные - палитра по порядку
+        // без цветов, уже занятых семействами
+        let mut colored_families = HashSet::new();
+        let family_colored: Vec<bool> = displayed
+            .iter()
+            .map(|&idx| curves_family[idx].is_some_and(|family| colored_families.insert(family)))
+            .collect();
+        let mut taken: HashSet<RGBColor> = displayed
+            .iter()
+            .zip(&family_colored)
+            .filter(|(_, colored)| **colored)
+            .filter_map(|(&idx, _)| curves_family[idx].map(|family| hex_to_rgb(family.color())))
+            .collect();
+        let mut palette = colors.iter();
+        let mut plot_colors = Vec::new();
+        // Маппинг: индекс кривой -> hex цвет (для таблицы кривых)
+        let mut curve_to_color = HashMap::new();
+        for (&idx, colored) in displayed.iter().zip(family_colored) {
+            let hex_color = match curves_family[idx] {
+                Some(family) if colored => family.color().to_string(),
+                _ => palette
+                    .find(|color| !taken.contains(&hex_to_rgb(color)))
+                    .cloned()
+                    .unwrap_or_else(|| generated_color(idx, &las_file.curves[idx].mnemonic)),
+            };
+            taken.insert(hex_to_rgb(&hex_color));
+            plot_colors.push(hex_to_rgb(&hex_color));
+            curve_to_color.insert(idx, hex_color);
+        }
+
+        // Пустые колонки не показываются
+        let mut used_tracks = curve_tracks.clone();
+        used_tracks.sort();
+        used_tracks.dedup();
+        let tracks: Vec<usize> = curve_tracks
+            .iter()
+            .map(|track| used_tracks.iter().position(|t| t == track).unwrap_or(0))
+            .collect();
+
+        let curves_info: Vec<_> = las_file
+            .curves
+            .iter()
+            .map(|c| (c.mnemonic.clone(), c.unit.clone(), c.description.clone()))
+            .collect();
+        let curves_stats: Vec<_> = (0..las_file.curves.len())
+            .map(|i| las_file.get_curve_stats(i))
+            .collect();
+
+        // Извлекаем информацию из секции ~Well
+        // Формат: (ключ, описание) - описание используется как заголовок
+        let well_info_keys = [
+            ("COMP", "COMPANY"),
+            ("WELL", "WELL"),
+            ("FLD", "FIELD"),
+            ("LOC", "LOCATION"),
+            ("SRVC", "SERVICE COMPANY"),
+            ("DATE", "LOG DATE"),
+            ("PROV", "PROVINCE"),
+        ];
+
+        let well_info: Vec<(String, String)> = well_info_keys
+            .iter()
+            .filter_map(|(key, description)| {
+                // Пробуем найти ключ с точкой и без точки
+                las_file.well_info.get(*key)
+                    .or_else(|| las_file.well_info.get(&format!("{}.", key)))
+                    .map(|value| (description.to_string(), value.clone()))
+            })
+            .collect();
+
+        // Кровли привязаны к индексу файла (первой кривой)
+        let tops = if main_param_idx == 0 {
+            let index_unit = &las_file.curves[0].unit;
+            las_file
+                .tops
+                .iter()
+                .map(|top| PlotTop {
+                    depth: top.depth_in(index_unit),
+                    label: top.name.clone(),
+                    color: hex_to_rgb(&top_color(&top.name, &BTreeMap::new())),
+                })
+                .collect()
+        } else {
+            Vec::new()
+        };
+
+        Ok(LogView {
+            curves_info,
+            curves_stats,
+            curves_family,
+            curves_data,
+            x_ranges,
+            colors: plot_colors,
+            tracks,
+            track_count: used_tracks.len(),
+            line_styles: vec![LineStyle::Solid; scale_types.len()],
+            scale_types,
+            depth_data,
+            depth_min,
+            depth_max,
+            depth_step,
+            main_param_idx,
+            curve_to_color,
+            well_info,
+            tops,
+            zone_report: None,
+            comparison: None,
+            rows: OnceLock::new(),
+        })
+    }
+
+    /// Цвета кровель из `[tops_colors]` (по названию пласта); остальные - из палитры по названию
+    pub fn with_top_colors(mut self, colors: &BTreeMap<String, String>) -> Self {
+        for top in &mut self.tops {
+            top.color = hex_to_rgb(&top_color(&top.label, colors));
+        }
+        self
+    }
+
+    /// Интервал основного параметра на одну строку: `html_row_steps` типичных шагов
+    fn row_span(&self, layout: &PageLayout) -> f64 {
+        layout.row_steps() as f64 * self.depth_step
+    }
+
+    /// Номер интервала длиной `row_span` от depth_min, в который попадает `depth`;
+    /// значение на нижней границе последнего интервала относится к нему
+    fn cell_of(&self, layout: &PageLayout, depth: f64) -> usize {
+        let span = self.row_span(layout);
+        let last = (((self.depth_max - self.depth_min) / span - 1e-9).ceil() as usize).max(1) - 1;
+        (((depth - self.depth_min) / span).floor().max(0.0) as usize).min(last)
+    }
+
+    /// Интервалы (см. [`LogView::cell_of`]), в которых есть отсчёты, по возрастанию
+    pub(crate) fn occupied_cells(&self, layout: &PageLayout) -> Vec<usize> {
+        let mut cells: Vec<usize> = self.depth_data.iter().flatten().map(|&d| self.cell_of(layout, d)).collect();
+        cells.dedup();
+        cells
+    }
+
+    /// Строки из занятых интервалов: пропуск без отсчётов любой длины (скажем, от случайного
+    /// значения глубины далеко за концом записи) сворачивается в одну пустую строку
+    pub(crate) fn rows_from_cells(cells: &[usize]) -> Vec<usize> {
+        let mut rows = Vec::with_capacity(cells.len());
+        for &cell in cells {
+            if rows.last().is_some_and(|&last| cell > last + 1) {
+                rows.push(rows[rows.len() - 1] + 1);
+            }
+            rows.push(cell);
+        }
+        if rows.is_empty() {
+            rows.push(0);
+        }
+        rows
+    }
+
+    /// Задаёт строки снаружи - у скважин корреляции они общие
+    pub(crate) fn share_rows(&self, layout: &PageLayout, rows: Vec<usize>) {
+        // Строки уже посчитаны - значит, вид уже рисуется, менять их поздно
+        let _ = self.rows.set((layout.row_steps(), rows));
+    }
+
+    /// Номера интервалов, показываемых строками диаграммы
+    fn rows(&self, layout: &PageLayout) -> Cow<'_, [usize]> {
+        let compute = || Self::rows_from_cells(&self.occupied_cells(layout));
+        let (row_steps, rows) = self.rows.get_or_init(|| (layout.row_steps(), compute()));
+        if *row_steps == layout.row_steps() {
+            Cow::Borrowed(rows)
+        } else {
+            Cow::Owned(compute())
+        }
+    }
+
+    /// Значение основного параметра у верхнего края строки `row_idx`
+    fn row_top(&self, layout: &PageLayout, row_idx: usize) -> f64 {
+        let cell = self.rows(layout).get(row_idx).copied().unwrap_or_default();
+        self.depth_min + cell as f64 * self.row_span(layout)
+    }
+
+    /// Количество строк с графиками: строки делят диапазон основного параметра на равные интервалы,
+    /// интервалы без отсчётов подряд показываются одной строкой
+    pub fn row_count(&self, layout: &PageLayout) -> usize {
+        self.rows(layout).len()
+    }
+
+    /// Раздел со статистикой по зонам (см. [`ZoneReport`])
+    pub fn with_zone_report(mut self, report: ZoneReport) -> Self {
+        self.zone_report = Some(report);
+        self
+    }
+
+    /// Раздел с итогами сравнения файлов (см. [`crate::compare`])
+    pub fn with_comparison(mut self, report: ComparisonReport) -> Self {
+        self.comparison = Some(report);
+        self
+    }
+
+    /// Строка, в которую попадает значение основного параметра `depth` (None - вне диапазона)
+    pub fn row_of(&self, layout: &PageLayout, depth: f64) -> Option<usize> {
+        if !(self.depth_min..=self.depth_max).contains(&depth) {
+            return None;
+        }
+        let cell = self.cell_of(layout, depth);
+        Some(self.rows(layout).partition_point(|&c| c <= cell).max(1) - 1)
+    }
+
+    /// Диапазон индексов данных строки `row_idx` и значение основного параметра у её верхнего края.
+    /// Строка захватывает ещё один шаг следующей строки, чтобы линии не разрывались между строками.
+    pub fn row_bounds(&self, layout: &PageLayout, row_idx: usize) -> (usize, usize, f64) {
+        let top = self.row_top(layout, row_idx);
+        let bottom = top + self.row_span(layout) + self.depth_step;
+        // Допуск на погрешность вычисления границ
+        let eps = self.depth_step * 1e-6;
+
+        let depth = |d: &Option<f64>| d.unwrap_or(f64::NEG_INFINITY);
+        let start = self.depth_data.partition_point(|d| depth(d) < top - eps);
+        let end = self.depth_data.partition_point(|d| depth(d) <= bottom + eps);
+
+        (start, end, top)
+    }
+
+    pub(crate) fn row_plot_config(&self, layout: &PageLayout, row_idx: usize) -> PlotConfig {
+        // Высота картинки block_height соответствует row_steps + 1 шагам
+        let top = self.row_top(layout, row_idx);
+        let bottom = top + (layout.row_steps() + 1) as f64 * self.depth_step;
+        PlotConfig {
+            width: layout.image_width as u32,
+            height: layout.block_height() as u32,
+            colors: self.colors.clone(),
+            x_ranges: self.x_ranges.clone(),
+            tracks: self.tracks.clone(),
+            track_count: self.track_count,
+            scale_types: self.scale_types.clone(),
+            line_styles: self.line_styles.clone(),
+            y_range: (top, bottom),
+            max_gap: GAP_STEPS * self.depth_step,
+            show_scales: false,
+            scale_spacing: layout.scale_spacing,
+            tick_size_major: layout.tick_size_major,
+            tick_size_minor: layout.tick_size_minor,
+            max_scales: layout.max_scales,
+            tops: self.tops.iter().filter(|t| (top..=bottom).contains(&t.depth)).cloned().collect(),
+        }
+    }
+
+    /// Картинка строки `row_idx` (высота - `layout.block_height()`)
+    pub fn render_row(&self, layout: &PageLayout, row_idx: usize) -> Result<RgbaImage> {
+        let (start, end, _) = self.row_bounds(layout, row_idx);
+        render_plot_image(&self.row_plot_config(layout, row_idx), &self.curves_data, &self.depth_data, start, end)
+    }
+
+    /// Высота картинки со шкалами
+    pub fn scale_height(&self, layout: &PageLayout) -> usize {
+        // Реальное количество отображаемых шкал: шкалы колонок идут рядом, высота - по самой длинной
+        let mut scales_in_track = vec![0; self.track_count.max(1)];
+        for &track in self.tracks.iter().take(layout.max_scales) {
+            if let Some(count) = scales_in_track.get_mut(track) {
+                *count += 1;
+            }
+        }
+        let actual_scales_count = scales_in_track.into_iter().max().unwrap_or(0);
+
+        // Первая шкала начинается с отступом scale_spacing сверху
+        // Последняя шкала находится на позиции: actual_scales_count * scale_spacing
+        // Нижняя точка засечки последней шкалы: actual_scales_count * scale_spacing + tick_size_major
+        // Добавляем такой же отступ снизу (scale_spacing) для симметрии
+        if actual_scales_count > 0 {
+            (actual_scales_count * layout.scale_spacing) + layout.tick_size_major + layout.scale_spacing
+        } else {
+            layout.scale_spacing * 2
+        }
+    }
+
+    /// Картинка с горизонтальными шкалами первых `max_scales` кривых
+    pub fn render_scale(&self, layout: &PageLayout) -> Result<RgbaImage> {
+        // Ограничиваем количество кривых для шкалы до max_scales
+        let scale_curves_data: Vec<_> = self.curves_data.iter().take(layout.max_scales).cloned().collect();
+        let scale_config = PlotConfig {
+            height: self.scale_height(layout) as u32,
+            colors: self.colors.iter().take(layout.max_scales).cloned().collect(),
+            x_ranges: self.x_ranges.iter().take(layout.max_scales).cloned().collect(),
+            tracks: self.tracks.iter().take(layout.max_scales).cloned().collect(),
+            scale_types: self.scale_types.iter().take(layout.max_scales).cloned().collect(),
+            show_scales: true,
+            tops: Vec::new(),
+            ..self.row_plot_config(layout, 0)
+        };
+
+        render_plot_image(
+            &scale_config,
+            &scale_curves_data,
+            &self.depth_data,
+            0,
+            layout.row_steps().min(self.depth_data.len()),
+        )
+    }
+}
+
+fn generate_html_row(
+    view: &LogView,
+    layout: &PageLayout,
+    row_idx: usize,
+) -> Result<String> {
+    let (start_block_value, end_block_value, start_depth) = view.row_bounds(layout, row_idx);
+
+    let png_data = generate_plot_png(
+        &view.row_plot_config(layout, row_idx),
+        &view.curves_data,
+        &view.depth_data,
+        start_block_value,
+        end_block_value,
+    )?;
+
+    let base64_img = base64::engine::general_purpose::STANDARD.encode(&png_data);
+
+    // Высота изображения равна высоте строки (block_height)
+    // независимо от количества шагов в этом блоке
+    let row_height = layout.block_height();
+    let image_width = layout.image_width;
+    let image_height = row_height;
+
+    // id строки - цель ссылок из таблицы кровель
+    let row_html = if layout.separate_depth_column {
+        format!(
+            "<tr id='row-{}' height='{}' style='vertical-align: top; margin: 0; padding: 0;'><td valign='top' style='padding: 0; margin: 0; border: 1px solid #ccc;'>{:.2}</td><td style='padding: 0; margin: 0; border: 1px solid #ccc; vertical-align: top;'><img src='data:image/png;base64,{}' alt='Plot' width='{}' height='{}' style='display: block; margin: 0; padding: 0;'></td></tr>\n",
+            row_idx, row_height, start_depth, base64_img, image_width, image_height
+        )
+    } else {
+        format!(
+            "<tr id='row-{}' height='{}' style='vertical-align: top; margin: 0; padding: 0;'><td style='padding: 0; margin: 0; border: 1px solid #ccc; vertical-align: top;'><div style='position:relative; margin: 0; padding: 0;'><div style='position:absolute;left:5px;top:5px'>{:.2}</div><img src='data:image/png;base64,{}' alt='Plot' width='{}' height='{}' style='display: block; margin: 0; padding: 0;'></div></td></tr>\n",
+            row_idx, row_height, start_depth, base64_img, image_width, image_height
+        )
+    };
+
+    Ok(row_html)
+}
+
+/// Строка таблицы с картинкой шкал
+fn scale_row_html(view: &LogView, layout: &PageLayout) -> Result<String> {
+    let scale_height = view.scale_height(layout);
+    let scale_png = crate::plot::encode_png(&view.render_scale(layout)?)?;
+    let scale_base64 = base64::engine::general_purpose::STANDARD.encode(&scale_png);
+    let image_width = layout.image_width;
+    let html_scale_row = if layout.separate_depth_column {
+        format!(
+            "<tr height='{}' style='vertical-align: top; margin: 0; padding: 0;'><td style='padding: 0; margin: 0; border: 1px solid #ccc;'></td><td style='padding: 0; margin: 0; border: 1px solid #ccc; vertical-align: top;'><img src='data:image/png;base64,{}' alt='Scales' width='{}' height='{}' style='display: block; margin: 0; padding: 0;'></td></tr>\n",
+            scale_height, scale_base64, image_width, scale_height
+        )
+    } else {
+        format!(
+            "<tr height='{}' style='vertical-align: top; margin: 0; padding: 0;'><td style='padding: 0; margin: 0; border: 1px solid #ccc; vertical-align: top;'><img src='data:image/png;base64,{}' alt='Scales' width='{}' height='{}' style='display: block; margin: 0; padding: 0;'></td></tr>\n",
+            scale_height, scale_base64, image_width, scale_height
+        )
+    };
+    Ok(html_scale_row)
+}
+
+/// Таблица кривых (цвет, мнемоника, семейство, единица, описание, min, max)
+fn curves_table_html(view: &LogView) -> String {
+    let mut curves_table_html = String::new();
+    curves_table_html.push_str("<table border='1' cellpadding='5' style='border-collapse: collapse; border: 1px solid #ccc; font-family: monospace;'>\n");
+    curves_table_html.push_str("<style>table th, table td { border: 1px solid #ccc; }</style>\n");
+    curves_table_html.push_str("<tr><th>Color</th><th>Mnemonic</th><th>Family</th><th>Measure</th><th>Description</th><th>min</th><th>max</th></tr>\n");
+
+    for (idx, (mnemonic, unit, description)) in view.curves_info.iter().enumerate() {
+        if let Some((min, max)) = view.curves_stats.get(idx).and_then(|s| *s) {
+            // Определяем цвет для этой кривой
+            let color_cell = if idx == view.main_param_idx {
+                // Для основного параметра - пустая ячейка
+                "<td></td>".to_string()
+            } else if let Some(hex_color) = view.curve_to_color.get(&idx) {
+                // Цвет мог прийти из параметра colors - нормализуем его до шести hex-цифр
+                let [r, g, b] = hex_to_rgb(hex_color);
+                let hex_color = format!("{:02X}{:02X}{:02X}", r, g, b);
+                format!(
+                    "<td style='background-color: #{}; color: black; text-align: center; font-weight: bold;'>{}</td>",
+                    hex_color, hex_color
+                )
+            } else {
+                // Если цвет не найден - пустая ячейка (не должно происходить)
+                "<td></td>".to_string()
+            };
+
+            let family = view.curves_family.get(idx).copied().flatten().map_or("", |f| f.name());
+            curves_table_html.push_str(&format!(
+                "<tr>{}<td>{}</td><td>{}</td><td>{}</td><td>{}</td><td style='text-align: right;'>{:.2}</td><td style='text-align: right;'>{:.2}</td></tr>\n",
+                color_cell, escape_html(mnemonic), family, escape_html(unit), escape_html(description), min, max
+            ));
+        }
+    }
+    curves_table_html.push_str("</table>\n");
+    curves_table_html
+}
+
+/// Таблица кровель (цвет, название, глубина); глубина - ссылка на строку с кровлей
+fn tops_table_html(view: &LogView, layout: &PageLayout) -> String {
+    let mut tops_table_html = String::new();
+    if view.tops.is_empty() {
+        return tops_table_html;
+    }
+    tops_table_html.push_str("<table border='1' cellpadding='5' style='border-collapse: collapse; border: 1px solid #ccc; font-family: monospace;'>\n");
+    tops_table_html.push_str("<tr><th></th><th>Top</th><th>Depth</th></tr>\n");
+    for top in &view.tops {
+        let [r, g, b] = top.color;
+        let depth = match view.row_of(layout, top.depth) {
+            Some(row_idx) => format!("<a href='#row-{}'>{:.2}</a>", row_idx, top.depth),
+            None => format!("{:.2}", top.depth),
+        };
+        tops_table_html.push_str(&format!(
+            "<tr><td style='background-color: #{:02X}{:02X}{:02X}; width: 1em;'></td><td>{}</td><td style='text-align: right;'>{}</td></tr>\n",
+            r, g, b, escape_html(&top.label), depth
+        ));
+    }
+    tops_table_html.push_str("</table>\n");
+    tops_table_html
+}
+
+/// Число в ячейке отчёта; пропуск - пустая ячейка
+fn report_cell(value: Option<f64>) -> String {
+    match value {
+        Some(value) => format!("<td style='text-align: right;'>{:.3}</td>", value),
+        None => "<td></td>".to_string(),
+    }
+}
+
+/// Раздел отчёта по зонам: эффективные толщины по зонам и (в раскрывающемся блоке)
+/// статистика кривых; название зоны - ссылка на строку с её кровлей
+fn zone_report_html(view: &LogView, layout: &PageLayout) -> String {
+    let Some(report) = &view.zone_report else {
+        return String::new();
+    };
+    let table_start = "<table border='1' cellpadding='5' style='border-collapse: collapse; border: 1px solid #ccc; font-family: monospace;'>\n";
+    let zone_link = |zone: &crate::stats::Zone| match view.row_of(layout, zone.top) {
+        Some(row_idx) => format!("<a href='#row-{}'>{}</a>", row_idx, escape_html(&zone.name)),
+        None => escape_html(&zone.name),
+    };
+
+    let mut html = String::from("<h3>Zones</h3>\n");
+    let cutoffs: Vec<String> = [(&report.vsh, "&lt;"), (&report.phi, "&gt;"), (&report.sw, "&lt;")]
+        .into_iter()
+        .filter_map(|(cutoff, op)| cutoff.as_ref().map(|c| format!("{} {} {}", escape_html(&c.mnemonic), op, c.limit)))
+        .collect();
+    if !cutoffs.is_empty() {
+        html.push_str(&format!("<p>Cutoffs: {}</p>\n", cutoffs.join(", ")));
+    }
+
+    html.push_str(table_start);
+    html.push_str(&format!(
+        "<tr><th>Zone</th><th>Top</th><th>Bottom</th><th>Gross, {unit}</th><th>Net, {unit}</th><th>Pay, {unit}</th><th>N/G</th><th>Pay/G</th><th>Avg Vsh</th><th>Avg PHI</th><th>Avg Sw</th></tr>\n",
+        unit = escape_html(&report.index_unit)
+    ));
+    for zone in &report.zones {
+        let net = zone.net.as_ref();
+        html.push_str(&format!(
+            "<tr><td>{}</td>{}{}{}{}{}{}{}{}{}{}</tr>\n",
+            zone_link(&zone.zone),
+            report_cell(Some(zone.zone.top)),
+            report_cell(Some(zone.zone.bottom)),
+            report_cell(Some(zone.zone.thickness())),
+            report_cell(net.map(|n| n.net_reservoir)),
+            report_cell(net.and_then(|n| n.net_pay)),
+            report_cell(net.and_then(|n| n.net_to_gross())),
+            report_cell(net.and_then(|n| n.pay_to_gross())),
+            report_cell(net.and_then(|n| n.avg_vsh)),
+            report_cell(net.and_then(|n| n.avg_phi)),
+            report_cell(net.and_then(|n| n.avg_sw)),
+        ));
+    }
+    html.push_str("</table>\n");
+
+    html.push_str("<details><summary>Curve statistics by zone</summary>\n");
+    html.push_str(table_start);
+    html.push_str("<tr><th>Zone</th><th>Curve</th><th>Measure</th><th>Count</th><th>Nulls</th><th>Mean</th><th>Median</th><th>P10</th><th>P50</th><th>P90</th><th>Std dev</th><th>min</th><th>max</th></tr>\n");
+    for zone in &report.zones {
+        for curve in &zone.curves {
+            html.push_str(&format!(
+                "<tr><td>{}</td><td>{}</td><td>{}</td><td style='text-align: right;'>{}</td>{}{}{}{}{}{}{}{}{}</tr>\n",
+                escape_html(&zone.zone.name),
+                escape_html(&curve.mnemonic),
+                escape_html(&curve.unit),
+                curve.count,
+                report_cell(curve.null_fraction()),
+                report_cell(curve.mean),
+                report_cell(curve.median),
+                report_cell(curve.p10),
+                report_cell(curve.p50),
+                report_cell(curve.p90),
+                report_cell(curve.std_dev),
+                report_cell(curve.min),
+                report_cell(curve.max),
+            ));
+        }
+    }
+    html.push_str("</table>\n</details>\n");
+    html
+}
+
+/// Раздел сравнения: расхождения кривых B с A и различия заголовков
+fn comparison_html(view: &LogView) -> String {
+    let Some(report) = &view.comparison else {
+        return String::new();
+    };
+    let table_start = "<table border='1' cellpadding='5' style='border-collapse: collapse; border: 1px solid #ccc; font-family: monospace;'>\n";
+
+    let mut html = format!(
+        "<h3>Comparison</h3>\n<p>A: {}<br>B: {} (dashed), depth shift {} {}</p>\n",
+        escape_html(&report.a_name),
+        escape_html(&report.b_name),
+        report.shift,
+        escape_html(&report.index_unit)
+    );
+    html.push_str(table_start);
+    html.push_str("<tr><th>Curve</th><th>Measure</th><th>Samples</th><th>Bias B-A</th><th>RMS diff</th><th>Max |diff|</th><th>Correlation</th></tr>\n");
+    for curve in &report.curves {
+        html.push_str(&format!(
+            "<tr><td>{}</td><td>{}</td><td style='text-align: right;'>{}</td>{}{}{}{}</tr>\n",
+            escape_html(&curve.mnemonic),
+            escape_html(&curve.unit),
+            curve.count,
+            report_cell(curve.bias),
+            report_cell(curve.rms),
+            report_cell(curve.max_abs),
+            report_cell(curve.correlation),
+        ));
+    }
+    html.push_str("</table>\n");
+
+    if report.header.is_empty() {
+        html.push_str("<p>No header differences</p>\n");
+        return html;
+    }
+    html.push_str("<h4>Header differences</h4>\n");
+    html.push_str(table_start);
+    html.push_str("<tr><th>Section</th><th>Mnemonic</th><th>A</th><th>B</th></tr>\n");
+    // Нет строки или кривой в файле - прочерк
+    let value = |v: &Option<String>| v.as_deref().map_or_else(|| "&mdash;".to_string(), escape_html);
+    for difference in &report.header {
+        html.push_str(&format!(
+            "<tr><td>{}</td><td>{}</td><td>{}</td><td>{}</td></tr>\n",
+            difference.section,
+            escape_html(&difference.mnemonic),
+            value(&difference.a),
+            value(&difference.b)
+        ));
+    }
+    html.push_str("</table>\n");
+    html
+}
+
+/// Информация из секции ~Well в таблице с 2 колонками
+fn well_table_html(view: &LogView) -> String {
+    let mut well_table_html = String::new();
+    if !view.well_info.is_empty() {
+        well_table_html.push_str("<table style='border: none; border-style: none; border-collapse: collapse; font-family: monospace; border-spacing: 0;'>\n");
+        for (key, value) in &view.well_info {
+            // Разделяем на часть до двоеточия и после
+            if let Some(colon_pos) = value.find(':') {
+                let before_colon = &value[..colon_pos].trim(); // Без двоеточия
+                let after_colon = &value[colon_pos + 1..].trim();
+                well_table_html.push_str(&format!(
+                    "<tr style='border: none'><td style='text-align: right; padding-right: 5px; border: none'>{}{}</td><td style='text-align: left; padding-left: 5px; border: none'>{}</td></tr>\n",
+                    escape_html(before_colon), ":", escape_html(after_colon)
+                ));
+            } else {
+                // Если нет двоеточия, выводим ключ в первой колонке с двоеточием, значение во второй
+                well_table_html.push_str(&format!(
+                    "<tr style='border: none'><td style='text-align: right; padding-right: 5px; border: none'>{}{}</td><td style='text-align: left; padding-left: 5px; border: none'>{}</td></tr>\n",
+                    escape_html(key), ":", escape_html(value)
+                ));
+            }
+        }
+        well_table_html.push_str("</table>\n");
+    }
+    well_table_html
+}
+
+/// HTML страница с графиками, отдаваемая потоком: заголовок сразу, затем шкала
+/// и строки таблицы по мере отрисовки картинок в `pool`.
+pub fn generate_html(
+    view: LogView,
+    layout: PageLayout,
+    file_name: &str,
+    pool: &RenderPool,
+) -> Result<impl Stream<Item = Result<Bytes>> + 'static> {
+    let view = Arc::new(view);
+    let layout = Arc::new(layout);
+    let num_rows = view.row_count(&layout);
+    let rows_in_flight = pool.threads();
+
+    // HTML над строки таблицы со шкалой
+    let mut html_before_scale = String::new();
+    let file_name = escape_html(file_name);
+    html_before_scale.push_str(&format!("<html><head><meta charset='utf-8'><title>LAS Plot - {}</title></head><body>\n", file_name));
+    html_before_scale.push_str(&format!("<h2>LAS Plot - {}</h2>\n", file_name));
+    html_before_scale.push_str("<p style='font-size: 0.9em; color: #666; margin-top: 5px; margin-bottom: 15px;'>Free for non-commercial use. License: <a href='https://github.com/shestero/lasplot/blob/main/LICENSE' target='_blank'>https://github.com/shestero/lasplot/blob/main/LICENSE</a></p>\n");
+
+    // Начинаем таблицу с графиками
+    html_before_scale.push_str("<table border='0' cellspacing='0' cellpadding='0' style='border-collapse: collapse; border-spacing: 0; margin: 0; padding: 0; font-family: monospace;'>\n");
+
+    // Первая строка с объединённой ячейкой для верхних таблиц
+    let colspan = if layout.separate_depth_column { 2 } else { 1 };
+    html_before_scale.push_str(&format!(
+        "<tr style='border: none; border-width: 0; border-collapse: collapse;'><td colspan='{}' style='padding: 10px 10px 10px 0; border: none; border-width: 0; border-collapse: collapse; vertical-align: top;'><div style='display: flex; gap: 20px; align-items: flex-start;'><div style='vertical-align: top;'>{}</div><div style='vertical-align: top;'>{}</div><div style='vertical-align: top; margin-left: auto; text-align: right;'>{}</div></div></td></tr>\n",
+        colspan, curves_table_html(&view), tops_table_html(&view, &layout), well_table_html(&view)
+    ));
+    if view.zone_report.is_some() {
+        html_before_scale.push_str(&format!(
+            "<tr style='border: none;'><td colspan='{}' style='padding: 0 10px 10px 0; border: none; vertical-align: top;'>{}</td></tr>\n",
+            colspan, zone_report_html(&view, &layout)
+        ));
+    }
+    if view.comparison.is_some() {
+        html_before_scale.push_str(&format!(
+            "<tr style='border: none;'><td colspan='{}' style='padding: 0 10px 10px 0; border: none; vertical-align: top;'>{}</td></tr>\n",
+            colspan, comparison_html(&view)
+        ));
+    }
+
+    // Шкала и строки рисуются в пуле; в очереди не больше pool.threads() строк этого запроса,
+    // следующая ставится, только когда клиент забрал готовую (buffered сохраняет порядок)
+    let scale_row = {
+        let view = view.clone();
+        let layout = layout.clone();
+        pool.run(move || scale_row_html(&view, &layout))
+    };
+
+    let pool = pool.clone();
+    // 1) поток индексов строк
+    let html_plot_rows = stream::iter(0..num_rows)
+        // 2) превращаем каждый индекс в задачу пула
+        .map(move |row_idx| {
+            // Arc clones
+            let view = view.clone();
+            let layout = layout.clone();
+            pool.run(move || generate_html_row(&view, &layout, row_idx))
+        })
+        // 3) параллельность
+        .buffered(rows_in_flight)
+        // 4) String → Bytes
+        .map(|res| res.map(Bytes::from));
+
+    // HTML конца таблицы и документа
+    let html_end = "</table>\n</body></html>\n";
+
+    let before = once(ok::<_, anyhow::Error>(Bytes::from(html_before_scale)));
+    let scale = once(scale_row).map(|res| res.map(Bytes::from));
+    let after = once(ok::<_, anyhow::Error>(Bytes::from(html_end)));
+
+    Ok(before.chain(scale).chain(html_plot_rows).chain(after))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use futures::TryStreamExt;
+
+    /// Заголовок и мнемоники с разметкой и кавычками
+    const HOSTILE_LAS: &str = include_str!("../tests/fixtures/hostile.las");
+
+    fn render_page(title: &str) -> String {
+        let las_file = LasFile::parse(HOSTILE_LAS).unwrap();
+        let view = LogView::new(&las_file, None, &["FF0000".to_string()]).unwrap();
+        let stream = generate_html(view, PageLayout::default(), title, &RenderPool::new(1)).unwrap();
+        let chunks: Vec<_> = futures::executor::block_on(stream.try_collect()).unwrap();
+        String::from_utf8(chunks.concat()).unwrap()
+    }
+
+    #[test]
+    fn depth_gap_collapses_into_one_row() {
+        let mut content = String::from(
+            "~Version Information\n VERS.  2.0:\n~Well Information\n NULL.  -999.25:\n~Curve Information\n DEPT.M  :  DEPTH\n GR.GAPI  :  GAMMA\n~ASCII\n",
+        );
+        for i in 0..=1000 {
+            content.push_str(&format!("{} {}\n", 1000.0 + i as f64 * 0.1, i % 50));
+        }
+        content.push_str("9000000 10\n");
+        let las_file = LasFile::parse(&content).unwrap();
+        let view = LogView::new(&las_file, None, &["FF0000".to_string()]).unwrap();
+        let layout = PageLayout::default();
+        let span = layout.row_steps() as f64 * 0.1;
+
+        let rows = view.row_count(&layout);
+        let regular = (100.0 / span).ceil() as usize;
+        assert!(rows <= regular + 3, "{} rows", rows);
+        assert_eq!(view.row_of(&layout, 1000.0), Some(0));
+        assert_eq!(view.row_of(&layout, 9000000.0), Some(rows - 1));
+        let last_top = view.row_bounds(&layout, rows - 1).2;
+        assert!(last_top <= 9000000.0 + 1e-6 && 9000000.0 - last_top < span, "last row at {}", last_top);
+        assert_eq!(LogView::rows_from_cells(&[0, 1, 2, 7, 8, 100]), vec![0, 1, 2, 3, 7, 8, 9, 100]);
+    }
+
+    #[test]
+    fn request_colors_must_be_hex() {
+        let params = |colors: &str| HashMap::from([("colors".to_string(), colors.to_string())]);
+        let overrides = LayoutOverrides::from_params(&params("FF0000, #00ff00,")).unwrap();
+        assert_eq!(overrides.colors, Some(vec!["FF0000".to_string(), "#00ff00".to_string()]));
+        assert_eq!(LayoutOverrides::from_params(&params("")).unwrap().colors, None);
+        for bad in ["zzz", "FF0000,<b>", "FFF", "12345G"] {
+            assert!(LayoutOverrides::from_params(&params(bad)).is_err(), "{}", bad);
+        }
+    }
+
+    #[test]
+    fn hostile_header_text_is_escaped() {
+        let page = render_page("<script>alert(4)</script>.las");
+        assert!(!page.contains("<script"), "raw script tag in page");
+        assert!(!page.contains("<img src=x"), "raw img tag in page");
+        assert!(!page.contains("GR<b>"), "raw mnemonic markup in page");
+        assert!(page.contains("&lt;img src=x onerror=alert(2)&gt;"));
+        assert!(page.contains("GR&lt;b&gt;"));
+        assert!(page.contains("&lt;script&gt;alert(1)&lt;/script&gt;"));
+        assert!(page.contains("&lt;script&gt;alert(3)&lt;/script&gt;"));
+        assert!(page.contains("&lt;script&gt;alert(4)&lt;/script&gt;.las"));
+        assert!(page.contains("&amp; Co"));
+        assert!(page.contains("&quot;quoted&quot; &amp; &#39;single&#39;"));
+    }
+}